@@ -1,65 +1,82 @@
+use std::fs;
 use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
 use crate::commands::CommandContext;
 use crate::database::blob::Blob;
+use crate::errors::Error;
 
-use crate::database::object::Object;
+use crate::database::object::Storable;
 
 use crate::repository::Repository;
 
-static INDEX_LOAD_OR_CREATE_FAILED: &'static str = "fatal: could not create/load .git/index\n";
-
-fn locked_index_message(e: &std::io::Error) -> String {
-    format!("fatal: {}
-
-Another jit process seems to be running in this repository. Please make sure all processes are terminated then try again.
-
-If it still fails, a jit process may have crashed in this repository earlier: remove the .git/index.lock file manually to continue.\n",
-            e)
-}
-
-fn add_failed_message(e: &std::io::Error) -> String {
-    format!(
+fn add_failed_message(e: &std::io::Error) -> Error {
+    Error::fatal(format!(
         "{}
 
 fatal: adding files failed\n",
         e
-    )
+    ))
 }
 
-fn add_to_index(repo: &mut Repository, pathname: &str) -> Result<(), String> {
-    let data = match repo.workspace.read_file(&pathname) {
-        Ok(data) => data,
-        Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => {
-            repo.index.release_lock().unwrap();
-            return Err(add_failed_message(&err));
-        }
-        _ => {
-            panic!("fatal: adding files failed");
+fn ignored_paths_message(paths: &[String]) -> Error {
+    Error::failure(format!(
+        "The following paths are ignored by one of your .gitignore files:
+{}
+hint: Use -f if you really want to add them.\n",
+        paths.join("\n")
+    ))
+}
+
+/// Canonicalize `path`, without following it if it is a symlink, so
+/// that the link itself is added rather than its target
+fn canonical_path(path: &Path) -> io::Result<PathBuf> {
+    match (fs::symlink_metadata(path)?, path.parent(), path.file_name()) {
+        (stat, Some(parent), Some(name)) if stat.file_type().is_symlink() => {
+            Ok(parent.canonicalize()?.join(name))
         }
-    };
+        _ => path.canonicalize(),
+    }
+}
 
-    let stat = repo
+pub fn add_to_index(repo: &mut Repository, pathname: &str) -> Result<(), Error> {
+    let path = repo.workspace.abs_path(pathname);
+    let data = repo
         .workspace
-        .stat_file(&pathname)
-        .expect("could not stat file");
-    let blob = Blob::new(data.as_bytes());
-    repo.database.store(&blob).expect("storing blob failed");
+        .read_file(pathname)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => add_failed_message(&e),
+            _ => Error::io("read", &path, e),
+        })?;
 
-    repo.index.add(&pathname, &blob.get_oid(), &stat);
+    let stat = repo
+        .workspace
+        .stat_file(pathname)
+        .map_err(|e| Error::io("stat", &path, e))?;
+    let blob = Blob::new(&data);
+    repo.database.store(&blob).map_err(|e| {
+        Error::io(
+            "write object",
+            repo.database.object_path(&blob.get_oid()),
+            e,
+        )
+    })?;
+
+    repo.index.add(pathname, &blob.get_oid(), &stat);
 
     Ok(())
 }
 
-pub fn add_command<I, O, E>(ctx: CommandContext<I, O, E>) -> Result<(), String>
+pub fn add_command<I, O, E>(ctx: CommandContext<I, O, E>) -> Result<(), Error>
 where
     I: Read,
     O: Write,
     E: Write,
 {
     let working_dir = ctx.dir;
-    let root_path = working_dir.as_path();
-    let mut repo = Repository::new(&root_path);
+    let mut repo = Repository::discover(&working_dir, ctx.env)?;
+    repo.require_work_tree()?;
+    let root_path = repo.root_path.clone();
     let options = ctx.options.as_ref().unwrap();
     let args: Vec<_> = if let Some(args) = options.values_of("args") {
         args.collect()
@@ -67,41 +84,66 @@ where
         vec![]
     };
 
-    match repo.index.load_for_update() {
-        Ok(_) => (),
-        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
-            return Err(locked_index_message(e));
-        }
-        Err(_) => {
-            return Err(INDEX_LOAD_OR_CREATE_FAILED.to_string());
-        }
-    }
+    repo.index.load_for_update()?;
 
+    let force = options.is_present("force");
     let mut paths = vec![];
+    let mut ignored = vec![];
     for arg in args {
-        let path = match working_dir.join(arg).canonicalize() {
+        let path = match canonical_path(&working_dir.join(arg)) {
             Ok(canon_path) => canon_path,
             Err(_) => {
                 repo.index.release_lock().unwrap();
-                return Err(format!(
+                return Err(Error::fatal(format!(
                     "fatal: pathspec '{:}' did not match any files\n",
                     arg
-                ));
+                )));
             }
         };
 
+        // Naming an ignored path is an error, but ignored files found
+        // inside a directory are just skipped
+        let relative = match path.strip_prefix(&root_path) {
+            Ok(relative) => relative.to_str().expect("invalid path"),
+            Err(_) => {
+                repo.index.release_lock().unwrap();
+                return Err(Error::fatal(format!(
+                    "fatal: '{}' is outside repository\n",
+                    arg
+                )));
+            }
+        };
+        if !force
+            && !relative.is_empty()
+            && !repo.index.is_tracked(relative)
+            && repo.ignore.is_ignored(relative, path.is_dir())
+        {
+            ignored.push(relative.to_string());
+            continue;
+        }
+
         for pathname in repo.workspace.list_files(&path).unwrap() {
-            paths.push(pathname);
+            if force
+                || repo.index.is_tracked_file(&pathname)
+                || !repo.ignore.is_ignored(&pathname, false)
+            {
+                paths.push(pathname);
+            }
         }
     }
 
     for pathname in paths {
-        add_to_index(&mut repo, &pathname)?;
+        if let Err(error) = add_to_index(&mut repo, &pathname) {
+            let _ = repo.index.release_lock();
+            return Err(error);
+        }
     }
 
-    repo.index
-        .write_updates()
-        .expect("writing updates to index failed");
+    repo.index.write_updates()?;
+
+    if !ignored.is_empty() {
+        return Err(ignored_paths_message(&ignored));
+    }
 
     Ok(())
 }
@@ -114,8 +156,8 @@ mod tests {
     fn add_regular_file_to_index() {
         let mut cmd_helper = CommandHelper::new();
         cmd_helper.write_file("hello.txt", b"hello").unwrap();
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.jit_cmd(&["add", "hello.txt"]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["add", "hello.txt"]).unwrap();
         cmd_helper
             .assert_index(vec![(0o100644, "hello.txt".to_string())])
             .unwrap();
@@ -127,8 +169,8 @@ mod tests {
         cmd_helper.write_file("hello.txt", b"hello").unwrap();
         cmd_helper.make_executable("hello.txt").unwrap();
 
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.jit_cmd(&["add", "hello.txt"]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["add", "hello.txt"]).unwrap();
         cmd_helper
             .assert_index(vec![(0o100755, "hello.txt".to_string())])
             .unwrap();
@@ -140,9 +182,9 @@ mod tests {
         cmd_helper.write_file("hello.txt", b"hello").unwrap();
         cmd_helper.write_file("world.txt", b"world").unwrap();
 
-        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
         cmd_helper
-            .jit_cmd(&["add", "hello.txt", "world.txt"])
+            .run_cmd(&["add", "hello.txt", "world.txt"])
             .unwrap();
 
         cmd_helper
@@ -159,14 +201,14 @@ mod tests {
         cmd_helper.write_file("hello.txt", b"hello").unwrap();
         cmd_helper.write_file("world.txt", b"world").unwrap();
 
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.jit_cmd(&["add", "hello.txt"]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["add", "hello.txt"]).unwrap();
 
         cmd_helper
             .assert_index(vec![(0o100644, "hello.txt".to_string())])
             .unwrap();
 
-        cmd_helper.jit_cmd(&["add", "world.txt"]).unwrap();
+        cmd_helper.run_cmd(&["add", "world.txt"]).unwrap();
         cmd_helper
             .assert_index(vec![
                 (0o100644, "hello.txt".to_string()),
@@ -179,9 +221,9 @@ mod tests {
     fn add_a_directory_to_index() {
         let mut cmd_helper = CommandHelper::new();
         cmd_helper.write_file("a-dir/nested.txt", b"hello").unwrap();
-        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
 
-        cmd_helper.jit_cmd(&["add", "a-dir"]).unwrap();
+        cmd_helper.run_cmd(&["add", "a-dir"]).unwrap();
         cmd_helper
             .assert_index(vec![(0o100644, "a-dir/nested.txt".to_string())])
             .unwrap();
@@ -192,8 +234,8 @@ mod tests {
         let mut cmd_helper = CommandHelper::new();
         cmd_helper.write_file("a/b/c/hello.txt", b"hello").unwrap();
 
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
         cmd_helper
             .assert_index(vec![(0o100644, "a/b/c/hello.txt".to_string())])
@@ -204,8 +246,8 @@ mod tests {
     fn add_fails_for_non_existent_files() {
         let mut cmd_helper = CommandHelper::new();
 
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        assert!(cmd_helper.jit_cmd(&["add", "hello.txt"]).is_err());
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        assert!(cmd_helper.run_cmd(&["add", "hello.txt"]).is_err());
     }
 
     #[test]
@@ -214,8 +256,8 @@ mod tests {
         cmd_helper.write_file("hello.txt", b"hello").unwrap();
         cmd_helper.make_unreadable("hello.txt").unwrap();
 
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        assert!(cmd_helper.jit_cmd(&["add", "hello.txt"]).is_err());
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        assert!(cmd_helper.run_cmd(&["add", "hello.txt"]).is_err());
     }
 
     #[test]
@@ -224,7 +266,45 @@ mod tests {
         cmd_helper.write_file("hello.txt", b"hello").unwrap();
         cmd_helper.write_file(".git/index.lock", b"hello").unwrap();
 
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        assert!(cmd_helper.jit_cmd(&["add", "hello.txt"]).is_err());
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        assert!(cmd_helper.run_cmd(&["add", "hello.txt"]).is_err());
+    }
+
+    #[test]
+    fn skip_ignored_files_unless_forced() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper
+            .write_file(".gitignore", b"*.log\nbuild/\n")
+            .unwrap();
+        cmd_helper.write_file("a.txt", b"").unwrap();
+        cmd_helper.write_file("a.log", b"").unwrap();
+        cmd_helper.write_file("build/out.txt", b"").unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, ".gitignore".to_string()),
+                (0o100644, "a.txt".to_string()),
+            ])
+            .unwrap();
+
+        let stderr = cmd_helper.run_cmd(&["add", "a.log", "build"]).unwrap_err();
+        assert_output(
+            &stderr,
+            "The following paths are ignored by one of your .gitignore files:
+a.log
+build
+hint: Use -f if you really want to add them.\n",
+        );
+
+        cmd_helper.run_cmd(&["add", "-f", "a.log"]).unwrap();
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, ".gitignore".to_string()),
+                (0o100644, "a.log".to_string()),
+                (0o100644, "a.txt".to_string()),
+            ])
+            .unwrap();
     }
 }
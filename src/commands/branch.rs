@@ -1,6 +1,7 @@
 use crate::commands::CommandContext;
-use crate::database::object::Object;
+use crate::database::object::Storable;
 use crate::database::{Database, ParsedObject};
+use crate::errors::Error;
 use crate::pager::Pager;
 use crate::refs::Ref;
 use crate::repository::Repository;
@@ -15,7 +16,7 @@ where
     E: Write,
 {
     repo: Repository,
-    ctx: CommandContext<'a, I, O, E>,
+    ctx: CommandContext<'a, I, Pager<O>, E>,
 }
 
 impl<'a, I, O, E> Branch<'a, I, O, E>
@@ -24,15 +25,16 @@ where
     O: Write,
     E: Write,
 {
-    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Branch<'a, I, O, E> {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Result<Branch<'a, I, O, E>, Error> {
+        let repo = Repository::discover(&ctx.dir, ctx.env)?;
 
-        Branch { repo, ctx }
+        Ok(Branch {
+            repo,
+            ctx: ctx.with_pager(),
+        })
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
+    pub fn run(&mut self) -> Result<(), Error> {
         let options = self.ctx.options.as_ref().unwrap().clone();
         let args: Vec<_> = if let Some(args) = options.values_of("args") {
             args.collect()
@@ -45,14 +47,12 @@ where
         } else if args.is_empty() {
             self.list_branches()?;
         } else {
-            let branch_name = args.get(0).expect("no branch name provided");
-            let start_point = args.get(1);
-            self.create_branch(branch_name, start_point)?;
+            self.create_branch(args[0], args.get(1))?;
         }
         Ok(())
     }
 
-    fn list_branches(&mut self) -> Result<(), String> {
+    fn list_branches(&mut self) -> Result<(), Error> {
         let current = self.repo.refs.current_ref("HEAD");
         let mut branches = self.repo.refs.list_branches();
         branches.sort();
@@ -63,12 +63,12 @@ where
             .max()
             .unwrap_or(0);
 
-        Pager::setup_pager();
+        self.ctx.stdout.start(self.ctx.env);
 
         for r#ref in branches {
             let info = self.format_ref(&r#ref, &current);
-            let extended_info = self.extended_branch_info(&r#ref, max_width);
-            println!("{}{}", info, extended_info);
+            let extended_info = self.extended_branch_info(&r#ref, max_width)?;
+            writeln!(self.ctx.stdout, "{}{}", info, extended_info).map_err(Error::Output)?;
         }
 
         Ok(())
@@ -82,7 +82,7 @@ where
         }
     }
 
-    fn extended_branch_info(&mut self, r#ref: &Ref, max_width: usize) -> String {
+    fn extended_branch_info(&mut self, r#ref: &Ref, max_width: usize) -> Result<String, Error> {
         if self
             .ctx
             .options
@@ -90,28 +90,32 @@ where
             .map(|o| o.is_present("verbose"))
             .unwrap_or(false)
         {
-            let oid = self
-                .repo
-                .refs
-                .read_oid(r#ref)
-                .expect("unable to resolve branch to oid");
-            let commit = if let ParsedObject::Commit(commit) = self.repo.database.load(&oid) {
+            let oid = self.repo.refs.read_oid(r#ref).ok_or_else(|| {
+                Error::fatal(format!(
+                    "fatal: branch '{}' does not point at a commit\n",
+                    self.repo.refs.ref_short_name(r#ref)
+                ))
+            })?;
+            let commit = if let ParsedObject::Commit(commit) = self.repo.database.load(&oid)? {
                 commit
             } else {
-                panic!("branch ref was not pointing to commit");
+                return Err(Error::fatal(format!(
+                    "fatal: branch '{}' does not point at a commit\n",
+                    self.repo.refs.ref_short_name(r#ref)
+                )));
             };
             let oid = commit.get_oid();
             let short = Database::short_oid(&oid);
             let ref_short_name = self.repo.refs.ref_short_name(r#ref);
-            format!(
+            Ok(format!(
                 "{:width$}{} {}",
                 " ",
                 short,
                 commit.title_line(),
                 width = (max_width - ref_short_name.len() + 1)
-            )
+            ))
         } else {
-            "".to_string()
+            Ok("".to_string())
         }
     }
 
@@ -119,41 +123,35 @@ where
         &mut self,
         branch_name: &str,
         start_point: Option<&&str>,
-    ) -> Result<(), String> {
-        let start_point = if start_point.is_none() {
-            self.repo.refs.read_head().expect("empty HEAD")
-        } else {
-            match Revision::new(&mut self.repo, start_point.unwrap()).resolve() {
-                Ok(rev) => rev,
-                Err(errors) => {
-                    let mut v = vec![];
-                    for error in errors {
-                        v.push(format!("error: {}", error.message));
-                        for h in error.hint {
-                            v.push(format!("hint: {}", h));
-                        }
-                    }
-
-                    v.push("\n".to_string());
-
-                    return Err(v.join("\n"));
-                }
-            }
+    ) -> Result<(), Error> {
+        let start_name = start_point.map_or("HEAD", |name| *name);
+        let start_point = match start_point {
+            None => self.repo.refs.read_head().ok_or_else(|| {
+                let current = self.repo.refs.current_ref("HEAD");
+                Error::fatal(format!(
+                    "fatal: not a valid object name: '{}'\n",
+                    self.repo.refs.ref_short_name(&current)
+                ))
+            })?,
+            Some(start_point) => Revision::new(&mut self.repo, start_point).resolve()?,
         };
 
-        self.repo.refs.create_branch(branch_name, &start_point)?;
+        let message = format!("branch: Created from {}", start_name);
+        self.repo
+            .refs
+            .create_branch(branch_name, &start_point, &message)?;
 
         Ok(())
     }
 
-    fn delete_branches(&mut self, branch_names: Vec<&str>) -> Result<(), String> {
+    fn delete_branches(&mut self, branch_names: Vec<&str>) -> Result<(), Error> {
         for branch in branch_names {
             self.delete_branch(branch)?;
         }
         Ok(())
     }
 
-    fn delete_branch(&mut self, branch_name: &str) -> Result<(), String> {
+    fn delete_branch(&mut self, branch_name: &str) -> Result<(), Error> {
         let force = self
             .ctx
             .options
@@ -167,7 +165,99 @@ where
         let oid = self.repo.refs.delete_branch(branch_name)?;
         let short = Database::short_oid(&oid);
 
-        println!("Deleted branch {} (was {})", branch_name, short);
+        writeln!(
+            self.ctx.stdout,
+            "Deleted branch {} (was {})",
+            branch_name, short
+        )
+        .map_err(Error::Output)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::database::Database;
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = setup_first_commit();
+        cmd_helper.run_cmd(&["branch", "topic"]).unwrap();
+        cmd_helper.write_file("a.txt", b"changed\n").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("second");
+
+        cmd_helper
+    }
+
+    #[test]
+    fn list_branches() {
+        let mut cmd_helper = setup();
+
+        let (stdout, stderr) = cmd_helper.run_cmd(&["branch"]).unwrap();
+        assert_output(&stdout, "* master\n  topic\n");
+        assert_output(&stderr, "");
+
+        let (stdout, _) = cmd_helper.run_cmd(&["branch", "-v"]).unwrap();
+        let short = |rev: &str| {
+            let oid = cmd_helper.git_cmd(&["rev-parse", rev]);
+            Database::short_oid(oid.trim()).to_string()
+        };
+        assert_output(
+            &stdout,
+            &format!(
+                "* master {} second\n  topic  {} first\n",
+                short("master"),
+                short("topic")
+            ),
+        );
+    }
+
+    #[test]
+    fn create_and_delete_branches() {
+        let mut cmd_helper = setup();
+
+        cmd_helper.run_cmd(&["branch", "old", "HEAD^"]).unwrap();
+        assert_eq!(
+            cmd_helper.git_cmd(&["rev-parse", "topic"]),
+            cmd_helper.git_cmd(&["rev-parse", "old"])
+        );
+
+        let stderr = cmd_helper.run_cmd(&["branch", "old"]).unwrap_err();
+        assert_output(&stderr, "A branch named old already exists.\n");
+
+        let oid = cmd_helper.git_cmd(&["rev-parse", "old"]);
+        let (stdout, _) = cmd_helper.run_cmd(&["branch", "-D", "old"]).unwrap();
+        assert_output(
+            &stdout,
+            &format!(
+                "Deleted branch old (was {})\n",
+                Database::short_oid(oid.trim())
+            ),
+        );
+    }
+
+    #[test]
+    fn send_output_through_the_pager() {
+        let mut cmd_helper = setup();
+
+        cmd_helper.set_pager(PAGER);
+        assert_eq!(
+            ("|* master\n|  topic\n".to_string(), String::new()),
+            cmd_helper.run_cmd(&["branch"]).unwrap()
+        );
+
+        cmd_helper.set_env("GIT_PAGER", "");
+        let (stdout, _) = cmd_helper.run_cmd(&["branch"]).unwrap();
+        assert_output(&stdout, "* master\n  topic\n");
+    }
+
+    #[test]
+    fn refuse_to_branch_from_an_unborn_head() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+
+        let stderr = cmd_helper.run_cmd(&["branch", "topic"]).unwrap_err();
+        assert_output(&stderr, "fatal: not a valid object name: 'master'\n");
+    }
+}
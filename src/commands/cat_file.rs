@@ -0,0 +1,243 @@
+use std::io::{BufRead, BufReader, Read, Write};
+
+use crate::commands::ls_tree::tree_line;
+use crate::commands::CommandContext;
+use crate::database::{ParsedObject, Raw};
+use crate::errors::Error;
+use crate::repository::Repository;
+use crate::revision::Revision;
+
+pub fn cat_file_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let mut repo = Repository::discover(&ctx.dir, ctx.env)?;
+    let options = ctx.options.as_ref().unwrap().clone();
+
+    if options.is_present("batch") || options.is_present("batch-check") {
+        let contents = options.is_present("batch");
+        for line in BufReader::new(&mut ctx.stdin).lines() {
+            let name = line.map_err(|e| Error::io("read", "standard input", e))?;
+            let raw = Revision::resolve_object(&mut repo, &name)
+                .and_then(|oid| repo.database.load_raw(&oid).ok().map(|raw| (oid, raw)));
+
+            let (oid, raw) = match raw {
+                Some(found) => found,
+                None => {
+                    writeln!(ctx.stdout, "{} missing", name).map_err(Error::Output)?;
+                    continue;
+                }
+            };
+            writeln!(ctx.stdout, "{} {} {}", oid, raw.obj_type, raw.data.len())
+                .map_err(Error::Output)?;
+            if contents {
+                ctx.stdout.write_all(&raw.data).map_err(Error::Output)?;
+                writeln!(ctx.stdout).map_err(Error::Output)?;
+            }
+        }
+        return Ok(());
+    }
+
+    let name = match options.value_of("object") {
+        Some(name) => name,
+        None => {
+            return Err(
+                Error::usage("usage: rug cat-file (-t | -s | -e | -p) <object>\n   or: rug cat-file (--batch | --batch-check)\n"),
+            )
+        }
+    };
+    let invalid = || Error::fatal(format!("fatal: Not a valid object name {}\n", name));
+    let oid = Revision::resolve_object(&mut repo, name);
+
+    // -e answers with its exit status alone, once given a well-formed
+    // object ID
+    if options.is_present("exists") {
+        let oid = match oid {
+            Some(oid) => oid,
+            None if is_full_oid(name) => name.to_string(),
+            None => return Err(invalid()),
+        };
+        return match repo.database.load_raw(&oid) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::Exit(1)),
+        };
+    }
+
+    let oid = oid.ok_or_else(invalid)?;
+    let raw = repo.database.load_raw(&oid)?;
+    if options.is_present("type") {
+        writeln!(ctx.stdout, "{}", raw.obj_type).map_err(Error::Output)?;
+    } else if options.is_present("size") {
+        writeln!(ctx.stdout, "{}", raw.data.len()).map_err(Error::Output)?;
+    } else {
+        pretty_print(&raw, &mut ctx.stdout)?;
+    }
+
+    Ok(())
+}
+
+fn is_full_oid(name: &str) -> bool {
+    name.len() == 40 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Show an object's contents, listing trees' entries one per line
+/// since their raw form is binary
+fn pretty_print<O: Write>(raw: &Raw, stdout: &mut O) -> Result<(), Error> {
+    match raw.parse()? {
+        ParsedObject::Tree(tree) => {
+            for (name, entry) in &tree.entries {
+                writeln!(stdout, "{}", tree_line(entry, name)).map_err(Error::Output)?;
+            }
+            Ok(())
+        }
+        _ => stdout.write_all(&raw.data).map_err(Error::Output),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"hello\n").unwrap();
+        cmd_helper
+            .write_file("dir/nested.txt", b"nested\n")
+            .unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        cmd_helper
+    }
+
+    #[test]
+    fn show_an_objects_type_size_and_contents() {
+        let mut cmd_helper = setup();
+        cmd_helper
+            .run_cmd(&["tag", "-a", "-m", "release", "v1.0"])
+            .unwrap();
+
+        for args in &[
+            vec!["cat-file", "-t", "HEAD"],
+            vec!["cat-file", "-t", "v1.0"],
+            vec!["cat-file", "-s", "HEAD:file.txt"],
+            vec!["cat-file", "-p", "HEAD"],
+            vec!["cat-file", "-p", "HEAD:"],
+            vec!["cat-file", "-p", "HEAD:dir/nested.txt"],
+            vec!["cat-file", "-p", "v1.0"],
+        ] {
+            let (stdout, _) = cmd_helper.run_cmd(args).unwrap();
+            assert_output(&stdout, &cmd_helper.git_cmd(args));
+        }
+    }
+
+    #[test]
+    fn check_whether_objects_exist() {
+        let mut cmd_helper = setup();
+
+        assert!(cmd_helper
+            .run_cmd(&["cat-file", "-e", "HEAD:file.txt"])
+            .is_ok());
+        assert_eq!(
+            (1, String::new(), String::new()),
+            cmd_helper.jit_status(&["cat-file", "-e", &"1".repeat(40)])
+        );
+        assert_eq!(
+            (
+                128,
+                String::new(),
+                "fatal: Not a valid object name HEAD:missing.txt\n".to_string()
+            ),
+            cmd_helper.jit_status(&["cat-file", "-e", "HEAD:missing.txt"])
+        );
+
+        let stderr = cmd_helper.run_cmd(&["cat-file", "-t", "nope"]).unwrap_err();
+        assert_output(&stderr, "fatal: Not a valid object name nope\n");
+    }
+
+    #[test]
+    fn report_missing_and_corrupt_objects() {
+        let mut cmd_helper = setup();
+        let oid = cmd_helper.git_cmd(&["rev-parse", "HEAD:file.txt"]);
+        let oid = oid.trim();
+        let path = object_file(oid);
+
+        cmd_helper.delete(&path).unwrap();
+        assert_eq!(
+            (1, String::new(), String::new()),
+            cmd_helper.jit_status(&["cat-file", "-e", "HEAD:file.txt"])
+        );
+        assert_eq!(
+            (
+                128,
+                String::new(),
+                format!("fatal: unable to read {}\n", oid)
+            ),
+            cmd_helper.jit_status(&["cat-file", "-p", "HEAD:file.txt"])
+        );
+
+        cmd_helper.write_file(&path, b"not zlib").unwrap();
+        let (status, _, stderr) = cmd_helper.jit_status(&["cat-file", "-p", "HEAD:file.txt"]);
+        assert_eq!(128, status);
+        assert!(
+            stderr.ends_with(&format!("fatal: object {} is corrupt\n", oid)),
+            "{}",
+            stderr
+        );
+
+        cmd_helper.write_file("odd.txt", b"odd").unwrap();
+        let odd = cmd_helper.git_cmd(&["hash-object", "-t", "odd", "--literally", "-w", "odd.txt"]);
+        let (status, _, stderr) = cmd_helper.jit_status(&["cat-file", "-p", odd.trim()]);
+        assert_eq!(128, status);
+        assert_output(
+            &stderr,
+            &format!(
+                "error: invalid object type \"odd\"\nfatal: object {} is corrupt\n",
+                odd.trim()
+            ),
+        );
+
+        cmd_helper
+            .write_file("tree.bin", b"100644 file.txt\0short")
+            .unwrap();
+        let tree =
+            cmd_helper.git_cmd(&["hash-object", "-t", "tree", "--literally", "-w", "tree.bin"]);
+        let (status, _, stderr) = cmd_helper.jit_status(&["cat-file", "-p", tree.trim()]);
+        assert_eq!(128, status);
+        assert_output(
+            &stderr,
+            &format!(
+                "error: truncated tree entry oid\nfatal: object {} is corrupt\n",
+                tree.trim()
+            ),
+        );
+    }
+
+    #[test]
+    fn answer_queries_in_batches() {
+        let mut cmd_helper = setup();
+        let input = "HEAD\nHEAD:file.txt\nmissing\n";
+
+        cmd_helper.set_stdin(input);
+        let (stdout, _) = cmd_helper.run_cmd(&["cat-file", "--batch-check"]).unwrap();
+        let blob = cmd_helper.git_cmd(&["rev-parse", "HEAD:file.txt"]);
+        let commit = cmd_helper.git_cmd(&["rev-parse", "HEAD"]);
+        let size = cmd_helper.git_cmd(&["cat-file", "-s", "HEAD"]);
+        assert_output(
+            &stdout,
+            &format!(
+                "{} commit {}\n{} blob 6\nmissing missing\n",
+                commit.trim(),
+                size.trim(),
+                blob.trim()
+            ),
+        );
+
+        cmd_helper.set_stdin("HEAD:file.txt\n");
+        let (stdout, _) = cmd_helper.run_cmd(&["cat-file", "--batch"]).unwrap();
+        assert_output(&stdout, &format!("{} blob 6\nhello\n\n", blob.trim()));
+    }
+}
@@ -1,14 +1,11 @@
 use crate::commands::CommandContext;
-use crate::database::object::Object;
-use crate::database::tree::TreeEntry;
-use crate::database::tree_diff::TreeDiff;
+use crate::database::object::Storable;
 use crate::database::{Database, ParsedObject};
+use crate::errors::Error;
 use crate::refs::Ref;
 use crate::repository::Repository;
 use crate::revision::Revision;
-use std::collections::HashMap;
 use std::io::{Read, Write};
-use std::path::PathBuf;
 
 const DETACHED_HEAD_MESSAGE: &str =
     "You are in 'detached HEAD' state. You can look around, make experimental 
@@ -37,26 +34,30 @@ where
     O: Write,
     E: Write,
 {
-    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Checkout<'a, I, O, E> {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Result<Checkout<'a, I, O, E>, Error> {
+        let repo = Repository::discover(&ctx.dir, ctx.env)?;
+        repo.require_work_tree()?;
 
-        Checkout { repo, ctx }
+        Ok(Checkout { repo, ctx })
     }
 
-    fn print_head_position(&mut self, message: &str, oid: &str) -> Result<(), String> {
-        let commit = match self.repo.database.load(oid) {
+    fn print_head_position(&mut self, message: &str, oid: &str) -> Result<(), Error> {
+        let commit = match self.repo.database.load(oid)? {
             ParsedObject::Commit(commit) => commit,
-            _ => panic!("oid not a commit"),
+            _ => return Err(Error::fatal(format!("fatal: {} is not a commit\n", oid))),
         };
         let oid = commit.get_oid();
         let short = Database::short_oid(&oid);
 
-        println!(
-            "{}",
-            format!("{} {} {}", message, short, commit.title_line())
-        );
+        writeln!(
+            self.ctx.stdout,
+            "{} {} {}",
+            message,
+            short,
+            commit.title_line()
+        )
+        .map_err(Error::Output)?;
+
         Ok(())
     }
 
@@ -65,7 +66,7 @@ where
         current_ref: &Ref,
         current_oid: &str,
         target_oid: &str,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         if current_ref.is_head() && current_oid != target_oid {
             return self.print_head_position("Previous HEAD position was", current_oid);
         }
@@ -77,16 +78,17 @@ where
         current_ref: &Ref,
         target: &str,
         new_ref: &Ref,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         if new_ref.is_head() && !current_ref.is_head() {
-            println!(
-                "{}
+            writeln!(
+                self.ctx.stdout,
+                "Note: checking out '{}'.
 
 {}
 ",
-                format!("Note: checking out '{}'.", target),
-                DETACHED_HEAD_MESSAGE
-            );
+                target, DETACHED_HEAD_MESSAGE
+            )
+            .map_err(Error::Output)?;
         }
         Ok(())
     }
@@ -97,87 +99,67 @@ where
         new_ref: &Ref,
         target: &str,
         target_oid: &str,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         if new_ref.is_head() {
             self.print_head_position("HEAD is now at", target_oid)?;
         } else if new_ref == current_ref {
-            eprintln!("{}", format!("Already on {}", target));
+            writeln!(self.ctx.stderr, "Already on {}", target).map_err(Error::Output)?;
         } else {
-            eprintln!("{}", format!("Switched to branch {}", target));
+            writeln!(self.ctx.stderr, "Switched to branch {}", target).map_err(Error::Output)?;
         }
         Ok(())
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
+    pub fn run(&mut self) -> Result<(), Error> {
         let options = self.ctx.options.as_ref().unwrap().clone();
         let args: Vec<_> = if let Some(args) = options.values_of("args") {
             args.collect()
         } else {
             vec![]
         };
-        let target = args.get(0).expect("no target provided");
+        let target = args.first().ok_or_else(|| {
+            Error::usage("usage: rug checkout <branch>\n   or: rug checkout <commit>\n")
+        })?;
+        let target =
+            &Revision::previous_branch(&self.repo, target).unwrap_or_else(|| target.to_string());
 
-        self.repo
-            .index
-            .load_for_update()
-            .map_err(|e| e.to_string())?;
+        self.repo.index.load_for_update()?;
 
         let current_ref = self.repo.refs.current_ref("HEAD");
         let current_oid = self
             .repo
             .refs
             .read_oid(&current_ref)
-            .unwrap_or_else(|| panic!("failed to read ref: {:?}", current_ref));
-
-        let mut revision = Revision::new(&mut self.repo, target);
-        let target_oid = match revision.resolve() {
-            Ok(oid) => oid,
-            Err(errors) => {
-                let mut v = vec![];
-                for error in errors {
-                    v.push(format!("error: {}", error.message));
-                    for h in error.hint {
-                        v.push(format!("hint: {}", h));
-                    }
-                }
-
-                v.push("\n".to_string());
-
-                return Err(v.join("\n"));
-            }
-        };
+            .ok_or_else(|| Error::fatal("fatal: You are on a branch yet to be born\n"))?;
+
+        let target_oid = Revision::new(&mut self.repo, target).resolve()?;
 
-        let tree_diff = self.tree_diff(&current_oid, &target_oid);
+        let tree_diff = self
+            .repo
+            .database
+            .tree_diff(Some(&current_oid), Some(&target_oid))?;
         let mut migration = self.repo.migration(tree_diff);
         migration.apply_changes()?;
 
-        self.repo.index.write_updates().map_err(|e| e.to_string())?;
-        self.repo
-            .refs
-            .set_head(&target, &target_oid)
-            .map_err(|e| e.to_string())?;
+        self.repo.index.write_updates()?;
+        let previous = if current_ref.is_head() {
+            current_oid.clone()
+        } else {
+            self.repo.refs.ref_short_name(&current_ref)
+        };
+        self.repo.refs.set_head(
+            target,
+            &target_oid,
+            &format!("checkout: moving from {} to {}", previous, target),
+        )?;
 
         let new_ref = self.repo.refs.current_ref("HEAD");
         self.print_previous_head(&current_ref, &current_oid, &target_oid)?;
-        self.print_detachment_notice(&current_ref, &target, &new_ref)?;
-        self.print_new_head(&current_ref, &new_ref, &target, &target_oid)?;
+        self.print_detachment_notice(&current_ref, target, &new_ref)?;
+        self.print_new_head(&current_ref, &new_ref, target, &target_oid)?;
 
         Ok(())
     }
-
-    fn tree_diff(
-        &mut self,
-        a: &str,
-        b: &str,
-    ) -> HashMap<PathBuf, (Option<TreeEntry>, Option<TreeEntry>)> {
-        let mut td = TreeDiff::new(&mut self.repo.database);
-        td.compare_oids(
-            Some(a.to_string()),
-            Some(b.to_string()),
-            std::path::Path::new(""),
-        );
-        td.changes
-    }
 }
 
 #[cfg(test)]
@@ -197,23 +179,23 @@ mod tests {
 
     fn commit_all(cmd_helper: &mut CommandHelper) {
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
         cmd_helper.commit("change");
     }
 
     fn commit_and_checkout(cmd_helper: &mut CommandHelper, revision: &str) {
         commit_all(cmd_helper);
-        cmd_helper.jit_cmd(&["checkout", revision]).unwrap();
+        cmd_helper.run_cmd(&["checkout", revision]).unwrap();
     }
 
     fn before(cmd_helper: &mut CommandHelper) {
-        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
         for (filename, contents) in BASE_FILES.iter() {
             cmd_helper
                 .write_file(filename, contents.as_bytes())
                 .unwrap();
         }
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
         cmd_helper.commit("first");
     }
 
@@ -222,7 +204,7 @@ mod tests {
             assert_eq!(error,
                        format!("Your local changes to the following files would be overwritten by checkout:\n\t{}\nPlease commit your changes to stash them before you switch branches\n\n", filename));
         } else {
-            assert!(false, format!("Expected Err but got {:?}", error));
+            panic!("Expected Err but got {:?}", error);
         }
     }
 
@@ -231,7 +213,7 @@ mod tests {
             assert_eq!(error,
                        format!("Updating the following directories would lose untracked files in them:\n\t{}\n\n\n\n", filename));
         } else {
-            assert!(false, format!("Expected Err but got {:?}", error));
+            panic!("Expected Err but got {:?}", error);
         }
     }
 
@@ -240,7 +222,7 @@ mod tests {
             assert_eq!(error,
                        format!("The following untracked working tree files would be removed by checkout:\n\t{}\nPlease commit your changes to stash them before you switch branches\n\n", filename));
         } else {
-            assert!(false, format!("Expected Err but got {:?}", error));
+            panic!("Expected Err but got {:?}", error);
         }
     }
 
@@ -249,7 +231,7 @@ mod tests {
             assert_eq!(error,
                        format!("The following untracked working tree files would be overwritten by checkout:\n\t{}\nPlease move or remove them before you switch branches\n\n", filename));
         } else {
-            assert!(false, format!("Expected Err but got {:?}", error));
+            panic!("Expected Err but got {:?}", error);
         }
     }
 
@@ -269,7 +251,7 @@ mod tests {
         cmd_helper.write_file("1.txt", b"changed").unwrap();
         commit_all(&mut cmd_helper);
         cmd_helper.write_file("1.txt", b"conflict").unwrap();
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "1.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "1.txt");
     }
 
     #[test]
@@ -281,7 +263,7 @@ mod tests {
 
         cmd_helper.write_file("1.txt", b"1").unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "1.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "1.txt");
     }
 
     #[test]
@@ -293,7 +275,7 @@ mod tests {
 
         cmd_helper.make_executable("1.txt").unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "1.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "1.txt");
     }
 
     #[test]
@@ -304,7 +286,7 @@ mod tests {
         commit_all(&mut cmd_helper);
 
         cmd_helper.delete("1.txt").unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
         cmd_helper.assert_workspace(BASE_FILES.clone());
     }
 
@@ -318,13 +300,12 @@ mod tests {
         commit_all(&mut cmd_helper);
 
         cmd_helper.delete("outer").unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         let mut expected_workspace = BASE_FILES.clone();
         expected_workspace.remove("outer/2.txt");
         cmd_helper.assert_workspace(expected_workspace);
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(" D outer/2.txt\n");
     }
 
@@ -336,9 +317,9 @@ mod tests {
         commit_all(&mut cmd_helper);
 
         cmd_helper.write_file("1.txt", b"conflict").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "1.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "1.txt");
     }
 
     #[test]
@@ -349,8 +330,8 @@ mod tests {
         commit_all(&mut cmd_helper);
 
         cmd_helper.write_file("1.txt", b"1").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
     }
@@ -363,9 +344,9 @@ mod tests {
         commit_all(&mut cmd_helper);
 
         cmd_helper.make_executable("1.txt").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "1.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "1.txt");
     }
 
     #[test]
@@ -377,9 +358,9 @@ mod tests {
 
         cmd_helper.delete("1.txt").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "1.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "1.txt");
     }
 
     #[test]
@@ -391,10 +372,10 @@ mod tests {
 
         cmd_helper.delete("1.txt").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
         cmd_helper.write_file("1.txt", b"conflict").unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "1.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "1.txt");
     }
 
     #[test]
@@ -408,9 +389,9 @@ mod tests {
 
         cmd_helper.delete("outer/inner").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/inner/3.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/inner/3.txt");
     }
 
     #[test]
@@ -425,7 +406,7 @@ mod tests {
         cmd_helper.delete("outer/inner").unwrap();
         cmd_helper.write_file("outer/inner", b"conflict").unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/inner/3.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/inner/3.txt");
     }
 
     #[test]
@@ -439,9 +420,9 @@ mod tests {
 
         cmd_helper.delete("outer/inner").unwrap();
         cmd_helper.write_file("outer/inner", b"conflict").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/inner/3.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/inner/3.txt");
     }
 
     #[test]
@@ -455,11 +436,11 @@ mod tests {
 
         cmd_helper.delete("outer/inner").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
         cmd_helper.write_file("outer/inner", b"conflict").unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/inner/3.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/inner/3.txt");
     }
 
     #[test]
@@ -474,7 +455,7 @@ mod tests {
             .write_file("outer/2.txt/extra.log", b"conflict")
             .unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/2.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/2.txt");
     }
 
     #[test]
@@ -488,9 +469,9 @@ mod tests {
         cmd_helper
             .write_file("outer/2.txt/extra.log", b"conflict")
             .unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/2.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/2.txt");
     }
 
     #[test]
@@ -501,7 +482,6 @@ mod tests {
         commit_and_checkout(&mut cmd_helper, "@^");
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -513,7 +493,6 @@ mod tests {
         commit_and_checkout(&mut cmd_helper, "@^");
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -526,7 +505,6 @@ mod tests {
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
         cmd_helper.assert_noent("new");
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -539,7 +517,6 @@ mod tests {
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
         cmd_helper.assert_noent("new");
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -552,7 +529,7 @@ mod tests {
 
         cmd_helper.write_file("outer/94.txt", b"conflict").unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/94.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/94.txt");
     }
 
     #[test]
@@ -564,7 +541,7 @@ mod tests {
 
         cmd_helper.make_executable("outer/94.txt").unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/94.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/94.txt");
     }
 
     #[test]
@@ -575,10 +552,9 @@ mod tests {
         commit_all(&mut cmd_helper);
 
         cmd_helper.delete("outer/94.txt").unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -590,13 +566,12 @@ mod tests {
         commit_all(&mut cmd_helper);
 
         cmd_helper.delete("outer/inner").unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         let mut expected_workspace = BASE_FILES.clone();
         expected_workspace.remove("outer/inner/3.txt").unwrap();
 
         cmd_helper.assert_workspace(expected_workspace);
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(" D outer/inner/3.txt\n");
     }
 
@@ -608,9 +583,9 @@ mod tests {
         commit_all(&mut cmd_helper);
 
         cmd_helper.write_file("outer/94.txt", b"conflict").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/94.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/94.txt");
     }
 
     #[test]
@@ -621,9 +596,9 @@ mod tests {
         commit_all(&mut cmd_helper);
 
         cmd_helper.make_executable("outer/94.txt").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/94.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/94.txt");
     }
 
     #[test]
@@ -635,11 +610,10 @@ mod tests {
 
         cmd_helper.delete("outer/94.txt").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -652,10 +626,10 @@ mod tests {
 
         cmd_helper.delete("outer/94.txt").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
         cmd_helper.write_file("outer/94.txt", b"conflict").unwrap();
 
-        assert_remove_conflict(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/94.txt");
+        assert_remove_conflict(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/94.txt");
     }
 
     #[test]
@@ -667,14 +641,13 @@ mod tests {
 
         cmd_helper.delete("outer/inner").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         let mut expected_workspace = BASE_FILES.clone();
         expected_workspace.remove("outer/inner/3.txt").unwrap();
 
         cmd_helper.assert_workspace(expected_workspace);
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("D  outer/inner/3.txt\n");
     }
 
@@ -689,7 +662,7 @@ mod tests {
         cmd_helper.write_file("outer/inner", b"conflict").unwrap();
 
         assert_stale_file(
-            cmd_helper.jit_cmd(&["checkout", "@^"]),
+            cmd_helper.run_cmd(&["checkout", "@^"]),
             "outer/inner/94.txt",
         );
     }
@@ -703,8 +676,8 @@ mod tests {
 
         cmd_helper.delete("outer/inner").unwrap();
         cmd_helper.write_file("outer/inner", b"conflict").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         let mut expected_workspace = BASE_FILES.clone();
         expected_workspace.remove("outer/inner/3.txt").unwrap();
@@ -712,7 +685,6 @@ mod tests {
 
         cmd_helper.assert_workspace(expected_workspace);
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(
             "A  outer/inner
 D  outer/inner/3.txt\n",
@@ -728,11 +700,11 @@ D  outer/inner/3.txt\n",
 
         cmd_helper.delete("outer/inner").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
         cmd_helper.write_file("outer/inner", b"conflict").unwrap();
 
-        assert_remove_conflict(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/inner");
+        assert_remove_conflict(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/inner");
     }
 
     #[test]
@@ -747,7 +719,7 @@ D  outer/inner/3.txt\n",
             .write_file("outer/94.txt/extra.log", b"conflict")
             .unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/94.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/94.txt");
     }
 
     #[test]
@@ -761,12 +733,11 @@ D  outer/inner/3.txt\n",
         cmd_helper
             .write_file("outer/94.txt/extra.log", b"conflict")
             .unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
         cmd_helper.assert_workspace(BASE_FILES.clone());
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -778,7 +749,6 @@ D  outer/inner/3.txt\n",
         commit_and_checkout(&mut cmd_helper, "@^");
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -790,7 +760,6 @@ D  outer/inner/3.txt\n",
         commit_and_checkout(&mut cmd_helper, "@^");
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -802,7 +771,6 @@ D  outer/inner/3.txt\n",
         commit_and_checkout(&mut cmd_helper, "@^");
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -814,7 +782,23 @@ D  outer/inner/3.txt\n",
         commit_all(&mut cmd_helper);
 
         cmd_helper.write_file("outer/2.txt", b"conflict").unwrap();
-        assert_overwrite_conflict(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/2.txt");
+        assert_overwrite_conflict(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/2.txt");
+    }
+
+    #[test]
+    fn overwrites_an_ignored_file() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.delete("outer/2.txt").unwrap();
+        commit_all(&mut cmd_helper);
+
+        cmd_helper
+            .write_file(".git/info/exclude", b"2.txt\n")
+            .unwrap();
+        cmd_helper.write_file("outer/2.txt", b"ignored").unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
+
+        cmd_helper.assert_workspace(BASE_FILES.clone());
     }
 
     #[test]
@@ -825,9 +809,9 @@ D  outer/inner/3.txt\n",
         commit_all(&mut cmd_helper);
 
         cmd_helper.write_file("outer/2.txt", b"conflict").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        assert_stale_file(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/2.txt");
+        assert_stale_file(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/2.txt");
     }
 
     #[test]
@@ -838,11 +822,10 @@ D  outer/inner/3.txt\n",
         commit_all(&mut cmd_helper);
 
         cmd_helper.write_file("outer/2.txt", b"2").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -856,7 +839,7 @@ D  outer/inner/3.txt\n",
         cmd_helper.delete("outer/inner").unwrap();
         cmd_helper.write_file("outer/inner", b"conflict").unwrap();
 
-        assert_overwrite_conflict(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/inner");
+        assert_overwrite_conflict(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/inner");
     }
 
     #[test]
@@ -869,11 +852,10 @@ D  outer/inner/3.txt\n",
         cmd_helper.delete("outer/inner").unwrap();
         cmd_helper.write_file("outer/inner", b"conflict").unwrap();
 
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -888,7 +870,7 @@ D  outer/inner/3.txt\n",
             .write_file("outer/2.txt/extra.log", b"conflict")
             .unwrap();
 
-        assert_stale_directory(cmd_helper.jit_cmd(&["checkout", "@^"]), "outer/2.txt");
+        assert_stale_directory(cmd_helper.run_cmd(&["checkout", "@^"]), "outer/2.txt");
     }
 
     #[test]
@@ -901,11 +883,10 @@ D  outer/inner/3.txt\n",
         cmd_helper
             .write_file("outer/2.txt/extra.log", b"conflict")
             .unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -918,7 +899,6 @@ D  outer/inner/3.txt\n",
         commit_and_checkout(&mut cmd_helper, "@^");
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -933,7 +913,6 @@ D  outer/inner/3.txt\n",
         commit_and_checkout(&mut cmd_helper, "@^");
 
         cmd_helper.assert_workspace(BASE_FILES.clone());
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -946,7 +925,7 @@ D  outer/inner/3.txt\n",
 
         cmd_helper.write_file("outer/2.txt", b"hello").unwrap();
         cmd_helper.delete("outer/inner").unwrap();
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         let mut expected_workspace = HashMap::new();
         expected_workspace.insert("1.txt", "1");
@@ -954,7 +933,6 @@ D  outer/inner/3.txt\n",
 
         cmd_helper.assert_workspace(expected_workspace);
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(
             " M outer/2.txt
  D outer/inner/3.txt\n",
@@ -972,9 +950,9 @@ D  outer/inner/3.txt\n",
         cmd_helper
             .write_file("outer/inner/4.txt", b"world")
             .unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
 
         let mut expected_workspace = BASE_FILES.clone();
         expected_workspace.insert("outer/2.txt", "hello");
@@ -982,10 +960,111 @@ D  outer/inner/3.txt\n",
 
         cmd_helper.assert_workspace(expected_workspace);
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(
             "M  outer/2.txt
 A  outer/inner/4.txt\n",
         );
     }
+
+    #[test]
+    fn stores_and_restores_a_symlink() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.symlink("outer/2.txt", "link").unwrap();
+        cmd_helper.run_cmd(&["add", "link"]).unwrap();
+        cmd_helper.commit("link");
+
+        let tree = cmd_helper.git_cmd(&["ls-tree", "HEAD", "link"]);
+        assert!(tree.starts_with("120000 blob "), "{}", tree);
+        assert_eq!(
+            "outer/2.txt",
+            cmd_helper.git_cmd(&["cat-file", "-p", "HEAD:link"])
+        );
+        cmd_helper.assert_status("");
+
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.assert_workspace(BASE_FILES.clone());
+
+        cmd_helper.run_cmd(&["checkout", "master"]).unwrap();
+        let link = cmd_helper.repo().workspace.abs_path("link");
+        assert_eq!(
+            "outer/2.txt",
+            std::fs::read_link(link).unwrap().to_str().unwrap()
+        );
+        cmd_helper.assert_status("");
+    }
+
+    #[test]
+    fn keeps_gitlinks_without_traversing_them() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let head = cmd_helper.git_cmd(&["rev-parse", "HEAD"]);
+        let cacheinfo = format!("160000,{},sub", head.trim());
+        cmd_helper.git_cmd(&["update-index", "--add", "--cacheinfo", &cacheinfo]);
+        cmd_helper.commit("submodule");
+
+        assert_eq!(
+            format!("160000 commit {}\tsub\n", head.trim()),
+            cmd_helper.git_cmd(&["ls-tree", "HEAD", "sub"])
+        );
+
+        cmd_helper.run_cmd(&["checkout", "@^"]).unwrap();
+        cmd_helper.assert_noent("sub");
+
+        cmd_helper.run_cmd(&["checkout", "master"]).unwrap();
+        assert!(cmd_helper.repo().workspace.is_dir("sub"));
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, "1.txt".to_string()),
+                (0o100644, "outer/2.txt".to_string()),
+                (0o100644, "outer/inner/3.txt".to_string()),
+                (0o160000, "sub".to_string()),
+            ])
+            .unwrap();
+        cmd_helper.assert_status("");
+    }
+
+    #[test]
+    fn report_revisions_that_name_no_commit() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let tree = cmd_helper.git_cmd(&["rev-parse", "HEAD^{tree}"]);
+        let tree = tree.trim();
+
+        assert_eq!(
+            (
+                1,
+                String::new(),
+                "error: Not a valid object name: 'nope^^'.\n".to_string()
+            ),
+            cmd_helper.jit_status(&["checkout", "nope^^"])
+        );
+        assert_eq!(
+            (
+                1,
+                String::new(),
+                format!(
+                    "error: object {} is a tree, not a commit\nerror: Not a valid object name: '{}'.\n",
+                    tree, tree
+                )
+            ),
+            cmd_helper.jit_status(&["checkout", tree])
+        );
+        assert!(!cmd_helper.repo().git_path.join("index.lock").exists());
+    }
+
+    #[test]
+    fn require_a_target() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        assert_eq!(
+            (
+                129,
+                String::new(),
+                "usage: rug checkout <branch>\n   or: rug checkout <commit>\n".to_string()
+            ),
+            cmd_helper.jit_status(&["checkout"])
+        );
+    }
 }
@@ -1,72 +1,1100 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
 use std::io::{Read, Write};
+use std::path::Path;
 
-use chrono::prelude::*;
+use clap::ArgMatches;
 
+use crate::commands::add::add_to_index;
+use crate::commands::status::{LABEL_WIDTH, LONG_STATUS};
 use crate::commands::CommandContext;
 use crate::database::commit::{Author, Commit};
-use crate::database::object::Object;
+use crate::database::object::Storable;
 use crate::database::tree::Tree;
-use crate::database::Entry;
-use crate::repository::Repository;
+use crate::database::{Entry, ParsedObject};
+use crate::date::{self, parse_date};
+use crate::editor::Editor;
+use crate::errors::{self, Error};
+use crate::gpg::Gpg;
+use crate::repository::{ChangeType, Repository, Status};
+use crate::util::normalize_path;
 
-pub fn commit_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), String>
+pub const CONFLICT_MESSAGE: &str =
+    "hint: Fix them up in the work tree, and then use 'rug add <file>'
+hint: as appropriate to mark resolution and make a commit.
+fatal: Exiting because of an unresolved conflict.\n";
+
+const STRIP_NOTE: &str = "Please enter the commit message for your changes. Lines starting
+with '#' will be ignored, and an empty message aborts the commit.
+";
+
+const KEEP_NOTE: &str = "Please enter the commit message for your changes. Lines starting
+with '#' will be kept; you may remove them yourself if you want to.
+An empty message aborts the commit.
+";
+
+/// How a commit message is tidied before it is stored
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cleanup {
+    // Drop comment lines as well as surplus whitespace
+    Strip,
+    Whitespace,
+    Verbatim,
+}
+
+impl Cleanup {
+    /// `default` strips comments only when the message was edited
+    fn parse(mode: &str, edit: bool) -> Result<Cleanup, Error> {
+        match mode {
+            "strip" => Ok(Cleanup::Strip),
+            "whitespace" => Ok(Cleanup::Whitespace),
+            "verbatim" => Ok(Cleanup::Verbatim),
+            "default" if edit => Ok(Cleanup::Strip),
+            "default" => Ok(Cleanup::Whitespace),
+            _ => Err(Error::fatal(format!(
+                "fatal: Invalid cleanup mode {}\n",
+                mode
+            ))),
+        }
+    }
+}
+
+/// Remove trailing whitespace from lines, runs of blank lines and
+/// blank lines at either end, and with `Strip`, comment lines too
+pub fn cleanup_message(message: &str, mode: Cleanup) -> String {
+    if mode == Cleanup::Verbatim {
+        return message.to_string();
+    }
+
+    let mut cleaned = String::new();
+    let mut blank_lines = 0;
+    for line in message.lines() {
+        if mode == Cleanup::Strip && line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if blank_lines > 0 && !cleaned.is_empty() {
+            cleaned.push('\n');
+        }
+        blank_lines = 0;
+        cleaned.push_str(line);
+        cleaned.push('\n');
+    }
+
+    cleaned
+}
+
+pub fn commit_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let mut repo = Repository::discover(&ctx.dir, ctx.env)?;
+    repo.require_work_tree()?;
+
+    repo.index.load_for_update()?;
+    let result = make_commit(&mut repo, &mut ctx);
+    if result.is_err() {
+        // The lock may already be gone if the index was written
+        let _ = repo.index.release_lock();
+    }
+
+    result
+}
+
+/// Stage whatever `-a` or the pathspecs ask for, build the tree and
+/// commit it with a message from the user
+fn make_commit<I, O, E>(
+    repo: &mut Repository,
+    ctx: &mut CommandContext<I, O, E>,
+) -> Result<(), Error>
 where
     I: Read,
     O: Write,
     E: Write,
 {
-    let working_dir = ctx.dir;
-    let root_path = working_dir.as_path();
-    let mut repo = Repository::new(&root_path);
+    let options = ctx.options.as_ref().unwrap().clone();
+    let paths: Vec<&str> = match options.values_of("args") {
+        Some(args) => args.collect(),
+        None => vec![],
+    };
+    let amend = options.is_present("amend");
+    let pending_commit = repo.pending_commit();
+
+    if options.is_present("all") && !paths.is_empty() {
+        return Err(Error::fatal(format!(
+            "fatal: paths '{} ...' with -a does not make sense\n",
+            paths[0]
+        )));
+    }
+    if pending_commit.in_progress() {
+        if amend {
+            return Err(Error::fatal(
+                "fatal: You are in the middle of a merge -- cannot amend.\n",
+            ));
+        }
+        if !paths.is_empty() {
+            return Err(Error::fatal(
+                "fatal: cannot do a partial commit during a merge.\n",
+            ));
+        }
+    }
+
+    check_conflicts(repo)?;
+    // Complain about a missing identity before asking for a message.
+    // An amended commit keeps its author, so only needs a committer.
+    if !amend {
+        Repository::author_identity(&repo.config, ctx.env)?;
+    }
+    Repository::committer_identity(&repo.config, ctx.env)?;
+
+    let head = repo.refs.read_head();
+    let amended = match (amend, &head) {
+        (false, _) => None,
+        (true, Some(head)) => Some(load_commit(repo, head)?),
+        (true, None) => return Err(Error::fatal("fatal: You have nothing to amend.\n")),
+    };
+    let parents = match &amended {
+        Some(commit) => commit.parents.clone(),
+        None => head.into_iter().collect(),
+    };
+
+    let mut message = read_message(&options, &mut ctx.stdin, &ctx.dir)?;
+    let edit = options.is_present("edit") || (message.is_none() && !options.is_present("no-edit"));
+    let cleanup = match options.value_of("cleanup") {
+        Some(mode) => mode.to_string(),
+        None => repo
+            .config
+            .get("commit.cleanup")
+            .unwrap_or_else(|| "default".to_string()),
+    };
+    let cleanup = Cleanup::parse(&cleanup, edit)?;
+    let sign_key = signing_key(repo, ctx.env, &options)?;
+
+    let mut status = repo.status()?;
+    let entries = if options.is_present("all") {
+        let changed: Vec<String> = status.workspace_changes.keys().cloned().collect();
+        stage_paths(repo, &changed)?;
+        status.workspace_changes.clear();
+        index_entries(repo)
+    } else if !paths.is_empty() {
+        let prefix = repo.prefix(&ctx.dir);
+        pathspec_entries(repo, &status, &prefix, &paths)?
+    } else {
+        index_entries(repo)
+    };
+    let tree_oid = write_tree(repo, &entries)?;
+
+    // What is being committed, compared against the first parent
+    let parent_tree = parents
+        .first()
+        .map(|parent| load_commit(repo, parent).map(|commit| commit.tree_oid))
+        .transpose()?;
+    status.index_changes = repo
+        .database
+        .tree_diff(parent_tree.as_deref(), Some(&tree_oid))?
+        .into_iter()
+        .map(|(path, (old, new))| {
+            let change_type = match (old, new) {
+                (None, _) => ChangeType::Added,
+                (_, None) => ChangeType::Deleted,
+                _ => ChangeType::Modified,
+            };
+            (path.to_string_lossy().to_string(), change_type)
+        })
+        .collect();
+
+    if status.index_changes.is_empty()
+        && !options.is_present("allow-empty")
+        && !pending_commit.in_progress()
+    {
+        return Err(Error::failure(nothing_to_commit(repo, &status)));
+    }
+
+    // An amended commit's message is reused byte for byte unless it
+    // is edited, since it need not be UTF-8
+    let message = match &amended {
+        Some(commit) if message.is_none() && !edit => commit.message.clone(),
+        _ => {
+            if message.is_none() {
+                if let Some(commit) = &amended {
+                    message = Some(commit.message().into_owned());
+                } else if pending_commit.in_progress() {
+                    message = Some(pending_commit.merge_message()?);
+                }
+            }
+            compose_message(
+                repo,
+                &status,
+                ctx.env,
+                &message.unwrap_or_default(),
+                edit,
+                cleanup,
+            )?
+            .into_bytes()
+        }
+    };
+
+    repo.index.write_updates()?;
+    if pending_commit.in_progress() {
+        return resume_merge(repo, ctx.env, &mut ctx.stdout, message, sign_key.as_deref());
+    }
+
+    // An amended commit keeps its original author and extra headers,
+    // but not its signature
+    let (author, headers) = match amended {
+        Some(commit) => (commit.author, commit.headers),
+        None => {
+            let (name, email) = Repository::author_identity(&repo.config, ctx.env)?;
+            (signature(ctx.env, "GIT_AUTHOR_DATE", name, email)?, vec![])
+        }
+    };
+    let committer = committer(repo, ctx.env)?;
+    let mut commit = Commit::new(parents, tree_oid, author, committer, message);
+    commit.headers = headers;
+    commit.remove_signature();
+    store_commit(repo, ctx.env, &mut commit, amend, sign_key.as_deref())?;
+    print_commit(&mut ctx.stdout, &commit)
+}
 
-    repo.index.load().expect("loading .git/index failed");
-    let entries: Vec<Entry> = repo
+fn load_commit(repo: &mut Repository, oid: &str) -> Result<Commit, Error> {
+    match repo.database.load(oid)? {
+        ParsedObject::Commit(commit) => Ok(commit.clone()),
+        _ => Err(Error::fatal(format!("fatal: {} is not a commit\n", oid))),
+    }
+}
+
+fn index_entries(repo: &Repository) -> Vec<Entry> {
+    repo.index.entries.values().map(Entry::from).collect()
+}
+
+/// Bring the index up to date with the workspace for each path,
+/// dropping paths whose files are gone
+fn stage_paths(repo: &mut Repository, paths: &[String]) -> Result<(), Error> {
+    for path in paths {
+        let exists = repo
+            .workspace
+            .stat_file(path)
+            .is_ok_and(|stat| !stat.is_dir());
+        if exists {
+            add_to_index(repo, path)?;
+        } else {
+            repo.index.remove(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Stage the tracked files matching `pathspecs` and return HEAD's
+/// tree with just those files replaced, leaving other staged changes
+/// out of the commit
+fn pathspec_entries(
+    repo: &mut Repository,
+    status: &Status,
+    prefix: &Path,
+    pathspecs: &[&str],
+) -> Result<Vec<Entry>, Error> {
+    let mut known: BTreeSet<String> = repo
         .index
         .entries
+        .values()
+        .map(|entry| entry.path.clone())
+        .collect();
+    known.extend(status.head_tree.keys().cloned());
+
+    let mut matched = vec![];
+    for pathspec in pathspecs {
+        // Pathspecs are relative to the current directory
+        let target = normalize_path(prefix, pathspec).ok_or_else(|| {
+            Error::fatal(format!("fatal: '{}' is outside repository\n", pathspec))
+        })?;
+        let matches: Vec<String> = known
+            .iter()
+            .filter(|path| {
+                target.is_empty() || **path == target || path.starts_with(&format!("{}/", target))
+            })
+            .cloned()
+            .collect();
+        if matches.is_empty() {
+            return Err(Error::failure(format!(
+                "error: pathspec '{}' did not match any file(s) known to git\n",
+                pathspec
+            )));
+        }
+        matched.extend(matches);
+    }
+    stage_paths(repo, &matched)?;
+
+    let mut entries: Vec<Entry> = status
+        .head_tree
         .iter()
-        .map(|(_path, idx_entry)| Entry::from(idx_entry))
+        .filter(|(path, _)| !matched.contains(path))
+        .map(|(path, item)| Entry::new(path, &item.get_oid(), item.mode()))
         .collect();
-    let root = Tree::build(&entries);
+    for path in &matched {
+        if let Some(entry) = repo.index.entry_for_path(path) {
+            entries.push(Entry::from(entry));
+        }
+    }
+
+    Ok(entries)
+}
+
+fn branch_line(repo: &Repository) -> String {
+    let current = repo.refs.current_ref("HEAD");
+    if current.is_head() {
+        "Not currently on any branch.\n".to_string()
+    } else {
+        format!("On branch {}\n", repo.refs.ref_short_name(&current))
+    }
+}
+
+fn nothing_to_commit(repo: &Repository, status: &Status) -> String {
+    let reason = if !status.workspace_changes.is_empty() {
+        "no changes added to commit (use \"git add\" and/or \"git commit -a\")"
+    } else if !status.untracked.is_empty() {
+        "nothing added to commit but untracked files present (use \"git add\" to track)"
+    } else if repo.refs.read_head().is_none() {
+        "nothing to commit (create/copy files and use \"git add\" to track)"
+    } else {
+        "nothing to commit, working tree clean"
+    };
+
+    format!("{}{}\n", branch_line(repo), reason)
+}
+
+/// The message given with `-m` or `-F`, if any. Each `-m` is a
+/// paragraph of its own, and `-F -` reads standard input.
+fn read_message<I: Read>(
+    options: &ArgMatches,
+    stdin: &mut I,
+    dir: &Path,
+) -> Result<Option<String>, Error> {
+    match (options.values_of("message"), options.value_of("file")) {
+        (Some(_), Some(_)) => Err(Error::fatal(
+            "fatal: Option -m cannot be combined with -F\n",
+        )),
+        (Some(messages), None) => {
+            let messages: Vec<&str> = messages.collect();
+            Ok(Some(format!("{}\n", messages.join("\n\n"))))
+        }
+        (None, Some("-")) => {
+            let mut message = String::new();
+            stdin.read_to_string(&mut message).map_err(|e| {
+                Error::fatal(format!(
+                    "fatal: could not read log from standard input: {}\n",
+                    errors::describe(&e)
+                ))
+            })?;
+            Ok(Some(message))
+        }
+        (None, Some(file)) => fs::read_to_string(dir.join(file))
+            .map(Some)
+            .map_err(|e| Error::io("read log file", file, e)),
+        (None, None) => Ok(None),
+    }
+}
+
+/// GIT_EDITOR, core.editor, VISUAL or EDITOR, whichever is set first
+fn editor_command(repo: &Repository, env: &HashMap<String, String>) -> Option<String> {
+    env.get("GIT_EDITOR")
+        .cloned()
+        .or_else(|| repo.config.get("core.editor"))
+        .or_else(|| env.get("VISUAL").cloned())
+        .or_else(|| env.get("EDITOR").cloned())
+        .filter(|editor| !editor.is_empty())
+}
+
+/// Write the message to COMMIT_EDITMSG, let the user edit it under a
+/// commented summary of what is being committed, and clean it up
+fn compose_message(
+    repo: &mut Repository,
+    status: &Status,
+    env: &HashMap<String, String>,
+    message: &str,
+    edit: bool,
+    cleanup: Cleanup,
+) -> Result<String, Error> {
+    let path = repo.git_path.join("COMMIT_EDITMSG");
+    let mut editor = Editor::new(&path, editor_command(repo, env));
+
+    editor.write(message);
+    if edit {
+        if !message.is_empty() {
+            editor.write("");
+        }
+        editor.note(match cleanup {
+            Cleanup::Strip => STRIP_NOTE,
+            _ => KEEP_NOTE,
+        });
+        editor.note(&status_template(repo, status));
+    } else {
+        editor.close();
+    }
+
+    let edited = editor.edit_file(env).map_err(|e| {
+        Error::failure(format!(
+            "{}Please supply the message using either -m or -F option.\n",
+            e
+        ))
+    })?;
+    let message = cleanup_message(&edited, cleanup);
+    if message.trim().is_empty() {
+        return Err(Error::failure(
+            "Aborting commit due to empty commit message.\n",
+        ));
+    }
+
+    Ok(message)
+}
+
+/// The branch and changes, as `rug status` would show them, for the
+/// user to review while writing the message
+fn status_template(repo: &Repository, status: &Status) -> String {
+    let mut template = format!("\n{}", branch_line(repo));
+    if repo.refs.read_head().is_none() {
+        template.push_str("\nInitial commit\n");
+    }
+
+    let sections = [
+        ("Changes to be committed:", &status.index_changes),
+        ("Changes not staged for commit:", &status.workspace_changes),
+    ];
+    for (title, changes) in sections.iter() {
+        if changes.is_empty() {
+            continue;
+        }
+        template.push_str(&format!("\n{}\n", title));
+        for (path, change_type) in changes.iter() {
+            if let Some(status) = LONG_STATUS.get(change_type) {
+                template.push_str(&format!(
+                    "\t{:width$}{}\n",
+                    status,
+                    path,
+                    width = LABEL_WIDTH
+                ));
+            }
+        }
+    }
+    if !status.untracked.is_empty() {
+        template.push_str("\nUntracked files:\n");
+        for path in &status.untracked {
+            template.push_str(&format!("\t{}\n", path));
+        }
+    }
+    template.push('\n');
+
+    template
+}
+
+fn check_conflicts(repo: &Repository) -> Result<(), Error> {
+    if repo.index.has_conflict() {
+        return Err(Error::fatal(format!(
+            "error: Committing is not possible because you have unmerged files.\n{}",
+            CONFLICT_MESSAGE
+        )));
+    }
+
+    Ok(())
+}
+
+/// Conclude a merge that stopped for conflicts, once they have been
+/// resolved, by committing with both heads as parents
+pub fn resume_merge<O: Write>(
+    repo: &mut Repository,
+    env: &HashMap<String, String>,
+    mut stdout: O,
+    message: Vec<u8>,
+    sign_key: Option<&str>,
+) -> Result<(), Error> {
+    check_conflicts(repo)?;
+
+    let pending_commit = repo.pending_commit();
+    let parents = vec![
+        repo.refs
+            .read_head()
+            .ok_or_else(|| Error::fatal("fatal: HEAD does not point to a commit\n"))?,
+        pending_commit.merge_oid()?,
+    ];
+    let commit = write_commit(repo, env, parents, message, sign_key)?;
+    pending_commit.clear()?;
+
+    print_commit(&mut stdout, &commit)
+}
+
+/// Store the tree for `entries` and each of its subtrees, returning
+/// the root tree's oid
+pub fn write_tree(repo: &mut Repository, entries: &[Entry]) -> Result<String, Error> {
+    let root = Tree::build(entries);
     root.traverse(&|tree| {
-        repo.database
-            .store(tree)
-            .expect("Traversing tree to write to database failed")
-    });
-
-    let parent = repo.refs.read_head();
-    let author_name = ctx
-        .env
-        .get("GIT_AUTHOR_NAME")
-        .expect("GIT_AUTHOR_NAME not set");
-    let author_email = ctx
-        .env
-        .get("GIT_AUTHOR_EMAIL")
-        .expect("GIT_AUTHOR_EMAIL not set");
-
-    let author = Author {
-        name: author_name.to_string(),
-        email: author_email.to_string(),
-        time: Utc::now().with_timezone(&FixedOffset::east(0)),
+        repo.database.store(tree).map_err(|e| {
+            Error::io(
+                "write object",
+                repo.database.object_path(&tree.get_oid()),
+                e,
+            )
+        })
+    })?;
+
+    Ok(root.get_oid())
+}
+
+/// Store the index as a tree and commit it with the given parents,
+/// moving HEAD to the new commit
+pub fn write_commit(
+    repo: &mut Repository,
+    env: &HashMap<String, String>,
+    parents: Vec<String>,
+    message: Vec<u8>,
+    sign_key: Option<&str>,
+) -> Result<Commit, Error> {
+    let tree_oid = write_tree(repo, &index_entries(repo))?;
+    let (name, email) = Repository::author_identity(&repo.config, env)?;
+    let author = signature(env, "GIT_AUTHOR_DATE", name, email)?;
+    let committer = committer(repo, env)?;
+
+    let mut commit = Commit::new(parents, tree_oid, author, committer, message);
+    store_commit(repo, env, &mut commit, false, sign_key)?;
+
+    Ok(commit)
+}
+
+/// Stamp an identity with the date in `date_var`, or else the
+/// current local time
+fn signature(
+    env: &HashMap<String, String>,
+    date_var: &str,
+    name: String,
+    email: String,
+) -> Result<Author, Error> {
+    let time = match env.get(date_var) {
+        Some(date) => parse_date(date)
+            .ok_or_else(|| Error::fatal(format!("fatal: invalid date format: {}\n", date)))?,
+        None => date::now(),
     };
 
-    let mut commit_message = String::new();
-    ctx.stdin
-        .read_to_string(&mut commit_message)
-        .expect("reading commit from STDIN failed");
+    Ok(Author::new(name, email, time))
+}
 
-    let commit = Commit::new(&parent, root.get_oid(), author, commit_message);
-    repo.database.store(&commit).expect("writing commit failed");
-    repo.refs
-        .update_head(&commit.get_oid())
-        .expect("updating HEAD failed");
+/// The committer's signature, from GIT_COMMITTER_NAME, _EMAIL and
+/// _DATE where they are set
+pub fn committer(repo: &Repository, env: &HashMap<String, String>) -> Result<Author, Error> {
+    let (name, email) = Repository::committer_identity(&repo.config, env)?;
+    signature(env, "GIT_COMMITTER_DATE", name, email)
+}
 
-    let commit_prefix = if parent.is_some() {
-        ""
+/// Whether `-S`, `--no-gpg-sign` or commit.gpgSign ask for a
+/// signature, and if so with which key
+pub fn signing_key(
+    repo: &Repository,
+    env: &HashMap<String, String>,
+    options: &ArgMatches,
+) -> Result<Option<String>, Error> {
+    let sign = if options.is_present("no-gpg-sign") {
+        false
     } else {
+        options.is_present("gpg-sign") || repo.config.get_bool("commit.gpgSign") == Some(true)
+    };
+    if !sign {
+        return Ok(None);
+    }
+
+    let committer = committer(repo, env)?;
+    let key = options.value_of("gpg-sign").filter(|key| !key.is_empty());
+    Gpg::new(&repo.config, env)
+        .signing_key(key, &format!("{} <{}>", committer.name, committer.email))
+        .map(Some)
+}
+
+fn store_commit(
+    repo: &mut Repository,
+    env: &HashMap<String, String>,
+    commit: &mut Commit,
+    amend: bool,
+    sign_key: Option<&str>,
+) -> Result<(), Error> {
+    if let Some(key) = sign_key {
+        let signature = Gpg::new(&repo.config, env)
+            .sign(&commit.signature_payload(), key)
+            .map_err(|e| Error::fatal(format!("{}fatal: failed to write commit object\n", e)))?;
+        commit.add_header("gpgsig", signature.trim_end());
+    }
+    repo.database.store(commit).map_err(|e| {
+        Error::io(
+            "write object",
+            repo.database.object_path(&commit.get_oid()),
+            e,
+        )
+    })?;
+
+    let action = match commit.parents.len() {
+        _ if amend => "commit (amend)",
+        0 => "commit (initial)",
+        1 => "commit",
+        _ => "commit (merge)",
+    };
+    repo.refs.update_head(
+        &commit.get_oid(),
+        &format!("{}: {}", action, commit.title_line()),
+    )?;
+
+    Ok(())
+}
+
+fn print_commit<O: Write>(stdout: &mut O, commit: &Commit) -> Result<(), Error> {
+    let commit_prefix = if commit.parents.is_empty() {
         "(root-commit) "
+    } else {
+        ""
     };
 
-    println!("[{}{}] {}", commit_prefix, commit.get_oid(), commit.message);
+    writeln!(
+        stdout,
+        "[{}{}] {}",
+        commit_prefix,
+        commit.get_oid(),
+        commit.title_line()
+    )
+    .map_err(Error::Output)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tests::*;
+    use std::fs;
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.set_env("GIT_AUTHOR_NAME", "A. U. Thor");
+        cmd_helper.set_env("GIT_AUTHOR_EMAIL", "author@example.com");
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+
+        cmd_helper
+    }
+
+    fn head_message(cmd_helper: &CommandHelper) -> String {
+        cmd_helper.git_cmd(&["log", "-1", "--format=%B"])
+    }
+
+    #[test]
+    fn join_repeated_messages_into_paragraphs() {
+        let mut cmd_helper = setup();
+
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["commit", "-m", "Subject  ", "-m", "Body"])
+            .unwrap();
+        assert!(stdout.ends_with("] Subject\n"), "{}", stdout);
+        assert_eq!("Subject\n\nBody\n\n", head_message(&cmd_helper));
+    }
+
+    #[test]
+    fn refuse_to_make_up_an_identity() {
+        let mut cmd_helper = setup();
+        cmd_helper.run_cmd(&["commit", "-m", "first"]).unwrap();
+        for var in &["GIT_AUTHOR_NAME", "GIT_COMMITTER_NAME", "EMAIL"] {
+            cmd_helper.set_env(var, "");
+        }
+
+        let stderr = cmd_helper
+            .run_cmd(&["commit", "--allow-empty", "-m", "second"])
+            .unwrap_err();
+        assert!(stderr.starts_with("Author identity unknown\n\n*** Please tell me who you are."));
+        assert!(
+            stderr.ends_with("fatal: empty ident name (for <author@example.com>) not allowed\n")
+        );
+
+        // Ref updates are logged, so they need a committer too
+        cmd_helper.set_env("GIT_AUTHOR_NAME", "A. U. Thor");
+        for args in &[
+            vec!["commit", "--allow-empty", "-m", "second"],
+            vec!["branch", "topic"],
+        ] {
+            let stderr = cmd_helper.run_cmd(args).unwrap_err();
+            assert!(
+                stderr.starts_with("Committer identity unknown\n"),
+                "{}",
+                stderr
+            );
+        }
+        assert_eq!("", cmd_helper.git_cmd(&["branch", "--list", "topic"]));
+
+        cmd_helper
+            .jit_cmd(&["-c", "user.name=C. O. Mitter", "branch", "topic"])
+            .unwrap();
+    }
+
+    #[test]
+    fn amend_with_only_a_committer_identity() {
+        let mut cmd_helper = setup();
+        cmd_helper.run_cmd(&["commit", "-m", "first"]).unwrap();
+        cmd_helper.set_env("GIT_AUTHOR_NAME", "");
+
+        cmd_helper
+            .run_cmd(&["commit", "--amend", "--no-edit"])
+            .unwrap();
+        assert_eq!(
+            "A. U. Thor <author@example.com>\n",
+            cmd_helper.git_cmd(&["log", "-1", "--format=%an <%ae>"])
+        );
+    }
+
+    #[test]
+    fn read_the_message_from_a_file() {
+        let mut cmd_helper = setup();
+        cmd_helper
+            .write_file("msg.txt", b"\n\nFrom a file\n# not a comment\n\n\n")
+            .unwrap();
+
+        cmd_helper.run_cmd(&["commit", "-F", "msg.txt"]).unwrap();
+        assert_eq!(
+            "From a file\n# not a comment\n\n",
+            head_message(&cmd_helper)
+        );
+
+        cmd_helper.set_stdin("From stdin\n");
+        cmd_helper
+            .run_cmd(&["commit", "--allow-empty", "-F", "-"])
+            .unwrap();
+        assert_eq!("From stdin\n\n", head_message(&cmd_helper));
+
+        let stderr = cmd_helper
+            .run_cmd(&["commit", "-F", "msg.txt", "-m", "both"])
+            .unwrap_err();
+        assert_output(&stderr, "fatal: Option -m cannot be combined with -F\n");
+    }
+
+    #[test]
+    fn edit_the_message_with_a_commented_template() {
+        let mut cmd_helper = setup();
+        cmd_helper.set_env(
+            "GIT_EDITOR",
+            "printf 'Edited\\n\\n# dropped\\n' >> \"$1\"; true",
+        );
+
+        cmd_helper.run_cmd(&["commit"]).unwrap();
+        assert_eq!("Edited\n\n", head_message(&cmd_helper));
+
+        let template =
+            fs::read_to_string(cmd_helper.repo().git_path.join("COMMIT_EDITMSG")).unwrap();
+        assert!(template
+            .starts_with("\n# Please enter the commit message for your changes. Lines starting\n"));
+        assert!(template.contains("# On branch master\n#\n# Initial commit\n"));
+        assert!(template.contains("# Changes to be committed:\n#\tnew file:   file.txt\n"));
+    }
+
+    #[test]
+    fn abort_on_an_empty_message() {
+        let mut cmd_helper = setup();
+
+        let stderr = cmd_helper.run_cmd(&["commit"]).unwrap_err();
+        assert_output(&stderr, "Aborting commit due to empty commit message.\n");
+        let stderr = cmd_helper.run_cmd(&["commit", "-m", "  \n"]).unwrap_err();
+        assert_output(&stderr, "Aborting commit due to empty commit message.\n");
+        assert!(cmd_helper.repo().refs.read_head().is_none());
+    }
+
+    #[test]
+    fn honour_the_cleanup_mode() {
+        let mut cmd_helper = setup();
+
+        cmd_helper
+            .run_cmd(&["commit", "--cleanup=verbatim", "-m", "# kept  "])
+            .unwrap();
+        assert_eq!("# kept  \n\n", head_message(&cmd_helper));
+
+        let stderr = cmd_helper
+            .run_cmd(&["commit", "--cleanup=bogus", "-m", "x"])
+            .unwrap_err();
+        assert_output(&stderr, "fatal: Invalid cleanup mode bogus\n");
+    }
+
+    #[test]
+    fn refuse_to_commit_nothing() {
+        let mut cmd_helper = setup();
+        cmd_helper.commit("first");
+
+        let stderr = cmd_helper.run_cmd(&["commit", "-m", "again"]).unwrap_err();
+        assert_output(
+            &stderr,
+            "On branch master\nnothing to commit, working tree clean\n",
+        );
+
+        cmd_helper.write_file("file.txt", b"changed").unwrap();
+        let stderr = cmd_helper.run_cmd(&["commit", "-m", "again"]).unwrap_err();
+        assert!(stderr
+            .ends_with("no changes added to commit (use \"git add\" and/or \"git commit -a\")\n"));
+
+        cmd_helper
+            .run_cmd(&["commit", "--allow-empty", "-m", "empty"])
+            .unwrap();
+        assert_eq!(
+            cmd_helper.git_cmd(&["rev-parse", "HEAD^{tree}"]),
+            cmd_helper.git_cmd(&["rev-parse", "HEAD^^{tree}"])
+        );
+    }
+
+    #[test]
+    fn report_a_locked_index_as_add_does() {
+        let mut cmd_helper = setup();
+        cmd_helper.write_file(".git/index.lock", b"").unwrap();
+
+        let stderr = cmd_helper.run_cmd(&["commit", "-m", "first"]).unwrap_err();
+        assert!(
+            stderr.starts_with("fatal: Unable to create '"),
+            "{}",
+            stderr
+        );
+        assert_eq!(
+            cmd_helper.run_cmd(&["add", "file.txt"]).unwrap_err(),
+            stderr
+        );
+    }
+
+    #[test]
+    fn amend_the_last_commit() {
+        let mut cmd_helper = setup();
+        cmd_helper.commit("first");
+        cmd_helper.write_file("file.txt", b"two").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("second");
+
+        cmd_helper.write_file("other.txt", b"other").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.set_env("GIT_AUTHOR_NAME", "Someone Else");
+        cmd_helper
+            .run_cmd(&["commit", "--amend", "--no-edit"])
+            .unwrap();
+
+        assert_eq!(
+            "second\nfirst\n",
+            cmd_helper.git_cmd(&["log", "--format=%s"])
+        );
+        assert_eq!(
+            "A. U. Thor\n",
+            cmd_helper.git_cmd(&["log", "-1", "--format=%an"])
+        );
+        assert_eq!(
+            "file.txt\nother.txt\n",
+            cmd_helper.git_cmd(&["ls-tree", "--name-only", "HEAD"])
+        );
+        assert_eq!(
+            "commit (amend): second\n",
+            cmd_helper.git_cmd(&["log", "-g", "-1", "--format=%gs"])
+        );
+
+        cmd_helper
+            .run_cmd(&["commit", "--amend", "-m", "reworded"])
+            .unwrap();
+        assert_eq!(
+            "reworded\nfirst\n",
+            cmd_helper.git_cmd(&["log", "--format=%s"])
+        );
+    }
+
+    #[test]
+    fn stage_tracked_changes_with_all() {
+        let mut cmd_helper = setup();
+        cmd_helper.write_file("gone.txt", b"gone").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        cmd_helper.write_file("file.txt", b"changed").unwrap();
+        cmd_helper.delete("gone.txt").unwrap();
+        cmd_helper.write_file("new.txt", b"untracked").unwrap();
+        cmd_helper
+            .run_cmd(&["commit", "-a", "-m", "second"])
+            .unwrap();
+
+        assert_eq!(
+            "file.txt\n",
+            cmd_helper.git_cmd(&["ls-tree", "--name-only", "HEAD"])
+        );
+        assert_eq!("changed", cmd_helper.git_cmd(&["show", "HEAD:file.txt"]));
+        assert_eq!(
+            "?? new.txt\n",
+            cmd_helper.git_cmd(&["status", "--porcelain"])
+        );
+    }
+
+    #[test]
+    fn commit_only_the_named_paths() {
+        let mut cmd_helper = setup();
+        cmd_helper.write_file("dir/a.txt", b"a").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        cmd_helper.write_file("file.txt", b"staged").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.write_file("dir/a.txt", b"changed").unwrap();
+        cmd_helper
+            .run_cmd(&["commit", "-m", "second", "dir"])
+            .unwrap();
+
+        assert_eq!(
+            "dir/a.txt\n",
+            cmd_helper.git_cmd(&["diff", "--name-only", "HEAD^", "HEAD"])
+        );
+        assert_eq!(
+            "M  file.txt\n",
+            cmd_helper.git_cmd(&["status", "--porcelain"])
+        );
+
+        let stderr = cmd_helper
+            .run_cmd(&["commit", "-m", "third", "nope.txt"])
+            .unwrap_err();
+        assert_output(
+            &stderr,
+            "error: pathspec 'nope.txt' did not match any file(s) known to git\n",
+        );
+        let stderr = cmd_helper
+            .run_cmd(&["commit", "-a", "-m", "third", "file.txt"])
+            .unwrap_err();
+        assert_output(
+            &stderr,
+            "fatal: paths 'file.txt ...' with -a does not make sense\n",
+        );
+    }
+
+    #[test]
+    fn record_author_and_committer_separately() {
+        let mut cmd_helper = setup();
+        cmd_helper.set_env("GIT_AUTHOR_DATE", "2005-04-07T22:13:13+02:00");
+        cmd_helper.set_env("GIT_COMMITTER_NAME", "C. O. Mitter");
+        cmd_helper.set_env("GIT_COMMITTER_EMAIL", "committer@example.com");
+        cmd_helper.set_env("GIT_COMMITTER_DATE", "@1112911993 -0730");
+        cmd_helper.commit("first");
+
+        let format = "--format=%an <%ae> %ad%n%cn <%ce> %cd";
+        assert_eq!(
+            "A. U. Thor <author@example.com> 1112904793 +0200
+C. O. Mitter <committer@example.com> 1112911993 -0730\n",
+            cmd_helper.git_cmd(&["log", "-1", "--date=raw", format])
+        );
+
+        cmd_helper.set_env("GIT_AUTHOR_DATE", "1500000000 +0000");
+        cmd_helper.set_env("GIT_COMMITTER_DATE", "Fri, 14 Jul 2017 02:40:00 +0000");
+        cmd_helper
+            .run_cmd(&["commit", "--amend", "-m", "amended"])
+            .unwrap();
+        assert_eq!(
+            "1112904793 +0200 1500000000 +0000\n",
+            cmd_helper.git_cmd(&["log", "-1", "--date=raw", "--format=%ad %cd"])
+        );
+
+        cmd_helper.set_env("GIT_COMMITTER_DATE", "whenever");
+        let stderr = cmd_helper
+            .run_cmd(&["commit", "--allow-empty", "-m", "bad date"])
+            .unwrap_err();
+        assert_output(&stderr, "fatal: invalid date format: whenever\n");
+    }
+
+    #[test]
+    fn keep_extra_headers_when_amending() {
+        let mut cmd_helper = setup();
+        cmd_helper.commit("first");
+        let oid = cmd_helper.git_cmd(&[
+            "-c",
+            "user.name=A. U. Thor",
+            "-c",
+            "user.email=author@example.com",
+            "-c",
+            "i18n.commitEncoding=ISO-8859-1",
+            "commit-tree",
+            "HEAD^{tree}",
+            "-m",
+            "encoded",
+        ]);
+        cmd_helper.git_cmd(&["update-ref", "HEAD", oid.trim()]);
+
+        cmd_helper
+            .run_cmd(&["commit", "--amend", "-m", "reworded"])
+            .unwrap();
+        let raw = cmd_helper.git_cmd(&["cat-file", "commit", "HEAD"]);
+        assert!(
+            raw.contains("\nencoding ISO-8859-1\n\nreworded\n"),
+            "{}",
+            raw
+        );
+    }
+
+    #[test]
+    fn amend_non_utf8_commits_without_changing_them() {
+        let mut cmd_helper = setup();
+        cmd_helper.commit("first");
+        let tree = cmd_helper.git_cmd(&["rev-parse", "HEAD^{tree}"]);
+        let mut data = format!("tree {}\n", tree.trim()).into_bytes();
+        data.extend_from_slice(
+            b"author Ren\xe9 <rene@example.com> 1577836800 -0000
+committer C. O. Mitter <committer@example.com> 1577840400 +0100
+encoding ISO-8859-1
+
+caf\xe9
+",
+        );
+        cmd_helper.write_file("raw.commit", &data).unwrap();
+        let oid = cmd_helper.git_cmd(&["hash-object", "-t", "commit", "-w", "raw.commit"]);
+        cmd_helper.git_cmd(&["update-ref", "HEAD", oid.trim()]);
+
+        cmd_helper.set_env("GIT_COMMITTER_DATE", "1577840400 +0100");
+        cmd_helper
+            .run_cmd(&["commit", "--amend", "--no-edit"])
+            .unwrap();
+        assert_eq!(oid, cmd_helper.git_cmd(&["rev-parse", "HEAD"]));
+    }
+
+    #[test]
+    fn clean_up_messages() {
+        let message = "\n# comment\nSubject \t\n\n\n\nBody\n#more\n\n";
+        assert_eq!(
+            "Subject\n\nBody\n",
+            cleanup_message(message, Cleanup::Strip)
+        );
+        assert_eq!(
+            "# comment\nSubject\n\nBody\n#more\n",
+            cleanup_message(message, Cleanup::Whitespace)
+        );
+        assert_eq!(message, cleanup_message(message, Cleanup::Verbatim));
+    }
+
+    #[test]
+    fn take_paths_relative_to_the_current_directory() {
+        let mut cmd_helper = setup();
+        cmd_helper.write_file("dir/a.txt", b"a").unwrap();
+        cmd_helper.write_file("dir/sub/b.txt", b"b").unwrap();
+        cmd_helper
+            .jit_cmd(&["-C", "dir", "add", "a.txt", "../file.txt"])
+            .unwrap();
+        cmd_helper.commit("first");
+        assert_eq!(
+            "?? dir/sub/\n",
+            cmd_helper.git_cmd(&["status", "--porcelain"])
+        );
+
+        cmd_helper.write_file("file.txt", b"changed").unwrap();
+        cmd_helper.write_file("dir/a.txt", b"changed").unwrap();
+        cmd_helper
+            .jit_cmd(&["-C", "dir/sub", "commit", "-m", "second", "../a.txt"])
+            .unwrap();
+        assert_eq!(
+            "dir/a.txt\n",
+            cmd_helper.git_cmd(&["diff", "--name-only", "HEAD^", "HEAD"])
+        );
+
+        let stderr = cmd_helper
+            .jit_cmd(&["-C", "dir", "commit", "-m", "third", "../../x"])
+            .unwrap_err();
+        assert_output(&stderr, "fatal: '../../x' is outside repository\n");
+    }
+}
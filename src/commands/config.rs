@@ -0,0 +1,338 @@
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use crate::commands::CommandContext;
+use crate::config::stack::{Scope, Stack};
+use crate::config::{Config, Key};
+use crate::errors::Error;
+use crate::repository::Repository;
+
+enum Action {
+    Get,
+    GetAll,
+    Set,
+    Add,
+    Unset,
+    UnsetAll,
+    List,
+}
+
+fn wrong_arguments(expected: usize) -> Error {
+    Error::failure(format!(
+        "error: wrong number of arguments, should be {}\n",
+        expected
+    ))
+}
+
+pub fn config_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let options = ctx.options.as_ref().unwrap();
+    let args: Vec<&str> = match options.values_of("args") {
+        Some(args) => args.collect(),
+        None => vec![],
+    };
+    // Config can be read and written outside a repository, where only
+    // the local file is missing
+    let git_path = match Repository::locate(&ctx.dir, ctx.env) {
+        Ok((git_path, _)) => git_path,
+        Err(_) => ctx.dir.join(".git"),
+    };
+
+    // The single file named on the command line, if any. Reads
+    // without one go through every layer.
+    let scope = [
+        ("system", Scope::System),
+        ("global", Scope::Global),
+        ("local", Scope::Local),
+    ]
+    .iter()
+    .find(|(name, _)| options.is_present(name))
+    .map(|(_, scope)| *scope);
+    let file: Option<PathBuf> = match (options.value_of("file"), scope) {
+        (Some(file), _) => Some(ctx.dir.join(file)),
+        (None, Some(scope)) => Some(Stack::path(scope, &git_path, ctx.env)?),
+        (None, None) => None,
+    };
+
+    let action = if options.is_present("list") {
+        Action::List
+    } else if options.is_present("get") {
+        Action::Get
+    } else if options.is_present("get-all") {
+        Action::GetAll
+    } else if options.is_present("set") {
+        Action::Set
+    } else if options.is_present("add") {
+        Action::Add
+    } else if options.is_present("unset") {
+        Action::Unset
+    } else if options.is_present("unset-all") {
+        Action::UnsetAll
+    } else {
+        match args.len() {
+            1 => Action::Get,
+            2 => Action::Set,
+            _ => return Err(Error::usage("usage: rug config [<options>]\n")),
+        }
+    };
+
+    let expected = match action {
+        Action::List => 0,
+        Action::Get | Action::GetAll | Action::Unset | Action::UnsetAll => 1,
+        Action::Set | Action::Add => 2,
+    };
+    if args.len() != expected {
+        return Err(wrong_arguments(expected));
+    }
+
+    match action {
+        Action::List => {
+            let variables = match file {
+                Some(file) => {
+                    let mut config = Config::new(&file);
+                    config.open()?;
+                    config.list()
+                }
+                None => Stack::load(&git_path, ctx.env)?.list(),
+            };
+
+            for (name, value) in variables {
+                match value {
+                    Some(value) => writeln!(ctx.stdout, "{}={}", name, value),
+                    None => writeln!(ctx.stdout, "{}", name),
+                }
+                .map_err(Error::Output)?;
+            }
+        }
+        Action::Get | Action::GetAll => {
+            let key = Key::parse(args[0])?;
+            let mut values = match file {
+                Some(file) => {
+                    let mut config = Config::new(&file);
+                    config.open()?;
+                    config.get_all(&key)
+                }
+                None => Stack::load(&git_path, ctx.env)?.get_all(args[0]),
+            };
+
+            // A missing key fails without saying anything
+            if values.is_empty() {
+                return Err(Error::Exit(1));
+            }
+            if let Action::Get = action {
+                values = values.split_off(values.len() - 1);
+            }
+
+            for value in values.into_iter().flatten() {
+                writeln!(ctx.stdout, "{}", value).map_err(Error::Output)?;
+            }
+        }
+        _ => {
+            let key = Key::parse(args[0])?;
+            let file = match file {
+                Some(file) => file,
+                None => Stack::path(Scope::Local, &git_path, ctx.env)?,
+            };
+            let mut config = Config::new(&file);
+            config.open_for_update()?;
+
+            let changed = match action {
+                Action::Set => config.set(&key, args[1]).map(|_| true)?,
+                Action::Add => {
+                    config.add(&key, args[1]);
+                    true
+                }
+                Action::Unset => config.unset(&key)?,
+                _ => config.unset_all(&key),
+            };
+            // As does unsetting one, with the status git gives it
+            if !changed {
+                return Err(Error::Exit(5));
+            }
+
+            config.save()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use std::fs;
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+
+        cmd_helper
+    }
+
+    #[test]
+    fn set_get_and_unset_values() {
+        let mut cmd_helper = setup();
+
+        cmd_helper
+            .run_cmd(&["config", "user.name", "A. U. Thor"])
+            .unwrap();
+        cmd_helper
+            .run_cmd(&["config", "--set", "Remote.Origin.url", "/tmp/x # y"])
+            .unwrap();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["config", "USER.NAME"]).unwrap();
+        assert_output(&stdout, "A. U. Thor\n");
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["config", "--get", "remote.Origin.url"])
+            .unwrap();
+        assert_output(&stdout, "/tmp/x # y\n");
+        assert_eq!(
+            "/tmp/x # y\n",
+            cmd_helper.git_cmd(&["config", "remote.Origin.url"])
+        );
+
+        cmd_helper
+            .run_cmd(&["config", "--unset", "user.name"])
+            .unwrap();
+        assert_eq!(
+            (1, String::new(), String::new()),
+            cmd_helper.jit_status(&["config", "user.name"])
+        );
+        assert_eq!(
+            (5, String::new(), String::new()),
+            cmd_helper.jit_status(&["config", "--unset", "user.name"])
+        );
+    }
+
+    #[test]
+    fn handle_multiple_values() {
+        let mut cmd_helper = setup();
+
+        cmd_helper
+            .run_cmd(&["config", "--add", "a.b", "1"])
+            .unwrap();
+        cmd_helper
+            .run_cmd(&["config", "--add", "a.b", "2"])
+            .unwrap();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["config", "--get-all", "a.b"]).unwrap();
+        assert_output(&stdout, "1\n2\n");
+        let (stdout, _) = cmd_helper.run_cmd(&["config", "--get", "a.b"]).unwrap();
+        assert_output(&stdout, "2\n");
+
+        let stderr = cmd_helper.run_cmd(&["config", "a.b", "3"]).unwrap_err();
+        assert_output(&stderr, "warning: a.b has multiple values\n");
+
+        cmd_helper
+            .run_cmd(&["config", "--unset-all", "a.b"])
+            .unwrap();
+        assert!(cmd_helper.run_cmd(&["config", "a.b"]).is_err());
+    }
+
+    #[test]
+    fn layer_global_local_and_command_line_values() {
+        let mut cmd_helper = setup();
+
+        cmd_helper
+            .run_cmd(&["config", "--global", "core.editor", "ed"])
+            .unwrap();
+        cmd_helper
+            .run_cmd(&["config", "--global", "user.name", "Global"])
+            .unwrap();
+        cmd_helper
+            .run_cmd(&["config", "user.name", "Local"])
+            .unwrap();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["config", "user.name"]).unwrap();
+        assert_output(&stdout, "Local\n");
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["config", "--global", "user.name"])
+            .unwrap();
+        assert_output(&stdout, "Global\n");
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["-c", "user.name=It's me", "config", "user.name"])
+            .unwrap();
+        assert_output(&stdout, "It's me\n");
+
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["-c", "x.flag", "config", "--list"])
+            .unwrap();
+        assert_output(
+            &stdout,
+            "core.editor=ed
+user.name=Global
+core.repositoryformatversion=0
+core.filemode=true
+core.bare=false
+core.logallrefupdates=true
+user.name=Local
+x.flag\n",
+        );
+
+        // Config files are found through the command's environment,
+        // not the process's
+        let global = cmd_helper.repo().git_path.join("other-global-config");
+        cmd_helper.set_env("GIT_CONFIG_GLOBAL", global.to_str().unwrap());
+        cmd_helper
+            .run_cmd(&["config", "--global", "user.name", "Other"])
+            .unwrap();
+        assert_eq!(
+            "[user]\n\tname = Other\n",
+            fs::read_to_string(&global).unwrap()
+        );
+    }
+
+    #[test]
+    fn read_and_write_a_named_file() {
+        let mut cmd_helper = setup();
+        cmd_helper
+            .write_file("other.cfg", b"[core]\n\tbare = false ; note\n")
+            .unwrap();
+
+        cmd_helper
+            .run_cmd(&["config", "-f", "other.cfg", "core.bare", "true"])
+            .unwrap();
+
+        let contents = fs::read_to_string(cmd_helper.repo().root_path.join("other.cfg")).unwrap();
+        assert_eq!("[core]\n\tbare = true\n", contents);
+    }
+
+    #[test]
+    fn reject_invalid_keys() {
+        let mut cmd_helper = setup();
+
+        let stderr = cmd_helper.run_cmd(&["config", "name", "x"]).unwrap_err();
+        assert_output(&stderr, "error: key does not contain a section: name\n");
+        let stderr = cmd_helper.run_cmd(&["config", "a.1b", "x"]).unwrap_err();
+        assert_output(&stderr, "error: invalid key: a.1b\n");
+    }
+
+    #[test]
+    fn use_configured_identity_for_commits() {
+        let mut cmd_helper = setup();
+        cmd_helper.write_file("file.txt", b"").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+
+        let stderr = cmd_helper
+            .run_cmd(&["commit", "-m", "message"])
+            .unwrap_err();
+        assert!(stderr.starts_with("Author identity unknown\n"));
+
+        cmd_helper
+            .run_cmd(&["config", "user.name", "Config User"])
+            .unwrap();
+        cmd_helper
+            .run_cmd(&["config", "user.email", "config@example.com"])
+            .unwrap();
+        cmd_helper.run_cmd(&["commit", "-m", "message"]).unwrap();
+
+        assert_eq!(
+            "Config User <config@example.com>\n",
+            cmd_helper.git_cmd(&["log", "--format=%an <%ae>"])
+        );
+    }
+}
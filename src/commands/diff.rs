@@ -1,11 +1,16 @@
 use crate::commands::CommandContext;
 use crate::database::blob::Blob;
-use crate::database::object::Object;
-use crate::database::{Database, ParsedObject};
+use crate::database::object::Storable;
+use crate::database::tree::GITLINK_MODE;
+use crate::database::Database;
 use crate::diff;
+use crate::diff::binary::{binary_patch, is_binary};
 use crate::diff::myers::{Edit, EditType};
+use crate::errors::Error;
+use crate::index;
 use crate::pager::Pager;
-use crate::repository::{ChangeType, Repository};
+use crate::repository::{ChangeType, Repository, Status};
+use crate::workspace::Workspace;
 use colored::*;
 use std::io::{Read, Write};
 use std::os::unix::fs::MetadataExt;
@@ -20,14 +25,25 @@ where
     E: Write,
 {
     repo: Repository,
-    ctx: CommandContext<'a, I, O, E>,
+    status: Status,
+    ctx: CommandContext<'a, I, Pager<O>, E>,
 }
 
 struct Target {
     path: String,
     oid: String,
     mode: Option<u32>,
-    data: String,
+    data: Vec<u8>,
+}
+
+impl Target {
+    fn diff_path(&self) -> &str {
+        if self.mode.is_some() {
+            &self.path
+        } else {
+            NULL_PATH
+        }
+    }
 }
 
 impl<'a, I, O, E> Diff<'a, I, O, E>
@@ -36,48 +52,62 @@ where
     O: Write,
     E: Write,
 {
-    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Diff<'a, I, O, E> {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
-
-        Diff { ctx, repo }
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Result<Diff<'a, I, O, E>, Error> {
+        let repo = Repository::discover(&ctx.dir, ctx.env)?;
+        repo.require_work_tree()?;
+
+        Ok(Diff {
+            ctx: ctx.with_pager(),
+            repo,
+            status: Status::default(),
+        })
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
-        self.repo.index.load().map_err(|e| e.to_string())?;
-        self.repo.initialize_status()?;
+    pub fn run(&mut self) -> Result<(), Error> {
+        self.repo.index.load()?;
+        self.status = self.repo.status()?;
 
-        Pager::setup_pager();
+        self.ctx.stdout.start(self.ctx.env);
 
-        if self
-            .ctx
-            .options
-            .as_ref()
-            .map(|o| o.is_present("cached"))
-            .unwrap_or(false)
-        {
+        if self.options_present("cached") {
             self.diff_head_index()
         } else {
             self.diff_index_workspace()
         }
     }
 
-    fn diff_head_index(&mut self) -> Result<(), String> {
-        for (path, state) in &self.repo.index_changes.clone() {
+    fn options_present(&self, name: &str) -> bool {
+        self.ctx
+            .options
+            .as_ref()
+            .map(|o| o.is_present(name))
+            .unwrap_or(false)
+    }
+
+    fn diff_head_index(&mut self) -> Result<(), Error> {
+        for path in &self.status.changed.clone() {
+            if self.status.conflicts.contains_key(path) {
+                self.print_conflict_diff(path)?;
+                continue;
+            }
+
+            let state = match self.status.index_changes.get(path) {
+                Some(state) => *state,
+                None => continue,
+            };
             match state {
                 ChangeType::Added => {
-                    let b = self.from_index(path);
-                    self.print_diff(self.from_nothing(path), b)?;
+                    let b = self.target_from_index(path)?;
+                    self.print_diff(self.target_from_nothing(path), b)?;
                 }
                 ChangeType::Modified => {
-                    let a = self.from_head(path);
-                    let b = self.from_index(path);
+                    let a = self.target_from_head(path)?;
+                    let b = self.target_from_index(path)?;
                     self.print_diff(a, b)?;
                 }
                 ChangeType::Deleted => {
-                    let a = self.from_head(path);
-                    self.print_diff(a, self.from_nothing(path))?;
+                    let a = self.target_from_head(path)?;
+                    self.print_diff(a, self.target_from_nothing(path))?;
                 }
                 state => panic!("NYI: {:?}", state),
             }
@@ -86,19 +116,30 @@ where
         Ok(())
     }
 
-    fn diff_index_workspace(&mut self) -> Result<(), String> {
-        for (path, state) in &self.repo.workspace_changes.clone() {
+    fn diff_index_workspace(&mut self) -> Result<(), Error> {
+        for path in &self.status.changed.clone() {
+            if self.status.conflicts.contains_key(path) {
+                self.print_conflict_diff(path)?;
+                continue;
+            }
+
+            let state = match self.status.workspace_changes.get(path) {
+                Some(state) => *state,
+                None => continue,
+            };
             match state {
                 ChangeType::Added => {
-                    self.print_diff(self.from_nothing(path), self.from_file(path))?;
+                    let b = self.target_from_file(path)?;
+                    self.print_diff(self.target_from_nothing(path), b)?;
                 }
                 ChangeType::Modified => {
-                    let a = self.from_index(path);
-                    self.print_diff(a, self.from_file(path))?;
+                    let a = self.target_from_index(path)?;
+                    let b = self.target_from_file(path)?;
+                    self.print_diff(a, b)?;
                 }
                 ChangeType::Deleted => {
-                    let a = self.from_index(path);
-                    self.print_diff(a, self.from_nothing(path))?;
+                    let a = self.target_from_index(path)?;
+                    self.print_diff(a, self.target_from_nothing(path))?;
                 }
                 state => panic!("NYI: {:?}", state),
             }
@@ -106,7 +147,11 @@ where
         Ok(())
     }
 
-    fn print_diff(&mut self, mut a: Target, mut b: Target) -> Result<(), String> {
+    fn print_conflict_diff(&mut self, path: &str) -> Result<(), Error> {
+        writeln!(self.ctx.stdout, "* Unmerged path {}", path).map_err(Error::Output)
+    }
+
+    fn print_diff(&mut self, mut a: Target, mut b: Target) -> Result<(), Error> {
         if a.oid == b.oid && a.mode == b.mode {
             return Ok(());
         }
@@ -114,49 +159,70 @@ where
         a.path = format!("a/{}", a.path);
         b.path = format!("b/{}", b.path);
 
-        println!("{}", format!("diff --git {} {}", a.path, b.path).bold());
+        writeln!(
+            self.ctx.stdout,
+            "{}",
+            format!("diff --git {} {}", a.path, b.path).bold()
+        )
+        .map_err(Error::Output)?;
 
         self.print_diff_mode(&a, &b)?;
         self.print_diff_content(&a, &b)
     }
 
-    fn print_diff_mode(&mut self, a: &Target, b: &Target) -> Result<(), String> {
-        if a.mode == None {
-            println!(
-                "{}",
-                format!("new file mode {:o}", b.mode.expect("missing mode")).bold()
-            );
-        } else if b.mode == None {
-            println!(
-                "{}",
-                format!("deleted file mode {:o}", a.mode.expect("missing mode")).bold()
-            );
-        } else if a.mode != b.mode {
-            println!(
-                "{}",
-                format!("old mode {:o}", a.mode.expect("missing mode")).bold()
-            );
-
-            println!(
-                "{}",
-                format!("new mode {:o}", b.mode.expect("missing mode")).bold()
-            );
+    fn print_diff_mode(&mut self, a: &Target, b: &Target) -> Result<(), Error> {
+        match (a.mode, b.mode) {
+            (None, Some(b_mode)) => {
+                writeln!(
+                    self.ctx.stdout,
+                    "{}",
+                    format!("new file mode {:o}", b_mode).bold()
+                )
+                .map_err(Error::Output)?;
+            }
+            (Some(a_mode), None) => {
+                writeln!(
+                    self.ctx.stdout,
+                    "{}",
+                    format!("deleted file mode {:o}", a_mode).bold()
+                )
+                .map_err(Error::Output)?;
+            }
+            (Some(a_mode), Some(b_mode)) if a_mode != b_mode => {
+                writeln!(
+                    self.ctx.stdout,
+                    "{}",
+                    format!("old mode {:o}", a_mode).bold()
+                )
+                .map_err(Error::Output)?;
+                writeln!(
+                    self.ctx.stdout,
+                    "{}",
+                    format!("new mode {:o}", b_mode).bold()
+                )
+                .map_err(Error::Output)?;
+            }
+            _ => (),
         }
 
         Ok(())
     }
 
-    fn print_diff_content(&mut self, a: &Target, b: &Target) -> Result<(), String> {
+    fn print_diff_content(&mut self, a: &Target, b: &Target) -> Result<(), Error> {
         if a.oid == b.oid {
             return Ok(());
         }
 
-        println!(
+        // Binary patches need the full object IDs to apply
+        let full_index = self.options_present("binary");
+        let oid = |oid| if full_index { oid } else { short(oid) };
+        writeln!(
+            self.ctx.stdout,
             "{}",
             format!(
                 "index {}..{}{}",
-                short(&a.oid),
-                short(&b.oid),
+                oid(&a.oid),
+                oid(&b.oid),
                 if a.mode == b.mode {
                     format!(" {:o}", a.mode.expect("Missing mode"))
                 } else {
@@ -164,114 +230,232 @@ where
                 }
             )
             .bold()
-        );
-        println!("{}", format!("--- {}", a.path).bold());
-        println!("{}", format!("+++ {}", b.path).bold());
+        )
+        .map_err(Error::Output)?;
+
+        if is_binary(&a.data) || is_binary(&b.data) {
+            if full_index {
+                write!(self.ctx.stdout, "{}", binary_patch(&a.data, &b.data))
+                    .map_err(Error::Output)?;
+            } else {
+                writeln!(
+                    self.ctx.stdout,
+                    "Binary files {} and {} differ",
+                    a.diff_path(),
+                    b.diff_path()
+                )
+                .map_err(Error::Output)?;
+            }
+            return Ok(());
+        }
+
+        writeln!(
+            self.ctx.stdout,
+            "{}",
+            format!("--- {}", a.diff_path()).bold()
+        )
+        .map_err(Error::Output)?;
+        writeln!(
+            self.ctx.stdout,
+            "{}",
+            format!("+++ {}", b.diff_path()).bold()
+        )
+        .map_err(Error::Output)?;
 
         let hunks = diff::Diff::diff_hunks(&a.data, &b.data);
         for h in hunks {
-            self.print_diff_hunk(h).map_err(|e| e.to_string())?;
+            self.print_diff_hunk(h)?;
         }
 
         Ok(())
     }
 
-    fn print_diff_edit(&mut self, edit: Edit) -> Result<(), String> {
+    fn print_diff_edit(&mut self, edit: Edit) -> Result<(), Error> {
         let edit_string = match &edit.edit_type {
             EditType::Ins => format!("{}", edit).green(),
             EditType::Del => format!("{}", edit).red(),
             EditType::Eql => format!("{}", edit).normal(),
         };
-        println!("{}", edit_string);
+        writeln!(self.ctx.stdout, "{}", edit_string).map_err(Error::Output)?;
+        if !edit.line().has_newline() {
+            writeln!(self.ctx.stdout, "\\ No newline at end of file").map_err(Error::Output)?;
+        }
 
         Ok(())
     }
 
-    fn print_diff_hunk(&mut self, hunk: diff::Hunk) -> Result<(), String> {
-        println!("{}", hunk.header().cyan());
+    fn print_diff_hunk(&mut self, hunk: diff::Hunk) -> Result<(), Error> {
+        writeln!(self.ctx.stdout, "{}", hunk.header().cyan()).map_err(Error::Output)?;
 
         for edit in hunk.edits {
-            self.print_diff_edit(edit).map_err(|e| e.to_string())?;
+            self.print_diff_edit(edit)?;
         }
 
         Ok(())
     }
 
-    fn from_index(&mut self, path: &str) -> Target {
+    fn target_from_index(&mut self, path: &str) -> Result<Target, Error> {
         let entry = self
             .repo
             .index
             .entry_for_path(path)
-            .expect("Path not found in index");
+            .ok_or_else(|| Error::fatal(format!("fatal: {}: not in the index\n", path)))?;
         let oid = entry.oid.clone();
-        let blob = match self.repo.database.load(&oid) {
-            ParsedObject::Blob(blob) => blob,
-            _ => panic!("path is not a blob"),
-        };
+        let mode = entry.mode;
 
-        Target {
+        Ok(Target {
             path: path.to_string(),
+            data: self.target_data(&oid, mode)?,
             oid,
-            mode: Some(entry.mode),
-            data: std::str::from_utf8(&blob.data)
-                .expect("utf8 conversion failed")
-                .to_string(),
-        }
+            mode: Some(mode),
+        })
     }
 
-    fn from_file(&self, path: &str) -> Target {
-        let blob = Blob::new(
-            self.repo
-                .workspace
-                .read_file(path)
-                .expect("Failed to read file")
-                .as_bytes(),
-        );
+    fn target_from_file(&self, path: &str) -> Result<Target, Error> {
+        let workspace = &self.repo.workspace;
+        let data = workspace
+            .read_file(path)
+            .map_err(|e| Error::io("read", workspace.abs_path(path), e))?;
+        let blob = Blob::new(&data);
         let oid = blob.get_oid();
-        let mode = self.repo.stats.get(path).unwrap().mode();
-        Target {
+        let stat = workspace
+            .stat_file(path)
+            .map_err(|e| Error::io("stat", workspace.abs_path(path), e))?;
+        let mode = index::Entry::mode(stat.mode());
+        Ok(Target {
             path: path.to_string(),
             oid,
             mode: Some(mode),
-            data: std::str::from_utf8(&blob.data)
-                .expect("utf8 conversion failed")
-                .to_string(),
-        }
+            data: blob.data,
+        })
     }
 
-    fn from_nothing(&self, path: &str) -> Target {
+    fn target_from_nothing(&self, path: &str) -> Target {
         Target {
             path: path.to_string(),
             oid: NULL_OID.to_string(),
             mode: None,
-            data: "".to_string(),
+            data: vec![],
         }
     }
 
-    fn from_head(&mut self, path: &str) -> Target {
+    fn target_from_head(&mut self, path: &str) -> Result<Target, Error> {
         let entry = self
-            .repo
+            .status
             .head_tree
             .get(path)
-            .expect("Path not found in HEAD");
+            .ok_or_else(|| Error::fatal(format!("fatal: {}: not in HEAD\n", path)))?;
         let oid = entry.get_oid();
         let mode = entry.mode();
-        let blob = match self.repo.database.load(&oid) {
-            ParsedObject::Blob(blob) => blob,
-            _ => panic!("path is not a blob"),
-        };
 
-        Target {
+        Ok(Target {
             path: path.to_string(),
+            data: self.target_data(&oid, mode)?,
             oid,
             mode: Some(mode),
-            data: std::str::from_utf8(&blob.data)
-                .expect("utf8 conversion failed")
-                .to_string(),
+        })
+    }
+
+    /// The text to diff for an object: a blob's contents, or for a
+    /// gitlink the commit it points at
+    fn target_data(&mut self, oid: &str, mode: u32) -> Result<Vec<u8>, Error> {
+        if mode == GITLINK_MODE {
+            return Ok(format!("Subproject commit {}\n", oid).into_bytes());
         }
+
+        Workspace::blob_data(&mut self.repo.database, oid)
     }
 }
 
 fn short(oid: &str) -> &str {
     Database::short_oid(oid)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use std::fs;
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("text.txt", b"one\ntwo").unwrap();
+        cmd_helper
+            .write_file("image.png", b"PNG\0\x01\x02")
+            .unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        cmd_helper
+    }
+
+    #[test]
+    fn mark_a_missing_newline_at_end_of_file() {
+        let mut cmd_helper = setup();
+        cmd_helper.write_file("text.txt", b"one\nthree\n").unwrap();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["diff"]).unwrap();
+        assert!(
+            stdout.ends_with("@@ -1,2 +1,2 @@\n one\n-two\n\\ No newline at end of file\n+three\n"),
+            "{}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn report_binary_and_non_utf8_files() {
+        let mut cmd_helper = setup();
+        cmd_helper.write_file("image.png", b"PNG\0\x03").unwrap();
+        cmd_helper.write_file("text.txt", b"caf\xe9\n").unwrap();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["diff"]).unwrap();
+        assert!(stdout.contains("Binary files a/image.png and b/image.png differ\n"));
+        assert!(!stdout.contains("--- a/image.png"));
+        assert!(stdout.contains("+caf\u{fffd}\n"), "{}", stdout);
+
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("second");
+        assert_eq!(
+            b"caf\xe9\n".to_vec(),
+            cmd_helper.git_output(&["cat-file", "blob", "HEAD:text.txt"])
+        );
+    }
+
+    #[test]
+    fn write_binary_patches_git_can_apply() {
+        let mut cmd_helper = setup();
+        let contents: Vec<u8> = (0..=255).cycle().take(300).collect();
+        cmd_helper.write_file("image.png", &contents).unwrap();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["diff", "--binary"]).unwrap();
+        assert!(
+            stdout.contains("GIT binary patch\nliteral 300\n"),
+            "{}",
+            stdout
+        );
+        let full_oid = cmd_helper.git_cmd(&["rev-parse", "HEAD:image.png"]);
+        assert!(stdout.contains(&format!("index {}..", full_oid.trim())));
+
+        let patch = cmd_helper.repo().git_path.join("binary.patch");
+        fs::write(&patch, stdout).unwrap();
+        cmd_helper.git_cmd(&["checkout", "--", "image.png"]);
+        cmd_helper.git_cmd(&["apply", patch.to_str().unwrap()]);
+
+        let path = cmd_helper.repo().workspace.abs_path("image.png");
+        assert_eq!(contents, fs::read(path).unwrap());
+    }
+
+    #[test]
+    fn send_output_through_the_pager() {
+        let mut cmd_helper = setup();
+        cmd_helper.write_file("text.txt", b"one\nthree\n").unwrap();
+        let (expected, _) = cmd_helper.run_cmd(&["diff"]).unwrap();
+        assert!(expected.contains("+three\n"), "{}", expected);
+
+        cmd_helper.set_pager(PAGER);
+        assert_eq!(
+            (paged(&expected), String::new()),
+            cmd_helper.run_cmd(&["diff"]).unwrap()
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::{Read, Write};
+
+use crate::commands::CommandContext;
+use crate::database::object::Storable;
+use crate::database::{ParsedObject, Raw};
+use crate::errors::Error;
+use crate::reflog::ZERO_OID;
+use crate::repository::Repository;
+
+// Bits of the exit status, one for each kind of problem found
+const CORRUPT: i32 = 1;
+const MISSING: i32 = 2;
+const DANGLING: i32 = 4;
+
+pub fn fsck_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let mut repo = Repository::discover(&ctx.dir, ctx.env)?;
+    let options = ctx.options.as_ref().unwrap().clone();
+
+    let mut fsck = Fsck {
+        repo: &mut repo,
+        full: options.is_present("full"),
+        objects: BTreeMap::new(),
+        reachable: HashSet::new(),
+        missing: BTreeSet::new(),
+        messages: vec![],
+        errors: vec![],
+        status: 0,
+    };
+    fsck.check_objects();
+    fsck.check_connectivity()?;
+
+    let unreachable: Vec<(&String, &Checked)> = fsck
+        .objects
+        .iter()
+        .filter(|(oid, _)| !fsck.reachable.contains(*oid))
+        .collect();
+    // Dangling objects are the unreachable ones that nothing at all
+    // points to
+    let referenced: HashSet<&str> = unreachable
+        .iter()
+        .flat_map(|(_, object)| object.links.iter().map(|(oid, _)| oid.as_str()))
+        .collect();
+    let report_dangling = !options.is_present("no-dangling");
+    let mut status = fsck.status;
+
+    for line in &fsck.messages {
+        writeln!(ctx.stdout, "{}", line).map_err(Error::Output)?;
+    }
+    for (oid, object) in unreachable {
+        let dangling = !referenced.contains(oid.as_str());
+        if dangling && report_dangling {
+            status |= DANGLING;
+        }
+
+        if options.is_present("unreachable") {
+            writeln!(ctx.stdout, "unreachable {} {}", object.obj_type, oid)
+                .map_err(Error::Output)?;
+        } else if dangling && report_dangling {
+            writeln!(ctx.stdout, "dangling {} {}", object.obj_type, oid).map_err(Error::Output)?;
+        }
+    }
+    for error in &fsck.errors {
+        writeln!(ctx.stderr, "{}", error).map_err(Error::Output)?;
+    }
+
+    if status != 0 {
+        return Err(Error::Exit(status));
+    }
+
+    Ok(())
+}
+
+/// What fsck learnt about an object: its type, and the objects it
+/// points to along with the type each is expected to have
+struct Checked {
+    obj_type: String,
+    links: Vec<(String, String)>,
+    // Corrupt objects are not followed, so that what they point to
+    // is not taken as missing
+    valid: bool,
+}
+
+struct Fsck<'a> {
+    repo: &'a mut Repository,
+    full: bool,
+    objects: BTreeMap<String, Checked>,
+    reachable: HashSet<String>,
+    missing: BTreeSet<String>,
+    // Findings for stdout and stderr respectively
+    messages: Vec<String>,
+    errors: Vec<String>,
+    status: i32,
+}
+
+impl<'a> Fsck<'a> {
+    fn corrupt(&mut self, error: String) {
+        self.errors.push(error);
+        self.status |= CORRUPT;
+    }
+
+    /// Read every object in the database, checking that each is well
+    /// formed and that loose objects, and with `--full` packed ones
+    /// too, hash to their names
+    fn check_objects(&mut self) {
+        let mut loose = self.repo.database.loose_oids();
+        loose.sort();
+        for oid in loose {
+            match self.repo.database.read_loose_checked(&oid) {
+                Ok(raw) => self.check_object(&oid, raw, true),
+                Err(e) => self.corrupt(format!("error: {}: object corrupt or missing: {}", oid, e)),
+            }
+        }
+
+        let mut packed = vec![];
+        for pack in self.repo.database.packs() {
+            for oid in pack.oids() {
+                if self.objects.contains_key(oid) {
+                    continue;
+                }
+                packed.push(match pack.load_raw(oid, &self.repo.database) {
+                    Ok(Some(raw)) => Ok((oid.clone(), raw)),
+                    Ok(None) => Err(format!("error: {}: object missing from its pack", oid)),
+                    Err(e) => Err(format!("error: {}: object corrupt in pack: {}", oid, e)),
+                });
+            }
+        }
+        for object in packed {
+            match object {
+                Ok((oid, raw)) => {
+                    let verify = self.full;
+                    self.check_object(&oid, raw, verify)
+                }
+                Err(error) => self.corrupt(error),
+            }
+        }
+    }
+
+    fn check_object(&mut self, oid: &str, raw: Raw, verify: bool) {
+        let parsed = match raw.obj_type.as_str() {
+            "blob" | "tree" | "commit" | "tag" => raw.parse(),
+            other => {
+                self.corrupt(format!("error: {}: invalid object type {}", oid, other));
+                return;
+            }
+        };
+
+        let mut valid = true;
+        if verify && raw.get_oid() != oid {
+            self.corrupt(format!(
+                "error: hash mismatch for {} (got {})",
+                oid,
+                raw.get_oid()
+            ));
+            valid = false;
+        }
+        let links = match parsed {
+            Ok(object) => links(&object),
+            Err(error) => {
+                let problem = match error {
+                    Error::CorruptObject { reason, .. } => reason,
+                    error => error.to_string(),
+                };
+                self.corrupt(format!("error in {} {}: {}", raw.obj_type, oid, problem));
+                valid = false;
+                vec![]
+            }
+        };
+
+        self.objects.insert(
+            oid.to_string(),
+            Checked {
+                obj_type: raw.obj_type,
+                links,
+                valid,
+            },
+        );
+    }
+
+    /// Walk from every ref, index entry and reflog entry, reporting
+    /// objects that are missing or not of the type they should be
+    fn check_connectivity(&mut self) -> Result<(), Error> {
+        let mut pending: Vec<(String, Option<String>, String)> = vec![];
+
+        for r#ref in self.repo.refs.list_all_refs() {
+            if let Some(oid) = self.repo.refs.read_oid(&r#ref) {
+                pending.push((oid, None, r#ref.path().to_string()));
+            }
+        }
+        for name in self.repo.refs.list_reflogs() {
+            for (n, entry) in self.repo.refs.reflog(&name).read().iter().rev().enumerate() {
+                for oid in &[&entry.old_oid, &entry.new_oid] {
+                    if oid.as_str() != ZERO_OID {
+                        pending.push((oid.to_string(), None, format!("{}@{{{}}}", name, n)));
+                    }
+                }
+            }
+        }
+        self.repo.index.load()?;
+        for entry in self.repo.index.entries.values() {
+            if !entry.is_gitlink() {
+                pending.push((
+                    entry.oid.clone(),
+                    Some("blob".to_string()),
+                    "index".to_string(),
+                ));
+            }
+        }
+        pending.reverse();
+
+        while let Some((oid, expected, from)) = pending.pop() {
+            let object = match self.objects.get(&oid) {
+                Some(object) => object,
+                None => {
+                    if self.missing.insert(oid.clone()) {
+                        let obj_type = expected.as_deref().unwrap_or("object");
+                        self.messages.push(format!("missing {} {}", obj_type, oid));
+                        self.errors
+                            .push(format!("error: {}: invalid sha1 pointer {}", from, oid));
+                        self.status |= MISSING;
+                    }
+                    continue;
+                }
+            };
+
+            if let Some(expected) = &expected {
+                if *expected != object.obj_type {
+                    let error = format!(
+                        "error: {}: object {} is a {}, not a {}",
+                        from, oid, object.obj_type, expected
+                    );
+                    self.corrupt(error);
+                    continue;
+                }
+            }
+            if !self.reachable.insert(oid.clone()) || !object.valid {
+                continue;
+            }
+            for (link, link_type) in object.links.iter().rev() {
+                pending.push((link.clone(), Some(link_type.clone()), oid.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The objects `object` points to, along with the type each is
+/// expected to have
+fn links(object: &ParsedObject) -> Vec<(String, String)> {
+    match object {
+        ParsedObject::Blob(_) => vec![],
+        ParsedObject::Tree(tree) => tree
+            .entries
+            .values()
+            // Gitlinks point into another repository
+            .filter(|entry| !entry.is_gitlink())
+            .map(|entry| (entry.get_oid(), entry.obj_type().to_string()))
+            .collect(),
+        ParsedObject::Commit(commit) => {
+            let mut links = vec![(commit.tree_oid.clone(), "tree".to_string())];
+            for parent in &commit.parents {
+                links.push((parent.clone(), "commit".to_string()));
+            }
+            links
+        }
+        ParsedObject::Tag(tag) => vec![(tag.object.clone(), tag.obj_type.clone())],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CORRUPT, DANGLING};
+    use crate::commands::tests::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn object_path(cmd_helper: &CommandHelper, rev: &str) -> (String, String) {
+        let oid = cmd_helper.git_cmd(&["rev-parse", rev]).trim().to_string();
+        let path = object_file(&oid);
+        (oid, path)
+    }
+
+    #[test]
+    fn pass_a_healthy_repository() {
+        let mut cmd_helper = setup_first_commit();
+        cmd_helper
+            .run_cmd(&["tag", "-a", "-m", "release", "v1.0"])
+            .unwrap();
+
+        assert_eq!(
+            (0, String::new(), String::new()),
+            cmd_helper.jit_status(&["fsck"])
+        );
+        cmd_helper.run_cmd(&["gc"]).unwrap();
+        assert_eq!(
+            (0, String::new(), String::new()),
+            cmd_helper.jit_status(&["fsck", "--full"])
+        );
+    }
+
+    #[test]
+    fn report_dangling_and_unreachable_objects() {
+        let mut cmd_helper = setup_first_commit();
+        let first = cmd_helper.git_cmd(&["rev-parse", "HEAD"]);
+        cmd_helper.write_file("a.txt", b"changed\n").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("second");
+        let (second, _) = object_path(&cmd_helper, "HEAD");
+        let (tree, _) = object_path(&cmd_helper, "HEAD^{tree}");
+        let (blob, _) = object_path(&cmd_helper, "HEAD:a.txt");
+
+        // Forget the second commit, logs and all
+        cmd_helper.git_cmd(&["update-ref", "refs/heads/master", first.trim()]);
+        cmd_helper.git_cmd(&["reset", "-q"]);
+        cmd_helper.delete(".git/logs").unwrap();
+
+        let (status, stdout, _) = cmd_helper.jit_status(&["fsck"]);
+        assert_eq!(4, status);
+        assert_output(&stdout, &format!("dangling commit {}\n", second));
+
+        let (_, stdout, _) = cmd_helper.jit_status(&["fsck", "--unreachable"]);
+        let mut expected = [
+            format!("unreachable commit {}", second),
+            format!("unreachable tree {}", tree),
+            format!("unreachable blob {}", blob),
+        ];
+        expected.sort_by(|a, b| a.split(' ').nth(2).cmp(&b.split(' ').nth(2)));
+        assert_output(&stdout, &format!("{}\n", expected.join("\n")));
+
+        assert_eq!(
+            (0, String::new(), String::new()),
+            cmd_helper.jit_status(&["fsck", "--no-dangling"])
+        );
+    }
+
+    #[test]
+    fn report_corrupt_and_missing_objects() {
+        let mut cmd_helper = setup_first_commit();
+        let (a_oid, a_path) = object_path(&cmd_helper, "HEAD:a.txt");
+        let (b_oid, b_path) = object_path(&cmd_helper, "HEAD:dir/b.txt");
+
+        // Store other content under one object's name, and lose an
+        // object the tree needs
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"blob 4\0two\n").unwrap();
+        cmd_helper.delete(&a_path).unwrap();
+        cmd_helper
+            .write_file(&a_path, &encoder.finish().unwrap())
+            .unwrap();
+        cmd_helper.delete(&b_path).unwrap();
+
+        let (status, stdout, stderr) = cmd_helper.jit_status(&["fsck"]);
+        assert_eq!(3, status);
+        assert_output(&stdout, &format!("missing blob {}\n", b_oid));
+        assert!(
+            stderr.contains(&format!(
+                "error: hash mismatch for {} (got {})",
+                a_oid, b_oid
+            )),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn check_tree_order_and_modes() {
+        let mut cmd_helper = setup_first_commit();
+        let (blob, _) = object_path(&cmd_helper, "HEAD:a.txt");
+        let entry = |mode: &str, name: &str| {
+            let mut entry = format!("{} {}\0", mode, name).into_bytes();
+            entry.extend(crate::util::decode_hex(&blob).unwrap());
+            entry
+        };
+
+        for (entries, problem) in &[
+            (
+                vec![entry("100644", "b"), entry("100644", "a")],
+                "not properly sorted",
+            ),
+            (
+                vec![entry("100644", "a"), entry("100644", "a")],
+                "contains duplicate file entries",
+            ),
+            (vec![entry("100600", "a")], "contains bad file modes"),
+        ] {
+            cmd_helper.write_file("tree", &entries.concat()).unwrap();
+            let oid =
+                cmd_helper.git_cmd(&["hash-object", "-t", "tree", "-w", "--literally", "tree"]);
+
+            let (status, _, stderr) = cmd_helper.jit_status(&["fsck"]);
+            assert_eq!(CORRUPT | DANGLING, status);
+            assert!(
+                stderr.contains(&format!("error in tree {}: {}", oid.trim(), problem)),
+                "{}",
+                stderr
+            );
+            let (_, path) = object_path(&cmd_helper, oid.trim());
+            cmd_helper.delete(&path).unwrap();
+        }
+    }
+}
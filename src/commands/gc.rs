@@ -0,0 +1,19 @@
+use std::io::{Read, Write};
+
+use crate::commands::repack::repack;
+use crate::commands::CommandContext;
+use crate::errors::Error;
+use crate::repository::Repository;
+
+pub fn gc_command<I, O, E>(ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let repo = Repository::discover(&ctx.dir, ctx.env)?;
+
+    repo.refs
+        .pack_refs(true, true, &|oid| repo.database.peel_tag(oid))?;
+    repack(&repo, true)
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::io::{Read, Write};
+
+use crate::commands::CommandContext;
+use crate::database::object::Storable;
+use crate::database::Raw;
+use crate::errors;
+use crate::errors::Error;
+use crate::repository::Repository;
+
+const OBJECT_TYPES: [&str; 4] = ["blob", "tree", "commit", "tag"];
+
+pub fn hash_object_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let options = ctx.options.as_ref().unwrap().clone();
+    let obj_type = options.value_of("type").unwrap_or("blob");
+    // --literally takes data of any type, in any shape
+    let literally = options.is_present("literally");
+    if !literally && !OBJECT_TYPES.contains(&obj_type) {
+        return Err(Error::fatal(format!(
+            "fatal: invalid object type \"{}\"\n",
+            obj_type
+        )));
+    }
+
+    // Only writing needs a repository
+    let repo = if options.is_present("write") {
+        Some(Repository::discover(&ctx.dir, ctx.env)?)
+    } else {
+        None
+    };
+
+    let mut inputs = vec![];
+    if options.is_present("stdin") {
+        let mut data = vec![];
+        ctx.stdin
+            .read_to_end(&mut data)
+            .map_err(|e| Error::io("read", "standard input", e))?;
+        inputs.push(data);
+    }
+    for file in options.values_of("args").into_iter().flatten() {
+        let data = fs::read(ctx.dir.join(file)).map_err(|e| {
+            Error::fatal(format!(
+                "fatal: could not open '{}' for reading: {}\n",
+                file,
+                errors::describe(&e)
+            ))
+        })?;
+        inputs.push(data);
+    }
+
+    for data in inputs {
+        let object = Raw {
+            obj_type: obj_type.to_string(),
+            data,
+        };
+        if !literally {
+            check_format(&object)?;
+        }
+        if let Some(repo) = &repo {
+            repo.database.store(&object).map_err(|e| {
+                Error::io(
+                    "write object",
+                    repo.database.object_path(&object.get_oid()),
+                    e,
+                )
+            })?;
+        }
+        writeln!(ctx.stdout, "{}", object.get_oid()).map_err(Error::Output)?;
+    }
+
+    Ok(())
+}
+
+/// Refuse to hash data that does not parse as the type it is given,
+/// as it could never be read back
+fn check_format(object: &Raw) -> Result<(), Error> {
+    match object.parse() {
+        Ok(_) => Ok(()),
+        Err(Error::CorruptObject { reason, .. }) => Err(Error::fatal(format!(
+            "error: object fails fsck: {}\nfatal: refusing to create malformed object\n",
+            reason
+        ))),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    #[test]
+    fn hash_files_without_writing_them() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"hello\n").unwrap();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["hash-object", "file.txt"]).unwrap();
+        assert_output(&stdout, &cmd_helper.git_cmd(&["hash-object", "file.txt"]));
+        assert!(cmd_helper
+            .run_cmd(&["cat-file", "-e", stdout.trim()])
+            .is_err());
+
+        let stderr = cmd_helper
+            .run_cmd(&["hash-object", "-t", "thing", "file.txt"])
+            .unwrap_err();
+        assert_output(&stderr, "fatal: invalid object type \"thing\"\n");
+    }
+
+    #[test]
+    fn refuse_malformed_objects_unless_taken_literally() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("junk", b"not a commit\n").unwrap();
+
+        for obj_type in &["tree", "commit", "tag"] {
+            let (status, _, stderr) =
+                cmd_helper.jit_status(&["hash-object", "-t", obj_type, "-w", "junk"]);
+            assert_eq!(128, status);
+            assert!(
+                stderr.ends_with("fatal: refusing to create malformed object\n"),
+                "{}",
+                stderr
+            );
+        }
+
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["hash-object", "-t", "commit", "--literally", "-w", "junk"])
+            .unwrap();
+        assert_output(
+            &cmd_helper.git_cmd(&["cat-file", "-t", stdout.trim()]),
+            "commit\n",
+        );
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["hash-object", "-t", "thing", "--literally", "junk"])
+            .unwrap();
+        assert_output(
+            &stdout,
+            &cmd_helper.git_cmd(&["hash-object", "-t", "thing", "--literally", "junk"]),
+        );
+    }
+
+    #[test]
+    fn write_objects_from_stdin() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+
+        cmd_helper.set_stdin("some data\n");
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["hash-object", "-w", "--stdin"])
+            .unwrap();
+        assert_eq!(
+            "some data\n",
+            cmd_helper.git_cmd(&["cat-file", "blob", stdout.trim()])
+        );
+
+        cmd_helper.set_stdin("");
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["hash-object", "-w", "-t", "tree", "--stdin"])
+            .unwrap();
+        assert_output(&stdout, "4b825dc642cb6eb9a060e54bf8d69288fbee4904\n");
+        assert_output(
+            &cmd_helper.git_cmd(&["cat-file", "-t", stdout.trim()]),
+            "tree\n",
+        );
+    }
+}
@@ -1,44 +1,266 @@
+use crate::config::stack::{Scope, Stack};
+use crate::config::{Config, Key};
 use crate::refs::Refs;
+use std::collections::HashMap;
 use std::fs;
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
 use crate::commands::CommandContext;
+use crate::errors::{self, Error};
 
 const DEFAULT_BRANCH: &str = "master";
 
-pub fn init_command<I, O, E>(ctx: CommandContext<I, O, E>) -> Result<(), String>
+pub fn init_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
 where
     I: Read,
     O: Write,
     E: Write,
 {
-    let working_dir = ctx.dir;
-    let options = ctx.options.as_ref().unwrap();
-    let args: Vec<_> = if let Some(args) = options.values_of("args") {
-        args.collect()
-    } else {
-        vec![]
+    let options = ctx.options.as_ref().unwrap().clone();
+    let bare = options.is_present("bare");
+    let root_path = match options.values_of("args").and_then(|mut args| args.next()) {
+        Some(dir) => ctx.dir.join(dir),
+        None => ctx.dir.clone(),
     };
-    let root_path = if !args.is_empty() {
-        Path::new(args[0])
+    let git_path = if bare {
+        root_path
     } else {
-        working_dir.as_path()
+        root_path.join(".git")
     };
-    let git_path = root_path.join(".git");
+    let reinit = git_path.join("HEAD").is_file();
+
+    let config = Stack::load(&git_path, ctx.env)?;
+    let initial_branch = options.value_of("initial-branch");
+    let branch = initial_branch
+        .map(|branch| branch.to_string())
+        .or_else(|| config.get("init.defaultBranch"))
+        .unwrap_or_else(|| DEFAULT_BRANCH.to_string());
+    if !Refs::is_valid_name(&branch) {
+        return Err(Error::fatal(format!(
+            "fatal: invalid initial branch name: '{}'\n",
+            branch
+        )));
+    }
+
+    for dir in ["objects", "refs/heads", "refs/tags"].iter() {
+        let path = git_path.join(dir);
+        fs::create_dir_all(&path).map_err(|e| {
+            Error::fatal(format!(
+                "fatal: cannot mkdir {}: {}\n",
+                path.display(),
+                errors::describe(&e)
+            ))
+        })?;
+    }
+
+    let template = options
+        .value_of("template")
+        .map(|dir| dir.to_string())
+        .or_else(|| ctx.env.get("GIT_TEMPLATE_DIR").cloned())
+        .or_else(|| config.get("init.templateDir"))
+        .filter(|dir| !dir.is_empty());
+    if let Some(template) = template {
+        let template = ctx.dir.join(template);
+        if template.is_dir() {
+            copy_template(&template, &git_path)
+                .map_err(|e| Error::io("copy templates from", &template, e))?;
+        } else {
+            writeln!(
+                ctx.stderr,
+                "warning: templates not found in {}",
+                template.display()
+            )
+            .map_err(Error::Output)?;
+        }
+    }
+
+    // An existing repository keeps its settings and its HEAD
+    if reinit {
+        if let Some(branch) = initial_branch {
+            writeln!(
+                ctx.stderr,
+                "warning: re-init: ignored --initial-branch={}",
+                branch
+            )
+            .map_err(Error::Output)?;
+        }
+    } else {
+        write_config(&git_path, bare, ctx.env)?;
+        let refs = Refs::new(&git_path);
+        let head_path = git_path.join("HEAD");
+        refs.update_ref_file(&head_path, &format!("ref: refs/heads/{}", branch))
+            .map_err(|e| Error::io("write", &head_path, e))?;
+    }
 
-    for d in ["objects", "refs/heads"].iter() {
-        fs::create_dir_all(git_path.join(d)).expect("failed to create dir");
+    if !options.is_present("quiet") {
+        let git_path = git_path
+            .canonicalize()
+            .map_err(|e| Error::io("resolve", &git_path, e))?;
+        writeln!(
+            ctx.stdout,
+            "{} Git repository in {}/",
+            if reinit {
+                "Reinitialized existing"
+            } else {
+                "Initialized empty"
+            },
+            git_path.display()
+        )
+        .map_err(Error::Output)?;
     }
 
-    let refs = Refs::new(&git_path);
-    let path = Path::new("refs/heads").join(DEFAULT_BRANCH);
-    refs.update_head(&format!(
-        "ref: {}",
-        path.to_str().expect("failed to convert path to str")
-    ))
-    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Record the repository's layout in its config file
+fn write_config(git_path: &Path, bare: bool, env: &HashMap<String, String>) -> Result<(), Error> {
+    let mut config = Config::new(&Stack::path(Scope::Local, git_path, env)?);
+    config.open_for_update()?;
+
+    let mut settings = vec![
+        ("core.repositoryformatversion", "0"),
+        ("core.filemode", "true"),
+        ("core.bare", if bare { "true" } else { "false" }),
+    ];
+    if !bare {
+        settings.push(("core.logallrefupdates", "true"));
+    }
+    for (key, value) in settings {
+        config.set(&Key::parse(key)?, value)?;
+    }
+
+    config.save()
+}
+
+/// Copy the contents of template directory `from` into `to`, keeping
+/// any files that are already there
+fn copy_template(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target: PathBuf = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            fs::create_dir_all(&target)?;
+            copy_template(&entry.path(), &target)?;
+        } else if !target.exists() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
 
-    println!("Initialized empty Jit repository in {:?}\n", git_path);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    #[test]
+    fn create_a_bare_repository_with_an_initial_branch() {
+        let mut cmd_helper = CommandHelper::new();
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["init", "--bare", "-b", "main", "bare.git"])
+            .unwrap();
+
+        assert!(stdout.starts_with("Initialized empty Git repository in "));
+        assert!(stdout.ends_with("/bare.git/\n"), "{}", stdout);
+        assert_eq!(
+            "refs/heads/main\n",
+            cmd_helper.git_cmd(&["-C", "bare.git", "symbolic-ref", "HEAD"])
+        );
+        assert_eq!(
+            "true\n",
+            cmd_helper.git_cmd(&["-C", "bare.git", "config", "core.bare"])
+        );
+    }
+
+    #[test]
+    fn take_the_default_branch_from_config() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper
+            .jit_cmd(&["-c", "init.defaultBranch=trunk", "init"])
+            .unwrap();
+
+        assert_eq!(
+            "refs/heads/trunk\n",
+            cmd_helper.git_cmd(&["symbolic-ref", "HEAD"])
+        );
+        assert_eq!("false\n", cmd_helper.git_cmd(&["config", "core.bare"]));
+
+        let stderr = cmd_helper
+            .run_cmd(&["init", "-b", "bad..name", "other"])
+            .unwrap_err();
+        assert_output(&stderr, "fatal: invalid initial branch name: 'bad..name'\n");
+    }
+
+    #[test]
+    fn copy_templates_and_reinitialize_safely() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.mkdir("template/hooks").unwrap();
+        cmd_helper
+            .write_file("template/hooks/post-commit", b"#!/bin/sh\n")
+            .unwrap();
+        cmd_helper
+            .write_file("template/description", b"A test repository\n")
+            .unwrap();
+
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["init", "-q", "--template=template", "-b", "main"])
+            .unwrap();
+        assert_output(&stdout, "");
+        assert_eq!(
+            "#!/bin/sh\n",
+            cmd_helper.read_file(".git/hooks/post-commit")
+        );
+        assert_eq!(
+            "A test repository\n",
+            cmd_helper.read_file(".git/description")
+        );
+
+        cmd_helper
+            .write_file(".git/description", b"Changed\n")
+            .unwrap();
+        let (stdout, stderr) = cmd_helper
+            .run_cmd(&["init", "--template=template", "-b", "other"])
+            .unwrap();
+        assert!(
+            stdout.starts_with("Reinitialized existing Git repository in "),
+            "{}",
+            stdout
+        );
+        assert_output(
+            &stderr,
+            "warning: re-init: ignored --initial-branch=other\n",
+        );
+        assert_eq!(
+            "refs/heads/main\n",
+            cmd_helper.git_cmd(&["symbolic-ref", "HEAD"])
+        );
+        assert_eq!("Changed\n", cmd_helper.read_file(".git/description"));
+    }
+
+    #[test]
+    fn read_from_a_bare_repository() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+        cmd_helper.git_cmd(&["clone", "-q", "--bare", ".", "copy.git"]);
+
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["-C", "copy.git", "log", "--oneline"])
+            .unwrap();
+        assert!(stdout.ends_with(" first\n"), "{}", stdout);
+        let (stdout, _) = cmd_helper.jit_cmd(&["-C", "copy.git", "branch"]).unwrap();
+        assert_output(&stdout, "* master\n");
+
+        let stderr = cmd_helper
+            .jit_cmd(&["-C", "copy.git", "status"])
+            .unwrap_err();
+        assert_output(
+            &stderr,
+            "fatal: this operation must be run in a work tree\n",
+        );
+    }
+}
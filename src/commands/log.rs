@@ -1,7 +1,10 @@
 use crate::commands::CommandContext;
 use crate::database::commit::Commit;
-use crate::database::object::Object;
-use crate::database::{Database, ParsedObject};
+use crate::database::history::History;
+use crate::database::object::Storable;
+use crate::database::Database;
+use crate::errors::Error;
+use crate::gpg::Gpg;
 use crate::pager::Pager;
 use crate::refs::Ref;
 use crate::repository::Repository;
@@ -27,6 +30,7 @@ struct Options {
     abbrev: bool,
     format: FormatOption,
     decorate: DecorateOption,
+    show_signature: bool,
 }
 
 pub struct Log<'a, I, O, E>
@@ -35,9 +39,8 @@ where
     O: Write,
     E: Write,
 {
-    current_oid: Option<String>,
     repo: Repository,
-    ctx: CommandContext<'a, I, O, E>,
+    ctx: CommandContext<'a, I, Pager<O>, E>,
     options: Options,
     reverse_refs: Option<HashMap<String, Vec<Ref>>>,
     current_ref: Option<Ref>,
@@ -49,22 +52,18 @@ where
     O: Write,
     E: Write,
 {
-    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Log<'a, I, O, E> {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
-        let current_oid = repo.refs.read_head();
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Result<Log<'a, I, O, E>, Error> {
+        let repo = Repository::discover(&ctx.dir, ctx.env)?;
         let ctx_options = ctx.options.as_ref().unwrap().clone();
         let options = Self::define_options(ctx_options);
 
-        Log {
-            ctx,
+        Ok(Log {
+            ctx: ctx.with_pager(),
             repo,
-            current_oid,
             options,
             reverse_refs: None,
             current_ref: None,
-        }
+        })
     }
 
     fn define_options(options: clap::ArgMatches) -> Options {
@@ -92,7 +91,7 @@ where
 
         if options.is_present("oneline") {
             format = FormatOption::OneLine;
-            if abbrev == None {
+            if abbrev.is_none() {
                 abbrev = Some(true);
             }
         }
@@ -101,6 +100,7 @@ where
 
         if options.is_present("decorate") {
             decorate = match options.value_of("decorate").unwrap() {
+                "auto" => DecorateOption::Auto,
                 "full" => DecorateOption::Full,
                 "short" => DecorateOption::Short,
                 "no" => DecorateOption::No,
@@ -116,29 +116,36 @@ where
             abbrev: abbrev.unwrap_or(false),
             format,
             decorate,
+            show_signature: options.is_present("show-signature"),
         }
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
-        Pager::setup_pager();
+    pub fn run(&mut self) -> Result<(), Error> {
+        let head = self.repo.refs.read_head().ok_or_else(|| {
+            let current = self.repo.refs.current_ref("HEAD");
+            Error::fatal(format!(
+                "fatal: your current branch '{}' does not have any commits yet\n",
+                self.repo.refs.ref_short_name(&current)
+            ))
+        })?;
+
+        self.ctx.stdout.start(self.ctx.env);
 
-        self.reverse_refs = Some(self.repo.refs.reverse_refs());
+        let database = &self.repo.database;
+        self.reverse_refs = Some(self.repo.refs.reverse_refs(&|oid| database.peel_tag(oid)));
         self.current_ref = Some(self.repo.refs.current_ref("HEAD"));
 
         // FIXME: Print commits as they are returned by the iterator
         // instead of collecting into a Vec.
-        let mut commits = vec![];
-        for c in &mut self.into_iter() {
-            commits.push(c);
-        }
+        let commits = History::new(&self.repo.database, &[head])?.collect::<Result<Vec<_>, _>>()?;
 
-        commits
-            .iter()
-            .for_each(|commit| self.show_commit(commit).unwrap());
+        for commit in commits.iter() {
+            self.show_commit(commit)?;
+        }
         Ok(())
     }
 
-    fn show_commit(&self, commit: &Commit) -> Result<(), String> {
+    fn show_commit(&mut self, commit: &Commit) -> Result<(), Error> {
         match self.options.format {
             FormatOption::Medium => {
                 self.show_commit_medium(commit)?; // , abbrev, decorate, reverse_refs, current_ref)
@@ -160,33 +167,62 @@ where
         }
     }
 
-    fn show_commit_medium(&self, commit: &Commit) -> Result<(), String> {
-        let author = &commit.author;
-        println!();
-        println!(
-            "commit {} {}",
-            self.abbrev(commit).yellow(),
-            self.decorate(commit)
-        );
-        println!("Author: {} <{}>", author.name, author.email);
-        println!("Date: {}", author.readable_time());
-        println!();
-
-        for line in commit.message.lines() {
-            println!("    {}", line);
+    /// What the signing program says about the commit's signature,
+    /// coloured by whether it is good
+    fn signature_check(&self, commit: &Commit) -> Result<String, Error> {
+        if !self.options.show_signature {
+            return Ok(String::new());
         }
-        Ok(())
+        let signature = match commit.signature() {
+            Some(signature) => signature,
+            None => return Ok(String::new()),
+        };
+
+        let verification = Gpg::new(&self.repo.config, self.ctx.env)
+            .verify(&commit.signature_payload(), &signature)?;
+        let color = if verification.good { "green" } else { "red" };
+        Ok(verification
+            .output
+            .lines()
+            .map(|line| format!("{}\n", line.color(color)))
+            .collect())
     }
 
-    fn show_commit_oneline(&self, commit: &Commit) -> Result<(), String> {
-        println!(
-            "{} {} {}",
-            self.abbrev(commit).yellow(),
-            self.decorate(commit),
-            commit.title_line()
-        );
+    fn show_commit_medium(&mut self, commit: &Commit) -> Result<(), Error> {
+        let author = &commit.author;
+        let (abbrev, decoration) = (self.abbrev(commit), self.decorate(commit));
+        let signature_check = self.signature_check(commit)?;
+        let out = &mut self.ctx.stdout;
+
+        (|| -> std::io::Result<()> {
+            writeln!(out)?;
+            writeln!(out, "commit {} {}", abbrev.yellow(), decoration)?;
+            write!(out, "{}", signature_check)?;
+            writeln!(out, "Author: {} <{}>", author.name, author.email)?;
+            writeln!(out, "Date: {}", author.readable_time())?;
+            writeln!(out)?;
+
+            for line in commit.message().lines() {
+                writeln!(out, "    {}", line)?;
+            }
+            Ok(())
+        })()
+        .map_err(Error::Output)
+    }
 
-        Ok(())
+    fn show_commit_oneline(&mut self, commit: &Commit) -> Result<(), Error> {
+        let (abbrev, decoration) = (self.abbrev(commit), self.decorate(commit));
+        let signature_check = self.signature_check(commit)?;
+
+        writeln!(
+            self.ctx.stdout,
+            "{}{} {} {}",
+            signature_check,
+            abbrev.yellow(),
+            decoration,
+            commit.title_line()
+        )
+        .map_err(Error::Output)
     }
 
     fn decorate(&self, commit: &Commit) -> String {
@@ -197,12 +233,12 @@ where
 
         let refs = self.reverse_refs.as_ref().unwrap().get(&commit.get_oid());
         if let Some(refs) = refs {
-            let (head, refs): (Vec<&Ref>, Vec<&Ref>) = refs.into_iter().partition(|r#ref| {
+            let (head, refs): (Vec<&Ref>, Vec<&Ref>) = refs.iter().partition(|r#ref| {
                 r#ref.is_head() && !self.current_ref.as_ref().unwrap().is_head()
             });
             let names: Vec<_> = refs
                 .iter()
-                .map(|r#ref| self.decoration_name(head.get(0), r#ref))
+                .map(|r#ref| self.decoration_name(head.first(), r#ref))
                 .collect();
 
             format!(
@@ -223,7 +259,10 @@ where
             _ => unimplemented!(),
         };
 
-        name = name.bold().color(Self::ref_color(&r#ref)).to_string();
+        if r#ref.is_tag() {
+            name = format!("tag: {}", name);
+        }
+        name = name.bold().color(Self::ref_color(r#ref)).to_string();
 
         if let Some(head) = head {
             if r#ref == self.current_ref.as_ref().unwrap() {
@@ -239,30 +278,128 @@ where
     fn ref_color(r#ref: &Ref) -> &str {
         if r#ref.is_head() {
             "cyan"
+        } else if r#ref.is_tag() {
+            "yellow"
         } else {
             "green"
         }
     }
 }
 
-impl<'a, I, O, E> Iterator for Log<'a, I, O, E>
-where
-    I: Read,
-    O: Write,
-    E: Write,
-{
-    type Item = Commit;
-
-    fn next(&mut self) -> Option<Commit> {
-        if let Some(current_oid) = &self.current_oid {
-            if let ParsedObject::Commit(commit) = self.repo.database.load(&current_oid) {
-                self.current_oid = commit.parent.clone();
-                Some(commit.clone())
-            } else {
-                None
-            }
-        } else {
-            None
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    fn commit_file(cmd_helper: &mut CommandHelper, message: &str) {
+        cmd_helper
+            .write_file(&format!("{}.txt", message), message.as_bytes())
+            .unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit(message);
+    }
+
+    // Builds A - B - M
+    //         \     /
+    //          C - D
+    fn setup_merge(cmd_helper: &mut CommandHelper) {
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        commit_file(cmd_helper, "A");
+        cmd_helper.run_cmd(&["branch", "topic"]).unwrap();
+        commit_file(cmd_helper, "B");
+        cmd_helper.run_cmd(&["checkout", "topic"]).unwrap();
+        commit_file(cmd_helper, "C");
+        commit_file(cmd_helper, "D");
+        cmd_helper.run_cmd(&["checkout", "master"]).unwrap();
+        cmd_helper.git_cmd(&[
+            "-c",
+            "user.name=A. U. Thor",
+            "-c",
+            "user.email=author@example.com",
+            "merge",
+            "--no-ff",
+            "-m",
+            "M",
+            "topic",
+        ]);
+    }
+
+    #[test]
+    fn list_all_ancestors_of_a_merge_once() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_merge(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["log", "--oneline", "--no-decorate"])
+            .unwrap();
+        let mut titles: Vec<&str> = stdout
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap())
+            .collect();
+
+        assert_eq!("M", titles[0]);
+        titles.sort_unstable();
+        assert_eq!(vec!["A", "B", "C", "D", "M"], titles);
+    }
+
+    #[test]
+    fn resolve_numbered_parents() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_merge(&mut cmd_helper);
+
+        for (rev, title) in &[
+            ("HEAD^0", "M"),
+            ("HEAD^", "B"),
+            ("HEAD^2", "D"),
+            ("HEAD^2~1", "C"),
+        ] {
+            let name = format!("at-{}", title);
+            cmd_helper.run_cmd(&["branch", &name, rev]).unwrap();
+            assert_eq!(
+                cmd_helper.git_cmd(&["rev-parse", rev]),
+                cmd_helper.git_cmd(&["rev-parse", &name])
+            );
+        }
+    }
+
+    #[test]
+    fn send_output_through_the_pager() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_merge(&mut cmd_helper);
+        let expected = cmd_helper.run_cmd(&["log", "--oneline"]).unwrap();
+
+        cmd_helper.set_pager(PAGER);
+        assert_eq!(
+            (paged(&expected.0), String::new()),
+            cmd_helper.run_cmd(&["log", "--oneline"]).unwrap()
+        );
+
+        cmd_helper.set_env("GIT_PAGER", "cat");
+        assert_eq!(expected, cmd_helper.run_cmd(&["log", "--oneline"]).unwrap());
+    }
+
+    #[test]
+    fn report_a_branch_with_no_commits() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper
+            .run_cmd(&["init", "--bare", "-b", "main", "bare.git"])
+            .unwrap();
+
+        for (args, branch) in &[
+            (vec!["log"], "master"),
+            (vec!["-C", "bare.git", "log", "--oneline"], "main"),
+        ] {
+            assert_eq!(
+                (
+                    128,
+                    String::new(),
+                    format!(
+                        "fatal: your current branch '{}' does not have any commits yet\n",
+                        branch
+                    )
+                ),
+                cmd_helper.jit_status(args)
+            );
         }
     }
 }
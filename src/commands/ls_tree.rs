@@ -0,0 +1,145 @@
+use std::io::{Read, Write};
+
+use crate::commands::CommandContext;
+use crate::database::tree::TreeEntry;
+use crate::database::ParsedObject;
+use crate::errors::Error;
+use crate::repository::Repository;
+use crate::revision::Revision;
+
+pub fn ls_tree_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let mut repo = Repository::discover(&ctx.dir, ctx.env)?;
+    let options = ctx.options.as_ref().unwrap().clone();
+    let name = match options.value_of("tree-ish") {
+        Some(name) => name,
+        None => {
+            return Err(Error::usage(
+                "usage: rug ls-tree [-r] [-t] [--name-only] <tree-ish>\n",
+            ))
+        }
+    };
+
+    let oid = Revision::resolve_object(&mut repo, name)
+        .ok_or_else(|| Error::fatal(format!("fatal: Not a valid object name {}\n", name)))?;
+    let tree_oid = repo
+        .database
+        .peel_tree(&oid)
+        .ok_or_else(|| Error::fatal("fatal: not a tree object\n"))?;
+
+    let mut lister = Lister {
+        repo: &mut repo,
+        recursive: options.is_present("recursive"),
+        show_trees: options.is_present("show-trees"),
+        name_only: options.is_present("name-only"),
+        lines: vec![],
+    };
+    lister.list(&tree_oid, "")?;
+
+    for line in lister.lines {
+        writeln!(ctx.stdout, "{}", line).map_err(Error::Output)?;
+    }
+
+    Ok(())
+}
+
+/// An entry as `ls-tree` and `cat-file -p` show it
+pub fn tree_line(entry: &TreeEntry, path: &str) -> String {
+    format!(
+        "{:06o} {} {}\t{}",
+        entry.mode(),
+        entry.obj_type(),
+        entry.get_oid(),
+        path
+    )
+}
+
+struct Lister<'a> {
+    repo: &'a mut Repository,
+    recursive: bool,
+    show_trees: bool,
+    name_only: bool,
+    lines: Vec<String>,
+}
+
+impl<'a> Lister<'a> {
+    fn list(&mut self, oid: &str, prefix: &str) -> Result<(), Error> {
+        let entries = match self.repo.database.load(oid)? {
+            ParsedObject::Tree(tree) => tree.entries.clone(),
+            _ => return Ok(()),
+        };
+
+        for (name, entry) in entries {
+            let path = format!("{}{}", prefix, name);
+            let descend = self.recursive && entry.obj_type() == "tree";
+
+            // Recursive listings leave out the trees themselves unless
+            // asked for them
+            if !descend || self.show_trees {
+                self.lines.push(if self.name_only {
+                    path.clone()
+                } else {
+                    tree_line(&entry, &path)
+                });
+            }
+            if descend {
+                self.list(&entry.get_oid(), &format!("{}/", path))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("a.txt", b"a").unwrap();
+        cmd_helper.write_file("dir/b.txt", b"b").unwrap();
+        cmd_helper.write_file("dir/sub/c.txt", b"c").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        cmd_helper
+    }
+
+    #[test]
+    fn list_the_top_level_of_a_tree() {
+        let mut cmd_helper = setup();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["ls-tree", "HEAD"]).unwrap();
+        assert_output(&stdout, &cmd_helper.git_cmd(&["ls-tree", "HEAD"]));
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["ls-tree", "--name-only", "HEAD:dir"])
+            .unwrap();
+        assert_output(&stdout, "b.txt\nsub\n");
+    }
+
+    #[test]
+    fn list_a_tree_recursively() {
+        let mut cmd_helper = setup();
+
+        for args in &[
+            vec!["ls-tree", "-r", "HEAD"],
+            vec!["ls-tree", "-r", "-t", "HEAD"],
+        ] {
+            let (stdout, _) = cmd_helper.run_cmd(args).unwrap();
+            assert_output(&stdout, &cmd_helper.git_cmd(args));
+        }
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["ls-tree", "-r", "--name-only", "HEAD"])
+            .unwrap();
+        assert_output(&stdout, "a.txt\ndir/b.txt\ndir/sub/c.txt\n");
+
+        let stderr = cmd_helper.run_cmd(&["ls-tree", "HEAD:a.txt"]).unwrap_err();
+        assert_output(&stderr, "fatal: not a tree object\n");
+    }
+}
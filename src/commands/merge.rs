@@ -0,0 +1,439 @@
+use crate::commands::commit::{
+    cleanup_message, resume_merge, signing_key, write_commit, Cleanup, CONFLICT_MESSAGE,
+};
+use crate::commands::CommandContext;
+use crate::database::Database;
+use crate::errors::Error;
+use crate::merge::inputs::Inputs;
+use crate::merge::resolve::Resolve;
+use crate::repository::Repository;
+use std::io::{Read, Write};
+
+pub struct Merge<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    repo: Repository,
+    ctx: CommandContext<'a, I, O, E>,
+}
+
+impl<'a, I, O, E> Merge<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Result<Merge<'a, I, O, E>, Error> {
+        let repo = Repository::discover(&ctx.dir, ctx.env)?;
+        repo.require_work_tree()?;
+
+        Ok(Merge { repo, ctx })
+    }
+
+    pub fn run(&mut self) -> Result<(), Error> {
+        let options = self.ctx.options.as_ref().unwrap().clone();
+
+        if options.is_present("continue") {
+            return self.handle_continue();
+        }
+
+        if self.repo.pending_commit().in_progress() {
+            return Err(Error::fatal(format!(
+                "error: Merging is not possible because you have unmerged files.\n{}",
+                CONFLICT_MESSAGE
+            )));
+        }
+
+        let target = options.value_of("args").ok_or_else(|| {
+            Error::usage("usage: rug merge [<options>] [<commit>]\n   or: rug merge --continue\n")
+        })?;
+        let inputs = Inputs::new(&mut self.repo, "HEAD", target).map_err(|error| match error {
+            Error::InvalidRevision { errors } => Error::failure(
+                errors
+                    .iter()
+                    .map(|error| format!("merge: {}\n", error.message))
+                    .collect::<String>(),
+            ),
+            error => error,
+        })?;
+
+        if inputs.base_oids.is_empty() {
+            return Err(Error::fatal(
+                "fatal: refusing to merge unrelated histories\n",
+            ));
+        }
+
+        if inputs.is_already_merged() {
+            writeln!(self.ctx.stdout, "Already up to date.").map_err(Error::Output)?;
+            return Ok(());
+        }
+
+        if inputs.is_fast_forward() && !options.is_present("no-ff") {
+            return self.handle_fast_forward(&inputs);
+        }
+
+        if options.is_present("ff-only") {
+            return Err(Error::fatal(
+                "fatal: Not possible to fast-forward, aborting.\n",
+            ));
+        }
+
+        let message = match options.value_of("message") {
+            Some(message) => format!("{}\n", message),
+            None => Self::default_message(&mut self.repo, target),
+        };
+        self.repo
+            .pending_commit()
+            .start(&inputs.right_oid, &message)?;
+
+        self.resolve_merge(&inputs)?;
+        self.commit_merge(&inputs, message)
+    }
+
+    fn default_message(repo: &mut Repository, target: &str) -> String {
+        if repo
+            .refs
+            .read_ref(&format!("refs/heads/{}", target))
+            .is_some()
+        {
+            format!("Merge branch '{}'\n", target)
+        } else {
+            format!("Merge commit '{}'\n", target)
+        }
+    }
+
+    fn handle_fast_forward(&mut self, inputs: &Inputs) -> Result<(), Error> {
+        let a = Database::short_oid(&inputs.left_oid).to_string();
+        let b = Database::short_oid(&inputs.right_oid).to_string();
+
+        writeln!(self.ctx.stdout, "Updating {}..{}", a, b).map_err(Error::Output)?;
+        writeln!(self.ctx.stdout, "Fast-forward").map_err(Error::Output)?;
+
+        self.repo.index.load_for_update()?;
+
+        let tree_diff = self
+            .repo
+            .database
+            .tree_diff(Some(&inputs.left_oid), Some(&inputs.right_oid))?;
+        self.repo.migration(tree_diff).apply_changes()?;
+
+        self.repo.index.write_updates()?;
+        self.repo.refs.update_head(
+            &inputs.right_oid,
+            &format!("merge {}: Fast-forward", inputs.right_name),
+        )?;
+
+        Ok(())
+    }
+
+    fn resolve_merge(&mut self, inputs: &Inputs) -> Result<(), Error> {
+        self.repo.index.load_for_update()?;
+
+        let mut merge = Resolve::new(&mut self.repo, inputs);
+        let result = merge.execute();
+        for message in &merge.messages {
+            writeln!(self.ctx.stdout, "{}", message).map_err(Error::Output)?;
+        }
+        if let Err(error) = result {
+            self.repo.index.release_lock()?;
+            self.repo.pending_commit().clear()?;
+            return Err(error);
+        }
+
+        self.repo.index.write_updates()?;
+
+        if self.repo.index.has_conflict() {
+            return Err(Error::failure(
+                "Automatic merge failed; fix conflicts and then commit the result.\n",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn commit_merge(&mut self, inputs: &Inputs, message: String) -> Result<(), Error> {
+        let parents = vec![inputs.left_oid.clone(), inputs.right_oid.clone()];
+        let options = self.ctx.options.as_ref().unwrap().clone();
+        let sign_key = signing_key(&self.repo, self.ctx.env, &options)?;
+        write_commit(
+            &mut self.repo,
+            self.ctx.env,
+            parents,
+            message.into_bytes(),
+            sign_key.as_deref(),
+        )?;
+        self.repo.pending_commit().clear()?;
+
+        writeln!(self.ctx.stdout, "Merge made by the 'resolve' strategy.")
+            .map_err(Error::Output)?;
+
+        Ok(())
+    }
+
+    fn handle_continue(&mut self) -> Result<(), Error> {
+        self.repo.index.load()?;
+
+        let pending_commit = self.repo.pending_commit();
+        pending_commit.merge_oid()?;
+        let message = pending_commit.merge_message()?;
+        let message = cleanup_message(&message, Cleanup::Strip);
+
+        let options = self.ctx.options.as_ref().unwrap().clone();
+        let sign_key = signing_key(&self.repo, self.ctx.env, &options)?;
+        resume_merge(
+            &mut self.repo,
+            self.ctx.env,
+            &mut self.ctx.stdout,
+            message.into_bytes(),
+            sign_key.as_deref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use std::collections::HashMap;
+
+    fn commit_tree(cmd_helper: &mut CommandHelper, message: &str, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            cmd_helper.write_file(path, contents.as_bytes()).unwrap();
+        }
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit(message);
+    }
+
+    // Commits `base` on master, then `left` on master and `right` on
+    // a topic branch, leaving master checked out
+    fn setup_branches(
+        cmd_helper: &mut CommandHelper,
+        base: &[(&str, &str)],
+        left: &[(&str, &str)],
+        right: &[(&str, &str)],
+    ) {
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        commit_tree(cmd_helper, "base", base);
+        cmd_helper.run_cmd(&["branch", "topic"]).unwrap();
+        if !left.is_empty() {
+            commit_tree(cmd_helper, "left", left);
+        }
+        cmd_helper.run_cmd(&["checkout", "topic"]).unwrap();
+        commit_tree(cmd_helper, "right", right);
+        cmd_helper.run_cmd(&["checkout", "master"]).unwrap();
+    }
+
+    fn parents(cmd_helper: &CommandHelper, rev: &str) -> Vec<String> {
+        let line = cmd_helper.git_cmd(&["rev-list", "--parents", "-n", "1", rev]);
+        line.split_whitespace().skip(1).map(String::from).collect()
+    }
+
+    fn rev_parse(cmd_helper: &CommandHelper, rev: &str) -> String {
+        cmd_helper.git_cmd(&["rev-parse", rev]).trim().to_string()
+    }
+
+    #[test]
+    fn fast_forward_when_head_is_an_ancestor() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_branches(&mut cmd_helper, &[("f.txt", "1")], &[], &[("g.txt", "2")]);
+
+        let (stdout, _) = cmd_helper.run_cmd(&["merge", "topic"]).unwrap();
+
+        assert!(stdout.ends_with("Fast-forward\n"));
+        assert_eq!(
+            rev_parse(&cmd_helper, "topic"),
+            rev_parse(&cmd_helper, "HEAD")
+        );
+        let mut workspace = HashMap::new();
+        workspace.insert("f.txt", "1");
+        workspace.insert("g.txt", "2");
+        cmd_helper.assert_workspace(workspace);
+        cmd_helper.assert_status("");
+    }
+
+    #[test]
+    fn create_merge_commit_with_no_ff() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_branches(&mut cmd_helper, &[("f.txt", "1")], &[], &[("g.txt", "2")]);
+        let base = rev_parse(&cmd_helper, "master");
+
+        cmd_helper.run_cmd(&["merge", "--no-ff", "topic"]).unwrap();
+
+        assert_eq!(
+            vec![base, rev_parse(&cmd_helper, "topic")],
+            parents(&cmd_helper, "HEAD")
+        );
+    }
+
+    #[test]
+    fn report_already_merged_commits() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_branches(&mut cmd_helper, &[("f.txt", "1")], &[], &[("g.txt", "2")]);
+        cmd_helper.run_cmd(&["checkout", "topic"]).unwrap();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["merge", "master"]).unwrap();
+
+        assert_output(&stdout, "Already up to date.\n");
+    }
+
+    #[test]
+    fn merge_changes_to_different_files() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_branches(
+            &mut cmd_helper,
+            &[("f.txt", "1"), ("g.txt", "1")],
+            &[("f.txt", "left")],
+            &[("g.txt", "right"), ("h/i.txt", "new")],
+        );
+        let left = rev_parse(&cmd_helper, "master");
+
+        let (stdout, _) = cmd_helper.run_cmd(&["merge", "topic"]).unwrap();
+
+        assert_output(&stdout, "Merge made by the 'resolve' strategy.\n");
+        assert_eq!(
+            vec![left, rev_parse(&cmd_helper, "topic")],
+            parents(&cmd_helper, "HEAD")
+        );
+        assert_eq!(
+            "Merge branch 'topic'",
+            cmd_helper.git_cmd(&["log", "-1", "--format=%s"]).trim()
+        );
+        let mut workspace = HashMap::new();
+        workspace.insert("f.txt", "left");
+        workspace.insert("g.txt", "right");
+        workspace.insert("h/i.txt", "new");
+        cmd_helper.assert_workspace(workspace);
+        cmd_helper.assert_status("");
+        cmd_helper.git_cmd(&["fsck", "--strict"]);
+    }
+
+    #[test]
+    fn merge_changes_to_different_lines_of_a_file() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_branches(
+            &mut cmd_helper,
+            &[("f.txt", "1\n2\n3\n4\n")],
+            &[("f.txt", "one\n2\n3\n4\n")],
+            &[("f.txt", "1\n2\n3\nfour\n")],
+        );
+
+        let (stdout, _) = cmd_helper.run_cmd(&["merge", "topic"]).unwrap();
+
+        assert_output(
+            &stdout,
+            "Auto-merging f.txt\nMerge made by the 'resolve' strategy.\n",
+        );
+        let mut workspace = HashMap::new();
+        workspace.insert("f.txt", "one\n2\n3\nfour\n");
+        cmd_helper.assert_workspace(workspace);
+    }
+
+    #[test]
+    fn refuse_non_fast_forward_with_ff_only() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_branches(
+            &mut cmd_helper,
+            &[("f.txt", "1")],
+            &[("f.txt", "left")],
+            &[("g.txt", "right")],
+        );
+
+        let stderr = cmd_helper
+            .run_cmd(&["merge", "--ff-only", "topic"])
+            .unwrap_err();
+
+        assert_output(&stderr, "fatal: Not possible to fast-forward, aborting.\n");
+    }
+
+    #[test]
+    fn record_content_conflicts_and_continue() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_branches(
+            &mut cmd_helper,
+            &[("f.txt", "1\n2\n3\n")],
+            &[("f.txt", "1\nleft\n3\n")],
+            &[("f.txt", "1\nright\n3\n")],
+        );
+        let left = rev_parse(&cmd_helper, "master");
+
+        let stderr = cmd_helper.run_cmd(&["merge", "topic"]).unwrap_err();
+        assert_output(
+            &stderr,
+            "Automatic merge failed; fix conflicts and then commit the result.\n",
+        );
+
+        let mut workspace = HashMap::new();
+        workspace.insert(
+            "f.txt",
+            "1\n<<<<<<< HEAD\nleft\n=======\nright\n>>>>>>> topic\n3\n",
+        );
+        cmd_helper.assert_workspace(workspace);
+
+        let stages: Vec<String> = cmd_helper
+            .git_cmd(&["ls-files", "--stage"])
+            .lines()
+            .map(|line| line.split_whitespace().nth(2).unwrap().to_string())
+            .collect();
+        assert_eq!(vec!["1", "2", "3"], stages);
+
+        assert!(cmd_helper.run_cmd(&["merge", "--continue"]).is_err());
+        assert!(cmd_helper.run_cmd(&["merge", "topic"]).is_err());
+
+        cmd_helper.write_file("f.txt", b"1\nboth\n3\n").unwrap();
+        cmd_helper.run_cmd(&["add", "f.txt"]).unwrap();
+        cmd_helper.run_cmd(&["merge", "--continue"]).unwrap();
+
+        assert_eq!(
+            vec![left, rev_parse(&cmd_helper, "topic")],
+            parents(&cmd_helper, "HEAD")
+        );
+        assert!(!cmd_helper.repo().git_path.join("MERGE_HEAD").exists());
+        let stderr = cmd_helper.run_cmd(&["merge", "--continue"]).unwrap_err();
+        assert_output(
+            &stderr,
+            "fatal: There is no merge in progress (MERGE_HEAD missing).\n",
+        );
+    }
+
+    #[test]
+    fn record_modify_delete_conflicts_and_commit() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_branches(
+            &mut cmd_helper,
+            &[("f.txt", "1"), ("g.txt", "1")],
+            &[("f.txt", "left")],
+            &[("g.txt", "2")],
+        );
+        cmd_helper.run_cmd(&["checkout", "topic"]).unwrap();
+        cmd_helper.delete("f.txt").unwrap();
+        cmd_helper.delete(".git/index").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("delete f.txt");
+        cmd_helper.run_cmd(&["checkout", "master"]).unwrap();
+
+        assert!(cmd_helper.run_cmd(&["merge", "topic"]).is_err());
+
+        let stages = cmd_helper.git_cmd(&["ls-files", "--stage", "f.txt"]);
+        assert_eq!(2, stages.lines().count());
+        let mut workspace = HashMap::new();
+        workspace.insert("f.txt", "left");
+        workspace.insert("g.txt", "2");
+        cmd_helper.assert_workspace(workspace);
+
+        cmd_helper.run_cmd(&["add", "f.txt"]).unwrap();
+        cmd_helper.run_cmd(&["commit"]).unwrap();
+
+        assert_eq!(2, parents(&cmd_helper, "HEAD").len());
+    }
+
+    #[test]
+    fn require_a_commit_to_merge() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_branches(&mut cmd_helper, &[("f.txt", "1")], &[], &[("g.txt", "2")]);
+
+        let (status, stdout, stderr) = cmd_helper.jit_status(&["merge"]);
+        assert_eq!((129, ""), (status, stdout.as_str()));
+        assert!(stderr.starts_with("usage: rug merge "), "{}", stderr);
+    }
+}
@@ -1,8 +1,11 @@
-use clap::{App, Arg, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
 use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::path::PathBuf;
 
+use crate::errors::Error;
+use crate::pager::Pager;
+
 mod add;
 use add::add_command;
 mod init;
@@ -19,6 +22,32 @@ mod checkout;
 use checkout::Checkout;
 mod log;
 use log::Log;
+mod merge;
+use merge::Merge;
+mod repack;
+use repack::repack_command;
+mod gc;
+use gc::gc_command;
+mod config;
+use config::config_command;
+mod reflog;
+use reflog::reflog_command;
+mod pack_refs;
+use pack_refs::pack_refs_command;
+mod tag;
+use tag::tag_command;
+mod verify_commit;
+use verify_commit::verify_commit_command;
+mod cat_file;
+use cat_file::cat_file_command;
+mod hash_object;
+use hash_object::hash_object_command;
+mod ls_tree;
+use ls_tree::ls_tree_command;
+mod fsck;
+use fsck::fsck_command;
+mod prune;
+use prune::prune_command;
 
 #[derive(Debug)]
 pub struct CommandContext<'a, I, O, E>
@@ -33,13 +62,81 @@ where
     pub stdin: I,
     pub stdout: O,
     pub stderr: E,
+    // Whether stdout is a terminal, where long output is paged
+    pub terminal: Terminal,
+}
+
+/// What a command's stdout is connected to, which decides whether its
+/// output is paged and where the pager writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminal {
+    /// A file or pipe, which is never paged
+    None,
+    /// This process's own terminal, which the pager writes to directly
+    Process,
+    /// A stand-in for a terminal, such as a buffer in a test, which
+    /// gets whatever the pager writes
+    Captured,
+}
+
+impl<'a, I, O, E> CommandContext<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    /// The same context, with stdout wrapped in a pager that a command
+    /// starts once it knows it has output worth paging
+    pub fn with_pager(self) -> CommandContext<'a, I, Pager<O>, E> {
+        CommandContext {
+            dir: self.dir,
+            env: self.env,
+            options: self.options,
+            stdin: self.stdin,
+            stdout: Pager::new(self.stdout, self.terminal),
+            stderr: self.stderr,
+            terminal: self.terminal,
+        }
+    }
 }
 
 pub fn get_app() -> App<'static, 'static> {
     App::new("rug")
+        .arg(
+            Arg::with_name("chdir")
+                .short("C")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("path"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("name=value"),
+        )
         .subcommand(
             SubCommand::with_name("init")
                 .about("Create an empty Git repository or reinitialize an existing one")
+                .arg(Arg::with_name("bare").long("bare"))
+                .arg(Arg::with_name("quiet").short("q").long("quiet"))
+                .arg(
+                    Arg::with_name("initial-branch")
+                        .short("b")
+                        .long("initial-branch")
+                        .takes_value(true)
+                        .value_name("name"),
+                )
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .takes_value(true)
+                        .empty_values(true)
+                        .value_name("template-directory"),
+                )
                 .arg(Arg::with_name("args").multiple(true)),
         )
         .subcommand(
@@ -51,17 +148,55 @@ pub fn get_app() -> App<'static, 'static> {
         .subcommand(
             SubCommand::with_name("commit")
                 .about("Record changes to the repository")
+                .arg(
+                    Arg::with_name("message")
+                        .short("m")
+                        .long("message")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .short("F")
+                        .long("file")
+                        .takes_value(true),
+                )
+                .arg(Arg::with_name("all").short("a").long("all"))
+                .arg(Arg::with_name("amend").long("amend"))
+                .arg(Arg::with_name("allow-empty").long("allow-empty"))
+                .arg(
+                    Arg::with_name("gpg-sign")
+                        .short("S")
+                        .long("gpg-sign")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .empty_values(true)
+                        .value_name("key-id"),
+                )
+                .arg(Arg::with_name("no-gpg-sign").long("no-gpg-sign"))
+                .arg(Arg::with_name("edit").short("e").long("edit"))
+                .arg(Arg::with_name("no-edit").long("no-edit"))
+                .arg(
+                    Arg::with_name("cleanup")
+                        .long("cleanup")
+                        .takes_value(true)
+                        .value_name("mode"),
+                )
                 .arg(Arg::with_name("args").multiple(true)),
         )
         .subcommand(
             SubCommand::with_name("add")
                 .about("Add file contents to the index")
+                .arg(Arg::with_name("force").short("f").long("force"))
                 .arg(Arg::with_name("args").multiple(true)),
         )
         .subcommand(
             SubCommand::with_name("diff")
                 .about("Show changes between commits, commit and working tree, etc")
                 .arg(Arg::with_name("cached").long("cached"))
+                .arg(Arg::with_name("binary").long("binary"))
                 .arg(Arg::with_name("args").multiple(true)),
         )
         .subcommand(
@@ -96,6 +231,7 @@ pub fn get_app() -> App<'static, 'static> {
                         .value_name("format"),
                 )
                 .arg(Arg::with_name("oneline").long("oneline"))
+                .arg(Arg::with_name("show-signature").long("show-signature"))
                 .arg(
                     Arg::with_name("decorate")
                         .long("decorate")
@@ -105,12 +241,179 @@ pub fn get_app() -> App<'static, 'static> {
                 .arg(Arg::with_name("no-decorate").long("no-decorate"))
                 .arg(Arg::with_name("args").multiple(true)),
         )
+        .subcommand(
+            SubCommand::with_name("merge")
+                .about("Join two or more development histories together")
+                .arg(Arg::with_name("continue").long("continue"))
+                .arg(Arg::with_name("ff-only").long("ff-only"))
+                .arg(Arg::with_name("no-ff").long("no-ff"))
+                .arg(
+                    Arg::with_name("message")
+                        .short("m")
+                        .long("message")
+                        .takes_value(true)
+                        .value_name("msg"),
+                )
+                .arg(Arg::with_name("args")),
+        )
+        .subcommand(
+            SubCommand::with_name("repack")
+                .about("Pack unpacked objects in a repository")
+                .arg(Arg::with_name("delete").short("d").long("delete")),
+        )
+        .subcommand(
+            SubCommand::with_name("gc")
+                .about("Cleanup unnecessary files and optimize the local repository"),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Get and set repository or global options")
+                .arg(Arg::with_name("global").long("global"))
+                .arg(Arg::with_name("system").long("system"))
+                .arg(Arg::with_name("local").long("local"))
+                .arg(
+                    Arg::with_name("file")
+                        .short("f")
+                        .long("file")
+                        .takes_value(true)
+                        .value_name("file"),
+                )
+                .group(ArgGroup::with_name("scope").args(&["global", "system", "local", "file"]))
+                .arg(Arg::with_name("get").long("get"))
+                .arg(Arg::with_name("get-all").long("get-all"))
+                .arg(Arg::with_name("set").long("set"))
+                .arg(Arg::with_name("add").long("add"))
+                .arg(Arg::with_name("unset").long("unset"))
+                .arg(Arg::with_name("unset-all").long("unset-all"))
+                .arg(Arg::with_name("list").short("l").long("list"))
+                .group(ArgGroup::with_name("action").args(&[
+                    "get",
+                    "get-all",
+                    "set",
+                    "add",
+                    "unset",
+                    "unset-all",
+                    "list",
+                ]))
+                .arg(Arg::with_name("args").multiple(true).max_values(2)),
+        )
+        .subcommand(
+            SubCommand::with_name("reflog")
+                .about("Manage reflog information")
+                .arg(
+                    Arg::with_name("expire")
+                        .long("expire")
+                        .takes_value(true)
+                        .value_name("time"),
+                )
+                .arg(Arg::with_name("all").long("all"))
+                .arg(Arg::with_name("args").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("pack-refs")
+                .about("Pack heads and tags for efficient repository access")
+                .arg(Arg::with_name("all").long("all"))
+                .arg(Arg::with_name("no-prune").long("no-prune")),
+        )
+        .subcommand(
+            SubCommand::with_name("tag")
+                .about("Create, list, delete or verify a tag object")
+                .arg(Arg::with_name("list").short("l").long("list"))
+                .arg(Arg::with_name("delete").short("d").long("delete"))
+                .arg(Arg::with_name("annotate").short("a").long("annotate"))
+                .arg(
+                    Arg::with_name("message")
+                        .short("m")
+                        .long("message")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("msg"),
+                )
+                .arg(
+                    Arg::with_name("lines")
+                        .short("n")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .empty_values(true)
+                        .value_name("n"),
+                )
+                .arg(Arg::with_name("args").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-commit")
+                .about("Check the GPG signature of commits")
+                .arg(Arg::with_name("verbose").short("v").long("verbose"))
+                .arg(Arg::with_name("args").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("cat-file")
+                .about("Provide content or type and size information for repository objects")
+                .arg(Arg::with_name("type").short("t"))
+                .arg(Arg::with_name("size").short("s"))
+                .arg(Arg::with_name("exists").short("e"))
+                .arg(Arg::with_name("pretty").short("p"))
+                .arg(Arg::with_name("batch").long("batch"))
+                .arg(Arg::with_name("batch-check").long("batch-check"))
+                .group(
+                    ArgGroup::with_name("mode")
+                        .args(&["type", "size", "exists", "pretty", "batch", "batch-check"])
+                        .required(true),
+                )
+                .arg(Arg::with_name("object")),
+        )
+        .subcommand(
+            SubCommand::with_name("hash-object")
+                .about("Compute object ID and optionally create an object from a file")
+                .arg(Arg::with_name("write").short("w"))
+                .arg(Arg::with_name("stdin").long("stdin"))
+                .arg(Arg::with_name("literally").long("literally"))
+                .arg(
+                    Arg::with_name("type")
+                        .short("t")
+                        .takes_value(true)
+                        .value_name("type"),
+                )
+                .arg(Arg::with_name("args").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("ls-tree")
+                .about("List the contents of a tree object")
+                .arg(Arg::with_name("recursive").short("r"))
+                .arg(Arg::with_name("show-trees").short("t"))
+                .arg(Arg::with_name("name-only").long("name-only"))
+                .arg(Arg::with_name("tree-ish")),
+        )
+        .subcommand(
+            SubCommand::with_name("fsck")
+                .about("Verifies the connectivity and validity of the objects in the database")
+                .arg(Arg::with_name("unreachable").long("unreachable"))
+                .arg(Arg::with_name("dangling").long("dangling"))
+                .arg(
+                    Arg::with_name("no-dangling")
+                        .long("no-dangling")
+                        .overrides_with("dangling"),
+                )
+                .arg(Arg::with_name("full").long("full")),
+        )
+        .subcommand(
+            SubCommand::with_name("prune")
+                .about("Prune all unreachable objects from the object database")
+                .arg(Arg::with_name("dry-run").short("n").long("dry-run"))
+                .arg(
+                    Arg::with_name("expire")
+                        .long("expire")
+                        .takes_value(true)
+                        .value_name("time"),
+                ),
+        )
 }
 
 pub fn execute<'a, I, O, E>(
     matches: ArgMatches<'a>,
     mut ctx: CommandContext<'a, I, O, E>,
-) -> Result<(), String>
+) -> Result<(), Error>
 where
     I: Read,
     O: Write,
@@ -131,35 +434,88 @@ where
         }
         ("status", sub_matches) => {
             ctx.options = sub_matches.cloned();
-            let mut cmd = Status::new(ctx);
+            let mut cmd = Status::new(ctx)?;
             cmd.run()
         }
         ("diff", sub_matches) => {
             ctx.options = sub_matches.cloned();
-            let mut cmd = Diff::new(ctx);
+            let mut cmd = Diff::new(ctx)?;
             cmd.run()
         }
         ("branch", sub_matches) => {
             ctx.options = sub_matches.cloned();
-            let mut cmd = Branch::new(ctx);
+            let mut cmd = Branch::new(ctx)?;
             cmd.run()
         }
         ("checkout", sub_matches) => {
             ctx.options = sub_matches.cloned();
-            let mut cmd = Checkout::new(ctx);
+            let mut cmd = Checkout::new(ctx)?;
             cmd.run()
         }
         ("log", sub_matches) => {
             ctx.options = sub_matches.cloned();
-            let mut cmd = Log::new(ctx);
+            let mut cmd = Log::new(ctx)?;
             cmd.run()
         }
+        ("merge", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            let mut cmd = Merge::new(ctx)?;
+            cmd.run()
+        }
+        ("repack", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            repack_command(ctx)
+        }
+        ("gc", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            gc_command(ctx)
+        }
+        ("config", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            config_command(ctx)
+        }
+        ("reflog", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            reflog_command(ctx)
+        }
+        ("pack-refs", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            pack_refs_command(ctx)
+        }
+        ("tag", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            tag_command(ctx)
+        }
+        ("verify-commit", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            verify_commit_command(ctx)
+        }
+        ("cat-file", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            cat_file_command(ctx)
+        }
+        ("hash-object", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            hash_object_command(ctx)
+        }
+        ("ls-tree", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            ls_tree_command(ctx)
+        }
+        ("fsck", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            fsck_command(ctx)
+        }
+        ("prune", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            prune_command(ctx)
+        }
         _ => Ok(()),
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::repository::Repository;
     use crate::util::*;
@@ -167,11 +523,10 @@ mod tests {
     use filetime::FileTime;
     use std::env;
     use std::fs::{self, File, OpenOptions};
-    use std::io::Cursor;
-    use std::io::Write;
+    use std::io::{self, Write};
     use std::os::unix::fs::PermissionsExt;
     use std::path::Path;
-    use std::process::{Command, Stdio};
+    use std::process::{Command, Output, Stdio};
     use std::str;
     use std::time::{SystemTime, UNIX_EPOCH};
     extern crate assert_cmd;
@@ -185,38 +540,119 @@ mod tests {
             .join(temp_dir)
     }
 
-    pub fn repo(repo_path: &Path) -> Repository {
-        Repository::new(&repo_path)
-    }
-
     pub struct CommandHelper {
         repo_path: PathBuf,
+        home_path: PathBuf,
         stdin: String,
-        stdout: Cursor<Vec<u8>>,
         env: HashMap<String, String>,
+        terminal: Terminal,
     }
 
     impl CommandHelper {
         pub fn new() -> CommandHelper {
             let repo_path = gen_repo_path();
             fs::create_dir_all(&repo_path).unwrap();
+            // The user's own config and ignore files must not change
+            // what commands do, so both rug and git get an empty home
+            // directory of their own
+            let home_path = PathBuf::from(format!("{}_home", repo_path.display()));
+            fs::create_dir_all(&home_path).unwrap();
+            let home = |path: &str| home_path.join(path).to_str().unwrap().to_string();
+
+            let mut env = HashMap::new();
+            env.insert("HOME".to_string(), home(""));
+            env.insert("XDG_CONFIG_HOME".to_string(), home(".config"));
+            env.insert("GIT_CONFIG_GLOBAL".to_string(), home(".gitconfig"));
+            env.insert("GIT_CONFIG_NOSYSTEM".to_string(), "1".to_string());
+            // Commands that would open an editor must never wait on one
+            env.insert("GIT_EDITOR".to_string(), ":".to_string());
+            env.insert("GIT_COMMITTER_NAME".to_string(), "C. O. Mitter".to_string());
+            env.insert(
+                "GIT_COMMITTER_EMAIL".to_string(),
+                "committer@example.com".to_string(),
+            );
             CommandHelper {
                 repo_path,
+                home_path,
                 stdin: String::new(),
-                stdout: Cursor::new(vec![]),
-                env: HashMap::new(),
+                env,
+                terminal: Terminal::None,
             }
         }
 
-        fn set_env(&mut self, key: &str, value: &str) {
+        pub fn set_env(&mut self, key: &str, value: &str) {
             self.env.insert(key.to_string(), value.to_string());
         }
 
-        fn set_stdin(&mut self, s: &str) {
+        pub fn set_stdin(&mut self, s: &str) {
             self.stdin = s.to_string();
         }
 
+        /// Have `run_cmd` treat stdout as a terminal, so that commands
+        /// which page their output send it through `pager`
+        pub fn set_pager(&mut self, pager: &str) {
+            self.set_env("GIT_PAGER", pager);
+            self.terminal = Terminal::Captured;
+        }
+
+        /// Run the rug binary, which tests of the global `-C` and `-c`
+        /// options need since `run_cmd` cannot take them
         pub fn jit_cmd(&mut self, args: &[&str]) -> Result<(String, String), String> {
+            let output = self.jit_output(args);
+            let (stdout, stderr) = (
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            );
+
+            if output.status.success() {
+                Ok((stdout, stderr))
+            } else {
+                Err(stderr)
+            }
+        }
+
+        /// Run rug, returning its exit code along with its stdout and
+        /// stderr
+        pub fn jit_status(&mut self, args: &[&str]) -> (i32, String, String) {
+            let output = self.jit_output(args);
+            (
+                output.status.code().expect("killed by a signal"),
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            )
+        }
+
+        /// Run rug's command in this process instead, capturing what
+        /// it writes. The global options, which change the process's
+        /// directory and environment, cannot be used.
+        pub fn run_cmd(&mut self, args: &[&str]) -> Result<(String, String), String> {
+            let matches = get_app()
+                .get_matches_from_safe([env!("CARGO_PKG_NAME")].iter().chain(args))
+                .map_err(|e| e.message)?;
+            let (mut stdout, mut stderr) = (vec![], vec![]);
+            let ctx = CommandContext {
+                dir: self.repo_path.clone(),
+                env: &self.env,
+                options: None,
+                stdin: self.stdin.as_bytes(),
+                stdout: &mut stdout,
+                stderr: &mut stderr,
+                terminal: self.terminal,
+            };
+            let result = execute(matches, ctx);
+
+            let stdout = String::from_utf8_lossy(&stdout).to_string();
+            let mut stderr = String::from_utf8_lossy(&stderr).to_string();
+            match result {
+                Ok(()) => Ok((stdout, stderr)),
+                Err(error) => {
+                    stderr.push_str(&error.to_string());
+                    Err(stderr)
+                }
+            }
+        }
+
+        fn jit_output(&mut self, args: &[&str]) -> Output {
             let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))
                 .unwrap()
                 .args(args)
@@ -228,31 +664,47 @@ mod tests {
                 .spawn()
                 .expect("Failed to spawn child process");
 
-            cmd.stdin
-                .as_mut()
-                .unwrap()
-                .write_all(self.stdin.as_bytes())
-                .unwrap();
+            // Commands that don't read stdin may exit before we are
+            // done writing to it
+            match cmd.stdin.as_mut().unwrap().write_all(self.stdin.as_bytes()) {
+                Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+                result => result.unwrap(),
+            }
 
-            let output = cmd.wait_with_output().expect("failed to run executable");
+            cmd.wait_with_output().expect("failed to run executable")
+        }
 
-            let (stdout, stderr) = (
-                String::from_utf8_lossy(&output.stdout).to_string(),
-                String::from_utf8_lossy(&output.stderr).to_string(),
+        /// Run stock git in the test repo, for setting up states that
+        /// rug cannot produce itself
+        pub fn git_cmd(&self, args: &[&str]) -> String {
+            String::from_utf8_lossy(&self.git_output(args)).to_string()
+        }
+
+        pub fn git_output(&self, args: &[&str]) -> Vec<u8> {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(&self.repo_path)
+                .envs(&self.env)
+                .output()
+                .expect("failed to run git");
+            assert!(
+                output.status.success(),
+                "git {:?} failed: {}",
+                args,
+                String::from_utf8_lossy(&output.stderr)
             );
 
-            if output.status.success() {
-                Ok((stdout, stderr))
-            } else {
-                Err(stderr)
-            }
+            output.stdout
+        }
+
+        pub fn repo(&self) -> Repository {
+            Repository::at(&self.repo_path.join(".git"), &self.repo_path, &self.env).unwrap()
         }
 
         pub fn commit(&mut self, msg: &str) {
             self.set_env("GIT_AUTHOR_NAME", "A. U. Thor");
             self.set_env("GIT_AUTHOR_EMAIL", "author@example.com");
-            self.set_stdin(msg);
-            self.jit_cmd(&["commit"]).unwrap();
+            self.run_cmd(&["commit", "-m", msg]).unwrap();
         }
 
         pub fn write_file(&self, file_name: &str, contents: &[u8]) -> Result<(), std::io::Error> {
@@ -285,6 +737,17 @@ mod tests {
             filetime::set_file_times(path, now, now)
         }
 
+        /// Set a file's times to `seconds` ago
+        pub fn backdate(&self, file_name: &str, seconds: i64) -> Result<(), std::io::Error> {
+            let path = Path::new(&self.repo_path).join(file_name);
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time is broken")
+                .as_secs() as i64;
+            let then = FileTime::from_unix_time(now - seconds, 0);
+            filetime::set_file_times(path, then, then)
+        }
+
         pub fn delete(&self, pathname: &str) -> Result<(), std::io::Error> {
             let path = Path::new(&self.repo_path).join(pathname);
 
@@ -295,6 +758,14 @@ mod tests {
             }
         }
 
+        pub fn read_file(&self, file_name: &str) -> String {
+            fs::read_to_string(self.repo_path.join(file_name)).unwrap()
+        }
+
+        pub fn symlink(&self, target: &str, link_name: &str) -> Result<(), std::io::Error> {
+            std::os::unix::fs::symlink(target, self.repo_path.join(link_name))
+        }
+
         pub fn make_executable(&self, file_name: &str) -> Result<(), std::io::Error> {
             let path = self.repo_path.join(file_name);
             let file = File::open(&path)?;
@@ -318,14 +789,14 @@ mod tests {
         }
 
         pub fn assert_index(&self, expected: Vec<(u32, String)>) -> Result<(), std::io::Error> {
-            let mut repo = repo(&self.repo_path);
-            repo.index.load()?;
+            let mut repo = self.repo();
+            repo.index.load().unwrap();
 
             let actual: Vec<(u32, String)> = repo
                 .index
                 .entries
-                .iter()
-                .map(|(_, entry)| (entry.mode, entry.path.clone()))
+                .values()
+                .map(|entry| (entry.mode, entry.path.clone()))
                 .collect();
 
             assert_eq!(expected, actual);
@@ -333,26 +804,19 @@ mod tests {
             Ok(())
         }
 
-        pub fn clear_stdout(&mut self) {
-            self.stdout = Cursor::new(vec![]);
-        }
-
         pub fn assert_status(&mut self, expected: &str) {
-            if let Ok((stdout, _stderr)) = self.jit_cmd(&["status", "--porcelain"]) {
-                assert_output(&stdout, expected)
-            } else {
-                assert!(false);
+            match self.run_cmd(&["status", "--porcelain"]) {
+                Ok((stdout, _stderr)) => assert_output(&stdout, expected),
+                Err(stderr) => panic!("status failed: {}", stderr),
             }
         }
 
         pub fn assert_workspace(&self, expected_contents: HashMap<&str, &str>) {
             let mut files = HashMap::new();
-            for file in repo(&self.repo_path)
-                .workspace
-                .list_files(&self.repo_path)
-                .unwrap()
-            {
-                let file_contents = repo(&self.repo_path).workspace.read_file(&file).unwrap();
+            let repo = self.repo();
+            for file in repo.workspace.list_files(&self.repo_path).unwrap() {
+                let file_contents = repo.workspace.read_file(&file).unwrap();
+                let file_contents = String::from_utf8(file_contents).unwrap();
                 files.insert(file, file_contents);
             }
 
@@ -360,13 +824,14 @@ mod tests {
         }
 
         pub fn assert_noent(&self, filename: &str) {
-            assert_eq!(false, Path::new(filename).exists())
+            assert!(!Path::new(filename).exists())
         }
     }
 
     impl Drop for CommandHelper {
         fn drop(&mut self) {
-            fs::remove_dir_all(&self.repo_path);
+            let _ = fs::remove_dir_all(&self.repo_path);
+            let _ = fs::remove_dir_all(&self.home_path);
         }
     }
 
@@ -374,6 +839,53 @@ mod tests {
         assert_eq!(stream, expected);
     }
 
+    /// A pager for tests, which marks each line it passes through
+    pub const PAGER: &str = "sed s/^/|/";
+
+    /// `output` as PAGER writes it
+    pub fn paged(output: &str) -> String {
+        output.lines().map(|line| format!("|{}\n", line)).collect()
+    }
+
+    /// `n` numbered lines, each ending in `suffix`
+    pub fn lines(n: usize, suffix: &str) -> String {
+        (0..n).map(|i| format!("line {}{}\n", i, suffix)).collect()
+    }
+
+    /// Make three commits, each changing every line of a large file a
+    /// little so that packs have deltas to find, and adding a file
+    pub fn setup_history(cmd_helper: &mut CommandHelper) {
+        cmd_helper.run_cmd(&["init"]).unwrap();
+
+        for i in 0..3 {
+            let contents = lines(100 + i, &"!".repeat(i));
+            cmd_helper.write_file("a.txt", contents.as_bytes()).unwrap();
+            cmd_helper
+                .write_file(&format!("dir/{}.txt", i), b"hello")
+                .unwrap();
+            cmd_helper.run_cmd(&["add", "."]).unwrap();
+            cmd_helper.commit(&format!("commit {}", i));
+        }
+    }
+
+    /// A repository with a single commit, of `a.txt` and `dir/b.txt`
+    pub fn setup_first_commit() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("a.txt", b"one\n").unwrap();
+        cmd_helper.write_file("dir/b.txt", b"two\n").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        cmd_helper
+    }
+
+    /// Where the loose copy of object `oid` is kept, relative to the
+    /// work tree
+    pub fn object_file(oid: &str) -> String {
+        format!(".git/objects/{}/{}", &oid[..2], &oid[2..])
+    }
+
     fn assert_maps_equal(a: HashMap<&str, &str>, b: HashMap<String, String>) {
         assert_eq!(a.len(), b.len());
         for (k, v) in a {
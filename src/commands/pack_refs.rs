@@ -0,0 +1,125 @@
+use std::io::{Read, Write};
+
+use crate::commands::CommandContext;
+use crate::errors::Error;
+use crate::repository::Repository;
+
+pub fn pack_refs_command<I, O, E>(ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let repo = Repository::discover(&ctx.dir, ctx.env)?;
+    let options = ctx.options.as_ref().unwrap();
+
+    repo.refs.pack_refs(
+        options.is_present("all"),
+        !options.is_present("no-prune"),
+        &|oid| repo.database.peel_tag(oid),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use std::fs;
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+        cmd_helper.run_cmd(&["branch", "topic"]).unwrap();
+        cmd_helper.run_cmd(&["branch", "nested/topic"]).unwrap();
+
+        cmd_helper
+    }
+
+    fn tag(cmd_helper: &CommandHelper) {
+        cmd_helper.git_cmd(&[
+            "-c",
+            "user.name=A. U. Thor",
+            "-c",
+            "user.email=author@example.com",
+            "tag",
+            "-a",
+            "-m",
+            "release",
+            "v1.0",
+        ]);
+    }
+
+    #[test]
+    fn read_refs_packed_by_git() {
+        let mut cmd_helper = setup();
+        let head = cmd_helper.git_cmd(&["rev-parse", "HEAD"]);
+        tag(&cmd_helper);
+        cmd_helper.git_cmd(&["pack-refs", "--all"]);
+        assert!(!cmd_helper.repo().git_path.join("refs/heads/topic").exists());
+
+        let (stdout, _) = cmd_helper.run_cmd(&["branch"]).unwrap();
+        assert_output(&stdout, "* master\n  nested/topic\n  topic\n");
+
+        cmd_helper.run_cmd(&["checkout", "topic"]).unwrap();
+        assert_eq!(head, cmd_helper.git_cmd(&["rev-parse", "HEAD"]));
+
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["log", "--oneline", "--decorate=short"])
+            .unwrap();
+        assert!(stdout.contains("v1.0"), "{}", stdout);
+    }
+
+    #[test]
+    fn prefer_loose_refs_over_packed_ones() {
+        let mut cmd_helper = setup();
+        cmd_helper.git_cmd(&["pack-refs", "--all"]);
+        cmd_helper.write_file("file.txt", b"two").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("second");
+
+        let (stdout, _) = cmd_helper.run_cmd(&["log", "--oneline"]).unwrap();
+        assert_eq!(2, stdout.lines().count());
+    }
+
+    #[test]
+    fn delete_a_packed_branch() {
+        let mut cmd_helper = setup();
+        cmd_helper.git_cmd(&["pack-refs", "--all"]);
+
+        cmd_helper.run_cmd(&["branch", "-D", "topic"]).unwrap();
+
+        let packed = fs::read_to_string(cmd_helper.repo().git_path.join("packed-refs")).unwrap();
+        assert!(!packed.contains("refs/heads/topic\n"));
+        assert!(packed.contains("refs/heads/nested/topic\n"));
+        assert_eq!(
+            "refs/heads/master\nrefs/heads/nested/topic\n",
+            cmd_helper.git_cmd(&["for-each-ref", "--format=%(refname)"])
+        );
+    }
+
+    #[test]
+    fn pack_loose_refs() {
+        let mut cmd_helper = setup();
+        tag(&cmd_helper);
+        let refs = cmd_helper.git_cmd(&["show-ref", "-d"]);
+
+        cmd_helper.run_cmd(&["pack-refs"]).unwrap();
+        let git_path = cmd_helper.repo().git_path;
+        assert!(!git_path.join("refs/tags/v1.0").exists());
+        assert!(git_path.join("refs/heads/topic").exists());
+
+        cmd_helper.run_cmd(&["pack-refs", "--all"]).unwrap();
+        assert!(!git_path.join("refs/heads/topic").exists());
+        assert!(!git_path.join("refs/heads/nested").exists());
+        assert!(git_path.join("refs/heads").exists());
+
+        assert_eq!(refs, cmd_helper.git_cmd(&["show-ref", "-d"]));
+        let packed = fs::read_to_string(git_path.join("packed-refs")).unwrap();
+        assert_eq!(6, packed.lines().count());
+        assert!(packed.lines().nth(5).unwrap().starts_with('^'));
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::commands::CommandContext;
+use crate::database::reachable::reachable_objects;
+use crate::date::parse_approxidate;
+use crate::errors::Error;
+use crate::reflog::ZERO_OID;
+use crate::repository::Repository;
+
+// How long unreachable objects are kept when gc.pruneExpire is unset,
+// so as not to race with commands still writing them
+const DEFAULT_EXPIRE: &str = "2.weeks.ago";
+
+pub fn prune_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let mut repo = Repository::discover(&ctx.dir, ctx.env)?;
+    let options = ctx.options.as_ref().unwrap().clone();
+    let expire = options
+        .value_of("expire")
+        .map(|expire| expire.to_string())
+        .or_else(|| repo.config.get("gc.pruneExpire"))
+        .unwrap_or_else(|| DEFAULT_EXPIRE.to_string());
+
+    // Only files last modified before the cutoff are removed
+    let cutoff = match expire.as_str() {
+        "never" => return Ok(()),
+        "all" => None,
+        _ => Some(
+            parse_approxidate(&expire)
+                .ok_or_else(|| {
+                    Error::fatal(format!("fatal: malformed expiration date '{}'\n", expire))
+                })?
+                .timestamp(),
+        ),
+    };
+    let expired = |path: &Path| match (cutoff, fs::metadata(path)) {
+        (None, _) => true,
+        (Some(cutoff), Ok(stat)) => stat.mtime() <= cutoff,
+        (Some(_), Err(_)) => false,
+    };
+    let dry_run = options.is_present("dry-run");
+
+    let roots = roots(&mut repo)?;
+    let reachable: HashSet<String> = reachable_objects(&repo.database, &roots)?
+        .into_iter()
+        .map(|object| object.oid)
+        .collect();
+
+    let mut loose = repo.database.loose_oids();
+    loose.sort();
+    for oid in loose {
+        if reachable.contains(&oid) || !expired(&repo.database.object_path(&oid)) {
+            continue;
+        }
+        if dry_run {
+            let obj_type = repo
+                .database
+                .read_loose_checked(&oid)
+                .map(|raw| raw.obj_type)
+                .unwrap_or_else(|_| "unknown".to_string());
+            writeln!(ctx.stdout, "{} {}", oid, obj_type).map_err(Error::Output)?;
+        } else {
+            repo.database
+                .remove_loose(&oid)
+                .map_err(|e| Error::failure(format!("error: unable to remove {}: {}\n", oid, e)))?;
+        }
+    }
+
+    for path in repo.database.temp_files() {
+        if !expired(&path) {
+            continue;
+        }
+        if dry_run {
+            writeln!(
+                ctx.stdout,
+                "Removing stale temporary file {}",
+                path.display()
+            )
+            .map_err(Error::Output)?;
+        } else {
+            repo.database.remove_file(&path).map_err(|e| {
+                Error::failure(format!(
+                    "error: unable to remove {}: {}\n",
+                    path.display(),
+                    e
+                ))
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Everything that keeps objects alive: refs and HEAD, the index,
+/// and every value recorded in a reflog that is still around
+fn roots(repo: &mut Repository) -> Result<Vec<String>, Error> {
+    let mut roots: Vec<String> = repo
+        .refs
+        .list_all_refs()
+        .iter()
+        .filter_map(|r#ref| repo.refs.read_oid(r#ref))
+        .collect();
+
+    for name in repo.refs.list_reflogs() {
+        for entry in repo.refs.reflog(&name).read() {
+            for oid in [entry.old_oid, entry.new_oid] {
+                if oid != ZERO_OID && repo.database.has(&oid) {
+                    roots.push(oid);
+                }
+            }
+        }
+    }
+
+    repo.index.load()?;
+    roots.extend(
+        repo.index
+            .entries
+            .values()
+            .filter(|entry| !entry.is_gitlink())
+            .map(|entry| entry.oid.clone()),
+    );
+
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn hash(cmd_helper: &mut CommandHelper, contents: &str) -> String {
+        cmd_helper.set_stdin(contents);
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["hash-object", "-w", "--stdin"])
+            .unwrap();
+        stdout.trim().to_string()
+    }
+
+    #[test]
+    fn remove_unreachable_objects() {
+        let mut cmd_helper = setup_first_commit();
+        let unreachable = hash(&mut cmd_helper, "lost\n");
+        // Staged but not committed, so kept alive by the index
+        cmd_helper.write_file("b.txt", b"staged\n").unwrap();
+        cmd_helper.run_cmd(&["add", "b.txt"]).unwrap();
+
+        // Too recent for the default grace period
+        cmd_helper.run_cmd(&["prune"]).unwrap();
+        assert!(cmd_helper
+            .run_cmd(&["cat-file", "-e", &unreachable])
+            .is_ok());
+
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["prune", "-n", "--expire=now"])
+            .unwrap();
+        assert_output(&stdout, &format!("{} blob\n", unreachable));
+        assert!(cmd_helper
+            .run_cmd(&["cat-file", "-e", &unreachable])
+            .is_ok());
+
+        cmd_helper.run_cmd(&["prune", "--expire=now"]).unwrap();
+        assert!(cmd_helper
+            .run_cmd(&["cat-file", "-e", &unreachable])
+            .is_err());
+        let staged = cmd_helper.git_cmd(&["rev-parse", ":b.txt"]);
+        assert!(cmd_helper
+            .run_cmd(&["cat-file", "-e", staged.trim()])
+            .is_ok());
+        cmd_helper.git_cmd(&["fsck", "--no-dangling"]);
+        cmd_helper.assert_status("A  b.txt\n");
+    }
+
+    #[test]
+    fn expire_old_objects_and_temporary_files() {
+        let mut cmd_helper = setup_first_commit();
+        let old = hash(&mut cmd_helper, "old\n");
+        let recent = hash(&mut cmd_helper, "recent\n");
+        cmd_helper.backdate(&object_file(&old), 3 * DAY).unwrap();
+        cmd_helper
+            .write_file(".git/objects/aa/tmp_obj_old", b"partial")
+            .unwrap();
+        cmd_helper
+            .backdate(".git/objects/aa/tmp_obj_old", 3 * DAY)
+            .unwrap();
+        cmd_helper
+            .write_file(".git/objects/aa/tmp_obj_new", b"partial")
+            .unwrap();
+
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["prune", "-n", "--expire=2.days.ago"])
+            .unwrap();
+        assert!(stdout.starts_with(&format!("{} blob\n", old)), "{}", stdout);
+        assert!(stdout.ends_with("/aa/tmp_obj_old\n"), "{}", stdout);
+
+        cmd_helper
+            .run_cmd(&["prune", "--expire=2.days.ago"])
+            .unwrap();
+        assert!(cmd_helper.run_cmd(&["cat-file", "-e", &old]).is_err());
+        assert!(cmd_helper.run_cmd(&["cat-file", "-e", &recent]).is_ok());
+        assert_eq!(
+            "partial",
+            cmd_helper.read_file(".git/objects/aa/tmp_obj_new")
+        );
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["prune", "-n", "--expire=2.days.ago"])
+            .unwrap();
+        assert_output(&stdout, "");
+
+        let stderr = cmd_helper
+            .run_cmd(&["prune", "--expire=someday"])
+            .unwrap_err();
+        assert_output(&stderr, "fatal: malformed expiration date 'someday'\n");
+    }
+}
@@ -0,0 +1,298 @@
+use regex::Regex;
+use std::io::{Read, Write};
+
+use crate::commands::CommandContext;
+use crate::database::Database;
+use crate::date::parse_approxidate;
+use crate::errors::Error;
+use crate::repository::Repository;
+
+const DEFAULT_EXPIRE: &str = "90.days.ago";
+
+lazy_static! {
+    static ref SELECTOR: Regex = Regex::new(r"^(.+)@\{(\d+)\}$").unwrap();
+}
+
+pub fn reflog_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let repo = Repository::discover(&ctx.dir, ctx.env)?;
+    let options = ctx.options.as_ref().unwrap();
+    let mut args: Vec<&str> = match options.values_of("args") {
+        Some(args) => args.collect(),
+        None => vec![],
+    };
+
+    // Anything other than a subcommand is a ref to show
+    let action = match args.first() {
+        Some(&"show") | Some(&"expire") | Some(&"delete") => args.remove(0),
+        _ => "show",
+    };
+
+    match action {
+        "show" => {
+            let name = args.first().copied().unwrap_or("HEAD");
+            let full_name = repo.refs.full_name(name).ok_or_else(|| {
+                Error::fatal(format!(
+                    "fatal: ambiguous argument '{}': unknown revision or path not in the working tree.\n",
+                    name
+                ))
+            })?;
+
+            let entries = repo.refs.reflog(&full_name).read();
+            for (n, entry) in entries.iter().rev().enumerate() {
+                writeln!(
+                    ctx.stdout,
+                    "{} {}@{{{}}}: {}",
+                    Database::short_oid(&entry.new_oid),
+                    name,
+                    n,
+                    entry.message
+                )
+                .map_err(Error::Output)?;
+            }
+        }
+        "expire" => {
+            let expire = match options.value_of("expire") {
+                Some(expire) => expire.to_string(),
+                None => repo
+                    .config
+                    .get("gc.reflogExpire")
+                    .unwrap_or_else(|| DEFAULT_EXPIRE.to_string()),
+            };
+            // Entries older than the cutoff are dropped
+            let cutoff = match expire.as_str() {
+                "all" | "now" => i64::MAX,
+                "never" | "false" => i64::MIN,
+                _ => parse_approxidate(&expire)
+                    .ok_or_else(|| {
+                        Error::failure(format!(
+                            "error: invalid timestamp '{}' given to '--expire'\n",
+                            expire
+                        ))
+                    })?
+                    .timestamp(),
+            };
+
+            let names = if options.is_present("all") {
+                repo.refs.list_reflogs()
+            } else {
+                let mut names = vec![];
+                for name in args {
+                    match repo.refs.full_name(name) {
+                        Some(full_name) => names.push(full_name),
+                        None => {
+                            return Err(Error::failure(format!(
+                                "error: {} points nowhere!\n",
+                                name
+                            )))
+                        }
+                    }
+                }
+                names
+            };
+
+            for name in names {
+                let reflog = repo.refs.reflog(&name);
+                if !reflog.exists() {
+                    continue;
+                }
+                let mut entries = reflog.read();
+                entries.retain(|entry| entry.time >= cutoff);
+                reflog
+                    .write(&entries)
+                    .map_err(|e| Error::io("write", reflog.path(), e))?;
+            }
+        }
+        _ => {
+            if args.is_empty() {
+                return Err(Error::failure("error: no reflog specified to delete\n"));
+            }
+
+            for selector in args {
+                let caps = SELECTOR.captures(selector).ok_or_else(|| {
+                    Error::failure(format!("error: not a reflog: {}\n", selector))
+                })?;
+                let not_found = || {
+                    Error::failure(format!(
+                        "error: reflog could not be found: '{}'\n",
+                        &caps[1]
+                    ))
+                };
+                let full_name = repo.refs.full_name(&caps[1]).ok_or_else(not_found)?;
+                let reflog = repo.refs.reflog(&full_name);
+                if !reflog.exists() {
+                    return Err(not_found());
+                }
+
+                // Selectors count back from the newest entry
+                let mut entries = reflog.read();
+                let n: usize = caps[2]
+                    .parse()
+                    .map_err(|_| Error::failure(format!("error: not a reflog: {}\n", selector)))?;
+                if n < entries.len() {
+                    let index = entries.len() - 1 - n;
+                    entries.remove(index);
+                    reflog
+                        .write(&entries)
+                        .map_err(|e| Error::io("write", reflog.path(), e))?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.set_env("GIT_COMMITTER_NAME", "C. O. Mitter");
+        cmd_helper.set_env("GIT_COMMITTER_EMAIL", "committer@example.com");
+
+        for message in &["first", "second"] {
+            cmd_helper
+                .write_file("file.txt", message.as_bytes())
+                .unwrap();
+            cmd_helper.run_cmd(&["add", "."]).unwrap();
+            cmd_helper.commit(message);
+        }
+        cmd_helper.run_cmd(&["branch", "topic", "@^"]).unwrap();
+        cmd_helper.run_cmd(&["checkout", "topic"]).unwrap();
+
+        cmd_helper
+    }
+
+    #[test]
+    fn record_ref_updates_the_way_git_does() {
+        let cmd_helper = setup();
+
+        assert_eq!(
+            "checkout: moving from master to topic
+commit: second
+commit (initial): first\n",
+            cmd_helper.git_cmd(&["log", "-g", "--format=%gs", "HEAD"])
+        );
+        assert_eq!(
+            "branch: Created from @^\n",
+            cmd_helper.git_cmd(&["log", "-g", "--format=%gs", "topic"])
+        );
+        assert_eq!(
+            "C. O. Mitter <committer@example.com>\n",
+            cmd_helper.git_cmd(&["log", "-g", "-1", "--format=%gn <%ge>", "master"])
+        );
+    }
+
+    #[test]
+    fn show_entries_newest_first() {
+        let mut cmd_helper = setup();
+        let oids = cmd_helper.git_cmd(&["log", "-g", "--format=%H", "master"]);
+        let oids: Vec<&str> = oids.lines().map(|oid| &oid[..6]).collect();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["reflog", "show", "master"]).unwrap();
+        assert_output(
+            &stdout,
+            &format!(
+                "{} master@{{0}}: commit: second\n{} master@{{1}}: commit (initial): first\n",
+                oids[0], oids[1]
+            ),
+        );
+    }
+
+    #[test]
+    fn resolve_reflog_selectors() {
+        let mut cmd_helper = setup();
+        let second = cmd_helper.git_cmd(&["rev-parse", "master"]);
+
+        cmd_helper
+            .run_cmd(&["branch", "at-head", "HEAD@{1}"])
+            .unwrap();
+        assert_eq!(second, cmd_helper.git_cmd(&["rev-parse", "at-head"]));
+        cmd_helper
+            .run_cmd(&["branch", "previous", "@{-1}"])
+            .unwrap();
+        assert_eq!(second, cmd_helper.git_cmd(&["rev-parse", "previous"]));
+
+        let stderr = cmd_helper
+            .run_cmd(&["branch", "nope", "master@{5}"])
+            .unwrap_err();
+        assert!(stderr.starts_with("error: log for 'master' only has 2 entries\n"));
+
+        cmd_helper.run_cmd(&["checkout", "@{-1}"]).unwrap();
+        assert_eq!(
+            "refs/heads/master\n",
+            cmd_helper.git_cmd(&["symbolic-ref", "HEAD"])
+        );
+    }
+
+    #[test]
+    fn delete_and_expire_entries() {
+        let mut cmd_helper = setup();
+
+        cmd_helper
+            .run_cmd(&["reflog", "delete", "HEAD@{1}"])
+            .unwrap();
+        assert_eq!(
+            "checkout: moving from master to topic\ncommit (initial): first\n",
+            cmd_helper.git_cmd(&["log", "-g", "--format=%gs", "HEAD"])
+        );
+
+        // Cutoffs older than every entry keep them all, and one too
+        // far back to represent is refused
+        for expire in &[
+            "--expire=never",
+            "--expire=2000-01-01",
+            "--expire=1.week.ago",
+        ] {
+            cmd_helper
+                .run_cmd(&["reflog", "expire", expire, "--all"])
+                .unwrap();
+        }
+        let (status, _, stderr) = cmd_helper.jit_status(&[
+            "reflog",
+            "expire",
+            "--expire=999999999999.weeks.ago",
+            "--all",
+        ]);
+        assert_eq!(1, status);
+        assert_output(
+            &stderr,
+            "error: invalid timestamp '999999999999.weeks.ago' given to '--expire'\n",
+        );
+        assert_eq!(
+            2,
+            cmd_helper
+                .git_cmd(&["log", "-g", "--format=%gs", "HEAD"])
+                .lines()
+                .count()
+        );
+
+        cmd_helper
+            .run_cmd(&["reflog", "expire", "--expire=now", "--all"])
+            .unwrap();
+        let (stdout, _) = cmd_helper.run_cmd(&["reflog"]).unwrap();
+        assert_output(&stdout, "");
+        let (stdout, _) = cmd_helper.run_cmd(&["reflog", "topic"]).unwrap();
+        assert_output(&stdout, "");
+    }
+
+    #[test]
+    fn remove_the_log_of_a_deleted_branch() {
+        let mut cmd_helper = setup();
+        cmd_helper.run_cmd(&["checkout", "master"]).unwrap();
+
+        cmd_helper.run_cmd(&["branch", "-D", "topic"]).unwrap();
+        assert!(!cmd_helper
+            .repo()
+            .git_path
+            .join("logs/refs/heads/topic")
+            .exists());
+    }
+}
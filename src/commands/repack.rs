@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+use crate::commands::CommandContext;
+use crate::database::reachable::reachable_objects;
+use crate::errors::Error;
+use crate::pack::compressor::Compressor;
+use crate::pack::entry::Entry;
+use crate::pack::writer::Writer;
+use crate::repository::Repository;
+
+pub fn repack_command<I, O, E>(ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let repo = Repository::discover(&ctx.dir, ctx.env)?;
+    let options = ctx.options.as_ref().unwrap();
+
+    repack(&repo, options.is_present("delete"))
+}
+
+/// Write every object reachable from a ref into one new pack. With
+/// `delete`, also remove the loose objects and older packs that the
+/// new pack makes redundant.
+pub fn repack(repo: &Repository, delete: bool) -> Result<(), Error> {
+    let roots: Vec<String> = repo
+        .refs
+        .list_all_refs()
+        .iter()
+        .filter_map(|r#ref| repo.refs.read_oid(r#ref))
+        .collect();
+
+    let objects = reachable_objects(&repo.database, &roots)?;
+    if objects.is_empty() {
+        return Ok(());
+    }
+
+    let mut entries: Vec<Entry> = objects
+        .iter()
+        .map(|object| {
+            let raw = repo.database.load_raw(&object.oid)?;
+            Ok(Entry::new(&object.oid, raw, &object.path))
+        })
+        .collect::<Result<_, Error>>()?;
+    Compressor::compress(&mut entries);
+
+    let pack_dir = repo.database.pack_path();
+    let name = Writer::write(&pack_dir, &entries)
+        .map_err(|e| Error::io("write a pack in", &pack_dir, e))?;
+
+    if delete {
+        let packed: HashSet<&str> = entries.iter().map(|entry| entry.oid.as_str()).collect();
+        let new_idx_path = pack_dir.join(format!("{}.idx", name));
+
+        for pack in repo.database.packs() {
+            if pack.idx_path() != new_idx_path
+                && pack.oids().iter().all(|oid| packed.contains(oid.as_str()))
+            {
+                pack.remove()
+                    .map_err(|e| Error::io("remove", pack.idx_path(), e))?;
+            }
+        }
+
+        for oid in packed {
+            repo.database
+                .remove_loose(oid)
+                .map_err(|e| Error::io("remove", repo.database.object_path(oid), e))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    fn count_objects(cmd_helper: &CommandHelper) -> String {
+        cmd_helper
+            .git_cmd(&["count-objects", "-v"])
+            .lines()
+            .filter(|line| line.starts_with("count:") || line.starts_with("packs:"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    #[test]
+    fn gc_writes_a_pack_readable_by_git() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_history(&mut cmd_helper);
+
+        cmd_helper.run_cmd(&["gc"]).unwrap();
+
+        assert_eq!("count: 0, packs: 1", count_objects(&cmd_helper));
+        cmd_helper.git_cmd(&["fsck", "--full", "--strict"]);
+
+        let deltas = cmd_helper.git_cmd(&[
+            "cat-file",
+            "--batch-all-objects",
+            "--batch-check=%(deltabase)",
+        ]);
+        assert!(deltas.lines().any(|base| base != "0".repeat(40)));
+
+        cmd_helper.assert_status("");
+        cmd_helper.run_cmd(&["log", "--oneline"]).unwrap();
+    }
+
+    #[test]
+    fn gc_keeps_unreachable_loose_objects() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_history(&mut cmd_helper);
+        cmd_helper.write_file("a.txt", b"not committed").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+
+        cmd_helper.run_cmd(&["gc"]).unwrap();
+
+        assert_eq!("count: 1, packs: 1", count_objects(&cmd_helper));
+    }
+
+    #[test]
+    fn repack_without_delete_keeps_loose_objects() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_history(&mut cmd_helper);
+
+        cmd_helper.run_cmd(&["repack"]).unwrap();
+        assert!(count_objects(&cmd_helper).ends_with("packs: 1"));
+        assert!(!count_objects(&cmd_helper).starts_with("count: 0"));
+
+        cmd_helper.run_cmd(&["repack", "-d"]).unwrap();
+        assert_eq!("count: 0, packs: 1", count_objects(&cmd_helper));
+
+        cmd_helper.write_file("b.txt", b"more").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("more");
+        cmd_helper.run_cmd(&["repack", "--delete"]).unwrap();
+        assert_eq!("count: 0, packs: 1", count_objects(&cmd_helper));
+    }
+
+    #[test]
+    fn gc_replaces_older_packs() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_history(&mut cmd_helper);
+        cmd_helper.run_cmd(&["gc"]).unwrap();
+
+        cmd_helper.write_file("b.txt", b"more").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("commit 3");
+        cmd_helper.run_cmd(&["gc"]).unwrap();
+        cmd_helper.run_cmd(&["gc"]).unwrap();
+
+        assert_eq!("count: 0, packs: 1", count_objects(&cmd_helper));
+        cmd_helper.git_cmd(&["fsck", "--full", "--strict"]);
+    }
+}
@@ -1,10 +1,14 @@
 use crate::commands::CommandContext;
-use crate::repository::{ChangeType, Repository};
+use crate::errors::Error;
+use crate::repository::{self, ChangeType, Repository};
+use crate::util::path_from_prefix;
 use colored::*;
 use std::collections::HashMap;
 use std::io::{Read, Write};
+use std::path::PathBuf;
 
-static LABEL_WIDTH: usize = 12;
+pub static LABEL_WIDTH: usize = 12;
+static CONFLICT_LABEL_WIDTH: usize = 17;
 
 lazy_static! {
     static ref SHORT_STATUS: HashMap<ChangeType, &'static str> = {
@@ -14,13 +18,36 @@ lazy_static! {
         m.insert(ChangeType::Deleted, "D");
         m
     };
-    static ref LONG_STATUS: HashMap<ChangeType, &'static str> = {
+    pub static ref LONG_STATUS: HashMap<ChangeType, &'static str> = {
         let mut m = HashMap::new();
         m.insert(ChangeType::Added, "new file:");
         m.insert(ChangeType::Modified, "modified:");
         m.insert(ChangeType::Deleted, "deleted:");
         m
     };
+    // Keyed by the index stages present for an unmerged path
+    static ref CONFLICT_SHORT_STATUS: HashMap<Vec<u8>, &'static str> = {
+        let mut m = HashMap::new();
+        m.insert(vec![1, 2, 3], "UU");
+        m.insert(vec![1, 2], "UD");
+        m.insert(vec![1, 3], "DU");
+        m.insert(vec![2, 3], "AA");
+        m.insert(vec![2], "AU");
+        m.insert(vec![3], "UA");
+        m.insert(vec![1], "DD");
+        m
+    };
+    static ref CONFLICT_LONG_STATUS: HashMap<Vec<u8>, &'static str> = {
+        let mut m = HashMap::new();
+        m.insert(vec![1, 2, 3], "both modified:");
+        m.insert(vec![1, 2], "deleted by them:");
+        m.insert(vec![1, 3], "deleted by us:");
+        m.insert(vec![2, 3], "both added:");
+        m.insert(vec![2], "added by us:");
+        m.insert(vec![3], "added by them:");
+        m.insert(vec![1], "both deleted:");
+        m
+    };
 }
 
 pub struct Status<'a, I, O, E>
@@ -30,7 +57,11 @@ where
     E: Write,
 {
     repo: Repository,
+    status: repository::Status,
     ctx: CommandContext<'a, I, O, E>,
+    // Where the current directory is in the work tree. The long
+    // format shows paths relative to it.
+    prefix: PathBuf,
 }
 
 impl<'a, I, O, E> Status<'a, I, O, E>
@@ -39,26 +70,36 @@ where
     O: Write,
     E: Write,
 {
-    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Status<'a, I, O, E>
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Result<Status<'a, I, O, E>, Error>
     where
         I: Read,
         O: Write,
         E: Write,
     {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
+        let repo = Repository::discover(&ctx.dir, ctx.env)?;
+        repo.require_work_tree()?;
+
+        let prefix = repo.prefix(&ctx.dir);
 
-        Status { repo, ctx }
+        Ok(Status {
+            repo,
+            status: repository::Status::default(),
+            ctx,
+            prefix,
+        })
     }
 
     fn status_for(&self, path: &str) -> String {
-        let left = if let Some(index_change) = self.repo.index_changes.get(path) {
+        if let Some(stages) = self.status.conflicts.get(path) {
+            return CONFLICT_SHORT_STATUS[stages].to_string();
+        }
+
+        let left = if let Some(index_change) = self.status.index_changes.get(path) {
             SHORT_STATUS.get(index_change).unwrap_or(&" ")
         } else {
             " "
         };
-        let right = if let Some(workspace_change) = self.repo.workspace_changes.get(path) {
+        let right = if let Some(workspace_change) = self.status.workspace_changes.get(path) {
             SHORT_STATUS.get(workspace_change).unwrap_or(&" ")
         } else {
             " "
@@ -66,20 +107,22 @@ where
         format!("{}{}", left, right)
     }
 
-    fn print_porcelain_format(&mut self) -> Result<(), String> {
-        for file in &self.repo.changed {
-            println!("{} {}", self.status_for(file), file);
+    fn print_porcelain_format(&mut self) -> Result<(), Error> {
+        for file in &self.status.changed {
+            writeln!(self.ctx.stdout, "{} {}", self.status_for(file), file)
+                .map_err(Error::Output)?;
         }
 
-        for file in &self.repo.untracked {
-            println!("?? {}", file);
+        for file in &self.status.untracked {
+            writeln!(self.ctx.stdout, "?? {}", file).map_err(Error::Output)?;
         }
 
         Ok(())
     }
 
-    fn print_long_format(&mut self) -> Result<(), String> {
+    fn print_long_format(&mut self) -> Result<(), Error> {
         self.print_index_changes("Changes to be committed", "green")?;
+        self.print_conflicts("Unmerged paths", "red")?;
         self.print_workspace_changes("Changes not staged for commit", "red")?;
         self.print_untracked_files("Untracked files", "red")?;
 
@@ -88,49 +131,96 @@ where
         Ok(())
     }
 
-    fn print_index_changes(&mut self, message: &str, style: &str) -> Result<(), String> {
-        println!("{}", message);
+    fn print_index_changes(&mut self, message: &str, style: &str) -> Result<(), Error> {
+        writeln!(self.ctx.stdout, "{}", message).map_err(Error::Output)?;
 
-        for (path, change_type) in &self.repo.index_changes {
+        for (path, change_type) in &self.status.index_changes {
             if let Some(status) = LONG_STATUS.get(change_type) {
-                println!(
+                writeln!(
+                    self.ctx.stdout,
                     "{}",
-                    format!("\t{:width$}{}", status, path, width = LABEL_WIDTH).color(style)
-                );
+                    format!(
+                        "\t{:width$}{}",
+                        status,
+                        path_from_prefix(path, &self.prefix),
+                        width = LABEL_WIDTH
+                    )
+                    .color(style)
+                )
+                .map_err(Error::Output)?;
             }
         }
 
-        println!();
+        writeln!(self.ctx.stdout).map_err(Error::Output)?;
         Ok(())
     }
 
-    fn print_workspace_changes(&mut self, message: &str, style: &str) -> Result<(), String> {
-        println!("{}", message);
+    fn print_conflicts(&mut self, message: &str, style: &str) -> Result<(), Error> {
+        if self.status.conflicts.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(self.ctx.stdout, "{}", message).map_err(Error::Output)?;
+
+        for (path, stages) in &self.status.conflicts {
+            writeln!(
+                self.ctx.stdout,
+                "{}",
+                format!(
+                    "\t{:width$}{}",
+                    CONFLICT_LONG_STATUS[stages],
+                    path_from_prefix(path, &self.prefix),
+                    width = CONFLICT_LABEL_WIDTH
+                )
+                .color(style)
+            )
+            .map_err(Error::Output)?;
+        }
 
-        for (path, change_type) in &self.repo.workspace_changes {
+        writeln!(self.ctx.stdout).map_err(Error::Output)?;
+        Ok(())
+    }
+
+    fn print_workspace_changes(&mut self, message: &str, style: &str) -> Result<(), Error> {
+        writeln!(self.ctx.stdout, "{}", message).map_err(Error::Output)?;
+
+        for (path, change_type) in &self.status.workspace_changes {
             if let Some(status) = LONG_STATUS.get(change_type) {
-                println!(
+                writeln!(
+                    self.ctx.stdout,
                     "{}",
-                    format!("\t{:width$}{}", status, path, width = LABEL_WIDTH).color(style)
-                );
+                    format!(
+                        "\t{:width$}{}",
+                        status,
+                        path_from_prefix(path, &self.prefix),
+                        width = LABEL_WIDTH
+                    )
+                    .color(style)
+                )
+                .map_err(Error::Output)?;
             }
         }
 
-        println!();
+        writeln!(self.ctx.stdout).map_err(Error::Output)?;
         Ok(())
     }
 
-    fn print_untracked_files(&mut self, message: &str, style: &str) -> Result<(), String> {
-        println!("{}", message);
+    fn print_untracked_files(&mut self, message: &str, style: &str) -> Result<(), Error> {
+        writeln!(self.ctx.stdout, "{}", message).map_err(Error::Output)?;
 
-        for path in &self.repo.untracked {
-            println!("{}", format!("\t{}", path).color(style));
+        for path in &self.status.untracked {
+            writeln!(
+                self.ctx.stdout,
+                "{}",
+                format!("\t{}", path_from_prefix(path, &self.prefix)).color(style)
+            )
+            .map_err(Error::Output)?;
         }
-        println!();
+        writeln!(self.ctx.stdout).map_err(Error::Output)?;
         Ok(())
     }
 
-    pub fn print_results(&mut self) -> Result<(), String> {
+    pub fn print_results(&mut self) -> Result<(), Error> {
         if self
             .ctx
             .options
@@ -146,37 +236,35 @@ where
         Ok(())
     }
 
-    fn print_commit_status(&mut self) -> Result<(), String> {
-        if !self.repo.index_changes.is_empty() {
+    fn print_commit_status(&mut self) -> Result<(), Error> {
+        if !self.status.index_changes.is_empty() {
             return Ok(());
         }
 
-        if !self.repo.workspace_changes.is_empty() {
-            println!("no changes added to commit");
-        } else if !self.repo.untracked.is_empty() {
-            println!("nothing added to commit but untracked files present");
+        if !self.status.workspace_changes.is_empty() || !self.status.conflicts.is_empty() {
+            writeln!(self.ctx.stdout, "no changes added to commit").map_err(Error::Output)?;
+        } else if !self.status.untracked.is_empty() {
+            writeln!(
+                self.ctx.stdout,
+                "nothing added to commit but untracked files present"
+            )
+            .map_err(Error::Output)?;
         } else {
-            println!("nothing to commit, working tree clean");
+            writeln!(self.ctx.stdout, "nothing to commit, working tree clean")
+                .map_err(Error::Output)?;
         }
 
         Ok(())
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
-        self.repo
-            .index
-            .load_for_update()
-            .expect("failed to load index");
+    pub fn run(&mut self) -> Result<(), Error> {
+        self.repo.index.load_for_update()?;
 
-        self.repo.initialize_status()?;
+        self.status = self.repo.status()?;
 
-        self.repo
-            .index
-            .write_updates()
-            .expect("failed to write index");
+        self.repo.index.write_updates()?;
 
-        self.print_results()
-            .expect("printing status results failed");
+        self.print_results()?;
 
         Ok(())
     }
@@ -191,11 +279,10 @@ mod tests {
     fn list_untracked_files_in_name_order() {
         let mut cmd_helper = CommandHelper::new();
 
-        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
         cmd_helper.write_file("file.txt", b"hello").unwrap();
         cmd_helper.write_file("another.txt", b"hello").unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(
             "?? another.txt
 ?? file.txt\n",
@@ -207,21 +294,19 @@ mod tests {
         let mut cmd_helper = CommandHelper::new();
 
         cmd_helper.write_file("committed.txt", b"").unwrap();
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
         cmd_helper.commit("commit message");
 
         cmd_helper.write_file("file.txt", b"").unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("?? file.txt\n");
     }
 
     #[test]
     fn list_untracked_dir_not_contents() {
         let mut cmd_helper = CommandHelper::new();
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.clear_stdout();
+        cmd_helper.run_cmd(&["init"]).unwrap();
         cmd_helper.write_file("file.txt", b"").unwrap();
         cmd_helper.write_file("dir/another.txt", b"").unwrap();
         cmd_helper.assert_status(
@@ -234,14 +319,13 @@ mod tests {
     fn list_untracked_files_inside_tracked_dir() {
         let mut cmd_helper = CommandHelper::new();
         cmd_helper.write_file("a/b/inner.txt", b"").unwrap();
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
         cmd_helper.commit("commit message");
 
         cmd_helper.write_file("a/outer.txt", b"").unwrap();
         cmd_helper.write_file("a/b/c/file.txt", b"").unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(
             "?? a/b/c/
 ?? a/outer.txt\n",
@@ -252,8 +336,7 @@ mod tests {
     fn does_not_list_empty_untracked_dirs() {
         let mut cmd_helper = CommandHelper::new();
         cmd_helper.mkdir("outer").unwrap();
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.clear_stdout();
+        cmd_helper.run_cmd(&["init"]).unwrap();
         cmd_helper.assert_status("");
     }
 
@@ -261,8 +344,7 @@ mod tests {
     fn list_untracked_dirs_that_indirectly_contain_files() {
         let mut cmd_helper = CommandHelper::new();
         cmd_helper.write_file("outer/inner/file.txt", b"").unwrap();
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.clear_stdout();
+        cmd_helper.run_cmd(&["init"]).unwrap();
         cmd_helper.assert_status("?? outer/\n");
     }
 
@@ -270,8 +352,8 @@ mod tests {
         cmd_helper.write_file("1.txt", b"one").unwrap();
         cmd_helper.write_file("a/2.txt", b"two").unwrap();
         cmd_helper.write_file("a/b/3.txt", b"three").unwrap();
-        cmd_helper.jit_cmd(&["init"]).unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
         cmd_helper.commit("commit message");
     }
 
@@ -280,7 +362,6 @@ mod tests {
         let mut cmd_helper = CommandHelper::new();
         create_and_commit(&mut cmd_helper);
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -289,7 +370,6 @@ mod tests {
         let mut cmd_helper = CommandHelper::new();
         create_and_commit(&mut cmd_helper);
 
-        cmd_helper.clear_stdout();
         cmd_helper.write_file("1.txt", b"changed").unwrap();
         cmd_helper.write_file("a/2.txt", b"modified").unwrap();
         cmd_helper.assert_status(
@@ -304,7 +384,6 @@ mod tests {
         create_and_commit(&mut cmd_helper);
 
         cmd_helper.make_executable("a/2.txt").unwrap();
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(" M a/2.txt\n");
     }
 
@@ -319,7 +398,6 @@ mod tests {
         thread::sleep(ten_millis);
 
         cmd_helper.write_file("a/b/3.txt", b"hello").unwrap();
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(" M a/b/3.txt\n");
     }
 
@@ -329,7 +407,6 @@ mod tests {
         create_and_commit(&mut cmd_helper);
         cmd_helper.touch("1.txt").unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("");
     }
 
@@ -339,7 +416,6 @@ mod tests {
         create_and_commit(&mut cmd_helper);
         cmd_helper.delete("a/2.txt").unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(" D a/2.txt\n");
     }
 
@@ -349,7 +425,6 @@ mod tests {
         create_and_commit(&mut cmd_helper);
         cmd_helper.delete("a").unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(
             " D a/2.txt
  D a/b/3.txt\n",
@@ -361,8 +436,7 @@ mod tests {
         let mut cmd_helper = CommandHelper::new();
         create_and_commit(&mut cmd_helper);
         cmd_helper.write_file("a/4.txt", b"four").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
-        cmd_helper.clear_stdout();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
         cmd_helper.assert_status("A  a/4.txt\n");
     }
 
@@ -371,8 +445,7 @@ mod tests {
         let mut cmd_helper = CommandHelper::new();
         create_and_commit(&mut cmd_helper);
         cmd_helper.write_file("d/e/5.txt", b"five").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
-        cmd_helper.clear_stdout();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
         cmd_helper.assert_status("A  d/e/5.txt\n");
     }
 
@@ -382,9 +455,8 @@ mod tests {
         create_and_commit(&mut cmd_helper);
 
         cmd_helper.make_executable("1.txt").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("M  1.txt\n");
     }
 
@@ -394,9 +466,8 @@ mod tests {
         create_and_commit(&mut cmd_helper);
 
         cmd_helper.write_file("a/b/3.txt", b"modified").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("M  a/b/3.txt\n");
     }
 
@@ -406,9 +477,8 @@ mod tests {
         create_and_commit(&mut cmd_helper);
         cmd_helper.delete("1.txt").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status("D  1.txt\n");
     }
 
@@ -418,12 +488,277 @@ mod tests {
         create_and_commit(&mut cmd_helper);
         cmd_helper.delete("a").unwrap();
         cmd_helper.delete(".git/index").unwrap();
-        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
 
-        cmd_helper.clear_stdout();
         cmd_helper.assert_status(
             "D  a/2.txt
 D  a/b/3.txt\n",
         );
     }
+
+    fn commit_all(cmd_helper: &mut CommandHelper, message: &str) {
+        // Rebuild the index so that deleted files are dropped from it
+        let _ = cmd_helper.delete(".git/index");
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit(message);
+    }
+
+    // Leaves a merge stopped with `both.txt` modified on both sides,
+    // `ours.txt` deleted by them and `new.txt` added on both sides
+    fn setup_conflicts(cmd_helper: &mut CommandHelper) {
+        cmd_helper.write_file("both.txt", b"base").unwrap();
+        cmd_helper.write_file("ours.txt", b"base").unwrap();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        commit_all(cmd_helper, "base");
+        cmd_helper.run_cmd(&["branch", "topic"]).unwrap();
+
+        cmd_helper.write_file("both.txt", b"left").unwrap();
+        cmd_helper.write_file("ours.txt", b"left").unwrap();
+        cmd_helper.write_file("new.txt", b"left").unwrap();
+        commit_all(cmd_helper, "left");
+
+        cmd_helper.run_cmd(&["checkout", "topic"]).unwrap();
+        cmd_helper.write_file("both.txt", b"right").unwrap();
+        cmd_helper.delete("ours.txt").unwrap();
+        cmd_helper.write_file("new.txt", b"right").unwrap();
+        commit_all(cmd_helper, "right");
+
+        cmd_helper.run_cmd(&["checkout", "master"]).unwrap();
+        assert!(cmd_helper.run_cmd(&["merge", "topic"]).is_err());
+    }
+
+    #[test]
+    fn reports_unmerged_paths() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_conflicts(&mut cmd_helper);
+
+        cmd_helper.assert_status(
+            "UU both.txt
+AA new.txt
+UD ours.txt\n",
+        );
+    }
+
+    #[test]
+    fn reports_resolved_paths_as_staged() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_conflicts(&mut cmd_helper);
+
+        cmd_helper.write_file("both.txt", b"merged").unwrap();
+        cmd_helper.run_cmd(&["add", "both.txt"]).unwrap();
+
+        cmd_helper.assert_status(
+            "M  both.txt
+AA new.txt
+UD ours.txt\n",
+        );
+    }
+
+    #[test]
+    fn lists_unmerged_paths_in_long_format() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_conflicts(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper.run_cmd(&["status"]).unwrap();
+
+        assert!(stdout.contains(
+            "Unmerged paths
+\tboth modified:   both.txt
+\tboth added:      new.txt
+\tdeleted by them: ours.txt\n"
+        ));
+        assert!(stdout.ends_with("no changes added to commit\n"));
+    }
+
+    #[test]
+    fn diff_and_commit_recognise_unmerged_paths() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_conflicts(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper.run_cmd(&["diff"]).unwrap();
+        assert_output(
+            &stdout,
+            "* Unmerged path both.txt
+* Unmerged path new.txt
+* Unmerged path ours.txt\n",
+        );
+
+        // Commit refuses to run even once the merge itself is gone
+        cmd_helper.delete(".git/MERGE_HEAD").unwrap();
+        let stderr = cmd_helper.run_cmd(&["commit"]).unwrap_err();
+        assert!(stderr
+            .starts_with("error: Committing is not possible because you have unmerged files.\n"));
+    }
+
+    #[test]
+    fn does_not_list_ignored_files() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper
+            .write_file(".gitignore", b"*.log\n!keep.log\nbuild/\n/top.txt\n")
+            .unwrap();
+        cmd_helper.write_file("debug.log", b"").unwrap();
+        cmd_helper.write_file("keep.log", b"").unwrap();
+        cmd_helper.write_file("build/out.o", b"").unwrap();
+        cmd_helper.write_file("top.txt", b"").unwrap();
+        cmd_helper.write_file("a/top.txt", b"").unwrap();
+        cmd_helper.write_file("a/b/debug.log", b"").unwrap();
+
+        cmd_helper.assert_status(
+            "?? .gitignore
+?? a/
+?? keep.log\n",
+        );
+    }
+
+    #[test]
+    fn apply_nested_gitignore_files_and_info_exclude() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper
+            .write_file(".git/info/exclude", b"*.tmp\n")
+            .unwrap();
+        cmd_helper
+            .write_file("sub/.gitignore", b"!x.tmp\n/data/\n")
+            .unwrap();
+        cmd_helper.write_file("y.tmp", b"").unwrap();
+        cmd_helper.write_file("sub/x.tmp", b"").unwrap();
+        cmd_helper.write_file("sub/y.tmp", b"").unwrap();
+        cmd_helper.write_file("sub/data/file.txt", b"").unwrap();
+        cmd_helper
+            .write_file("sub/deep/data/file.txt", b"")
+            .unwrap();
+        cmd_helper.run_cmd(&["add", "sub/.gitignore"]).unwrap();
+
+        cmd_helper.assert_status(
+            "A  sub/.gitignore
+?? sub/deep/
+?? sub/x.tmp\n",
+        );
+    }
+
+    #[test]
+    fn track_a_directory_named_target() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("target/file.txt", b"").unwrap();
+
+        cmd_helper.assert_status("?? target/\n");
+    }
+
+    #[test]
+    fn apply_core_excludes_file() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper
+            .write_file("excludes", b"excludes\n*.bak\n")
+            .unwrap();
+        cmd_helper.write_file("file.bak", b"").unwrap();
+        let excludes = cmd_helper.repo().root_path.join("excludes");
+        cmd_helper
+            .run_cmd(&["config", "core.excludesFile", excludes.to_str().unwrap()])
+            .unwrap();
+
+        cmd_helper.assert_status("");
+    }
+
+    #[test]
+    fn find_the_repository_from_a_subdirectory() {
+        let mut cmd_helper = CommandHelper::new();
+        create_and_commit(&mut cmd_helper);
+        cmd_helper.write_file("1.txt", b"changed").unwrap();
+        cmd_helper.write_file("a/b/4.txt", b"four").unwrap();
+
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["-C", "a", "-C", "b", "status"])
+            .unwrap();
+        assert!(stdout.contains("\tmodified:   ../../1.txt\n"), "{}", stdout);
+        assert!(stdout.contains("Untracked files\n\t4.txt\n"), "{}", stdout);
+
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["-C", "a/b", "status", "--porcelain"])
+            .unwrap();
+        assert_output(&stdout, " M 1.txt\n?? a/b/4.txt\n");
+
+        let stderr = cmd_helper
+            .jit_cmd(&["-C", "missing", "status"])
+            .unwrap_err();
+        assert_output(
+            &stderr,
+            "fatal: cannot change to 'missing': No such file or directory\n",
+        );
+    }
+
+    #[test]
+    fn use_git_dir_and_work_tree_from_the_environment() {
+        let mut cmd_helper = CommandHelper::new();
+        create_and_commit(&mut cmd_helper);
+        cmd_helper.write_file("a/2.txt", b"changed").unwrap();
+        let root = cmd_helper.repo().root_path;
+
+        // A gitdir file pointing at the real repository
+        cmd_helper.mkdir("elsewhere").unwrap();
+        let git_dir = root.join("elsewhere/.git");
+        std::fs::rename(root.join(".git"), &git_dir).unwrap();
+        cmd_helper
+            .write_file(".git", b"gitdir: elsewhere/.git\n")
+            .unwrap();
+        cmd_helper.assert_status(" M a/2.txt\n");
+        cmd_helper.delete(".git").unwrap();
+
+        let stderr = cmd_helper.run_cmd(&["status"]).unwrap_err();
+        assert_output(
+            &stderr,
+            "fatal: not a git repository (or any of the parent directories): .git\n",
+        );
+
+        cmd_helper.set_env("GIT_DIR", git_dir.to_str().unwrap());
+        cmd_helper.set_env("GIT_WORK_TREE", root.to_str().unwrap());
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["-C", "a", "status", "--porcelain"])
+            .unwrap();
+        assert_output(&stdout, " M a/2.txt\n");
+    }
+
+    #[test]
+    fn report_a_corrupt_or_locked_index() {
+        let mut cmd_helper = CommandHelper::new();
+        create_and_commit(&mut cmd_helper);
+
+        cmd_helper.write_file(".git/index.lock", b"").unwrap();
+        let (status, _, stderr) = cmd_helper.jit_status(&["status"]);
+        assert_eq!(128, status);
+        let lock = cmd_helper.repo().git_path.join("index.lock");
+        assert!(
+            stderr.starts_with(&format!(
+                "fatal: Unable to create '{}': File exists.\n",
+                lock.display()
+            )),
+            "{}",
+            stderr
+        );
+        assert!(lock.exists());
+
+        cmd_helper.delete(".git/index.lock").unwrap();
+        cmd_helper
+            .write_file(".git/index", b"XXXX\0\0\0\x02")
+            .unwrap();
+        assert_eq!(
+            (
+                128,
+                String::new(),
+                "error: index file smaller than expected\nfatal: index file corrupt\n".to_string()
+            ),
+            cmd_helper.jit_status(&["status"])
+        );
+
+        cmd_helper
+            .write_file(".git/index", b"XXXX\0\0\0\x02\0\0\0\0")
+            .unwrap();
+        let (_, _, stderr) = cmd_helper.jit_status(&["diff"]);
+        assert_output(
+            &stderr,
+            "error: bad signature 0x58585858\nfatal: index file corrupt\n",
+        );
+    }
 }
@@ -0,0 +1,236 @@
+use regex::Regex;
+use std::io::{Read, Write};
+
+use crate::commands::commit::committer;
+use crate::commands::CommandContext;
+use crate::database::object::Storable;
+use crate::database::tag::Tag;
+use crate::database::{Database, ParsedObject};
+use crate::errors::Error;
+use crate::ignore::glob_to_regex;
+use crate::repository::Repository;
+use crate::revision::Revision;
+
+pub fn tag_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let mut repo = Repository::discover(&ctx.dir, ctx.env)?;
+    let options = ctx.options.as_ref().unwrap().clone();
+    let args: Vec<&str> = match options.values_of("args") {
+        Some(args) => args.collect(),
+        None => vec![],
+    };
+
+    if options.is_present("delete") {
+        for name in args {
+            let oid = repo.refs.delete_tag(name)?;
+            writeln!(
+                ctx.stdout,
+                "Deleted tag '{}' (was {})",
+                name,
+                Database::short_oid(&oid)
+            )
+            .map_err(Error::Output)?;
+        }
+        return Ok(());
+    }
+
+    if options.is_present("list") || options.is_present("lines") || args.is_empty() {
+        let lines = match options.value_of("lines") {
+            Some(lines) => lines
+                .parse()
+                .map_err(|_| Error::failure("error: option `n' expects a numerical value\n"))?,
+            None if options.is_present("lines") => 1,
+            None => 0,
+        };
+        return list_tags(&mut repo, &mut ctx.stdout, &args, lines);
+    }
+
+    let name = args[0];
+    let target = args.get(1).copied().unwrap_or("HEAD");
+    let oid = match Revision::new(&mut repo, target).resolve() {
+        Ok(oid) => oid,
+        Err(_) => {
+            return Err(Error::fatal(format!(
+                "fatal: Failed to resolve '{}' as a valid ref.\n",
+                target
+            )))
+        }
+    };
+
+    let messages: Vec<&str> = match options.values_of("message") {
+        Some(messages) => messages.collect(),
+        None => vec![],
+    };
+    if !messages.is_empty() || options.is_present("annotate") {
+        if messages.is_empty() {
+            return Err(Error::fatal("fatal: no tag message given, use -m\n"));
+        }
+        let tagger = committer(&repo, ctx.env)?;
+        // Each -m is a paragraph of its own
+        let message = format!("{}\n", messages.join("\n\n").trim_end());
+
+        let tag = Tag::new(&oid, "commit", name, tagger, message);
+        repo.database
+            .store(&tag)
+            .map_err(|e| Error::io("write object", repo.database.object_path(&tag.get_oid()), e))?;
+        repo.refs.create_tag(name, &tag.get_oid())?;
+    } else {
+        repo.refs.create_tag(name, &oid)?;
+    }
+
+    Ok(())
+}
+
+/// Print tag names, filtered by `patterns`, along with the first
+/// `lines` lines of each tag's message
+fn list_tags<O: Write>(
+    repo: &mut Repository,
+    stdout: &mut O,
+    patterns: &[&str],
+    lines: usize,
+) -> Result<(), Error> {
+    let patterns: Vec<Regex> = patterns
+        .iter()
+        .map(|pattern| Regex::new(&format!("^{}$", glob_to_regex(pattern))).unwrap())
+        .collect();
+
+    for r#ref in repo.refs.list_tags() {
+        let name = repo.refs.ref_short_name(&r#ref);
+        if !patterns.is_empty() && !patterns.iter().any(|pattern| pattern.is_match(&name)) {
+            continue;
+        }
+        if lines == 0 {
+            writeln!(stdout, "{}", name).map_err(Error::Output)?;
+            continue;
+        }
+
+        let oid = repo.refs.read_oid(&r#ref).expect("tag has no value");
+        let message = match repo.database.load(&oid)? {
+            ParsedObject::Tag(tag) => tag.message.clone(),
+            ParsedObject::Commit(commit) => commit.message().into_owned(),
+            _ => String::new(),
+        };
+        let mut message_lines = message.lines().take(lines);
+        write!(
+            stdout,
+            "{:<15} {}",
+            name,
+            message_lines.next().unwrap_or("")
+        )
+        .map_err(Error::Output)?;
+        for line in message_lines {
+            write!(stdout, "\n    {}", line).map_err(Error::Output)?;
+        }
+        writeln!(stdout).map_err(Error::Output)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        for message in &["first", "second"] {
+            cmd_helper
+                .write_file("file.txt", message.as_bytes())
+                .unwrap();
+            cmd_helper.run_cmd(&["add", "."]).unwrap();
+            cmd_helper.commit(message);
+        }
+
+        cmd_helper
+    }
+
+    #[test]
+    fn create_lightweight_and_annotated_tags() {
+        let mut cmd_helper = setup();
+
+        cmd_helper.run_cmd(&["tag", "light", "@^"]).unwrap();
+        cmd_helper
+            .run_cmd(&["tag", "-a", "v1.0", "-m", "Release 1.0", "-m", "Notes."])
+            .unwrap();
+
+        assert_eq!(
+            cmd_helper.git_cmd(&["rev-parse", "HEAD^"]),
+            cmd_helper.git_cmd(&["rev-parse", "light"])
+        );
+        assert_eq!("tag\n", cmd_helper.git_cmd(&["cat-file", "-t", "v1.0"]));
+        assert_eq!(
+            cmd_helper.git_cmd(&["rev-parse", "HEAD"]),
+            cmd_helper.git_cmd(&["rev-parse", "v1.0^{commit}"])
+        );
+        assert_eq!(
+            "Release 1.0\n\nNotes.\n\n",
+            cmd_helper.git_cmd(&["tag", "-l", "--format=%(contents)", "v1.0"])
+        );
+        cmd_helper.git_cmd(&["fsck", "--strict"]);
+
+        let stderr = cmd_helper.run_cmd(&["tag", "light"]).unwrap_err();
+        assert_output(&stderr, "fatal: tag 'light' already exists\n");
+    }
+
+    #[test]
+    fn list_tags() {
+        let mut cmd_helper = setup();
+        cmd_helper.run_cmd(&["tag", "v2.0"]).unwrap();
+        cmd_helper
+            .run_cmd(&["tag", "-m", "Old\n\nrelease", "v1.0", "@^"])
+            .unwrap();
+        cmd_helper.run_cmd(&["tag", "other"]).unwrap();
+
+        let (stdout, _) = cmd_helper.run_cmd(&["tag"]).unwrap();
+        assert_output(&stdout, "other\nv1.0\nv2.0\n");
+        let (stdout, _) = cmd_helper.run_cmd(&["tag", "-l", "v*"]).unwrap();
+        assert_output(&stdout, "v1.0\nv2.0\n");
+        let (stdout, _) = cmd_helper.run_cmd(&["tag", "-n3", "v*"]).unwrap();
+        assert_output(
+            &stdout,
+            "v1.0            Old\n    \n    release\nv2.0            second\n",
+        );
+    }
+
+    #[test]
+    fn delete_tags() {
+        let mut cmd_helper = setup();
+        cmd_helper.run_cmd(&["tag", "v1.0"]).unwrap();
+        let short = cmd_helper.git_cmd(&["rev-parse", "--short=6", "v1.0"]);
+
+        let (stdout, _) = cmd_helper.run_cmd(&["tag", "-d", "v1.0"]).unwrap();
+        assert_output(
+            &stdout,
+            &format!("Deleted tag 'v1.0' (was {})\n", short.trim()),
+        );
+        assert_eq!("", cmd_helper.git_cmd(&["tag"]));
+
+        let stderr = cmd_helper.run_cmd(&["tag", "-d", "v1.0"]).unwrap_err();
+        assert_output(&stderr, "error: tag 'v1.0' not found.\n");
+    }
+
+    #[test]
+    fn resolve_and_decorate_tags() {
+        let mut cmd_helper = setup();
+        cmd_helper
+            .run_cmd(&["tag", "-a", "-m", "release", "v1.0", "@^"])
+            .unwrap();
+
+        cmd_helper.run_cmd(&["branch", "from-tag", "v1.0"]).unwrap();
+        assert_eq!(
+            cmd_helper.git_cmd(&["rev-parse", "HEAD^"]),
+            cmd_helper.git_cmd(&["rev-parse", "from-tag"])
+        );
+
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["log", "--oneline", "--decorate=short"])
+            .unwrap();
+        let tagged = stdout.lines().nth(1).unwrap();
+        assert!(tagged.contains("tag: v1.0"), "{}", tagged);
+    }
+}
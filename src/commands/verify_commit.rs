@@ -0,0 +1,260 @@
+use std::io::{Read, Write};
+
+use crate::commands::CommandContext;
+use crate::database::ParsedObject;
+use crate::errors::Error;
+use crate::gpg::Gpg;
+use crate::repository::Repository;
+use crate::revision::Revision;
+
+pub fn verify_commit_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), Error>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let mut repo = Repository::discover(&ctx.dir, ctx.env)?;
+    let options = ctx.options.as_ref().unwrap().clone();
+    let args: Vec<&str> = match options.values_of("args") {
+        Some(args) => args.collect(),
+        None => {
+            return Err(Error::usage(
+                "usage: rug verify-commit [-v | --verbose] <commit>...\n",
+            ))
+        }
+    };
+
+    // Keep going past bad signatures, so that each one is reported
+    let mut all_good = true;
+    for name in args {
+        let oid = Revision::new(&mut repo, name)
+            .resolve()
+            .map_err(|_| Error::failure(format!("error: commit '{}' not found.\n", name)))?;
+        let commit = match repo.database.load(&oid)? {
+            ParsedObject::Commit(commit) => commit.clone(),
+            _ => unreachable!("revisions resolve to commits"),
+        };
+
+        let signature = match commit.signature() {
+            Some(signature) => signature,
+            None => {
+                all_good = false;
+                continue;
+            }
+        };
+        let payload = commit.signature_payload();
+        if options.is_present("verbose") {
+            ctx.stdout.write_all(&payload).map_err(Error::Output)?;
+        }
+
+        let verification = Gpg::new(&repo.config, ctx.env).verify(&payload, &signature)?;
+        ctx.stderr
+            .write_all(verification.output.as_bytes())
+            .map_err(Error::Output)?;
+        all_good &= verification.good;
+    }
+
+    if all_good {
+        Ok(())
+    } else {
+        Err(Error::Exit(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    // Stand-ins for gpg and ssh-keygen whose "signature" is a checksum
+    // of the payload, naming the key it was made with
+    const GPG_STUB: &str = r#"#!/bin/sh
+case "$*" in
+*-bsau*)
+    echo "-----BEGIN PGP SIGNATURE-----"
+    echo
+    echo "key $3"
+    echo "home ${GNUPGHOME:-none}"
+    cksum | cut -d' ' -f1
+    echo "-----END PGP SIGNATURE-----"
+    ;;
+*--verify*)
+    if grep -qx "$(cksum | cut -d' ' -f1)" "$4"; then
+        echo "gpg: Good signature from \"$(sed -n 's/^key //p' "$4")\"" >&2
+    else
+        echo "gpg: BAD signature" >&2
+        exit 1
+    fi
+    ;;
+esac
+"#;
+
+    const SSH_STUB: &str = r#"#!/bin/sh
+case "$2" in
+sign)
+    { echo "-----BEGIN SSH SIGNATURE-----"; cksum < "$7" | cut -d' ' -f1; echo "-----END SSH SIGNATURE-----"; } > "$7.sig"
+    ;;
+find-principals)
+    cut -d' ' -f1 "$4"
+    ;;
+verify)
+    grep -qx "$(cksum | cut -d' ' -f1)" "${10}" || exit 1
+    echo "Good \"git\" signature for $8"
+    ;;
+esac
+"#;
+
+    fn install_stub(cmd_helper: &CommandHelper, name: &str, script: &str) -> String {
+        cmd_helper.write_file(name, script.as_bytes()).unwrap();
+        let path = cmd_helper.repo().root_path.join(name);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        path.to_str().unwrap().to_string()
+    }
+
+    fn setup() -> CommandHelper {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        let gpg = install_stub(&cmd_helper, ".gpg-stub", GPG_STUB);
+        cmd_helper
+            .run_cmd(&["config", "gpg.program", &gpg])
+            .unwrap();
+        cmd_helper.set_env("GIT_AUTHOR_NAME", "A. U. Thor");
+        cmd_helper.set_env("GIT_AUTHOR_EMAIL", "author@example.com");
+        cmd_helper.set_env("GIT_COMMITTER_NAME", "C. O. Mitter");
+        cmd_helper.set_env("GIT_COMMITTER_EMAIL", "committer@example.com");
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.run_cmd(&["add", "file.txt"]).unwrap();
+
+        cmd_helper
+    }
+
+    #[test]
+    fn sign_and_verify_commits() {
+        let mut cmd_helper = setup();
+        cmd_helper.commit("unsigned");
+        cmd_helper.write_file("file.txt", b"two").unwrap();
+        cmd_helper.run_cmd(&["add", "file.txt"]).unwrap();
+        cmd_helper
+            .run_cmd(&["commit", "-S", "-m", "signed"])
+            .unwrap();
+
+        let raw = cmd_helper.git_cmd(&["cat-file", "commit", "HEAD"]);
+        assert!(
+            raw.contains("\ngpgsig -----BEGIN PGP SIGNATURE-----\n \n key C. O. Mitter <committer@example.com>\n"),
+            "{}",
+            raw
+        );
+
+        let (_, stderr) = cmd_helper.run_cmd(&["verify-commit", "HEAD"]).unwrap();
+        assert_output(
+            &stderr,
+            "gpg: Good signature from \"C. O. Mitter <committer@example.com>\"\n",
+        );
+        let stderr = cmd_helper.run_cmd(&["verify-commit", "HEAD^"]).unwrap_err();
+        assert_output(&stderr, "");
+    }
+
+    #[test]
+    fn run_the_signing_program_in_the_commands_environment() {
+        let mut cmd_helper = setup();
+        cmd_helper.set_env("GNUPGHOME", "/keyring");
+        cmd_helper
+            .run_cmd(&["commit", "-S", "-m", "signed"])
+            .unwrap();
+
+        let raw = cmd_helper.git_cmd(&["cat-file", "commit", "HEAD"]);
+        assert!(raw.contains("\n home /keyring\n"), "{}", raw);
+    }
+
+    #[test]
+    fn reject_a_tampered_commit() {
+        let mut cmd_helper = setup();
+        cmd_helper
+            .run_cmd(&["commit", "-S", "-m", "signed"])
+            .unwrap();
+
+        let raw = cmd_helper.git_cmd(&["cat-file", "commit", "HEAD"]);
+        cmd_helper
+            .write_file("forged", raw.replace("signed", "forged").as_bytes())
+            .unwrap();
+        let forged = cmd_helper.git_cmd(&["hash-object", "-t", "commit", "-w", "forged"]);
+
+        let stderr = cmd_helper
+            .run_cmd(&["verify-commit", forged.trim()])
+            .unwrap_err();
+        assert_output(&stderr, "gpg: BAD signature\n");
+    }
+
+    #[test]
+    fn sign_by_configuration() {
+        let mut cmd_helper = setup();
+        cmd_helper
+            .run_cmd(&["config", "commit.gpgSign", "true"])
+            .unwrap();
+        cmd_helper
+            .run_cmd(&["config", "user.signingKey", "ABCD1234"])
+            .unwrap();
+
+        cmd_helper.commit("signed");
+        let raw = cmd_helper.git_cmd(&["cat-file", "commit", "HEAD"]);
+        assert!(raw.contains("\n key ABCD1234\n"), "{}", raw);
+
+        cmd_helper
+            .run_cmd(&["commit", "--allow-empty", "--no-gpg-sign", "-m", "unsigned"])
+            .unwrap();
+        let raw = cmd_helper.git_cmd(&["cat-file", "commit", "HEAD"]);
+        assert!(!raw.contains("gpgsig"), "{}", raw);
+    }
+
+    #[test]
+    fn show_signatures_in_the_log() {
+        let mut cmd_helper = setup();
+        cmd_helper
+            .run_cmd(&["commit", "-S", "-m", "signed"])
+            .unwrap();
+
+        let (stdout, _) = cmd_helper
+            .run_cmd(&["log", "--show-signature", "--no-decorate"])
+            .unwrap();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert!(lines[1].starts_with("commit "));
+        assert_output(
+            lines[2],
+            "gpg: Good signature from \"C. O. Mitter <committer@example.com>\"",
+        );
+        assert!(lines[3].starts_with("Author: "));
+    }
+
+    #[test]
+    fn sign_with_ssh_keys() {
+        let mut cmd_helper = setup();
+        let ssh = install_stub(&cmd_helper, ".ssh-stub", SSH_STUB);
+        cmd_helper
+            .write_file(".allowed", b"alice ssh-ed25519 AAAA\n")
+            .unwrap();
+        let allowed = cmd_helper.repo().root_path.join(".allowed");
+        for (key, value) in &[
+            ("gpg.format", "ssh"),
+            ("gpg.ssh.program", &ssh),
+            ("gpg.ssh.allowedSignersFile", allowed.to_str().unwrap()),
+        ] {
+            cmd_helper.run_cmd(&["config", key, value]).unwrap();
+        }
+
+        let stderr = cmd_helper
+            .run_cmd(&["commit", "-S", "-m", "signed"])
+            .unwrap_err();
+        assert_output(
+            &stderr,
+            "error: user.signingKey needs to be set for ssh signing\n",
+        );
+
+        cmd_helper
+            .run_cmd(&["commit", "-S/keys/alice", "-m", "signed"])
+            .unwrap();
+        let (_, stderr) = cmd_helper.run_cmd(&["verify-commit", "HEAD"]).unwrap();
+        assert_output(&stderr, "Good \"git\" signature for alice\n");
+    }
+}
@@ -0,0 +1,583 @@
+use crate::errors::{self, Error};
+use crate::lockfile::Lockfile;
+use regex::Regex;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+pub mod stack;
+
+lazy_static! {
+    static ref SECTION_LINE: Regex =
+        Regex::new(r#"^\s*\[\s*([A-Za-z0-9.-]+)(?:\s+"((?:[^"\\]|\\.)*)")?\s*\]\s*(?:[#;].*)?$"#)
+            .unwrap();
+    static ref VARIABLE_LINE: Regex =
+        Regex::new(r"(?s)^\s*([A-Za-z][A-Za-z0-9-]*)\s*(?:=(.*)|([#;].*)?)$").unwrap();
+    static ref BLANK_LINE: Regex = Regex::new(r"^\s*(?:[#;].*)?$").unwrap();
+}
+
+/// A variable name such as `remote.origin.url`. Section and variable
+/// names are case-insensitive, subsection names are not.
+#[derive(Clone, Debug)]
+pub struct Key {
+    section: String,
+    subsection: Option<String>,
+    name: String,
+    // The variable name as it was given, for writing new lines
+    written_name: String,
+}
+
+impl Key {
+    pub fn parse(key: &str) -> Result<Key, Error> {
+        let (first, last) = match (key.find('.'), key.rfind('.')) {
+            (Some(first), Some(last)) => (first, last),
+            _ => {
+                return Err(Error::failure(format!(
+                    "error: key does not contain a section: {}\n",
+                    key
+                )))
+            }
+        };
+
+        let section = &key[..first];
+        let name = &key[last + 1..];
+        let valid_section = !section.is_empty()
+            && section
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-');
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid_section || !valid_name {
+            return Err(Error::failure(format!("error: invalid key: {}\n", key)));
+        }
+
+        Ok(Key {
+            section: section.to_lowercase(),
+            subsection: if first < last {
+                Some(key[first + 1..last].to_string())
+            } else {
+                None
+            },
+            name: name.to_lowercase(),
+            written_name: name.to_string(),
+        })
+    }
+
+    fn matches(&self, other: &Key) -> bool {
+        self.section() == other.section() && self.name == other.name
+    }
+
+    fn section(&self) -> Section {
+        Section {
+            name: self.section.clone(),
+            subsection: self.subsection.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.subsection {
+            Some(subsection) => write!(f, "{}.{}.{}", self.section, subsection, self.name),
+            None => write!(f, "{}.{}", self.section, self.name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Section {
+    name: String,
+    subsection: Option<String>,
+}
+
+impl Section {
+    fn heading_line(&self) -> String {
+        match &self.subsection {
+            Some(subsection) => format!(
+                "[{} \"{}\"]\n",
+                self.name,
+                subsection.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+            None => format!("[{}]\n", self.name),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Variable {
+    name: String,
+    // `None` for a bare name with no `=`, which git reads as true
+    value: Option<String>,
+}
+
+impl Variable {
+    fn line(name: &str, value: &str) -> String {
+        format!("\t{} = {}\n", name, Self::serialize(value))
+    }
+
+    fn serialize(value: &str) -> String {
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t");
+
+        let needs_quotes = value.starts_with(char::is_whitespace)
+            || value.ends_with(char::is_whitespace)
+            || value.contains('#')
+            || value.contains(';');
+
+        if needs_quotes {
+            format!("\"{}\"", escaped)
+        } else {
+            escaped
+        }
+    }
+}
+
+/// One logical line of a config file, kept verbatim so that the file
+/// can be written back with only the changed lines touched
+#[derive(Debug)]
+struct Line {
+    text: String,
+    section: Option<Section>,
+    variable: Option<Variable>,
+}
+
+impl Line {
+    fn is_variable(&self, key: &Key) -> bool {
+        self.section.as_ref() == Some(&key.section())
+            && self
+                .variable
+                .as_ref()
+                .map(|variable| variable.name == key.name)
+                .unwrap_or(false)
+    }
+}
+
+/// Reads the value part of a variable line, returning `None` when it
+/// ends in a backslash and continues on the next line
+fn parse_value(raw: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = raw.chars().peekable();
+    let mut in_quotes = false;
+    let mut started = false;
+    // Unquoted whitespace at the end of the value is dropped
+    let mut trailing = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => break,
+            '\r' if chars.peek().map(|&c| c == '\n').unwrap_or(true) => break,
+            '\\' => {
+                let escaped = match chars.next() {
+                    None => return None,
+                    Some('\r') if chars.peek() == Some(&'\n') => {
+                        chars.next();
+                        continue;
+                    }
+                    Some('\n') => continue,
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('b') => '\u{8}',
+                    Some(c) => c,
+                };
+                value.push(escaped);
+                started = true;
+                trailing = 0;
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                started = true;
+                trailing = 0;
+            }
+            '#' | ';' if !in_quotes => break,
+            c if c.is_whitespace() && !in_quotes => {
+                if started {
+                    value.push(c);
+                    trailing += 1;
+                }
+            }
+            c => {
+                value.push(c);
+                started = true;
+                trailing = 0;
+            }
+        }
+    }
+
+    value.truncate(value.len() - trailing);
+    Some(value)
+}
+
+fn parse_section(captures: &regex::Captures) -> Section {
+    let name = &captures[1];
+
+    match captures.get(2) {
+        Some(subsection) => {
+            let mut unescaped = String::new();
+            let mut chars = subsection.as_str().chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => unescaped.extend(chars.next()),
+                    c => unescaped.push(c),
+                }
+            }
+
+            Section {
+                name: name.to_lowercase(),
+                subsection: Some(unescaped),
+            }
+        }
+        // The deprecated `[section.subsection]` form, whose
+        // subsection is case-insensitive
+        None => match name.find('.') {
+            Some(dot) => Section {
+                name: name[..dot].to_lowercase(),
+                subsection: Some(name[dot + 1..].to_lowercase()),
+            },
+            None => Section {
+                name: name.to_lowercase(),
+                subsection: None,
+            },
+        },
+    }
+}
+
+/// A single config file, such as `.git/config` or `~/.gitconfig`
+pub struct Config {
+    path: PathBuf,
+    lockfile: Lockfile,
+    lines: Vec<Line>,
+}
+
+impl Config {
+    pub fn new(path: &Path) -> Config {
+        Config {
+            path: path.to_path_buf(),
+            lockfile: Lockfile::new(path),
+            lines: vec![],
+        }
+    }
+
+    /// Read and parse the file. A missing file reads as empty.
+    pub fn open(&mut self) -> Result<(), Error> {
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(Error::io("read", &self.path, e)),
+        };
+        let contents = String::from_utf8_lossy(&contents);
+
+        let path = self.path.display().to_string();
+        let bad_line = |number: usize| {
+            Error::fatal(format!(
+                "fatal: bad config line {} in file {}\n",
+                number + 1,
+                path
+            ))
+        };
+
+        self.lines = vec![];
+        let mut section = None;
+        let mut physical_lines = contents.split_inclusive('\n').enumerate();
+
+        while let Some((number, text)) = physical_lines.next() {
+            let mut text = text.to_string();
+
+            if let Some(captures) = SECTION_LINE.captures(text.trim_end()) {
+                section = Some(parse_section(&captures));
+                self.lines.push(Line {
+                    text,
+                    section: section.clone(),
+                    variable: None,
+                });
+                continue;
+            }
+
+            if BLANK_LINE.is_match(text.trim_end()) {
+                self.lines.push(Line {
+                    text,
+                    section: section.clone(),
+                    variable: None,
+                });
+                continue;
+            }
+
+            let variable = loop {
+                let captures =
+                    match VARIABLE_LINE.captures(text.trim_end_matches(&['\r', '\n'][..])) {
+                        Some(captures) if section.is_some() => captures,
+                        _ => return Err(bad_line(number)),
+                    };
+                let name = captures[1].to_lowercase();
+
+                let value = match captures.get(2) {
+                    Some(raw) => match parse_value(raw.as_str()) {
+                        Some(value) => Some(value),
+                        None => match physical_lines.next() {
+                            Some((_, next)) => {
+                                text.push_str(next);
+                                continue;
+                            }
+                            None => return Err(bad_line(number)),
+                        },
+                    },
+                    None => None,
+                };
+
+                break Variable { name, value };
+            };
+
+            self.lines.push(Line {
+                text,
+                section: section.clone(),
+                variable: Some(variable),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Lock the file and read it, ready for changes to be saved
+    pub fn open_for_update(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.lockfile.hold_for_update() {
+            return Err(Error::failure(format!(
+                "error: could not lock config file {}: {}\n",
+                self.path.display(),
+                errors::describe(&e)
+            )));
+        }
+
+        self.open()
+    }
+
+    pub fn save(&mut self) -> Result<(), Error> {
+        let contents: String = self.lines.iter().map(|line| line.text.as_str()).collect();
+
+        self.lockfile
+            .write(&contents)
+            .and_then(|_| self.lockfile.commit())
+            .map_err(|e| Error::io("write", &self.path, e))
+    }
+
+    pub fn get_all(&self, key: &Key) -> Vec<Option<String>> {
+        self.lines
+            .iter()
+            .filter(|line| line.is_variable(key))
+            .map(|line| line.variable.as_ref().unwrap().value.clone())
+            .collect()
+    }
+
+    /// Every variable in the file, in order, under its full name
+    pub fn list(&self) -> Vec<(String, Option<String>)> {
+        self.lines
+            .iter()
+            .filter_map(|line| {
+                let section = line.section.as_ref()?;
+                let variable = line.variable.as_ref()?;
+                let name = match &section.subsection {
+                    Some(subsection) => {
+                        format!("{}.{}.{}", section.name, subsection, variable.name)
+                    }
+                    None => format!("{}.{}", section.name, variable.name),
+                };
+
+                Some((name, variable.value.clone()))
+            })
+            .collect()
+    }
+
+    /// Add a value for `key`, keeping any it already has
+    pub fn add(&mut self, key: &Key, value: &str) {
+        let section = key.section();
+        let line = Line {
+            text: Variable::line(&key.written_name, value),
+            section: Some(section.clone()),
+            variable: Some(Variable {
+                name: key.name.clone(),
+                value: Some(value.to_string()),
+            }),
+        };
+
+        match self
+            .lines
+            .iter()
+            .rposition(|line| line.section.as_ref() == Some(&section))
+        {
+            Some(position) => self.lines.insert(position + 1, line),
+            None => {
+                if let Some(last) = self.lines.last_mut() {
+                    if !last.text.ends_with('\n') {
+                        last.text.push('\n');
+                    }
+                }
+
+                self.lines.push(Line {
+                    text: section.heading_line(),
+                    section: Some(section),
+                    variable: None,
+                });
+                self.lines.push(line);
+            }
+        }
+    }
+
+    /// Set the value of `key`, which must not have more than one
+    pub fn set(&mut self, key: &Key, value: &str) -> Result<(), Error> {
+        let positions = self.positions(key);
+
+        match positions.len() {
+            0 => self.add(key, value),
+            1 => {
+                let line = &mut self.lines[positions[0]];
+                line.text = Variable::line(&key.written_name, value);
+                line.variable.as_mut().unwrap().value = Some(value.to_string());
+            }
+            _ => return Err(Self::multiple_values_error(key)),
+        }
+
+        Ok(())
+    }
+
+    /// Remove the value of `key`, returning whether there was one to
+    /// remove. Fails if it has several.
+    pub fn unset(&mut self, key: &Key) -> Result<bool, Error> {
+        let positions = self.positions(key);
+        if positions.len() > 1 {
+            return Err(Self::multiple_values_error(key));
+        }
+
+        Ok(self.unset_all(key))
+    }
+
+    pub fn unset_all(&mut self, key: &Key) -> bool {
+        let count = self.lines.len();
+        self.lines.retain(|line| !line.is_variable(key));
+
+        self.lines.len() < count
+    }
+
+    fn positions(&self, key: &Key) -> Vec<usize> {
+        (0..self.lines.len())
+            .filter(|&i| self.lines[i].is_variable(key))
+            .collect()
+    }
+
+    fn multiple_values_error(key: &Key) -> Error {
+        Error::failure(format!("warning: {} has multiple values\n", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn open_config(contents: &str) -> (Config, PathBuf) {
+        let path = Path::new("/tmp").join(format!("{}_config_test", generate_temp_name()));
+        fs::write(&path, contents).unwrap();
+        let mut config = Config::new(&path);
+        config.open().unwrap();
+
+        (config, path)
+    }
+
+    fn get(config: &Config, key: &str) -> Vec<Option<String>> {
+        config.get_all(&Key::parse(key).unwrap())
+    }
+
+    #[test]
+    fn parse_sections_subsections_and_values() {
+        let (config, path) = open_config(
+            "# comment
+[Core]
+\tbare
+\tEditor = vim ; trailing comment
+[remote \"Origin\"]
+\turl = \"one ; two\"  # comment
+\turl = esc\\\"aped\\ttab
+[legacy.Sub]
+\tkey = joined \\
+   line
+",
+        );
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(vec![None], get(&config, "core.bare"));
+        assert_eq!(vec![Some("vim".to_string())], get(&config, "CORE.editor"));
+        assert_eq!(
+            vec![
+                Some("one ; two".to_string()),
+                Some("esc\"aped\ttab".to_string())
+            ],
+            get(&config, "remote.Origin.url")
+        );
+        assert!(get(&config, "remote.origin.url").is_empty());
+        assert_eq!(
+            vec![Some("joined    line".to_string())],
+            get(&config, "legacy.sub.key")
+        );
+    }
+
+    #[test]
+    fn reject_malformed_lines() {
+        let path = Path::new("/tmp").join(format!("{}_config_test", generate_temp_name()));
+        fs::write(&path, "[core]\n\tbare\n= value\n").unwrap();
+        let mut config = Config::new(&path);
+        let error = config.open().unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(128, error.exit_code());
+        assert!(error
+            .to_string()
+            .starts_with("fatal: bad config line 3 in file"));
+    }
+
+    #[test]
+    fn validate_keys() {
+        assert!(Key::parse("nosection").is_err());
+        assert!(Key::parse("core.1bad").is_err());
+        assert!(Key::parse("co re.name").is_err());
+        assert!(Key::parse("a.sub.with.dots.name").is_ok());
+    }
+
+    #[test]
+    fn write_changes_and_keep_other_lines() {
+        let (mut config, path) = open_config(
+            "[user]
+\tname = Old  # keep me
+[core]
+\tx = 1
+\tx = 2
+",
+        );
+        config.open_for_update().unwrap();
+        config
+            .set(&Key::parse("user.name").unwrap(), "New")
+            .unwrap();
+        config.add(&Key::parse("user.email").unwrap(), "a@b.c");
+        config
+            .set(&Key::parse("a.B.c").unwrap(), " spaced ")
+            .unwrap();
+        assert!(config.set(&Key::parse("core.x").unwrap(), "3").is_err());
+        assert!(config.unset(&Key::parse("core.x").unwrap()).is_err());
+        assert!(config.unset_all(&Key::parse("core.x").unwrap()));
+        config.save().unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            "[user]
+\tname = New
+\temail = a@b.c
+[core]
+[a \"B\"]
+\tc = \" spaced \"
+",
+            contents
+        );
+    }
+}
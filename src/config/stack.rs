@@ -0,0 +1,226 @@
+use crate::config::{Config, Key};
+use crate::errors::Error;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+/// Read one single-quoted item of GIT_CONFIG_PARAMETERS
+fn read_quoted(chars: &mut Peekable<Chars>) -> Option<String> {
+    if chars.next() != Some('\'') {
+        return None;
+    }
+
+    let mut item = String::new();
+    loop {
+        match chars.next()? {
+            '\'' => match chars.peek() {
+                // A quote in the item is written as '\''
+                Some('\\') => {
+                    chars.next();
+                    item.push(chars.next()?);
+                    if chars.next() != Some('\'') {
+                        return None;
+                    }
+                }
+                _ => return Some(item),
+            },
+            c => item.push(c),
+        }
+    }
+}
+
+/// Which file a config command reads from or writes to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    System,
+    Global,
+    Local,
+}
+
+/// All the config files that apply to a repository, plus any `-c`
+/// overrides, consulted so that later layers take precedence
+pub struct Stack {
+    layers: Vec<Config>,
+    overrides: Vec<(Key, Option<String>)>,
+}
+
+impl Stack {
+    /// Load the config files for the repository in `git_path`, found
+    /// through the variables in `env` rather than the process's own
+    /// environment
+    pub fn load(git_path: &Path, env: &HashMap<String, String>) -> Result<Stack, Error> {
+        let mut paths = vec![];
+        if let Some(path) = Self::system_path(env) {
+            paths.push(path);
+        }
+        paths.extend(Self::global_paths(env));
+        paths.push(Self::local_path(git_path));
+
+        let mut layers = vec![];
+        for path in paths {
+            let mut config = Config::new(&path);
+            config.open()?;
+            layers.push(config);
+        }
+
+        Ok(Stack {
+            layers,
+            overrides: Self::parse_overrides(env)?,
+        })
+    }
+
+    /// The path of the file to write to for `scope`
+    pub fn path(
+        scope: Scope,
+        git_path: &Path,
+        env: &HashMap<String, String>,
+    ) -> Result<PathBuf, Error> {
+        match scope {
+            Scope::System => Self::system_path(env).ok_or_else(|| {
+                Error::fatal("fatal: system config disabled by GIT_CONFIG_NOSYSTEM\n")
+            }),
+            Scope::Global => Self::global_paths(env)
+                .pop()
+                .ok_or_else(|| Error::fatal("fatal: $HOME not set\n")),
+            Scope::Local => Ok(Self::local_path(git_path)),
+        }
+    }
+
+    fn system_path(env: &HashMap<String, String>) -> Option<PathBuf> {
+        if env.contains_key("GIT_CONFIG_NOSYSTEM") {
+            return None;
+        }
+
+        Some(
+            env.get("GIT_CONFIG_SYSTEM")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("/etc/gitconfig")),
+        )
+    }
+
+    /// The XDG config file and `~/.gitconfig`, with the latter being
+    /// the one written to
+    fn global_paths(env: &HashMap<String, String>) -> Vec<PathBuf> {
+        if let Some(path) = env.get("GIT_CONFIG_GLOBAL") {
+            return vec![PathBuf::from(path)];
+        }
+
+        let home = match env.get("HOME") {
+            Some(home) => PathBuf::from(home),
+            None => return vec![],
+        };
+        let config_home = match env.get("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => home.join(".config"),
+        };
+
+        vec![
+            config_home.join("git").join("config"),
+            home.join(".gitconfig"),
+        ]
+    }
+
+    fn local_path(git_path: &Path) -> PathBuf {
+        git_path.join("config")
+    }
+
+    /// `rug -c name=value` passes its overrides on through
+    /// GIT_CONFIG_PARAMETERS, as a list of single-quoted
+    /// `'name=value'` or `'name'='value'` items
+    fn parse_overrides(env: &HashMap<String, String>) -> Result<Vec<(Key, Option<String>)>, Error> {
+        let parameters = env
+            .get("GIT_CONFIG_PARAMETERS")
+            .cloned()
+            .unwrap_or_default();
+        let mut chars = parameters.chars().peekable();
+        let mut overrides = vec![];
+        let bad = || {
+            Error::failure(format!(
+                "error: bogus format in GIT_CONFIG_PARAMETERS: {}\n",
+                parameters
+            ))
+        };
+
+        loop {
+            while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                chars.next();
+            }
+            if chars.peek().is_none() {
+                break;
+            }
+
+            let first = read_quoted(&mut chars).ok_or_else(bad)?;
+            let (name, value) = if chars.peek() == Some(&'=') {
+                chars.next();
+                (first, Some(read_quoted(&mut chars).ok_or_else(bad)?))
+            } else {
+                match first.find('=') {
+                    Some(equals) => (
+                        first[..equals].to_string(),
+                        Some(first[equals + 1..].to_string()),
+                    ),
+                    None => (first, None),
+                }
+            };
+
+            overrides.push((Key::parse(&name)?, value));
+        }
+
+        Ok(overrides)
+    }
+
+    /// Quote a `-c` argument for adding to GIT_CONFIG_PARAMETERS
+    pub fn quote_override(parameter: &str) -> String {
+        format!("'{}'", parameter.replace('\'', "'\\''"))
+    }
+
+    /// The value that takes precedence for `key`. A name given with
+    /// no value reads as "true".
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.get_all(key)
+            .pop()
+            .map(|value| value.unwrap_or_else(|| "true".to_string()))
+    }
+
+    /// The value of `key` read as a boolean, if it is one
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)?.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Some(true),
+            "false" | "no" | "off" | "0" | "" => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<Option<String>> {
+        let key = match Key::parse(key) {
+            Ok(key) => key,
+            Err(_) => return vec![],
+        };
+
+        let mut values: Vec<Option<String>> = self
+            .layers
+            .iter()
+            .flat_map(|config| config.get_all(&key))
+            .collect();
+        values.extend(
+            self.overrides
+                .iter()
+                .filter(|(name, _)| name.matches(&key))
+                .map(|(_, value)| value.clone()),
+        );
+
+        values
+    }
+
+    pub fn list(&self) -> Vec<(String, Option<String>)> {
+        let mut variables: Vec<_> = self.layers.iter().flat_map(Config::list).collect();
+        variables.extend(
+            self.overrides
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone())),
+        );
+
+        variables
+    }
+}
@@ -1,5 +1,5 @@
-use crate::database::object::Object;
-use crate::database::ParsedObject;
+use crate::database::object::{Object, Storable};
+use crate::errors::Error;
 
 #[derive(Debug)]
 pub struct Blob {
@@ -14,7 +14,7 @@ impl Blob {
     }
 }
 
-impl Object for Blob {
+impl Storable for Blob {
     fn r#type(&self) -> String {
         "blob".to_string()
     }
@@ -22,8 +22,10 @@ impl Object for Blob {
     fn to_string(&self) -> Vec<u8> {
         self.data.clone()
     }
+}
 
-    fn parse(s: &[u8]) -> ParsedObject {
-        ParsedObject::Blob(Blob::new(s))
+impl Object for Blob {
+    fn parse(s: &[u8]) -> Result<Blob, Error> {
+        Ok(Blob::new(s))
     }
 }
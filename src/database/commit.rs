@@ -1,24 +1,36 @@
 use chrono::prelude::*;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::fmt;
 use std::str;
 
-use crate::database::{Object, ParsedObject};
+use crate::database::object::{self, Object, Storable};
+use crate::errors::Error;
 
 #[derive(Debug, Clone)]
 pub struct Author {
     pub name: String,
     pub email: String,
     pub time: DateTime<FixedOffset>,
+    // The ident exactly as it was read or written, which is what gets
+    // stored so that rewriting an object keeps its oid
+    raw: Vec<u8>,
+}
+
+impl fmt::Display for Author {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.raw))
+    }
 }
 
 impl Author {
-    fn to_string(&self) -> String {
-        format!(
-            "{} <{}> {}",
-            self.name,
-            self.email,
-            self.time.format("%s %z")
-        )
+    pub fn new(name: String, email: String, time: DateTime<FixedOffset>) -> Author {
+        let raw = format!("{} <{}> {}", name, email, time.format("%s %z")).into_bytes();
+        Author {
+            name,
+            email,
+            time,
+            raw,
+        }
     }
 
     pub fn short_date(&self) -> String {
@@ -29,100 +41,358 @@ impl Author {
         self.time.format("%a %b %-d  %H:%M:%S %Y %Z").to_string()
     }
 
-    pub fn parse(s: &str) -> Author {
-        let split_author_str = s
-            .split(&['<', '>'][..])
-            .map(|s| s.trim())
-            .collect::<Vec<_>>();
+    /// The ident as it is stored
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
 
-        let name = split_author_str[0].to_string();
-        let email = split_author_str[1].to_string();
-        let time = DateTime::parse_from_str(split_author_str[2], "%s %z")
-            .expect("could not parse datetime");
+    /// Read a `Name <email> <seconds> <offset>` line, or None if it
+    /// is not one. The name and email need not be UTF-8.
+    pub fn parse(s: &[u8]) -> Option<Author> {
+        let open = s.iter().position(|&b| b == b'<')?;
+        let close = open + s[open..].iter().position(|&b| b == b'>')?;
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).trim().to_string();
+        let time = str::from_utf8(&s[close + 1..]).ok()?;
+        let time = DateTime::parse_from_str(time.trim(), "%s %z").ok()?;
 
-        Author { name, email, time }
+        Some(Author {
+            name: text(&s[..open]),
+            email: text(&s[open + 1..close]),
+            time,
+            raw: s.to_vec(),
+        })
     }
 }
 
+/// Headers that hold a signature over the rest of the commit
+const SIGNATURE_HEADERS: [&str; 2] = ["gpgsig", "gpgsig-sha256"];
+
 #[derive(Debug, Clone)]
 pub struct Commit {
-    pub parent: Option<String>,
+    pub parents: Vec<String>,
     pub tree_oid: String,
     pub author: Author,
-    pub message: String,
+    pub committer: Author,
+    // Any other headers, such as `encoding`, `mergetag` or `gpgsig`,
+    // in the order they appear. Each value is kept exactly as written
+    // after the name, separating space and continuation lines
+    // included, so that the commit is stored as it was read.
+    pub headers: Vec<(String, Vec<u8>)>,
+    // Not necessarily UTF-8; see the `encoding` header
+    pub message: Vec<u8>,
 }
 
 impl Commit {
     pub fn new(
-        parent: &Option<String>,
+        parents: Vec<String>,
         tree_oid: String,
         author: Author,
-        message: String,
+        committer: Author,
+        message: Vec<u8>,
     ) -> Commit {
         Commit {
-            parent: parent.clone(),
+            parents,
             tree_oid,
             author,
+            committer,
+            headers: vec![],
             message,
         }
     }
 
+    /// The message as text, with any invalid UTF-8 replaced
+    pub fn message(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.message)
+    }
+
     pub fn title_line(&self) -> String {
-        self.message
-            .lines()
-            .next()
-            .expect("could not get first line of commit")
-            .to_string()
+        self.message().lines().next().unwrap_or("").to_string()
+    }
+
+    /// The value of header `name`, with continuation lines joined by
+    /// newlines
+    pub fn header(&self, name: &str) -> Option<Vec<u8>> {
+        let (_, raw) = self.headers.iter().find(|(key, _)| key == name)?;
+        let raw = raw.strip_prefix(b" ").unwrap_or(raw);
+        let mut value = Vec::with_capacity(raw.len());
+        for (i, line) in raw.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                value.push(b'\n');
+            }
+            value.extend_from_slice(line.strip_prefix(b" ").unwrap_or(line));
+        }
+
+        Some(value)
+    }
+
+    /// Add header `name`, folding a value of several lines
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        let raw = format!(" {}", value.replace('\n', "\n "));
+        self.headers.push((name.to_string(), raw.into_bytes()));
+    }
+
+    /// The signature made over this commit, if it is signed
+    pub fn signature(&self) -> Option<String> {
+        SIGNATURE_HEADERS
+            .iter()
+            .find_map(|name| self.header(name))
+            .and_then(|value| String::from_utf8(value).ok())
+    }
+
+    /// Drop any signature, which a rewritten commit no longer matches
+    pub fn remove_signature(&mut self) {
+        self.headers
+            .retain(|(key, _)| !SIGNATURE_HEADERS.contains(&key.as_str()));
+    }
+
+    /// The bytes a signature covers: the whole commit, less the
+    /// signature headers themselves
+    pub fn signature_payload(&self) -> Vec<u8> {
+        let mut unsigned = self.clone();
+        unsigned.remove_signature();
+        unsigned.to_string()
     }
 }
 
-impl Object for Commit {
+impl Storable for Commit {
     fn r#type(&self) -> String {
         "commit".to_string()
     }
 
     fn to_string(&self) -> Vec<u8> {
-        let author_str = self.author.to_string();
-        let mut lines = String::new();
-        lines.push_str(&format!("tree {}\n", self.tree_oid));
-        if let Some(parent_oid) = &self.parent {
-            lines.push_str(&format!("parent {}\n", parent_oid));
+        let mut bytes = format!("tree {}\n", self.tree_oid).into_bytes();
+        for parent_oid in &self.parents {
+            bytes.extend_from_slice(format!("parent {}\n", parent_oid).as_bytes());
+        }
+        for (key, person) in &[("author", &self.author), ("committer", &self.committer)] {
+            bytes.extend_from_slice(format!("{} ", key).as_bytes());
+            bytes.extend_from_slice(person.as_bytes());
+            bytes.push(b'\n');
+        }
+        for (key, value) in &self.headers {
+            bytes.extend_from_slice(key.as_bytes());
+            bytes.extend_from_slice(value);
+            bytes.push(b'\n');
+        }
+        bytes.push(b'\n');
+
+        bytes.extend_from_slice(&self.message);
+        bytes
+    }
+}
+
+impl Object for Commit {
+    fn parse(s: &[u8]) -> Result<Commit, Error> {
+        let corrupt = |reason: &str| object::corrupt("commit", s, reason);
+        let text = |value: &[u8]| String::from_utf8_lossy(value).to_string();
+        let person = |line: Option<&[u8]>, name: &str| {
+            let value = line
+                .and_then(|line| line.strip_prefix(name.as_bytes()))
+                .and_then(|line| line.strip_prefix(b" "))
+                .ok_or_else(|| corrupt(&format!("missing {} line", name)))?;
+            Author::parse(value).ok_or_else(|| {
+                corrupt(&format!(
+                    "invalid ident line: {}",
+                    String::from_utf8_lossy(value)
+                ))
+            })
+        };
+
+        // Headers and the message are separated by an empty line
+        let (headers, message) = match s.windows(2).position(|pair| pair == b"\n\n") {
+            Some(end) => (&s[..end], &s[end + 2..]),
+            None => (s.strip_suffix(b"\n").unwrap_or(s), &b""[..]),
+        };
+        let mut lines = headers.split(|&b| b == b'\n').peekable();
+
+        // The tree comes first, then any parents, the author and the
+        // committer, in that order
+        let tree_oid = lines
+            .next()
+            .and_then(|line| line.strip_prefix(b"tree "))
+            .map(text)
+            .ok_or_else(|| corrupt("missing tree line"))?;
+        if !object::is_oid(&tree_oid) {
+            return Err(corrupt("invalid 'tree' line format - bad sha1"));
         }
-        lines.push_str(&format!("author {}\n", author_str));
-        lines.push_str(&format!("committer {}\n", author_str));
-        lines.push_str("\n");
-        lines.push_str(&self.message);
-
-        lines.as_bytes().to_vec()
-    }
-
-    fn parse(s: &[u8]) -> ParsedObject {
-        let mut s = str::from_utf8(s).expect("invalid utf-8");
-        let mut headers = HashMap::new();
-        // Parse headers
-        loop {
-            if let Some(newline) = s.find('\n') {
-                let line = &s[..newline];
-                s = &s[newline + 1..];
-
-                // Headers and commit message is separated by empty
-                // line
-                if line == "" {
-                    break;
-                }
-
-                let v: Vec<&str> = line.splitn(2, ' ').collect();
-                headers.insert(v[0], v[1]);
-            } else {
-                panic!("no body in commit");
+        let mut parents = vec![];
+        while let Some(parent) = lines.peek().and_then(|line| line.strip_prefix(b"parent ")) {
+            let parent = text(parent);
+            if !object::is_oid(&parent) {
+                return Err(corrupt("invalid 'parent' line format - bad sha1"));
             }
+            parents.push(parent);
+            lines.next();
+        }
+        let author = person(lines.next(), "author")?;
+        let committer = person(lines.next(), "committer")?;
+
+        let mut extra: Vec<(String, Vec<u8>)> = vec![];
+        for line in lines {
+            // A leading space continues the previous header's value
+            if line.starts_with(b" ") {
+                let (_, value) = extra
+                    .last_mut()
+                    .ok_or_else(|| corrupt("continuation line without a header"))?;
+                value.push(b'\n');
+                value.extend_from_slice(line);
+                continue;
+            }
+
+            let split = line.iter().position(|&b| b == b' ').unwrap_or(line.len());
+            let (key, rest) = line.split_at(split);
+            extra.push((text(key), rest.to_vec()));
         }
 
-        ParsedObject::Commit(Commit::new(
-            &headers.get("parent").map(|s| s.to_string()),
-            headers.get("tree").expect("no tree header").to_string(),
-            Author::parse(headers.get("author").expect("no author found in commit")),
-            s.to_string(),
-        ))
+        Ok(Commit {
+            parents,
+            tree_oid,
+            author,
+            committer,
+            headers: extra,
+            message: message.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_merge_commit() {
+        let data = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+parent 1111111111111111111111111111111111111111
+parent 2222222222222222222222222222222222222222
+parent 3333333333333333333333333333333333333333
+author A. U. Thor <author@example.com> 1577836800 +0530
+committer C. O. Mitter <committer@example.com> 1577840400 -0800
+
+Merge branches 'a' and 'b'
+";
+
+        let commit = Commit::parse(data).unwrap();
+
+        assert_eq!(
+            vec![
+                "1111111111111111111111111111111111111111",
+                "2222222222222222222222222222222222222222",
+                "3333333333333333333333333333333333333333",
+            ],
+            commit.parents
+        );
+        assert_eq!("A. U. Thor", commit.author.name);
+        assert_eq!("C. O. Mitter", commit.committer.name);
+        assert_eq!("-08:00", commit.committer.time.offset().to_string());
+        assert_eq!(&data[..], &commit.to_string()[..]);
+    }
+
+    const SIGNED: &[u8] = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author A. U. Thor <author@example.com> 1577836800 +0530
+committer A. U. Thor <author@example.com> 1577836800 +0530
+encoding ISO-8859-1
+gpgsig -----BEGIN PGP SIGNATURE-----
+ 
+ iQEzBAABCAAdFiEE
+ -----END PGP SIGNATURE-----
+
+Caf\xe9
+";
+
+    #[test]
+    fn round_trip_extra_headers_and_raw_messages() {
+        let commit = Commit::parse(SIGNED).unwrap();
+
+        assert_eq!(Some(b"ISO-8859-1".to_vec()), commit.header("encoding"));
+        assert_eq!(
+            Some(
+                "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----"
+                    .to_string()
+            ),
+            commit.signature()
+        );
+        assert_eq!(b"Caf\xe9\n".to_vec(), commit.message);
+        assert_eq!(SIGNED, &commit.to_string()[..]);
+    }
+
+    #[test]
+    fn round_trip_raw_idents_and_headers() {
+        let data = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author Ren\xe9  <rene@example.com> 1577836800 -0000
+committer C. O. Mitter <committer@example.com>  1577840400 +0100
+encoding ISO-8859-1
+empty
+blank 
+
+caf\xe9
+";
+
+        let commit = Commit::parse(data).unwrap();
+
+        assert_eq!("Ren\u{fffd}", commit.author.name);
+        assert_eq!("+00:00", commit.author.time.offset().to_string());
+        assert_eq!(Some(vec![]), commit.header("empty"));
+        assert_eq!(&data[..], &commit.to_string()[..]);
+        assert_eq!("4459bbe08a70b0fc8da26741c8cc8d54516711a8", commit.get_oid());
+    }
+
+    #[test]
+    fn leave_the_signature_out_of_its_payload() {
+        let commit = Commit::parse(SIGNED).unwrap();
+
+        assert_eq!(
+            &b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author A. U. Thor <author@example.com> 1577836800 +0530
+committer A. U. Thor <author@example.com> 1577836800 +0530
+encoding ISO-8859-1
+
+Caf\xe9
+"[..],
+            &commit.signature_payload()[..]
+        );
+    }
+
+    #[test]
+    fn report_damaged_commits_as_corrupt() {
+        for (data, reason) in &[
+            (&b"author A <a@b> 1 +0000\n\nmsg\n"[..], "missing tree line"),
+            (
+                &b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\nmsg\n"[..],
+                "missing author line",
+            ),
+            (
+                &b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author A <a@b> 1 +0000
+committer A <a@b> 1 +0000
+ continued
+"[..],
+                "continuation line without a header",
+            ),
+            (
+                &b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\nauthor nobody\n"[..],
+                "invalid ident line: nobody",
+            ),
+            (
+                &b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author A <a@b> 1 +0000
+
+msg
+"[..],
+                "missing committer line",
+            ),
+            (
+                &b"tree 4b825dc\n"[..],
+                "invalid 'tree' line format - bad sha1",
+            ),
+            (
+                &b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\nparent HEAD\n"[..],
+                "invalid 'parent' line format - bad sha1",
+            ),
+        ] {
+            match Commit::parse(data) {
+                Err(Error::CorruptObject { reason: actual, .. }) => assert_eq!(*reason, actual),
+                other => panic!("expected a corrupt object, got {:?}", other),
+            }
+        }
     }
 }
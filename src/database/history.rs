@@ -0,0 +1,63 @@
+use crate::database::commit::Commit;
+use crate::database::{Database, ParsedObject};
+use crate::errors::Error;
+use std::collections::HashSet;
+
+/// The commits reachable from a set of starting points, as `rug log`
+/// lists them
+pub struct History<'a> {
+    database: &'a Database,
+    // Commits waiting to be listed, and every commit that has ever been
+    // queued so that shared ancestors of a merge are listed once
+    queue: Vec<Commit>,
+    seen: HashSet<String>,
+}
+
+impl<'a> History<'a> {
+    pub fn new(database: &'a Database, starts: &[String]) -> Result<History<'a>, Error> {
+        let mut history = History {
+            database,
+            queue: vec![],
+            seen: HashSet::new(),
+        };
+        for oid in starts {
+            history.enqueue(oid)?;
+        }
+
+        Ok(history)
+    }
+
+    fn enqueue(&mut self, oid: &str) -> Result<(), Error> {
+        if !self.seen.insert(oid.to_string()) {
+            return Ok(());
+        }
+
+        if let ParsedObject::Commit(commit) = self.database.load_raw(oid)?.parse()? {
+            self.queue.push(commit);
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for History<'a> {
+    type Item = Result<Commit, Error>;
+
+    /// Commits are listed newest first. Commits with the same date
+    /// come out in the order they were queued.
+    fn next(&mut self) -> Option<Result<Commit, Error>> {
+        let position = (0..self.queue.len()).max_by(|&a, &b| {
+            let (a_time, b_time) = (self.queue[a].committer.time, self.queue[b].committer.time);
+            a_time.cmp(&b_time).then(b.cmp(&a))
+        })?;
+        let commit = self.queue.remove(position);
+
+        for parent in &commit.parents {
+            if let Err(error) = self.enqueue(parent) {
+                return Some(Err(error));
+            }
+        }
+
+        Some(Ok(commit))
+    }
+}
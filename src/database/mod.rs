@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::fs::{self, OpenOptions};
 use std::io::prelude::*;
+use std::io::{self, ErrorKind};
 use std::path::{Path, PathBuf};
 use std::str;
 
@@ -8,25 +9,34 @@ use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 
+use crate::errors::{self, Error};
 use crate::index;
 use crate::util::*;
 
 pub mod blob;
 pub mod commit;
+pub mod history;
 pub mod object;
+pub mod packed;
+pub mod reachable;
+pub mod tag;
 pub mod tree;
 pub mod tree_diff;
 
 use blob::Blob;
 use commit::Commit;
-use object::Object;
-use tree::{Tree, TREE_MODE};
+use object::{Object, Storable};
+use packed::Packed;
+use tag::Tag;
+use tree::{Tree, GITLINK_MODE, SYMLINK_MODE, TREE_MODE};
+use tree_diff::{TreeChanges, TreeDiff};
 
 #[derive(Debug)]
 pub enum ParsedObject {
     Commit(Commit),
     Blob(Blob),
     Tree(Tree),
+    Tag(Tag),
 }
 
 impl ParsedObject {
@@ -35,6 +45,7 @@ impl ParsedObject {
             ParsedObject::Commit(_) => "commit",
             ParsedObject::Blob(_) => "blob",
             ParsedObject::Tree(_) => "tree",
+            ParsedObject::Tag(_) => "tag",
         }
     }
 
@@ -43,10 +54,44 @@ impl ParsedObject {
             ParsedObject::Commit(obj) => obj.get_oid(),
             ParsedObject::Blob(obj) => obj.get_oid(),
             ParsedObject::Tree(obj) => obj.get_oid(),
+            ParsedObject::Tag(obj) => obj.get_oid(),
         }
     }
 }
 
+/// An object's type and serialized content, before parsing
+#[derive(Debug, Clone)]
+pub struct Raw {
+    pub obj_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Raw {
+    pub fn parse(&self) -> Result<ParsedObject, Error> {
+        match self.obj_type.as_str() {
+            "commit" => Commit::parse(&self.data).map(ParsedObject::Commit),
+            "blob" => Blob::parse(&self.data).map(ParsedObject::Blob),
+            "tree" => Tree::parse(&self.data).map(ParsedObject::Tree),
+            "tag" => Tag::parse(&self.data).map(ParsedObject::Tag),
+            obj_type => Err(Error::CorruptObject {
+                oid: self.get_oid(),
+                reason: format!("invalid object type \"{}\"", obj_type),
+            }),
+        }
+    }
+}
+
+// Raw objects are stored as they are, whatever their type
+impl Storable for Raw {
+    fn r#type(&self) -> String {
+        self.obj_type.clone()
+    }
+
+    fn to_string(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct Entry {
     name: String,
@@ -80,88 +125,202 @@ impl Entry {
     }
 
     fn mode(&self) -> u32 {
-        if self.mode == TREE_MODE {
-            return TREE_MODE;
+        // Trees, symlinks and gitlinks keep their modes as they are
+        match self.mode & 0o170000 {
+            TREE_MODE | SYMLINK_MODE | GITLINK_MODE => return self.mode & 0o170000,
+            _ => (),
         }
         if self.is_executable() {
-            return 0o100755;
+            0o100755
         } else {
-            return 0o100644;
+            0o100644
         }
     }
 }
 
+// Objects are written to files with this prefix, then renamed into
+// place
+const TEMP_PREFIX: &str = "tmp_obj_";
+
 pub struct Database {
     path: PathBuf,
     objects: HashMap<String, ParsedObject>,
+    packs: Vec<Packed>,
 }
 
 impl Database {
-    pub fn new(path: &Path) -> Database {
-        Database {
+    pub fn new(path: &Path) -> Result<Database, Error> {
+        let pack_path = path.join("pack");
+        let packs =
+            Packed::load_all(&pack_path).map_err(|e| Error::io("load packs in", pack_path, e))?;
+
+        Ok(Database {
             path: path.to_path_buf(),
             objects: HashMap::new(),
+            packs,
+        })
+    }
+
+    pub fn pack_path(&self) -> PathBuf {
+        self.path.join("pack")
+    }
+
+    pub fn packs(&self) -> &[Packed] {
+        &self.packs
+    }
+
+    /// Compare two commits or trees, returning the old and new entries
+    /// for every path that differs between them
+    pub fn tree_diff(&mut self, a: Option<&str>, b: Option<&str>) -> Result<TreeChanges, Error> {
+        let mut diff = TreeDiff::new(self);
+        diff.compare_oids(a.map(String::from), b.map(String::from), Path::new(""))?;
+        Ok(diff.changes)
+    }
+
+    /// Check whether an object exists, either loose or in a pack
+    pub fn has(&self, oid: &str) -> bool {
+        self.object_path(oid).exists() || self.packs.iter().any(|pack| pack.has(oid))
+    }
+
+    pub fn read_object(&self, oid: &str) -> Result<ParsedObject, Error> {
+        self.load_raw(oid)?.parse()
+    }
+
+    /// Look up an object's type and content, trying loose objects
+    /// first and then each packfile
+    pub fn load_raw(&self, oid: &str) -> Result<Raw, Error> {
+        if let Some(raw) = self.read_loose(oid)? {
+            return Ok(raw);
+        }
+
+        for pack in &self.packs {
+            match pack.load_raw(oid, self) {
+                Ok(Some(raw)) => return Ok(raw),
+                Ok(None) => (),
+                Err(error) => {
+                    return Err(Error::CorruptObject {
+                        oid: oid.to_string(),
+                        reason: format!("failed to read packed object {}: {}", oid, error),
+                    })
+                }
+            }
         }
+
+        Err(Error::MissingObject {
+            oid: oid.to_string(),
+        })
     }
 
-    pub fn read_object(&self, oid: &str) -> Option<ParsedObject> {
-        let mut contents = vec![];
-        let mut file = OpenOptions::new()
-            .read(true)
-            .create(false)
-            .open(self.object_path(oid))
-            .unwrap_or_else(|_| panic!("failed to open file: {:?}", self.object_path(oid)));
-        file.read_to_end(&mut contents)
-            .expect("reading file failed");
-
-        let mut z = ZlibDecoder::new(&contents[..]);
-        let mut v = vec![];
-        z.read_to_end(&mut v).unwrap();
-        let mut vs = &v[..];
-
-        let (obj_type, rest) = match vs
-            .splitn(2, |c| *c as char == ' ')
-            .collect::<Vec<&[u8]>>()
-            .as_slice()
-        {
-            &[type_bytes, rest] => (
-                str::from_utf8(type_bytes).expect("failed to parse type"),
-                rest,
-            ),
-            _ => panic!("EOF while parsing type"),
-        };
-        vs = rest;
-
-        let (_size, rest) = match *vs
-            .splitn(2, |c| *c as char == '\u{0}')
-            .collect::<Vec<&[u8]>>()
-            .as_slice()
-        {
-            [size_bytes, rest] => (
-                str::from_utf8(size_bytes).expect("failed to parse size"),
-                rest,
-            ),
-            _ => panic!("EOF while parsing size"),
-        };
+    /// The object an annotated tag ultimately points at, or None if
+    /// `oid` is not a tag
+    pub fn peel_tag(&self, oid: &str) -> Option<String> {
+        let mut peeled = None;
+        let mut object = self.read_object(oid).ok()?;
 
-        match obj_type {
-            "commit" => Some(Commit::parse(&rest)),
-            "blob" => Some(Blob::parse(&rest)),
-            "tree" => Some(Tree::parse(&rest)),
-            _ => unimplemented!(),
+        while let ParsedObject::Tag(tag) = object {
+            object = self.read_object(&tag.object).ok()?;
+            peeled = Some(tag.object);
         }
+
+        peeled
     }
 
-    pub fn load(&mut self, oid: &str) -> &ParsedObject {
-        let o = self.read_object(oid);
-        self.objects.insert(oid.to_string(), o.unwrap());
+    /// The tree a commit, a tree or a tag pointing at either stands
+    /// for
+    pub fn peel_tree(&self, oid: &str) -> Option<String> {
+        let oid = self.peel_tag(oid).unwrap_or_else(|| oid.to_string());
+        match self.read_object(&oid).ok()? {
+            ParsedObject::Commit(commit) => Some(commit.tree_oid),
+            ParsedObject::Tree(_) => Some(oid),
+            _ => None,
+        }
+    }
 
-        self.objects.get(oid).unwrap()
+    fn read_loose(&self, oid: &str) -> Result<Option<Raw>, Error> {
+        let path = self.object_path(oid);
+        match fs::read(&path) {
+            Ok(contents) => {
+                Self::decode_loose(&contents)
+                    .map(Some)
+                    .map_err(|reason| Error::CorruptObject {
+                        oid: oid.to_string(),
+                        reason: format!("{}: {}", path.display(), reason),
+                    })
+            }
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::io("read object", path, e)),
+        }
+    }
+
+    /// Read a loose object, describing rather than panicking on damage
+    /// to its file, for fsck and prune to report alongside the oid
+    pub fn read_loose_checked(&self, oid: &str) -> Result<Raw, String> {
+        let contents = fs::read(self.object_path(oid)).map_err(|e| errors::describe(&e))?;
+        Self::decode_loose(&contents)
+    }
+
+    /// Inflate a loose object file, then split its `<type> <size>`
+    /// header from the content
+    fn decode_loose(contents: &[u8]) -> Result<Raw, String> {
+        let mut data = vec![];
+        ZlibDecoder::new(contents)
+            .read_to_end(&mut data)
+            .map_err(|e| format!("inflate failed: {}", e))?;
+
+        let nul = data
+            .iter()
+            .position(|&c| c == 0)
+            .ok_or_else(|| "missing object header".to_string())?;
+        let header = str::from_utf8(&data[..nul]).map_err(|_| "invalid object header")?;
+        let (obj_type, size) = header
+            .split_once(' ')
+            .ok_or_else(|| "invalid object header".to_string())?;
+        let size: usize = size.parse().map_err(|_| "invalid object size")?;
+
+        let content = data[nul + 1..].to_vec();
+        if content.len() != size {
+            return Err(format!(
+                "object size {} does not match header size {}",
+                content.len(),
+                size
+            ));
+        }
+
+        Ok(Raw {
+            obj_type: obj_type.to_string(),
+            data: content,
+        })
+    }
+
+    /// The IDs of every loose object, in no particular order
+    pub fn loose_oids(&self) -> Vec<String> {
+        let mut oids = vec![];
+        for dir in fs::read_dir(&self.path).into_iter().flatten().flatten() {
+            let prefix = dir.file_name().to_string_lossy().to_string();
+            if prefix.len() != 2 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+            for file in fs::read_dir(dir.path()).into_iter().flatten().flatten() {
+                let rest = file.file_name().to_string_lossy().to_string();
+                if rest.len() == 38 && rest.chars().all(|c| c.is_ascii_hexdigit()) {
+                    oids.push(format!("{}{}", prefix, rest));
+                }
+            }
+        }
+
+        oids
+    }
+
+    pub fn load(&mut self, oid: &str) -> Result<&ParsedObject, Error> {
+        let o = self.read_object(oid)?;
+        self.objects.insert(oid.to_string(), o);
+
+        Ok(&self.objects[oid])
     }
 
     pub fn store<T>(&self, obj: &T) -> Result<(), std::io::Error>
     where
-        T: Object,
+        T: Storable,
     {
         let oid = obj.get_oid();
         let content = obj.get_content();
@@ -169,7 +328,7 @@ impl Database {
         self.write_object(oid, content)
     }
 
-    fn object_path(&self, oid: &str) -> PathBuf {
+    pub fn object_path(&self, oid: &str) -> PathBuf {
         let dir: &str = &oid[0..2];
         let filename: &str = &oid[2..];
 
@@ -181,13 +340,13 @@ impl Database {
 
         // If object already exists, we are certain that the contents
         // have not changed. So there is no need to write it again.
-        if object_path.exists() {
+        if self.has(&oid) {
             return Ok(());
         }
 
         let dir_path = object_path.parent().expect("invalid parent path");
         fs::create_dir_all(dir_path)?;
-        let mut temp_file_name = String::from("tmp_obj_");
+        let mut temp_file_name = String::from(TEMP_PREFIX);
         temp_file_name.push_str(&generate_temp_name());
         let temp_path = dir_path.join(temp_file_name);
 
@@ -206,6 +365,45 @@ impl Database {
         Ok(())
     }
 
+    /// Delete the loose copy of an object, along with its directory
+    /// if that leaves it empty
+    pub fn remove_loose(&self, oid: &str) -> io::Result<()> {
+        self.remove_file(&self.object_path(oid))
+    }
+
+    /// Files left behind by writes of loose objects that never
+    /// finished
+    pub fn temp_files(&self) -> Vec<PathBuf> {
+        let mut paths = vec![];
+        for dir in fs::read_dir(&self.path).into_iter().flatten().flatten() {
+            for file in fs::read_dir(dir.path()).into_iter().flatten().flatten() {
+                if file.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+                    paths.push(file.path());
+                }
+            }
+        }
+        paths.sort();
+
+        paths
+    }
+
+    /// Delete a file from one of the object directories, and the
+    /// directory too if that leaves it empty
+    pub fn remove_file(&self, path: &Path) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Ok(()) => (),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        let dir_path = path.parent().expect("invalid parent path");
+        if fs::read_dir(dir_path)?.next().is_none() {
+            fs::remove_dir(dir_path)?;
+        }
+
+        Ok(())
+    }
+
     pub fn short_oid(oid: &str) -> &str {
         &oid[0..6]
     }
@@ -216,8 +414,13 @@ impl Database {
             .parent()
             .expect("Could not get parent from object_path");
 
-        let oids: Vec<_> = fs::read_dir(&dirname)
-            .expect("read_dir call failed")
+        let loose = match fs::read_dir(dirname) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return self.packed_prefix_match(name),
+            Err(e) => panic!("read_dir call failed: {}", e),
+        };
+
+        let mut oids: Vec<_> = loose
             .map(|f| {
                 format!(
                     "{}{}",
@@ -235,6 +438,25 @@ impl Database {
             .filter(|o| o.starts_with(name))
             .collect();
 
+        for oid in self.packed_prefix_match(name) {
+            if !oids.contains(&oid) {
+                oids.push(oid);
+            }
+        }
+
+        oids
+    }
+
+    fn packed_prefix_match(&self, name: &str) -> Vec<String> {
+        let mut oids: Vec<String> = vec![];
+        for pack in &self.packs {
+            for oid in pack.prefix_match(name) {
+                if !oids.contains(&oid) {
+                    oids.push(oid);
+                }
+            }
+        }
+
         oids
     }
 }
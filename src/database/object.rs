@@ -1,29 +1,60 @@
-use crate::database::ParsedObject;
+use crate::errors::Error;
 use crypto::digest::Digest;
 use crypto::sha1::Sha1;
 
-pub trait Object {
+/// Anything that can be written to the database: a type and the data
+/// stored under it
+pub trait Storable {
     fn r#type(&self) -> String;
     fn to_string(&self) -> Vec<u8>;
 
-    fn parse(s: &[u8]) -> ParsedObject;
-
     fn get_oid(&self) -> String {
-        let mut hasher = Sha1::new();
-        hasher.input(&self.get_content());
-        hasher.result_str()
+        hash(&self.get_content())
     }
 
     fn get_content(&self) -> Vec<u8> {
-        // TODO: need to do something to force ASCII encoding?
-        let string = self.to_string();
-        let mut content: Vec<u8> = self.r#type().as_bytes().to_vec();
+        content(&self.r#type(), &self.to_string())
+    }
+}
+
+/// An object of one of git's types, which can also be read back
+pub trait Object: Storable {
+    /// Read an object back from its serialized form, failing if it
+    /// is damaged
+    fn parse(s: &[u8]) -> Result<Self, Error>
+    where
+        Self: Sized;
+}
+
+/// An object as it is hashed and stored: a `<type> <size>` header,
+/// then the data
+fn content(obj_type: &str, data: &[u8]) -> Vec<u8> {
+    let mut content: Vec<u8> = obj_type.as_bytes().to_vec();
 
-        content.push(0x20);
-        content.extend_from_slice(format!("{}", string.len()).as_bytes());
-        content.push(0x0);
-        content.extend_from_slice(&string);
+    content.push(0x20);
+    content.extend_from_slice(format!("{}", data.len()).as_bytes());
+    content.push(0x0);
+    content.extend_from_slice(data);
+
+    content
+}
+
+fn hash(content: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input(content);
+    hasher.result_str()
+}
+
+/// Whether `name` is written as an object's full oid
+pub fn is_oid(name: &str) -> bool {
+    name.len() == 40 && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
 
-        content
+/// The error for a `obj_type` object whose data cannot be parsed,
+/// naming it by the oid the data hashes to
+pub fn corrupt(obj_type: &str, data: &[u8], reason: &str) -> Error {
+    Error::CorruptObject {
+        oid: hash(&content(obj_type, data)),
+        reason: reason.to_string(),
     }
 }
@@ -0,0 +1,168 @@
+use crate::database::{Database, Raw};
+use crate::pack::expander::Expander;
+use crate::pack::index::Index;
+use crate::pack::reader::Reader;
+use crate::pack::Record;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// An object store backed by a single `.pack`/`.idx` pair
+pub struct Packed {
+    idx_path: PathBuf,
+    index: Index,
+    reader: Reader,
+}
+
+impl Packed {
+    pub fn new(idx_path: &Path) -> io::Result<Packed> {
+        Ok(Packed {
+            idx_path: idx_path.to_path_buf(),
+            index: Index::load(idx_path)?,
+            reader: Reader::open(&idx_path.with_extension("pack"))?,
+        })
+    }
+
+    /// Open every pack in `pack_dir`, newest first so that recently
+    /// written packs are searched before older ones
+    pub fn load_all(pack_dir: &Path) -> io::Result<Vec<Packed>> {
+        let mut idx_paths = match fs::read_dir(pack_dir) {
+            Ok(entries) => entries
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()?,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        idx_paths.retain(|path| path.extension().map(|ext| ext == "idx").unwrap_or(false));
+
+        let mut packs = vec![];
+        for path in idx_paths {
+            let mtime = fs::metadata(&path)?.modified()?;
+            packs.push((mtime, Packed::new(&path)?));
+        }
+        packs.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        Ok(packs.into_iter().map(|(_, pack)| pack).collect())
+    }
+
+    pub fn idx_path(&self) -> &Path {
+        &self.idx_path
+    }
+
+    pub fn oids(&self) -> &[String] {
+        self.index.oids()
+    }
+
+    /// Delete the pack and its index from disk
+    pub fn remove(&self) -> io::Result<()> {
+        fs::remove_file(self.idx_path.with_extension("pack"))?;
+        fs::remove_file(&self.idx_path)
+    }
+
+    pub fn has(&self, oid: &str) -> bool {
+        self.index.oid_offset(oid).is_some()
+    }
+
+    pub fn prefix_match(&self, name: &str) -> Vec<String> {
+        self.index.prefix_match(name)
+    }
+
+    /// `database` is used to find the bases of `REF_DELTA` records,
+    /// which may live outside this pack
+    pub fn load_raw(&self, oid: &str, database: &Database) -> io::Result<Option<Raw>> {
+        match self.index.oid_offset(oid) {
+            Some(offset) => self.load_raw_at(offset, database).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn load_raw_at(&self, offset: u64, database: &Database) -> io::Result<Raw> {
+        match self.reader.read_record(offset)? {
+            Record::Object { obj_type, data } => Ok(Raw {
+                obj_type: obj_type.to_string(),
+                data,
+            }),
+            Record::OfsDelta { base_ofs, delta } => {
+                let base_offset = offset.checked_sub(base_ofs).ok_or_else(|| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("bad delta base offset at {}", offset),
+                    )
+                })?;
+                let base = self.load_raw_at(base_offset, database)?;
+                Self::expand(base, &delta)
+            }
+            Record::RefDelta { base_oid, delta } => {
+                let base = database.load_raw(&base_oid).map_err(|_| {
+                    io::Error::new(
+                        ErrorKind::NotFound,
+                        format!("missing delta base {}", base_oid),
+                    )
+                })?;
+                Self::expand(base, &delta)
+            }
+        }
+    }
+
+    fn expand(base: Raw, delta: &[u8]) -> io::Result<Raw> {
+        Ok(Raw {
+            data: Expander::expand(&base.data, delta)?,
+            obj_type: base.obj_type,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    fn assert_objects_match_git(cmd_helper: &CommandHelper) {
+        let repo = cmd_helper.repo();
+        let listing = cmd_helper.git_cmd(&["rev-list", "--objects", "--all"]);
+        let oids: Vec<&str> = listing
+            .lines()
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        assert!(oids.len() > 5);
+
+        for oid in oids {
+            let obj_type = cmd_helper.git_cmd(&["cat-file", "-t", oid]);
+            let data = cmd_helper.git_output(&["cat-file", obj_type.trim(), oid]);
+            let raw = repo.database.load_raw(oid).unwrap();
+
+            assert_eq!(obj_type.trim(), raw.obj_type);
+            assert_eq!(data, raw.data);
+        }
+    }
+
+    #[test]
+    fn read_objects_stored_as_offset_deltas() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_history(&mut cmd_helper);
+        cmd_helper.git_cmd(&["repack", "-adf"]);
+
+        assert_objects_match_git(&cmd_helper);
+    }
+
+    #[test]
+    fn read_objects_stored_as_ref_deltas() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_history(&mut cmd_helper);
+        cmd_helper.git_cmd(&["-c", "repack.useDeltaBaseOffset=false", "repack", "-adf"]);
+
+        assert_objects_match_git(&cmd_helper);
+    }
+
+    #[test]
+    fn resolve_abbreviated_oids_from_packs() {
+        let mut cmd_helper = CommandHelper::new();
+        setup_history(&mut cmd_helper);
+        cmd_helper.git_cmd(&["repack", "-adf"]);
+
+        let head = cmd_helper.git_cmd(&["rev-parse", "HEAD"]);
+        let matches = cmd_helper.repo().database.prefix_match(&head[0..7]);
+        assert_eq!(vec![head.trim().to_string()], matches);
+
+        cmd_helper.assert_status("");
+    }
+}
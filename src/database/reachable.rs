@@ -0,0 +1,65 @@
+use crate::database::tree::TreeEntry;
+use crate::database::{Database, ParsedObject};
+use crate::errors::Error;
+use std::collections::HashSet;
+
+/// An object found by walking from a set of starting points, along
+/// with the path it was found at(empty for commits and root trees)
+pub struct ReachableObject {
+    pub oid: String,
+    pub path: String,
+}
+
+/// Lists every object reachable from `roots`, following commit
+/// parents and tree entries. Fails if any object is missing.
+pub fn reachable_objects(
+    database: &Database,
+    roots: &[String],
+) -> Result<Vec<ReachableObject>, Error> {
+    let mut seen = HashSet::new();
+    let mut objects = vec![];
+    let mut pending: Vec<(String, String)> = roots
+        .iter()
+        .rev()
+        .map(|oid| (oid.to_string(), String::new()))
+        .collect();
+
+    while let Some((oid, path)) = pending.pop() {
+        if !seen.insert(oid.clone()) {
+            continue;
+        }
+
+        let raw = database.load_raw(&oid)?;
+
+        match raw.parse()? {
+            ParsedObject::Commit(commit) => {
+                for parent in commit.parents.into_iter().rev() {
+                    pending.push((parent, String::new()));
+                }
+                pending.push((commit.tree_oid, String::new()));
+            }
+            ParsedObject::Tree(tree) => {
+                for (name, entry) in tree.entries.iter().rev() {
+                    let entry_path = if path.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{}/{}", path, name)
+                    };
+
+                    // Gitlinks point into another repository
+                    if let TreeEntry::Entry(_) = entry {
+                        if !entry.is_gitlink() {
+                            pending.push((entry.get_oid(), entry_path));
+                        }
+                    }
+                }
+            }
+            ParsedObject::Tag(tag) => pending.push((tag.object, String::new())),
+            ParsedObject::Blob(_) => (),
+        }
+
+        objects.push(ReachableObject { oid, path });
+    }
+
+    Ok(objects)
+}
@@ -0,0 +1,148 @@
+use std::str;
+
+use crate::database::commit::Author;
+use crate::database::object::{self, Object, Storable};
+use crate::errors::Error;
+
+/// An annotated tag, naming another object along with who tagged it
+/// and why
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub object: String,
+    pub obj_type: String,
+    pub name: String,
+    // Very old tags were written without a tagger
+    pub tagger: Option<Author>,
+    pub message: String,
+}
+
+impl Tag {
+    pub fn new(object: &str, obj_type: &str, name: &str, tagger: Author, message: String) -> Tag {
+        Tag {
+            object: object.to_string(),
+            obj_type: obj_type.to_string(),
+            name: name.to_string(),
+            tagger: Some(tagger),
+            message,
+        }
+    }
+}
+
+impl Storable for Tag {
+    fn r#type(&self) -> String {
+        "tag".to_string()
+    }
+
+    fn to_string(&self) -> Vec<u8> {
+        let mut lines = String::new();
+        lines.push_str(&format!("object {}\n", self.object));
+        lines.push_str(&format!("type {}\n", self.obj_type));
+        lines.push_str(&format!("tag {}\n", self.name));
+        if let Some(tagger) = &self.tagger {
+            lines.push_str(&format!("tagger {}\n", tagger));
+        }
+        lines.push('\n');
+        lines.push_str(&self.message);
+
+        lines.as_bytes().to_vec()
+    }
+}
+
+impl Object for Tag {
+    fn parse(data: &[u8]) -> Result<Tag, Error> {
+        let corrupt = |reason: &str| object::corrupt("tag", data, reason);
+        let s = str::from_utf8(data).map_err(|_| corrupt("invalid utf-8"))?;
+        // The blank line before the message is missing when there is
+        // no message
+        let (headers, message) = match s.find("\n\n") {
+            Some(end) => (&s[..end], &s[end + 2..]),
+            None => (s.trim_end_matches('\n'), ""),
+        };
+
+        // The object, its type and the tag's name come in that order,
+        // then the tagger if there is one
+        let mut lines = headers.lines();
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|line| line.strip_prefix(' '))
+                .ok_or_else(|| corrupt(&format!("missing {} line", name)))
+        };
+        let object = field("object")?;
+        if !object::is_oid(object) {
+            return Err(corrupt("invalid 'object' line format - bad sha1"));
+        }
+        let obj_type = field("type")?;
+        if !["blob", "tree", "commit", "tag"].contains(&obj_type) {
+            return Err(corrupt(&format!("invalid 'type' value: {}", obj_type)));
+        }
+        let name = field("tag")?;
+        let tagger = match field("tagger") {
+            Ok(value) => Some(
+                Author::parse(value.as_bytes())
+                    .ok_or_else(|| corrupt(&format!("invalid ident line: {}", value)))?,
+            ),
+            Err(_) => None,
+        };
+
+        Ok(Tag {
+            object: object.to_string(),
+            obj_type: obj_type.to_string(),
+            name: name.to_string(),
+            tagger,
+            message: message.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_tag() {
+        let data = b"object 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+type commit
+tag v1.0
+tagger A. U. Thor <author@example.com> 1577836800 +0530
+
+Release 1.0
+
+With notes.
+";
+
+        let tag = Tag::parse(data).unwrap();
+
+        assert_eq!("4b825dc642cb6eb9a060e54bf8d69288fbee4904", tag.object);
+        assert_eq!("commit", tag.obj_type);
+        assert_eq!("v1.0", tag.name);
+        assert_eq!("A. U. Thor", tag.tagger.as_ref().unwrap().name);
+        assert_eq!("Release 1.0\n\nWith notes.\n", tag.message);
+        assert_eq!(&data[..], &tag.to_string()[..]);
+    }
+
+    #[test]
+    fn report_damaged_tags_as_corrupt() {
+        for (data, reason) in &[
+            ("type commit\ntag v1.0\n", "missing object line"),
+            (
+                "object 4b825dc\ntype commit\ntag v1.0\n",
+                "invalid 'object' line format - bad sha1",
+            ),
+            (
+                "object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\ntype thing\ntag v1.0\n",
+                "invalid 'type' value: thing",
+            ),
+            (
+                "object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\ntype commit\n",
+                "missing tag line",
+            ),
+        ] {
+            match Tag::parse(data.as_bytes()) {
+                Err(Error::CorruptObject { reason: actual, .. }) => assert_eq!(*reason, actual),
+                other => panic!("expected a corrupt object, got {:?}", other),
+            }
+        }
+    }
+}
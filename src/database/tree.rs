@@ -1,12 +1,37 @@
-use crate::database::object::Object;
-use crate::database::{Entry, ParsedObject};
+use crate::database::object::{self, Object, Storable};
+use crate::database::Entry;
+use crate::errors::Error;
 use crate::util::*;
 
-use std::collections::{BTreeMap};
-use std::path::{Path};
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::str;
 
 pub const TREE_MODE: u32 = 0o40000;
+pub const SYMLINK_MODE: u32 = 0o120000;
+/// A commit in another repository, as used for submodules
+pub const GITLINK_MODE: u32 = 0o160000;
+
+// Modes a tree entry may have. 100664 is accepted since early
+// versions of git wrote it.
+const VALID_MODES: [u32; 6] = [
+    TREE_MODE,
+    0o100644,
+    0o100755,
+    0o100664,
+    SYMLINK_MODE,
+    GITLINK_MODE,
+];
+
+/// The key git orders a tree's entries by, in which a tree sorts as
+/// though its name ended with `/`
+fn sort_key(name: &str, mode: u32) -> String {
+    if mode == TREE_MODE {
+        format!("{}/", name)
+    } else {
+        name.to_string()
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum TreeEntry {
@@ -35,9 +60,22 @@ impl TreeEntry {
             _ => false,
         }
     }
+
+    pub fn is_gitlink(&self) -> bool {
+        self.mode() == GITLINK_MODE
+    }
+
+    /// The type of object the entry points at
+    pub fn obj_type(&self) -> &str {
+        match self.mode() {
+            TREE_MODE => "tree",
+            GITLINK_MODE => "commit",
+            _ => "blob",
+        }
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Tree {
     pub entries: BTreeMap<String, TreeEntry>,
 }
@@ -78,30 +116,33 @@ impl Tree {
         };
     }
 
-    pub fn traverse<F>(&self, f: &F)
+    pub fn traverse<F, E>(&self, f: &F) -> Result<(), E>
     where
-        F: Fn(&Tree) -> (),
+        F: Fn(&Tree) -> Result<(), E>,
     {
         // Do a postorder traversal(visit all children first, then
         // process `self`
         for (_name, entry) in self.entries.clone() {
             if let TreeEntry::Tree(tree) = entry {
-                tree.traverse(f);
+                tree.traverse(f)?;
             }
         }
 
-        f(self);
+        f(self)
     }
 }
 
-impl Object for Tree {
+impl Storable for Tree {
     fn r#type(&self) -> String {
         "tree".to_string()
     }
 
     fn to_string(&self) -> Vec<u8> {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by_key(|(name, entry)| sort_key(name, entry.mode()));
+
         let mut tree_vec = Vec::new();
-        for (name, entry) in self.entries.iter() {
+        for (name, entry) in entries {
             let mut entry_vec: Vec<u8> =
                 format!("{:o} {}\0", entry.mode(), name).as_bytes().to_vec();
             entry_vec.extend_from_slice(&decode_hex(&entry.get_oid()).expect("invalid oid"));
@@ -109,44 +150,129 @@ impl Object for Tree {
         }
         tree_vec
     }
+}
 
-    fn parse(v: &[u8]) -> ParsedObject {
+impl Object for Tree {
+    fn parse(v: &[u8]) -> Result<Tree, Error> {
+        let corrupt = |reason: &str| object::corrupt("tree", v, reason);
         let mut entries: Vec<Entry> = vec![];
+        let mut previous: Option<(&str, String)> = None;
 
+        // Each entry is `<octal mode> <name>\0` followed by the
+        // entry's oid as 20 raw bytes
         let mut vs = v;
-
         while !vs.is_empty() {
-            let (mode, rest): (u32, &[u8]) = match vs
-                .splitn(2, |c| *c as char == ' ')
-                .collect::<Vec<&[u8]>>()
-                .as_slice()
-            {
-                &[mode, rest] => (
-                    u32::from_str_radix(str::from_utf8(mode).expect("invalid utf8"), 8)
-                        .expect("parsing mode failed"),
-                    rest,
-                ),
-                _ => panic!("EOF while parsing mode"),
-            };
-            vs = rest;
+            let space = vs
+                .iter()
+                .position(|&c| c == b' ')
+                .ok_or_else(|| corrupt("truncated tree entry mode"))?;
+            let mode_text =
+                str::from_utf8(&vs[..space]).map_err(|_| corrupt("invalid tree entry mode"))?;
+            let mode = u32::from_str_radix(mode_text, 8)
+                .map_err(|_| corrupt("invalid tree entry mode"))?;
+            vs = &vs[space + 1..];
 
-            let (name, rest) = match *vs
-                .splitn(2, |c| *c as char == '\u{0}')
-                .collect::<Vec<&[u8]>>()
-                .as_slice()
-            {
-                [name_bytes, rest] => (str::from_utf8(name_bytes).expect("invalid utf8"), rest),
-                _ => panic!("EOF while parsing name"),
-            };
-            vs = rest;
+            let nul = vs
+                .iter()
+                .position(|&c| c == 0)
+                .ok_or_else(|| corrupt("truncated tree entry name"))?;
+            let name = str::from_utf8(&vs[..nul])
+                .map_err(|_| corrupt("tree entry name is not valid utf-8"))?;
+            vs = &vs[nul + 1..];
 
+            if vs.len() < 20 {
+                return Err(corrupt("truncated tree entry oid"));
+            }
             let (oid_bytes, rest) = vs.split_at(20);
             vs = rest;
 
-            let oid = encode_hex(&oid_bytes);
+            if mode_text.starts_with('0') {
+                return Err(corrupt("contains zero-padded file modes"));
+            }
+            if !VALID_MODES.contains(&mode) {
+                return Err(corrupt("contains bad file modes"));
+            }
+            if name.is_empty() {
+                return Err(corrupt("contains empty pathname"));
+            }
+            if name.contains('/') {
+                return Err(corrupt("contains full pathnames"));
+            }
+            if name == "." || name == ".." {
+                return Err(corrupt("contains '.' or '..'"));
+            }
+
+            let key = sort_key(name, mode);
+            if let Some((previous_name, previous_key)) = &previous {
+                if *previous_name == name {
+                    return Err(corrupt("contains duplicate file entries"));
+                }
+                if *previous_key > key {
+                    return Err(corrupt("not properly sorted"));
+                }
+            }
+            previous = Some((name, key));
+
+            entries.push(Entry::new(name, &encode_hex(oid_bytes), mode));
+        }
+
+        Ok(Tree::build(&entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(mode: &str, name: &str) -> Vec<u8> {
+        let mut entry = format!("{} {}\0", mode, name).into_bytes();
+        entry.extend(vec![0xab; 20]);
+        entry
+    }
 
-            entries.push(Entry::new(name, &oid, mode));
+    #[test]
+    fn round_trip_trees_in_git_order() {
+        let data = [
+            entry("100644", "a.txt"),
+            entry("40000", "a"),
+            entry("160000", "b"),
+        ]
+        .concat();
+
+        let tree = Tree::parse(&data).unwrap();
+
+        assert_eq!(&data[..], &tree.to_string()[..]);
+    }
+
+    #[test]
+    fn report_malformed_trees_as_corrupt() {
+        for (entries, reason) in &[
+            (
+                vec![entry("100644", "b"), entry("100644", "a")],
+                "not properly sorted",
+            ),
+            (
+                vec![entry("40000", "a"), entry("100644", "a.txt")],
+                "not properly sorted",
+            ),
+            (
+                vec![entry("100644", "a"), entry("100644", "a")],
+                "contains duplicate file entries",
+            ),
+            (vec![entry("100600", "a")], "contains bad file modes"),
+            (
+                vec![entry("0100644", "a")],
+                "contains zero-padded file modes",
+            ),
+            (vec![entry("100644", "")], "contains empty pathname"),
+            (vec![entry("100644", "a/b")], "contains full pathnames"),
+            (vec![entry("40000", "..")], "contains '.' or '..'"),
+            (vec![b"100644 a\0ab".to_vec()], "truncated tree entry oid"),
+        ] {
+            match Tree::parse(&entries.concat()) {
+                Err(Error::CorruptObject { reason: actual, .. }) => assert_eq!(*reason, actual),
+                other => panic!("expected a corrupt object, got {:?}", other),
+            }
         }
-        ParsedObject::Tree(Tree::build(&entries))
     }
 }
@@ -1,40 +1,49 @@
 use crate::database::tree::TreeEntry;
 use crate::database::{Database, ParsedObject, Tree};
+use crate::errors::Error;
 use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
+/// The old and new entries for every path that differs
+pub type TreeChanges = HashMap<PathBuf, (Option<TreeEntry>, Option<TreeEntry>)>;
+
 pub struct TreeDiff<'a> {
     database: &'a mut Database,
-    pub changes: HashMap<PathBuf, (Option<TreeEntry>, Option<TreeEntry>)>,
+    pub changes: TreeChanges,
 }
 
 impl<'a> TreeDiff<'a> {
-    pub fn new(database: &mut Database) -> TreeDiff {
+    pub fn new(database: &mut Database) -> TreeDiff<'_> {
         TreeDiff {
             database,
             changes: HashMap::new(),
         }
     }
 
-    pub fn compare_oids(&mut self, a: Option<String>, b: Option<String>, prefix: &Path) {
+    pub fn compare_oids(
+        &mut self,
+        a: Option<String>,
+        b: Option<String>,
+        prefix: &Path,
+    ) -> Result<(), Error> {
         if a == b {
-            return;
+            return Ok(());
         }
 
         let a_entries = if let Some(a_oid) = a {
-            self.oid_to_tree(&a_oid).entries
+            self.oid_to_tree(&a_oid)?.entries
         } else {
             BTreeMap::new()
         };
 
         let b_entries = if let Some(b_oid) = b {
-            self.oid_to_tree(&b_oid).entries
+            self.oid_to_tree(&b_oid)?.entries
         } else {
             BTreeMap::new()
         };
 
-        self.detect_deletions(&a_entries, &b_entries, prefix);
-        self.detect_additions(&a_entries, &b_entries, prefix);
+        self.detect_deletions(&a_entries, &b_entries, prefix)?;
+        self.detect_additions(&a_entries, &b_entries, prefix)
     }
 
     fn detect_deletions(
@@ -42,7 +51,7 @@ impl<'a> TreeDiff<'a> {
         a_entries: &BTreeMap<String, TreeEntry>,
         b_entries: &BTreeMap<String, TreeEntry>,
         prefix: &Path,
-    ) {
+    ) -> Result<(), Error> {
         for (name, entry) in a_entries {
             let path = prefix.join(name);
             let other = b_entries.get(name);
@@ -67,9 +76,12 @@ impl<'a> TreeDiff<'a> {
                 None
             };
 
-            self.compare_oids(tree_a, tree_b, &path);
+            self.compare_oids(tree_a, tree_b, &path)?;
 
-            let blobs = match (!entry.is_tree(), other.map(|e| !e.is_tree()).unwrap_or(false)) {
+            let blobs = match (
+                !entry.is_tree(),
+                other.map(|e| !e.is_tree()).unwrap_or(false),
+            ) {
                 (true, true) => (Some(entry.clone()), other.cloned()),
                 (true, false) => (Some(entry.clone()), None),
                 (false, true) => (None, other.cloned()),
@@ -77,6 +89,8 @@ impl<'a> TreeDiff<'a> {
             };
             self.changes.insert(path, blobs);
         }
+
+        Ok(())
     }
 
     fn detect_additions(
@@ -84,7 +98,7 @@ impl<'a> TreeDiff<'a> {
         a_entries: &BTreeMap<String, TreeEntry>,
         b_entries: &BTreeMap<String, TreeEntry>,
         prefix: &Path,
-    ) {
+    ) -> Result<(), Error> {
         for (name, entry) in b_entries {
             let path = prefix.join(name);
             let other = a_entries.get(name);
@@ -94,23 +108,32 @@ impl<'a> TreeDiff<'a> {
             }
 
             if entry.is_tree() {
-                self.compare_oids(None, Some(entry.get_oid()), &path);
+                self.compare_oids(None, Some(entry.get_oid()), &path)?;
             } else {
                 self.changes.insert(path, (None, Some(entry.clone())));
             }
         }
+
+        Ok(())
     }
 
-    fn oid_to_tree(&mut self, oid: &str) -> Tree {
-        let tree_oid = match self.database.load(oid) {
-            ParsedObject::Tree(tree) => return tree.clone(),
+    fn oid_to_tree(&mut self, oid: &str) -> Result<Tree, Error> {
+        let tree_oid = match self.database.load(oid)? {
+            ParsedObject::Tree(tree) => return Ok(tree.clone()),
             ParsedObject::Commit(commit) => commit.tree_oid.clone(),
-            _ => panic!("oid not a commit or tree"),
+            object => return Err(not_a_tree(oid, object)),
         };
 
-        match self.database.load(&tree_oid) {
-            ParsedObject::Tree(tree) => tree.clone(),
-            _ => panic!("oid not a tree"),
+        match self.database.load(&tree_oid)? {
+            ParsedObject::Tree(tree) => Ok(tree.clone()),
+            object => Err(not_a_tree(&tree_oid, object)),
         }
     }
 }
+
+fn not_a_tree(oid: &str, object: &ParsedObject) -> Error {
+    Error::CorruptObject {
+        oid: oid.to_string(),
+        reason: format!("object {} is a {}, not a tree", oid, object.obj_type()),
+    }
+}
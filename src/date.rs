@@ -0,0 +1,169 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use regex::Regex;
+
+lazy_static! {
+    static ref RAW: Regex = Regex::new(r"^@?(\d+)(?: ([+-]\d{4}))?$").unwrap();
+    static ref AGO: Regex =
+        Regex::new(r"^(\d+)[. ](second|minute|hour|day|week|month|year)s?[. ]ago$").unwrap();
+    static ref ISO: Regex = Regex::new(
+        r"^(\d{4})[-.](\d{2})[-.](\d{2})[T ](\d{2}):(\d{2})(?::(\d{2}))?(?:\.\d+)?\s*(Z|[+-]\d{2}:?\d{2})?$"
+    )
+    .unwrap();
+}
+
+/// The current time, at the local timezone's offset
+pub fn now() -> DateTime<FixedOffset> {
+    Local::now().fixed_offset()
+}
+
+/// Parse a `+hhmm` or `+hh:mm` offset, or `Z` for UTC
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    if offset == "Z" {
+        return FixedOffset::east_opt(0);
+    }
+
+    let digits = offset[1..].replace(':', "");
+    let hours: i32 = digits.get(..2)?.parse().ok()?;
+    let minutes: i32 = digits.get(2..)?.parse().ok()?;
+    let seconds = (hours * 60 + minutes) * 60;
+    if offset.starts_with('-') {
+        FixedOffset::west_opt(seconds)
+    } else {
+        FixedOffset::east_opt(seconds)
+    }
+}
+
+/// Parse a date in one of the formats git takes for GIT_AUTHOR_DATE
+/// and GIT_COMMITTER_DATE: raw `<seconds> <offset>`, RFC 2822 or ISO
+/// 8601. Times without an offset are taken to be local.
+pub fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+
+    if let Some(caps) = RAW.captures(date) {
+        let offset = match caps.get(2) {
+            Some(offset) => parse_offset(offset.as_str())?,
+            None => FixedOffset::east_opt(0)?,
+        };
+        return offset.timestamp_opt(caps[1].parse().ok()?, 0).single();
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc2822(date) {
+        return Some(time);
+    }
+
+    let caps = ISO.captures(date)?;
+    let field = |i: usize| -> Option<u32> {
+        caps.get(i)
+            .map_or(Some(0), |value| value.as_str().parse().ok())
+    };
+    let day = NaiveDate::from_ymd_opt(caps[1].parse().ok()?, field(2)?, field(3)?)?;
+    let naive = day.and_hms_opt(field(4)?, field(5)?, field(6)?)?;
+    match caps.get(7) {
+        Some(offset) => parse_offset(offset.as_str())?
+            .from_local_datetime(&naive)
+            .single(),
+        None => Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|time| time.fixed_offset()),
+    }
+}
+
+/// Parse a date as `parse_date` does, or as `now`, a day such as
+/// `2005-04-07` or a time ago such as `2.weeks.ago` or `3 days ago`, as
+/// git takes for expiry times. Times too far back to represent are
+/// rejected.
+pub fn parse_approxidate(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+    if date == "now" {
+        return Some(now());
+    }
+
+    if let Some(caps) = AGO.captures(date) {
+        let n: i64 = caps[1].parse().ok()?;
+        let seconds = match &caps[2] {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            "month" => 30 * 24 * 60 * 60,
+            _ => 365 * 24 * 60 * 60,
+        };
+        let ago = Duration::try_seconds(n.checked_mul(seconds)?)?;
+        return now().checked_sub_signed(ago);
+    }
+
+    // A day alone stands for its local midnight
+    if let Ok(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return Local
+            .from_local_datetime(&day.and_hms_opt(0, 0, 0)?)
+            .earliest()
+            .map(|time| time.fixed_offset());
+    }
+
+    parse_date(date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted(date: &str) -> String {
+        parse_date(date).unwrap().format("%s %z").to_string()
+    }
+
+    #[test]
+    fn parse_raw_dates() {
+        assert_eq!("1112911993 +0200", formatted("1112911993 +0200"));
+        assert_eq!("1112911993 -0730", formatted("@1112911993 -0730"));
+        assert_eq!("1112911993 +0000", formatted("@1112911993"));
+    }
+
+    #[test]
+    fn parse_rfc2822_and_iso_dates() {
+        assert_eq!(
+            "1112904793 +0200",
+            formatted("Thu, 07 Apr 2005 22:13:13 +0200")
+        );
+        assert_eq!("1112904793 +0200", formatted("2005-04-07T22:13:13+02:00"));
+        assert_eq!("1112904793 +0200", formatted("2005-04-07 22:13:13 +0200"));
+        assert_eq!("1112912000 +0000", formatted("2005.04.07T22:13:20Z"));
+        assert!(parse_date("2005-04-07 22:13").is_some());
+    }
+
+    #[test]
+    fn parse_relative_dates() {
+        let ago = |date: &str| {
+            let time = parse_approxidate(date).unwrap();
+            (now() - time).num_seconds()
+        };
+
+        assert!(ago("now") <= 1);
+        assert!((14 * 86400..14 * 86400 + 2).contains(&ago("2.weeks.ago")));
+        assert!((3600..3602).contains(&ago("1 hour ago")));
+        assert_eq!(
+            "1112911993 +0200",
+            parse_approxidate("1112911993 +0200")
+                .unwrap()
+                .format("%s %z")
+                .to_string()
+        );
+        assert_eq!(
+            Local
+                .with_ymd_and_hms(2005, 4, 7, 0, 0, 0)
+                .unwrap()
+                .timestamp(),
+            parse_approxidate("2005-04-07").unwrap().timestamp()
+        );
+        assert!(parse_approxidate("999999999999.weeks.ago").is_none());
+        assert!(parse_approxidate("9223372036854775807.seconds.ago").is_none());
+    }
+
+    #[test]
+    fn reject_unknown_formats() {
+        assert!(parse_date("yesterday-ish").is_none());
+        assert!(parse_date("2005-13-07 22:13:13").is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+// How far into a file git looks for a NUL byte
+const FIRST_FEW_BYTES: usize = 8000;
+
+// The longest run of bytes encoded on one line of a binary patch
+const MAX_LINE_BYTES: usize = 52;
+
+const BASE85: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Whether `data` should be treated as binary, which git decides by
+/// looking for a NUL byte near the start
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(FIRST_FEW_BYTES).any(|&b| b == 0)
+}
+
+/// A `GIT binary patch` turning `a` into `b`: a literal hunk for the
+/// new contents, then one for the old so the patch can be reversed
+pub fn binary_patch(a: &[u8], b: &[u8]) -> String {
+    let mut patch = String::from("GIT binary patch\n");
+    for data in &[b, a] {
+        patch.push_str(&format!("literal {}\n", data.len()));
+        patch.push_str(&encode_lines(&deflate(data)));
+        patch.push('\n');
+    }
+
+    patch
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).expect("compression failed");
+    encoder.finish().expect("compression failed")
+}
+
+/// Base85 encode `data` a line at a time, each line led by a letter
+/// giving how many bytes it holds: `A`-`Z` for 1-26, `a`-`z` for 27-52
+fn encode_lines(data: &[u8]) -> String {
+    let mut lines = String::new();
+    for chunk in data.chunks(MAX_LINE_BYTES) {
+        let len = chunk.len() as u8;
+        let prefix = if len <= 26 {
+            b'A' + len - 1
+        } else {
+            b'a' + len - 27
+        };
+        lines.push(prefix as char);
+        lines.push_str(&encode_base85(chunk));
+        lines.push('\n');
+    }
+
+    lines
+}
+
+/// Encode each group of 4 bytes, zero padded, as 5 base85 digits
+fn encode_base85(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    for group in data.chunks(4) {
+        let mut acc = (0..4).fold(0u32, |acc, i| {
+            (acc << 8) | u32::from(group.get(i).copied().unwrap_or(0))
+        });
+
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = BASE85[(acc % 85) as usize];
+            acc /= 85;
+        }
+        encoded.extend(digits.iter().map(|&d| d as char));
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_binary_data() {
+        assert!(is_binary(b"PNG\0\x01"));
+        assert!(!is_binary("caf\u{e9}\n".as_bytes()));
+        assert!(!is_binary(b"\xff\xfe not utf-8"));
+    }
+
+    #[test]
+    fn encode_lines_in_base85() {
+        assert_eq!("00000", encode_base85(&[0, 0, 0, 0]));
+        assert_eq!("|NsC0", encode_base85(&[0xff, 0xff, 0xff, 0xff]));
+        assert_eq!("A00000\n", encode_lines(&[0]));
+
+        let lines = encode_lines(&[1; 53]);
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with('z'));
+        assert_eq!(1 + 13 * 5, lines[0].len());
+        assert!(lines[1].starts_with('A'));
+    }
+}
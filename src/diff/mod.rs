@@ -1,48 +1,59 @@
+pub mod binary;
 pub mod myers;
 use myers::{Edit, EditType, Myers};
 use std::fmt;
 
 pub struct Diff {}
 
+/// A line of a file, including its newline unless it is the last
+/// line of a file that does not end in one
 #[derive(Clone, Debug)]
 pub struct Line {
-    number: usize,
-    text: String,
+    pub number: usize,
+    text: Vec<u8>,
 }
 
 impl Line {
-    fn new(number: usize, text: &str) -> Line {
+    fn new(number: usize, text: &[u8]) -> Line {
         Line {
             number,
-            text: text.to_string(),
+            text: text.to_vec(),
         }
     }
+
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+
+    pub fn has_newline(&self) -> bool {
+        self.text.ends_with(b"\n")
+    }
 }
 
 impl fmt::Display for Line {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.text)
+        let text = self.text.strip_suffix(b"\n").unwrap_or(&self.text);
+        write!(f, "{}", String::from_utf8_lossy(text))
     }
 }
 
-fn lines(a: &str) -> Vec<Line> {
-    let mut a_lines = vec![];
-    for (i, text) in a.split('\n').enumerate() {
-        a_lines.push(Line::new(i + 1, text));
-    }
-
-    a_lines
+/// Split `a` into lines, keeping each line's newline
+pub fn lines(a: &[u8]) -> Vec<Line> {
+    a.split_inclusive(|&c| c == b'\n')
+        .enumerate()
+        .map(|(i, text)| Line::new(i + 1, text))
+        .collect()
 }
 
 impl Diff {
-    pub fn diff(a: &str, b: &str) -> Vec<Edit> {
+    pub fn diff(a: &[u8], b: &[u8]) -> Vec<Edit> {
         let a_lines = lines(a);
         let b_lines = lines(b);
 
         Myers::new(a_lines, b_lines).diff()
     }
 
-    pub fn diff_hunks(a: &str, b: &str) -> Vec<Hunk> {
+    pub fn diff_hunks(a: &[u8], b: &[u8]) -> Vec<Hunk> {
         Hunk::filter(Self::diff(a, b))
     }
 }
@@ -100,7 +111,7 @@ impl Hunk {
             })
             .filter_map(|l| l.as_ref())
             .collect();
-        let start = if lines.len() > 0 {
+        let start = if !lines.is_empty() {
             lines[0].number
         } else {
             default
@@ -113,7 +124,7 @@ impl Hunk {
         let mut hunks = vec![];
         let mut offset: isize = 0;
 
-        let empty_line = Line::new(0, "");
+        let empty_line = Line::new(0, b"");
 
         loop {
             // Skip over Eql edits
@@ -36,19 +36,22 @@ impl Edit {
         }
     }
 
-}
-
-
-impl fmt::Display for Edit {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let line = if let Some(a) = &self.a_line {
+    /// The line this edit shows: the old one, unless it is an
+    /// insertion
+    pub fn line(&self) -> &Line {
+        if let Some(a) = &self.a_line {
             a
         } else if let Some(b) = &self.b_line {
             b
         } else {
             panic!("both lines None")
-        };
-        write!(f, "{}{}", self.edit_type.to_string(), line)
+        }
+    }
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.edit_type.to_string(), self.line())
     }
 }
 
@@ -120,8 +123,8 @@ impl Myers {
 
                 let mut y: isize = x - k;
                 while x < n && y < m && self.a[to_usize(x)].text == self.b[to_usize(y)].text {
-                    x = x + 1;
-                    y = y + 1;
+                    x += 1;
+                    y += 1;
                 }
 
                 v.insert(k, x);
@@ -154,8 +157,8 @@ impl Myers {
 
             while x > prev_x && y > prev_y {
                 seq.push((x - 1, y - 1, x, y));
-                x = x - 1;
-                y = y - 1;
+                x -= 1;
+                y -= 1;
             }
 
             if d > 0 {
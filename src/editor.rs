@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::errors::Error;
+
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Writes a file for the user to edit, runs their editor on it and
+/// reads back the result
+pub struct Editor {
+    path: PathBuf,
+    command: String,
+    contents: String,
+    closed: bool,
+}
+
+impl Editor {
+    pub fn new(path: &Path, command: Option<String>) -> Editor {
+        Editor {
+            path: path.to_path_buf(),
+            command: command.unwrap_or_else(|| DEFAULT_EDITOR.to_string()),
+            contents: String::new(),
+            closed: false,
+        }
+    }
+
+    pub fn write(&mut self, text: &str) {
+        self.contents.push_str(text);
+        if !text.ends_with('\n') {
+            self.contents.push('\n');
+        }
+    }
+
+    /// Add text as comment lines, for instructions the user should
+    /// not have to delete
+    pub fn note(&mut self, text: &str) {
+        for line in text.lines() {
+            if line.is_empty() {
+                self.contents.push_str("#\n");
+            } else if line.starts_with('\t') {
+                self.contents.push_str(&format!("#{}\n", line));
+            } else {
+                self.contents.push_str(&format!("# {}\n", line));
+            }
+        }
+    }
+
+    /// Keep the file as written, without asking the user to edit it
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Write the file out and, unless closed, wait for the editor to
+    /// exit. `:` is the editor that changes nothing.
+    pub fn edit_file(&self, env: &HashMap<String, String>) -> Result<String, Error> {
+        fs::write(&self.path, &self.contents).map_err(|e| Error::io("write", &self.path, e))?;
+
+        if !self.closed && self.command != ":" {
+            // Run through the shell, as git does, so the command may
+            // carry its own arguments
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(format!("{} \"$@\"", self.command))
+                .arg(&self.command)
+                .arg(&self.path)
+                .envs(env)
+                .status();
+
+            if !status.map(|status| status.success()).unwrap_or(false) {
+                return Err(Error::failure(format!(
+                    "error: There was a problem with the editor '{}'.\n",
+                    self.command
+                )));
+            }
+        }
+
+        fs::read_to_string(&self.path).map_err(|e| Error::io("read", &self.path, e))
+    }
+}
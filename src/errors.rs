@@ -0,0 +1,192 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::revision::HintedError;
+
+/// Why a command failed. Each kind is shown in git's words, and exits
+/// with the status git would: 128 for `fatal:` errors, 1 for `error:`
+/// ones and 129 for usage errors. Messages carry their status with
+/// them, as given by `fatal`, `failure` or `usage`.
+#[derive(Debug)]
+pub enum Error {
+    /// A file that could not be read, written or the like
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A lock file that some other process, or one that crashed, holds
+    LockHeld {
+        path: PathBuf,
+    },
+    MissingObject {
+        oid: String,
+    },
+    CorruptObject {
+        oid: String,
+        reason: String,
+    },
+    CorruptIndex {
+        reason: String,
+    },
+    /// A revision that names no object, with whatever was learnt while
+    /// trying to resolve it
+    InvalidRevision {
+        errors: Vec<HintedError>,
+    },
+    /// Output that could not be written, such as to a pager the user
+    /// has quit
+    Output(io::Error),
+    /// A message already worded as git words it, and the status to
+    /// exit with
+    Message {
+        text: String,
+        code: i32,
+    },
+    /// A failure that has been reported already, or that is reported
+    /// by the exit status alone
+    Exit(i32),
+}
+
+impl Error {
+    /// Describe a failed operation on `path`
+    pub fn io(action: &'static str, path: impl Into<PathBuf>, error: io::Error) -> Error {
+        Error::Io {
+            action,
+            path: path.into(),
+            error,
+        }
+    }
+
+    /// A message ending in a `fatal:` line, exiting with 128
+    pub fn fatal(text: impl Into<String>) -> Error {
+        Error::Message {
+            text: text.into(),
+            code: 128,
+        }
+    }
+
+    /// An `error:` message, or any other failure that exits with 1
+    pub fn failure(text: impl Into<String>) -> Error {
+        Error::Message {
+            text: text.into(),
+            code: 1,
+        }
+    }
+
+    /// A `usage:` message for a command given the wrong arguments,
+    /// exiting with 129
+    pub fn usage(text: impl Into<String>) -> Error {
+        Error::Message {
+            text: text.into(),
+            code: 129,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidRevision { .. } => 1,
+            Error::Message { code, .. } | Error::Exit(code) => *code,
+            _ => 128,
+        }
+    }
+}
+
+/// An I/O error's description, without the "(os error N)" that Rust
+/// adds
+pub fn describe(error: &io::Error) -> String {
+    let description = error.to_string();
+    match description.find(" (os error") {
+        Some(end) => description[..end].to_string(),
+        None => description,
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io {
+                action,
+                path,
+                error,
+            } => writeln!(
+                f,
+                "fatal: could not {} '{}': {}",
+                action,
+                path.display(),
+                describe(error)
+            ),
+            Error::LockHeld { path } => write!(
+                f,
+                "fatal: Unable to create '{}': File exists.
+
+Another git process seems to be running in this repository, e.g.
+an editor opened by 'git commit'. Please make sure all processes
+are terminated then try again. If it still fails, a git process
+may have crashed in this repository earlier:
+remove the file manually to continue.
+",
+                path.display()
+            ),
+            Error::MissingObject { oid } => writeln!(f, "fatal: unable to read {}", oid),
+            Error::CorruptObject { oid, reason } => {
+                writeln!(f, "error: {}\nfatal: object {} is corrupt", reason, oid)
+            }
+            Error::CorruptIndex { reason } => {
+                writeln!(f, "error: {}\nfatal: index file corrupt", reason)
+            }
+            Error::InvalidRevision { errors } => {
+                for error in errors {
+                    write!(f, "error: {}", error)?;
+                }
+                Ok(())
+            }
+            Error::Output(error) => {
+                writeln!(f, "fatal: unable to write output: {}", describe(error))
+            }
+            Error::Message { text, .. } => write!(f, "{}", text),
+            Error::Exit(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_with_gits_status_for_each_kind_of_message() {
+        let fatal = Error::fatal("error: bad object\nfatal: cannot continue\n");
+        assert_eq!(128, fatal.exit_code());
+        assert_eq!(
+            1,
+            Error::failure("error: pathspec did not match\n").exit_code()
+        );
+        assert_eq!(129, Error::usage("usage: rug thing\n").exit_code());
+        assert_eq!(5, Error::Exit(5).exit_code());
+        assert_eq!(
+            128,
+            Error::MissingObject {
+                oid: "1234".to_string()
+            }
+            .exit_code()
+        );
+    }
+
+    #[test]
+    fn describe_io_errors_without_their_code() {
+        let error = Error::io("open", "dir/file", io::Error::from_raw_os_error(2));
+        assert_eq!(
+            "fatal: could not open 'dir/file': No such file or directory\n",
+            error.to_string()
+        );
+
+        let error = Error::Output(io::Error::from_raw_os_error(32));
+        assert_eq!(128, error.exit_code());
+        assert_eq!(
+            "fatal: unable to write output: Broken pipe\n",
+            error.to_string()
+        );
+    }
+}
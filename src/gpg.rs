@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use crate::config::stack::Stack;
+use crate::errors::{self, Error};
+use crate::util::generate_temp_name;
+
+const SSH_SIGNATURE: &str = "-----BEGIN SSH SIGNATURE-----";
+const LITERAL_KEY: &str = "key::";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    OpenPgp,
+    Ssh,
+}
+
+/// Whether a signature checked out, and what the program that checked
+/// it had to say
+pub struct Verification {
+    pub good: bool,
+    pub output: String,
+}
+
+/// Signs and verifies payloads with gpg, or with ssh-keygen when
+/// gpg.format is `ssh`, as git does
+pub struct Gpg<'a> {
+    config: &'a Stack,
+    // The command's environment, such as GNUPGHOME, for the programs
+    // run
+    env: &'a HashMap<String, String>,
+}
+
+/// A file in the temporary directory that is removed when dropped
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn new(contents: &[u8]) -> Result<TempFile, Error> {
+        let path = env::temp_dir().join(format!(".git_signing_tmp_{}", generate_temp_name()));
+        fs::write(&path, contents).map_err(|e| Error::io("write", &path, e))?;
+
+        Ok(TempFile { path })
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Run `program` with `input` on its standard input
+fn run(
+    program: &str,
+    args: &[&str],
+    input: &[u8],
+    env: &HashMap<String, String>,
+) -> Result<Output, Error> {
+    let mut child = Command::new(program)
+        .args(args)
+        .envs(env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            Error::failure(format!(
+                "error: cannot run {}: {}\n",
+                program,
+                errors::describe(&e)
+            ))
+        })?;
+
+    // The program may not read all of its input
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child
+        .wait_with_output()
+        .map_err(|e| Error::io("wait for", program, e))
+}
+
+impl<'a> Gpg<'a> {
+    pub fn new(config: &'a Stack, env: &'a HashMap<String, String>) -> Gpg<'a> {
+        Gpg { config, env }
+    }
+
+    fn format(&self) -> Result<Format, Error> {
+        match self.config.get("gpg.format").as_deref() {
+            None | Some("openpgp") => Ok(Format::OpenPgp),
+            Some("ssh") => Ok(Format::Ssh),
+            Some(other) => Err(Error::failure(format!(
+                "error: unsupported value for gpg.format: {}\n",
+                other
+            ))),
+        }
+    }
+
+    fn program(&self, format: Format) -> String {
+        match format {
+            Format::OpenPgp => self
+                .config
+                .get("gpg.openpgp.program")
+                .or_else(|| self.config.get("gpg.program"))
+                .unwrap_or_else(|| "gpg".to_string()),
+            Format::Ssh => self
+                .config
+                .get("gpg.ssh.program")
+                .unwrap_or_else(|| "ssh-keygen".to_string()),
+        }
+    }
+
+    /// The key to sign with: `key` if given, else user.signingKey,
+    /// else for OpenPGP the committer's identity
+    pub fn signing_key(&self, key: Option<&str>, committer: &str) -> Result<String, Error> {
+        if let Some(key) = key.or(self.config.get("user.signingKey").as_deref()) {
+            return Ok(key.to_string());
+        }
+
+        match self.format()? {
+            Format::OpenPgp => Ok(committer.to_string()),
+            Format::Ssh => Err(Error::failure(
+                "error: user.signingKey needs to be set for ssh signing\n",
+            )),
+        }
+    }
+
+    /// Make a detached signature over `payload`
+    pub fn sign(&self, payload: &[u8], key: &str) -> Result<String, Error> {
+        let format = self.format()?;
+        let program = self.program(format);
+
+        let signature = match format {
+            Format::OpenPgp => {
+                let output = run(
+                    &program,
+                    &["--status-fd=2", "-bsau", key],
+                    payload,
+                    self.env,
+                )?;
+                if !output.status.success() {
+                    return Err(Error::failure(format!(
+                        "{}error: gpg failed to sign the data\n",
+                        String::from_utf8_lossy(&output.stderr)
+                    )));
+                }
+                output.stdout
+            }
+            Format::Ssh => {
+                // ssh-keygen signs a file, writing the signature next
+                // to it, and takes the key as a file too
+                let buffer = TempFile::new(payload)?;
+                let literal = key
+                    .strip_prefix(LITERAL_KEY)
+                    .map(|key| TempFile::new(key.as_bytes()))
+                    .transpose()?;
+                let key_path = match &literal {
+                    Some(file) => file.path.clone(),
+                    None => PathBuf::from(key),
+                };
+                let mut args = vec!["-Y", "sign", "-n", "git", "-f", path_str(&key_path)];
+                if literal.is_some() {
+                    args.push("-U");
+                }
+                args.push(path_str(&buffer.path));
+
+                let output = run(&program, &args, b"", self.env)?;
+                let signature_file = TempFile {
+                    path: PathBuf::from(format!("{}.sig", buffer.path.display())),
+                };
+                if !output.status.success() {
+                    return Err(Error::failure(format!(
+                        "{}error: ssh-keygen failed to sign the data\n",
+                        String::from_utf8_lossy(&output.stderr)
+                    )));
+                }
+                fs::read(&signature_file.path).map_err(|e| {
+                    Error::failure(format!(
+                        "error: failed reading ssh signing data buffer: {}\n",
+                        errors::describe(&e)
+                    ))
+                })?
+            }
+        };
+
+        if signature.is_empty() {
+            return Err(Error::failure("error: gpg failed to sign the data\n"));
+        }
+        Ok(String::from_utf8_lossy(&signature).to_string())
+    }
+
+    /// Check `signature` against `payload`, with whichever program
+    /// suits the kind of signature it is
+    pub fn verify(&self, payload: &[u8], signature: &str) -> Result<Verification, Error> {
+        let format = if signature.starts_with(SSH_SIGNATURE) {
+            Format::Ssh
+        } else {
+            Format::OpenPgp
+        };
+        let program = self.program(format);
+        let signature_file = TempFile::new(signature.as_bytes())?;
+        let signature_path = path_str(&signature_file.path);
+
+        let output = match format {
+            Format::OpenPgp => run(
+                &program,
+                &[
+                    "--keyid-format=long",
+                    "--status-fd=1",
+                    "--verify",
+                    signature_path,
+                    "-",
+                ],
+                payload,
+                self.env,
+            )?,
+            Format::Ssh => {
+                let allowed = self
+                    .config
+                    .get("gpg.ssh.allowedSignersFile")
+                    .filter(|file| Path::new(file).exists())
+                    .ok_or_else(|| {
+                        Error::failure("error: gpg.ssh.allowedSignersFile needs to be configured and exist for ssh signature verification\n")
+                    })?;
+
+                let found = run(
+                    &program,
+                    &[
+                        "-Y",
+                        "find-principals",
+                        "-f",
+                        &allowed,
+                        "-s",
+                        signature_path,
+                    ],
+                    b"",
+                    self.env,
+                )?;
+                let principals = String::from_utf8_lossy(&found.stdout).to_string();
+                match principals.lines().next() {
+                    Some(principal) if found.status.success() => run(
+                        &program,
+                        &[
+                            "-Y",
+                            "verify",
+                            "-n",
+                            "git",
+                            "-f",
+                            &allowed,
+                            "-I",
+                            principal,
+                            "-s",
+                            signature_path,
+                        ],
+                        payload,
+                        self.env,
+                    )?,
+                    _ => {
+                        return Ok(Verification {
+                            good: false,
+                            output: "No principal matched.\n".to_string(),
+                        })
+                    }
+                }
+            }
+        };
+
+        // gpg keeps its status lines on stdout and its report for
+        // people on stderr; ssh-keygen reports on stdout
+        let output_text = match format {
+            Format::OpenPgp => String::from_utf8_lossy(&output.stderr).to_string(),
+            Format::Ssh => format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        };
+
+        Ok(Verification {
+            good: output.status.success(),
+            output: output_text,
+        })
+    }
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().expect("invalid path")
+}
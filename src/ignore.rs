@@ -0,0 +1,328 @@
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A single line of an ignore file
+struct Pattern {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Pattern> {
+        let mut line = line.trim_end_matches('\r');
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+
+        // A slash anywhere but the end ties the pattern to the
+        // directory of the ignore file, otherwise it matches a name at
+        // any depth below it
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+
+        let prefix = if anchored { "" } else { "(?:.*/)?" };
+        let regex = Regex::new(&format!("^{}{}$", prefix, glob_to_regex(line))).ok()?;
+
+        Some(Pattern {
+            regex,
+            negated,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && self.regex.is_match(path)
+    }
+}
+
+pub fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+                let at_end = i + 2 == chars.len() || chars[i + 2] == '/';
+
+                if at_start && i + 2 == chars.len() {
+                    regex.push_str(".*");
+                    i += 2;
+                } else if at_start && at_end {
+                    regex.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    regex.push_str("[^/]*");
+                    i += 2;
+                }
+            }
+            '*' => {
+                regex.push_str("[^/]*");
+                i += 1;
+            }
+            '?' => {
+                regex.push_str("[^/]");
+                i += 1;
+            }
+            '[' => match bracket_to_regex(&chars[i..]) {
+                Some((class, len)) => {
+                    regex.push_str(&class);
+                    i += len;
+                }
+                None => {
+                    regex.push_str("\\[");
+                    i += 1;
+                }
+            },
+            '\\' if i + 1 < chars.len() => {
+                regex.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+            }
+            c => {
+                regex.push_str(&regex::escape(&c.to_string()));
+                i += 1;
+            }
+        }
+    }
+
+    regex
+}
+
+/// Translate a `[...]` character class, returning it along with the
+/// number of glob characters it used up
+fn bracket_to_regex(chars: &[char]) -> Option<(String, usize)> {
+    let mut class = String::from("[");
+    let mut i = 1;
+
+    if let Some('!') | Some('^') = chars.get(i) {
+        class.push('^');
+        i += 1;
+    }
+
+    let start = i;
+    while i < chars.len() {
+        match chars[i] {
+            ']' if i > start => {
+                class.push(']');
+                return Some((class, i + 1));
+            }
+            '\\' | '[' | ']' | '^' | '&' | '~' => {
+                class.push('\\');
+                class.push(chars[i]);
+            }
+            c => class.push(c),
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// The patterns read from one ignore file, which apply to paths under
+/// `base`
+struct PatternList {
+    base: String,
+    patterns: Vec<Pattern>,
+}
+
+impl PatternList {
+    fn load(base: &str, path: &Path) -> PatternList {
+        let contents = fs::read(path).unwrap_or_default();
+        let patterns = String::from_utf8_lossy(&contents)
+            .lines()
+            .filter_map(Pattern::parse)
+            .collect();
+
+        PatternList {
+            base: base.to_string(),
+            patterns,
+        }
+    }
+
+    /// `Some(true)` if the last matching pattern ignores the path,
+    /// `Some(false)` if it re-includes it
+    fn last_match(&self, path: &str, is_dir: bool) -> Option<bool> {
+        let path = if self.base.is_empty() {
+            path
+        } else {
+            path.strip_prefix(&self.base)?.strip_prefix('/')?
+        };
+
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .map(|pattern| !pattern.negated)
+    }
+}
+
+/// Decides which workspace paths git should not treat as untracked,
+/// from `.gitignore` files, `.git/info/exclude` and core.excludesFile
+pub struct Ignore {
+    root_path: PathBuf,
+    // Lowest precedence first
+    global: Vec<PatternList>,
+    per_directory: RefCell<HashMap<String, PatternList>>,
+}
+
+impl Ignore {
+    /// `excludes_file` is the value of core.excludesFile, if set
+    pub fn new(
+        root_path: &Path,
+        git_path: &Path,
+        excludes_file: Option<String>,
+        env: &HashMap<String, String>,
+    ) -> Ignore {
+        let excludes_file = match excludes_file {
+            Some(path) => Self::expand_home(&path, env),
+            None => Self::default_excludes_file(env),
+        };
+
+        let mut global = vec![];
+        if let Some(path) = excludes_file {
+            global.push(PatternList::load("", &path));
+        }
+        global.push(PatternList::load(
+            "",
+            &git_path.join("info").join("exclude"),
+        ));
+
+        Ignore {
+            root_path: root_path.to_path_buf(),
+            global,
+            per_directory: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn expand_home(path: &str, env: &HashMap<String, String>) -> Option<PathBuf> {
+        match path.strip_prefix("~/") {
+            Some(rest) => Some(PathBuf::from(env.get("HOME")?).join(rest)),
+            None => Some(PathBuf::from(path)),
+        }
+    }
+
+    fn default_excludes_file(env: &HashMap<String, String>) -> Option<PathBuf> {
+        let config_home = match env.get("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env.get("HOME")?).join(".config"),
+        };
+
+        Some(config_home.join("git").join("ignore"))
+    }
+
+    /// `path` is relative to the root of the workspace. A path inside
+    /// an ignored directory is ignored too, whatever its own patterns
+    /// say.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let components: Vec<&str> = path.split('/').collect();
+        if components.contains(&".git") {
+            return true;
+        }
+
+        for n in 1..components.len() {
+            if self.is_excluded(&components[..n].join("/"), true) {
+                return true;
+            }
+        }
+
+        self.is_excluded(path, is_dir)
+    }
+
+    fn is_excluded(&self, path: &str, is_dir: bool) -> bool {
+        let components: Vec<&str> = path.split('/').collect();
+        let dirs: Vec<String> = (0..components.len())
+            .map(|n| components[..n].join("/"))
+            .collect();
+
+        let mut per_directory = self.per_directory.borrow_mut();
+        for dir in &dirs {
+            if !per_directory.contains_key(dir) {
+                let file = self.root_path.join(dir).join(".gitignore");
+                per_directory.insert(dir.clone(), PatternList::load(dir, &file));
+            }
+        }
+
+        let lists = self
+            .global
+            .iter()
+            .chain(dirs.iter().map(|dir| &per_directory[dir]));
+
+        lists
+            .rev()
+            .find_map(|list| list.last_match(path, is_dir))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str, is_dir: bool) -> bool {
+        Pattern::parse(pattern).unwrap().matches(path, is_dir)
+    }
+
+    #[test]
+    fn match_names_at_any_depth() {
+        assert!(matches("*.o", "main.o", false));
+        assert!(matches("*.o", "src/lib/main.o", false));
+        assert!(!matches("*.o", "main.c", false));
+        assert!(matches("build", "a/build", true));
+        assert!(matches("fo?.[ch]", "foo.c", false));
+        assert!(!matches("fo?.[!ch]", "foo.c", false));
+    }
+
+    #[test]
+    fn anchor_patterns_containing_a_slash() {
+        assert!(matches("/build", "build", true));
+        assert!(!matches("/build", "src/build", true));
+        assert!(matches("doc/*.txt", "doc/notes.txt", false));
+        assert!(!matches("doc/*.txt", "doc/api/notes.txt", false));
+    }
+
+    #[test]
+    fn match_double_asterisks() {
+        assert!(matches("**/logs", "logs", true));
+        assert!(matches("**/logs", "a/b/logs", true));
+        assert!(matches("logs/**", "logs/a/b.txt", false));
+        assert!(!matches("logs/**", "logs", true));
+        assert!(matches("a/**/b", "a/b", false));
+        assert!(matches("a/**/b", "a/x/y/b", false));
+        assert!(!matches("a/**/b", "ab/b", false));
+    }
+
+    #[test]
+    fn match_directory_only_patterns() {
+        assert!(matches("target/", "target", true));
+        assert!(!matches("target/", "target", false));
+    }
+
+    #[test]
+    fn skip_comments_and_blank_lines() {
+        assert!(Pattern::parse("# comment").is_none());
+        assert!(Pattern::parse("   ").is_none());
+        assert!(matches("\\#file", "#file", false));
+        assert!(matches("\\!important", "!important", false));
+        assert!(matches("trailing  ", "trailing", false));
+    }
+}
@@ -4,11 +4,13 @@ use std::cmp;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, ErrorKind, Read, Write};
+use std::io::{self, Read, Write};
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::str;
 
+use crate::database::tree::{TreeEntry, GITLINK_MODE, SYMLINK_MODE};
+use crate::errors::Error;
 use crate::lockfile::Lockfile;
 use crate::util::*;
 
@@ -18,6 +20,9 @@ const CHECKSUM_SIZE: u64 = 20;
 const HEADER_SIZE: usize = 12; // bytes
 const MIN_ENTRY_SIZE: usize = 64;
 
+// The file type bits of a stat mode
+const S_IFMT: u32 = 0o170000;
+
 #[derive(Debug, Clone)]
 pub struct Entry {
     ctime: i64,
@@ -40,14 +45,39 @@ impl Entry {
         (mode >> 6) & 0b1 == 1
     }
 
-    fn mode(mode: u32) -> u32 {
-        if Entry::is_executable(mode) {
+    /// The mode git records for a file with the given stat mode
+    pub fn mode(mode: u32) -> u32 {
+        if mode & S_IFMT == SYMLINK_MODE {
+            SYMLINK_MODE
+        } else if Entry::is_executable(mode) {
             0o100755u32
         } else {
             0o100644u32
         }
     }
 
+    /// Build an entry for a conflicted path from one version of it in
+    /// a tree. `stage` is 1 for the merge base, 2 for our side and 3
+    /// for theirs.
+    fn from_tree_entry(pathname: &str, item: &TreeEntry, stage: u8) -> Entry {
+        let path = pathname.to_string();
+        Entry {
+            ctime: 0,
+            ctime_nsec: 0,
+            mtime: 0,
+            mtime_nsec: 0,
+            dev: 0,
+            ino: 0,
+            mode: item.mode(),
+            uid: 0,
+            gid: 0,
+            size: 0,
+            oid: item.get_oid(),
+            flags: cmp::min(path.len() as u16, MAX_PATH_SIZE) | (u16::from(stage) << 12),
+            path,
+        }
+    }
+
     fn new(pathname: &str, oid: &str, metadata: &fs::Metadata) -> Entry {
         let path = pathname.to_string();
         Entry {
@@ -107,9 +137,9 @@ impl Entry {
         bytes.extend_from_slice(&(self.mtime_nsec as u32).to_be_bytes());
         bytes.extend_from_slice(&(self.dev as u32).to_be_bytes());
         bytes.extend_from_slice(&(self.ino as u32).to_be_bytes());
-        bytes.extend_from_slice(&(self.mode as u32).to_be_bytes());
-        bytes.extend_from_slice(&(self.uid as u32).to_be_bytes());
-        bytes.extend_from_slice(&(self.gid as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.mode.to_be_bytes());
+        bytes.extend_from_slice(&self.uid.to_be_bytes());
+        bytes.extend_from_slice(&self.gid.to_be_bytes());
         bytes.extend_from_slice(&(self.size as u32).to_be_bytes());
 
         // 20 bytes (40-char hex-string)
@@ -129,6 +159,14 @@ impl Entry {
         bytes
     }
 
+    pub fn stage(&self) -> u8 {
+        ((self.flags >> 12) & 0x3) as u8
+    }
+
+    fn key(&self) -> (String, u8) {
+        (self.path.clone(), self.stage())
+    }
+
     fn parent_dirs(&self) -> Vec<&str> {
         let path = Path::new(&self.path);
         let mut parent_dirs: Vec<_> = path
@@ -142,11 +180,23 @@ impl Entry {
         parent_dirs
     }
 
+    /// Gitlinks stand for a whole directory, which is another
+    /// repository's business, so they always match the workspace
+    pub fn is_gitlink(&self) -> bool {
+        self.mode == GITLINK_MODE
+    }
+
     pub fn stat_match(&self, stat: &fs::Metadata) -> bool {
+        if self.is_gitlink() {
+            return true;
+        }
         (self.mode == Entry::mode(stat.mode())) && (self.size == 0 || self.size == stat.size())
     }
 
     pub fn times_match(&self, stat: &fs::Metadata) -> bool {
+        if self.is_gitlink() {
+            return true;
+        }
         self.ctime == stat.ctime()
             && self.ctime_nsec == stat.ctime_nsec()
             && self.mtime == stat.mtime()
@@ -154,6 +204,9 @@ impl Entry {
     }
 
     pub fn update_stat(&mut self, stat: &fs::Metadata) {
+        if self.is_gitlink() {
+            return;
+        }
         self.ctime = stat.ctime();
         self.ctime_nsec = stat.ctime_nsec();
         self.mtime = stat.mtime();
@@ -218,8 +271,8 @@ where
 
         if sum != hash {
             return Err(io::Error::new(
-                ErrorKind::Other,
-                "Checksum does not match value stored on disk",
+                io::ErrorKind::InvalidData,
+                "bad index file sha1 signature",
             ));
         }
 
@@ -229,7 +282,9 @@ where
 
 pub struct Index {
     pathname: PathBuf,
-    pub entries: BTreeMap<String, Entry>,
+    // Entries are keyed by path and stage, so a conflicted path can
+    // have up to three entries
+    pub entries: BTreeMap<(String, u8), Entry>,
     parents: HashMap<String, HashSet<String>>,
     lockfile: Lockfile,
     hasher: Option<Sha1>,
@@ -248,11 +303,17 @@ impl Index {
         }
     }
 
-    pub fn write_updates(&mut self) -> Result<(), std::io::Error> {
+    pub fn write_updates(&mut self) -> Result<(), Error> {
         if !self.changed {
-            return self.lockfile.rollback();
+            return self.release_lock();
         }
 
+        let lock_path = self.lockfile.lock_path().to_path_buf();
+        self.write_entries()
+            .map_err(|e| Error::io("write", lock_path, e))
+    }
+
+    fn write_entries(&mut self) -> Result<(), std::io::Error> {
         let lock = &mut self.lockfile;
         let mut writer: Checksum<&Lockfile> = Checksum::new(lock);
 
@@ -303,7 +364,17 @@ impl Index {
     }
 
     fn remove_entry(&mut self, pathname: &str) {
-        if let Some(entry) = self.entries.remove(pathname) {
+        for stage in 0..=3 {
+            self.remove_entry_with_stage(pathname, stage);
+        }
+    }
+
+    fn remove_entry_with_stage(&mut self, pathname: &str, stage: u8) {
+        if let Some(entry) = self.entries.remove(&(pathname.to_string(), stage)) {
+            if self.is_tracked_file(pathname) {
+                return;
+            }
+
             for dirname in entry.parent_dirs() {
                 if let Some(ref mut children_set) = self.parents.get_mut(dirname) {
                     children_set.remove(pathname);
@@ -317,13 +388,63 @@ impl Index {
 
     pub fn add(&mut self, pathname: &str, oid: &str, metadata: &fs::Metadata) {
         let entry = Entry::new(pathname, oid, metadata);
+        self.resolve_conflict(pathname);
         self.discard_conflicts(&entry);
         self.store_entry(entry);
         self.changed = true;
     }
 
+    /// Add an entry straight from a tree, with no stat information.
+    /// Used for gitlinks, which have no file of their own to stat.
+    pub fn add_tree_entry(&mut self, pathname: &str, item: &TreeEntry) {
+        let entry = Entry::from_tree_entry(pathname, item, 0);
+        self.resolve_conflict(pathname);
+        self.discard_conflicts(&entry);
+        self.store_entry(entry);
+        self.changed = true;
+    }
+
+    /// Replace the entry for `pathname` with the base, ours and
+    /// theirs versions of it, in stages 1 to 3
+    pub fn add_conflict_set(&mut self, pathname: &str, items: [Option<TreeEntry>; 3]) {
+        self.remove_entry_with_stage(pathname, 0);
+
+        for (n, item) in items.iter().enumerate() {
+            if let Some(item) = item {
+                self.store_entry(Entry::from_tree_entry(pathname, item, n as u8 + 1));
+            }
+        }
+        self.changed = true;
+    }
+
+    /// Drop the unmerged stages of `pathname`, leaving only its stage
+    /// 0 entry if it has one
+    pub fn resolve_conflict(&mut self, pathname: &str) {
+        for stage in 1..=3 {
+            self.remove_entry_with_stage(pathname, stage);
+        }
+        self.changed = true;
+    }
+
+    pub fn has_conflict(&self) -> bool {
+        self.entries.values().any(|entry| entry.stage() > 0)
+    }
+
+    /// Every unmerged path, with the stages present for it
+    pub fn conflicts(&self) -> BTreeMap<String, Vec<u8>> {
+        let mut conflicts: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        for entry in self.entries.values().filter(|entry| entry.stage() > 0) {
+            conflicts
+                .entry(entry.path.clone())
+                .or_default()
+                .push(entry.stage());
+        }
+
+        conflicts
+    }
+
     pub fn store_entry(&mut self, entry: Entry) {
-        self.entries.insert(entry.path.clone(), entry.clone());
+        self.entries.insert(entry.key(), entry.clone());
 
         for dirname in entry.parent_dirs() {
             if let Some(ref mut children_set) = self.parents.get_mut(dirname) {
@@ -336,9 +457,20 @@ impl Index {
         }
     }
 
-    pub fn load_for_update(&mut self) -> Result<(), std::io::Error> {
-        self.lockfile.hold_for_update()?;
-        self.load()?;
+    pub fn load_for_update(&mut self) -> Result<(), Error> {
+        match self.lockfile.hold_for_update() {
+            Ok(()) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(Error::LockHeld {
+                    path: self.lockfile.lock_path().to_path_buf(),
+                })
+            }
+            Err(e) => return Err(Error::io("create", self.lockfile.lock_path(), e)),
+        }
+        if let Err(error) = self.load() {
+            self.lockfile.rollback().unwrap_or(());
+            return Err(error);
+        }
 
         Ok(())
     }
@@ -361,23 +493,27 @@ impl Index {
         }
     }
 
-    fn read_header(checksum: &mut Checksum<File>) -> usize {
-        let data = checksum
-            .read(HEADER_SIZE)
-            .expect("could not read checksum header");
-        let signature = str::from_utf8(&data[0..4]).expect("invalid signature");
+    fn read_header(checksum: &mut Checksum<File>) -> Result<usize, std::io::Error> {
+        let data = checksum.read(HEADER_SIZE)?;
+        let signature = u32::from_be_bytes(data[0..4].try_into().unwrap());
         let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
         let count = u32::from_be_bytes(data[8..12].try_into().unwrap());
 
-        if signature != "DIRC" {
-            panic!("Signature: expected 'DIRC', but found {}", signature);
+        if &data[0..4] != b"DIRC" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad signature 0x{:08x}", signature),
+            ));
         }
 
         if version != 2 {
-            panic!("Version: expected '2', but found {}", version);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad index version {}", version),
+            ));
         }
 
-        count as usize
+        Ok(count as usize)
     }
 
     fn read_entries(
@@ -397,24 +533,27 @@ impl Index {
         Ok(())
     }
 
-    pub fn load(&mut self) -> Result<(), std::io::Error> {
+    pub fn load(&mut self) -> Result<(), Error> {
         self.clear();
         if let Some(file) = self.open_index_file() {
             let mut reader = Checksum::new(file);
-            let count = Index::read_header(&mut reader);
-            self.read_entries(&mut reader, count)?;
-            reader.verify_checksum()?;
+            let count = Index::read_header(&mut reader).map_err(corrupt)?;
+            self.read_entries(&mut reader, count).map_err(corrupt)?;
+            reader.verify_checksum().map_err(corrupt)?;
         }
 
         Ok(())
     }
 
-    pub fn release_lock(&mut self) -> Result<(), std::io::Error> {
-        self.lockfile.rollback()
+    pub fn release_lock(&mut self) -> Result<(), Error> {
+        let lock_path = self.lockfile.lock_path().to_path_buf();
+        self.lockfile
+            .rollback()
+            .map_err(|e| Error::io("remove", lock_path, e))
     }
 
     pub fn is_tracked_file(&self, pathname: &str) -> bool {
-        self.entries.contains_key(pathname)
+        (0..=3).any(|stage| self.entries.contains_key(&(pathname.to_string(), stage)))
     }
 
     pub fn is_tracked(&self, pathname: &str) -> bool {
@@ -427,15 +566,26 @@ impl Index {
     }
 
     pub fn entry_for_path(&self, path: &str) -> Option<&Entry> {
-        self.entries.get(path)
+        self.entries.get(&(path.to_string(), 0))
     }
 }
 
+/// Describe a failure to read the index as git does
+fn corrupt(error: io::Error) -> Error {
+    let reason = match error.kind() {
+        io::ErrorKind::UnexpectedEof => "index file smaller than expected".to_string(),
+        _ => error.to_string(),
+    };
+
+    Error::CorruptIndex { reason }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::database;
     use crate::database::blob::Blob;
-    use crate::database::object::Object;
+    use crate::database::object::Storable;
     use crate::repository::Repository;
     use rand::random;
     use std::process::Command;
@@ -447,14 +597,15 @@ mod tests {
         temp_dir.push_str("_jit_test");
 
         let root_path = Path::new("/tmp").join(temp_dir);
-        let mut repo = Repository::new(&root_path);
+        let mut repo =
+            Repository::at(&root_path.join(".git"), &root_path, &HashMap::new()).unwrap();
         fs::create_dir(&root_path)?;
 
         let oid = encode_hex(&(0..20).map(|_n| random::<u8>()).collect::<Vec<u8>>());
 
         let f1_filename = "alice.txt";
         let f1_path = root_path.join(f1_filename);
-        File::create(&f1_path)?.write(b"file 1")?;
+        File::create(&f1_path)?.write_all(b"file 1")?;
         let stat = repo.workspace.stat_file(f1_filename)?;
 
         {
@@ -462,7 +613,7 @@ mod tests {
             repo.index.add(f1_filename, &oid, &stat);
 
             let index_entry_paths: Vec<&String> =
-                repo.index.entries.iter().map(|(path, _)| path).collect();
+                repo.index.entries.keys().map(|(path, _)| path).collect();
 
             assert_eq!(vec![f1_filename], index_entry_paths);
         }
@@ -474,7 +625,7 @@ mod tests {
             repo.index.add("alice.txt/nested.txt", &oid, &stat);
             repo.index.add("bob.txt", &oid, &stat);
             let index_entry_paths: Vec<&String> =
-                repo.index.entries.iter().map(|(path, _)| path).collect();
+                repo.index.entries.keys().map(|(path, _)| path).collect();
 
             assert_eq!(vec!["alice.txt/nested.txt", "bob.txt"], index_entry_paths);
         }
@@ -488,7 +639,7 @@ mod tests {
             repo.index.add("nested", &oid, &stat);
 
             let index_entry_paths: Vec<&String> =
-                repo.index.entries.iter().map(|(path, _)| path).collect();
+                repo.index.entries.keys().map(|(path, _)| path).collect();
 
             assert_eq!(vec!["alice.txt", "nested"], index_entry_paths);
         }
@@ -503,7 +654,7 @@ mod tests {
             repo.index.add("nested", &oid, &stat);
 
             let index_entry_paths: Vec<&String> =
-                repo.index.entries.iter().map(|(path, _)| path).collect();
+                repo.index.entries.keys().map(|(path, _)| path).collect();
 
             assert_eq!(vec!["alice.txt", "nested"], index_entry_paths);
         }
@@ -514,6 +665,50 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn store_and_resolve_conflicts() -> Result<(), std::io::Error> {
+        let mut temp_dir = generate_temp_name();
+        temp_dir.push_str("_jit_test");
+
+        let root_path = Path::new("/tmp").join(temp_dir);
+        let mut repo =
+            Repository::at(&root_path.join(".git"), &root_path, &HashMap::new()).unwrap();
+        fs::create_dir_all(root_path.join(".git"))?;
+
+        let oid = encode_hex(&(0..20).map(|_n| random::<u8>()).collect::<Vec<u8>>());
+        let item = |oid: &str| Some(TreeEntry::Entry(database::Entry::new("", oid, 0o100644)));
+
+        File::create(root_path.join("alice.txt"))?.write_all(b"file 1")?;
+        let stat = repo.workspace.stat_file("alice.txt")?;
+
+        repo.index.load_for_update().unwrap();
+        repo.index.add("alice.txt", &oid, &stat);
+        repo.index
+            .add_conflict_set("bob.txt", [item(&oid), None, item(&oid)]);
+        repo.index.write_updates().unwrap();
+
+        // Stage bits survive a round trip through the index file
+        repo.index.load().unwrap();
+        let mut expected = BTreeMap::new();
+        expected.insert("bob.txt".to_string(), vec![1, 3]);
+        assert_eq!(expected, repo.index.conflicts());
+        assert!(repo.index.is_tracked_file("bob.txt"));
+        assert!(repo.index.entry_for_path("bob.txt").is_none());
+
+        repo.index.add("bob.txt", &oid, &stat);
+        assert!(!repo.index.has_conflict());
+        let keys: Vec<(String, u8)> = repo.index.entries.keys().cloned().collect();
+        assert_eq!(
+            vec![("alice.txt".to_string(), 0), ("bob.txt".to_string(), 0)],
+            keys
+        );
+
+        // Cleanup
+        fs::remove_dir_all(&root_path)?;
+
+        Ok(())
+    }
+
     #[test]
     fn emit_index_file_same_as_stock_git() -> Result<(), std::io::Error> {
         // Create index file, using "stock" git and our implementation and
@@ -523,33 +718,34 @@ mod tests {
         temp_dir.push_str("_jit_test");
 
         let root_path = Path::new("/tmp").join(temp_dir);
-        let mut repo = Repository::new(&root_path);
+        let mut repo =
+            Repository::at(&root_path.join(".git"), &root_path, &HashMap::new()).unwrap();
         fs::create_dir(&root_path)?;
 
         let git_path = root_path.join(".git");
         fs::create_dir(&git_path)?;
 
-        repo.index.load_for_update()?;
+        repo.index.load_for_update().unwrap();
 
         // Create some files
-        File::create(root_path.join("f1.txt"))?.write(b"file 1")?;
-        File::create(root_path.join("f2.txt"))?.write(b"file 2")?;
+        File::create(root_path.join("f1.txt"))?.write_all(b"file 1")?;
+        File::create(root_path.join("f2.txt"))?.write_all(b"file 2")?;
 
         // Create an index out of those files
         for pathname in repo.workspace.list_files(&root_path)? {
             let data = repo.workspace.read_file(&pathname)?;
             let stat = repo.workspace.stat_file(&pathname)?;
 
-            let blob = Blob::new(data.as_bytes());
+            let blob = Blob::new(&data);
             repo.database.store(&blob)?;
 
             repo.index.add(&pathname, &blob.get_oid(), &stat);
         }
 
-        repo.index.write_updates()?;
+        repo.index.write_updates().unwrap();
 
         // Store contents of our index file
-        let mut our_index = File::open(&git_path.join("index"))?;
+        let mut our_index = File::open(git_path.join("index"))?;
         let mut our_index_contents = Vec::new();
         our_index.read_to_end(&mut our_index_contents)?;
 
@@ -568,7 +764,7 @@ mod tests {
             .arg(".")
             .output();
 
-        let mut git_index = File::open(&git_path.join("index"))?;
+        let mut git_index = File::open(git_path.join("index"))?;
         let mut git_index_contents = Vec::new();
         git_index.read_to_end(&mut git_index_contents)?;
 
@@ -0,0 +1,48 @@
+//! rug is a git implementation. Besides the `rug` command, it can be
+//! used as a library to open a repository and read or write its
+//! objects, refs and index, resolve revisions, walk history and
+//! inspect the work tree's status.
+//!
+//! ```no_run
+//! use rug::repository::Repository;
+//! use rug::revision::Revision;
+//!
+//! let mut repo = Repository::open(std::path::Path::new(".")).unwrap();
+//! let head = Revision::new(&mut repo, "HEAD").resolve().unwrap();
+//! repo.index.load().unwrap();
+//! let status = repo.status().unwrap();
+//! println!("{} with {} changed paths", head, status.changed.len());
+//! ```
+
+extern crate chrono;
+extern crate crypto;
+extern crate flate2;
+extern crate rand;
+#[macro_use]
+extern crate lazy_static;
+extern crate clap;
+extern crate regex;
+
+mod lockfile;
+
+pub mod config;
+pub mod database;
+mod date;
+mod diff;
+mod editor;
+pub mod errors;
+mod gpg;
+mod ignore;
+pub mod index;
+mod merge;
+mod pack;
+mod pager;
+mod reflog;
+pub mod refs;
+pub mod repository;
+pub mod revision;
+mod util;
+pub mod workspace;
+
+#[doc(hidden)]
+pub mod commands;
@@ -1,6 +1,6 @@
 use std::fs::{self, File, OpenOptions};
+use std::io;
 use std::io::prelude::*;
-use std::io::{self, ErrorKind};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
@@ -12,13 +12,21 @@ pub struct Lockfile {
 
 impl Lockfile {
     pub fn new(path: &Path) -> Lockfile {
+        // Appended rather than swapped in, so `v1.0` locks as `v1.0.lock`
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+
         Lockfile {
             file_path: path.to_path_buf(),
-            lock_path: path.with_extension("lock"),
+            lock_path: PathBuf::from(lock_path),
             lock: None,
         }
     }
 
+    pub fn lock_path(&self) -> &Path {
+        &self.lock_path
+    }
+
     pub fn hold_for_update(&mut self) -> Result<(), std::io::Error> {
         if self.lock.is_none() {
             let open_file = OpenOptions::new()
@@ -64,10 +72,10 @@ impl Lockfile {
 
     fn raise_on_stale_lock(&self) -> Result<(), std::io::Error> {
         if self.lock.is_none() {
-            Err(io::Error::new(
-                ErrorKind::Other,
-                format!("Not holding lock on file: {:?}", self.lock_path),
-            ))
+            Err(io::Error::other(format!(
+                "Not holding lock on file: {:?}",
+                self.lock_path
+            )))
         } else {
             Ok(())
         }
@@ -75,11 +83,11 @@ impl Lockfile {
 }
 
 impl Read for Lockfile {
-    fn read(&mut self, mut buf: &mut [u8]) -> Result<usize, io::Error> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
         self.raise_on_stale_lock()?;
 
         let mut lock = self.lock.as_ref().unwrap();
-        lock.read(&mut buf)
+        lock.read(buf)
     }
 }
 
@@ -97,16 +105,16 @@ impl Write for Lockfile {
     }
 }
 
-impl<'a> Read for &'a Lockfile {
-    fn read(&mut self, mut buf: &mut [u8]) -> Result<usize, io::Error> {
+impl Read for &Lockfile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
         self.raise_on_stale_lock()?;
 
         let mut lock = self.lock.as_ref().unwrap();
-        lock.read(&mut buf)
+        lock.read(buf)
     }
 }
 
-impl<'a> Write for &'a Lockfile {
+impl Write for &Lockfile {
     fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
         self.raise_on_stale_lock()?;
 
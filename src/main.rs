@@ -1,32 +1,39 @@
-extern crate chrono;
-extern crate crypto;
-extern crate flate2;
-extern crate rand;
-#[macro_use]
-extern crate lazy_static;
-extern crate regex;
-extern crate clap;
-
 use std::collections::HashMap;
 use std::env;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 
-mod lockfile;
+use rug::commands::{execute, get_app, CommandContext, Terminal};
+use rug::config::stack::Stack;
+use rug::errors;
 
-mod database;
-mod index;
-mod refs;
-mod repository;
-mod util;
-mod workspace;
-mod diff;
-mod pager;
-mod revision;
+fn main() {
+    let matches = get_app().get_matches();
 
-mod commands;
-use commands::{execute, get_app, CommandContext};
+    // `-c` overrides travel the way git passes them on, so that every
+    // config lookup sees them
+    if let Some(overrides) = matches.values_of("config") {
+        let mut parameters = env::var("GIT_CONFIG_PARAMETERS").unwrap_or_default();
+        for parameter in overrides {
+            if !parameters.is_empty() {
+                parameters.push(' ');
+            }
+            parameters.push_str(&Stack::quote_override(parameter));
+        }
+        env::set_var("GIT_CONFIG_PARAMETERS", parameters);
+    }
+
+    // Each `-C` is taken relative to the one before it
+    for dir in matches.values_of("chdir").into_iter().flatten() {
+        if let Err(e) = env::set_current_dir(dir) {
+            eprintln!(
+                "fatal: cannot change to '{}': {}",
+                dir,
+                errors::describe(&e)
+            );
+            std::process::exit(128);
+        }
+    }
 
-fn main() {
     let ctx = CommandContext {
         dir: env::current_dir().unwrap(),
         env: &env::vars().collect::<HashMap<String, String>>(),
@@ -34,15 +41,21 @@ fn main() {
         stdin: io::stdin(),
         stdout: io::stdout(),
         stderr: io::stderr(),
+        terminal: if io::stdout().is_terminal() {
+            Terminal::Process
+        } else {
+            Terminal::None
+        },
     };
 
-    let matches = get_app().get_matches();
-
     match execute(matches, ctx) {
         Ok(_) => (),
-        Err(msg) => {
-            io::stderr().write_all(msg.as_bytes()).unwrap();
-            std::process::exit(128);
+        Err(error) => {
+            io::stdout().flush().unwrap_or(());
+            io::stderr()
+                .write_all(error.to_string().as_bytes())
+                .unwrap();
+            std::process::exit(error.exit_code());
         }
     }
 }
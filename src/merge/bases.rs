@@ -0,0 +1,72 @@
+use crate::database::Database;
+use crate::errors::Error;
+use crate::merge::common_ancestors::{CommonAncestors, PARENT1, PARENT2};
+use std::collections::HashSet;
+
+/// The best common ancestors of two commits: common ancestors that
+/// are not themselves ancestors of another common ancestor
+pub struct Bases<'a> {
+    database: &'a mut Database,
+    one: String,
+    two: String,
+}
+
+impl<'a> Bases<'a> {
+    pub fn new(database: &'a mut Database, one: &str, two: &str) -> Bases<'a> {
+        Bases {
+            database,
+            one: one.to_string(),
+            two: two.to_string(),
+        }
+    }
+
+    pub fn find(&mut self) -> Result<Vec<String>, Error> {
+        let commits =
+            CommonAncestors::new(self.database, &self.one, std::slice::from_ref(&self.two))?
+                .find()?;
+        if commits.len() <= 1 {
+            return Ok(commits);
+        }
+
+        let mut redundant = HashSet::new();
+        for commit in &commits {
+            self.filter_commit(commit, &commits, &mut redundant)?;
+        }
+
+        Ok(commits
+            .into_iter()
+            .filter(|oid| !redundant.contains(oid))
+            .collect())
+    }
+
+    fn filter_commit(
+        &mut self,
+        commit: &str,
+        commits: &[String],
+        redundant: &mut HashSet<String>,
+    ) -> Result<(), Error> {
+        if redundant.contains(commit) {
+            return Ok(());
+        }
+
+        let others: Vec<String> = commits
+            .iter()
+            .filter(|oid| *oid != commit && !redundant.contains(*oid))
+            .cloned()
+            .collect();
+
+        let mut common = CommonAncestors::new(self.database, commit, &others)?;
+        common.find()?;
+
+        if common.is_marked(commit, PARENT2) {
+            redundant.insert(commit.to_string());
+        }
+        for oid in others {
+            if common.is_marked(&oid, PARENT1) {
+                redundant.insert(oid);
+            }
+        }
+
+        Ok(())
+    }
+}
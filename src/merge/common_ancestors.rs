@@ -0,0 +1,114 @@
+use crate::database::commit::Commit;
+use crate::database::{Database, ParsedObject};
+use crate::errors::Error;
+use std::collections::HashMap;
+
+pub const PARENT1: u8 = 1;
+pub const PARENT2: u8 = 2;
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+const BOTH_PARENTS: u8 = PARENT1 | PARENT2;
+
+/// Finds the commits that are ancestors of both `one` and any of
+/// `twos`, by walking back from all of them in date order and marking
+/// each commit with the side(s) it was reached from
+pub struct CommonAncestors<'a> {
+    database: &'a mut Database,
+    flags: HashMap<String, u8>,
+    queue: Vec<(String, Commit)>,
+    results: Vec<(String, Commit)>,
+}
+
+impl<'a> CommonAncestors<'a> {
+    pub fn new(
+        database: &'a mut Database,
+        one: &str,
+        twos: &[String],
+    ) -> Result<CommonAncestors<'a>, Error> {
+        let mut common = CommonAncestors {
+            database,
+            flags: HashMap::new(),
+            queue: vec![],
+            results: vec![],
+        };
+
+        common.enqueue(one, PARENT1)?;
+        for two in twos {
+            common.enqueue(two, PARENT2)?;
+        }
+
+        Ok(common)
+    }
+
+    pub fn find(&mut self) -> Result<Vec<String>, Error> {
+        while !self.all_stale() {
+            self.process_queue()?;
+        }
+
+        Ok(self
+            .results
+            .iter()
+            .map(|(oid, _)| oid.clone())
+            .filter(|oid| !self.is_marked(oid, STALE))
+            .collect())
+    }
+
+    pub fn is_marked(&self, oid: &str, flag: u8) -> bool {
+        self.flags.get(oid).map(|f| f & flag != 0).unwrap_or(false)
+    }
+
+    fn all_stale(&self) -> bool {
+        self.queue.iter().all(|(oid, _)| self.is_marked(oid, STALE))
+    }
+
+    fn process_queue(&mut self) -> Result<(), Error> {
+        let (oid, commit) = self.queue.remove(0);
+        let flags = self.flags[&oid];
+
+        if flags == BOTH_PARENTS {
+            self.flags.insert(oid.clone(), flags | RESULT);
+            Self::insert_by_date(&mut self.results, oid, commit.clone());
+            self.add_parents(&commit, flags | STALE)
+        } else {
+            self.add_parents(&commit, flags)
+        }
+    }
+
+    fn add_parents(&mut self, commit: &Commit, flags: u8) -> Result<(), Error> {
+        for parent in &commit.parents {
+            let parent_flags = self.flags.get(parent).cloned().unwrap_or(0);
+            if parent_flags & flags == flags {
+                continue;
+            }
+            self.enqueue(parent, flags)?;
+        }
+
+        Ok(())
+    }
+
+    fn enqueue(&mut self, oid: &str, flags: u8) -> Result<(), Error> {
+        *self.flags.entry(oid.to_string()).or_insert(0) |= flags;
+
+        let commit = match self.database.load(oid)? {
+            ParsedObject::Commit(commit) => commit.clone(),
+            object => {
+                return Err(Error::CorruptObject {
+                    oid: oid.to_string(),
+                    reason: format!("object {} is a {}, not a commit", oid, object.obj_type()),
+                })
+            }
+        };
+        Self::insert_by_date(&mut self.queue, oid.to_string(), commit);
+
+        Ok(())
+    }
+
+    /// Keep the list ordered newest first
+    fn insert_by_date(list: &mut Vec<(String, Commit)>, oid: String, commit: Commit) {
+        let index = list
+            .iter()
+            .position(|(_, c)| c.committer.time < commit.committer.time)
+            .unwrap_or(list.len());
+        list.insert(index, (oid, commit));
+    }
+}
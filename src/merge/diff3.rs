@@ -0,0 +1,250 @@
+use crate::diff::myers::EditType;
+use crate::diff::{self, Diff};
+use std::collections::HashMap;
+
+// Lines keep their newlines, so that a file's last line can be told
+// apart from one that has a newline added
+type Line = Vec<u8>;
+
+pub enum Chunk {
+    Clean(Vec<Line>),
+    Conflict {
+        a_lines: Vec<Line>,
+        b_lines: Vec<Line>,
+    },
+}
+
+/// Line-based three-way merge of `a` and `b`, which are both derived
+/// from `o`. The inputs are split into alternating runs of lines that
+/// match in all three versions and runs where at least one differs.
+pub struct Diff3 {
+    o: Vec<Line>,
+    a: Vec<Line>,
+    b: Vec<Line>,
+    match_a: HashMap<usize, usize>,
+    match_b: HashMap<usize, usize>,
+    line_o: usize,
+    line_a: usize,
+    line_b: usize,
+    chunks: Vec<Chunk>,
+}
+
+impl Diff3 {
+    pub fn merge(o: &[u8], a: &[u8], b: &[u8]) -> Diff3 {
+        let mut diff3 = Diff3 {
+            o: Self::lines(o),
+            a: Self::lines(a),
+            b: Self::lines(b),
+            match_a: Self::match_set(o, a),
+            match_b: Self::match_set(o, b),
+            line_o: 0,
+            line_a: 0,
+            line_b: 0,
+            chunks: vec![],
+        };
+        diff3.generate_chunks();
+
+        diff3
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.chunks.iter().all(|chunk| match chunk {
+            Chunk::Clean(_) => true,
+            Chunk::Conflict { .. } => false,
+        })
+    }
+
+    /// Render the merge result, with conflict markers naming the two
+    /// sides around any conflicting chunks
+    pub fn to_bytes(&self, a_name: &str, b_name: &str) -> Vec<u8> {
+        let mut output = vec![];
+
+        for chunk in &self.chunks {
+            match chunk {
+                Chunk::Clean(clean) => output.extend(clean.concat()),
+                Chunk::Conflict { a_lines, b_lines } => {
+                    output.extend(format!("<<<<<<< {}\n", a_name).bytes());
+                    Self::write_side(&mut output, a_lines);
+                    output.extend(b"=======\n");
+                    Self::write_side(&mut output, b_lines);
+                    output.extend(format!(">>>>>>> {}\n", b_name).bytes());
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Write one side of a conflict, ending it with a newline so the
+    /// marker after it starts a line of its own
+    fn write_side(output: &mut Vec<u8>, lines: &[Line]) {
+        output.extend(lines.concat());
+        if !output.ends_with(b"\n") {
+            output.push(b'\n');
+        }
+    }
+
+    fn lines(text: &[u8]) -> Vec<Line> {
+        diff::lines(text)
+            .iter()
+            .map(|line| line.text().to_vec())
+            .collect()
+    }
+
+    /// Map the numbers of lines in `o` to the numbers of the lines in
+    /// `other` they are unchanged in
+    fn match_set(o: &[u8], other: &[u8]) -> HashMap<usize, usize> {
+        Diff::diff(o, other)
+            .into_iter()
+            .filter(|edit| edit.edit_type == EditType::Eql)
+            .map(|edit| (edit.a_line.unwrap().number, edit.b_line.unwrap().number))
+            .collect()
+    }
+
+    fn generate_chunks(&mut self) {
+        loop {
+            match self.find_next_mismatch() {
+                Some(1) => match self.find_next_match() {
+                    (o, Some(a), Some(b)) => self.emit_chunk(o, a, b),
+                    _ => return self.emit_final_chunk(),
+                },
+                Some(i) => self.emit_chunk(self.line_o + i, self.line_a + i, self.line_b + i),
+                None => return self.emit_final_chunk(),
+            }
+        }
+    }
+
+    fn find_next_mismatch(&self) -> Option<usize> {
+        let mut i = 1;
+        while self.in_bounds(i)
+            && Self::is_match(&self.match_a, self.line_o, self.line_a, i)
+            && Self::is_match(&self.match_b, self.line_o, self.line_b, i)
+        {
+            i += 1;
+        }
+
+        if self.in_bounds(i) {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    fn in_bounds(&self, i: usize) -> bool {
+        self.line_o + i <= self.o.len()
+            || self.line_a + i <= self.a.len()
+            || self.line_b + i <= self.b.len()
+    }
+
+    fn is_match(matches: &HashMap<usize, usize>, line_o: usize, offset: usize, i: usize) -> bool {
+        matches.get(&(line_o + i)) == Some(&(offset + i))
+    }
+
+    fn find_next_match(&self) -> (usize, Option<usize>, Option<usize>) {
+        let mut o = self.line_o + 1;
+        while o <= self.o.len() && !(self.match_a.contains_key(&o) && self.match_b.contains_key(&o))
+        {
+            o += 1;
+        }
+
+        (
+            o,
+            self.match_a.get(&o).cloned(),
+            self.match_b.get(&o).cloned(),
+        )
+    }
+
+    fn emit_chunk(&mut self, o: usize, a: usize, b: usize) {
+        let chunk = Self::make_chunk(
+            &self.o[self.line_o..o - 1],
+            &self.a[self.line_a..a - 1],
+            &self.b[self.line_b..b - 1],
+        );
+        self.chunks.push(chunk);
+
+        self.line_o = o - 1;
+        self.line_a = a - 1;
+        self.line_b = b - 1;
+    }
+
+    fn emit_final_chunk(&mut self) {
+        let chunk = Self::make_chunk(
+            &self.o[self.line_o..],
+            &self.a[self.line_a..],
+            &self.b[self.line_b..],
+        );
+        self.chunks.push(chunk);
+    }
+
+    fn make_chunk(o: &[Line], a: &[Line], b: &[Line]) -> Chunk {
+        if a == o || a == b {
+            Chunk::Clean(b.to_vec())
+        } else if b == o {
+            Chunk::Clean(a.to_vec())
+        } else {
+            Chunk::Conflict {
+                a_lines: a.to_vec(),
+                b_lines: b.to_vec(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_changes_to_different_lines() {
+        let merge = Diff3::merge(
+            b"1\n2\n3\n4\n5\n",
+            b"one\n2\n3\n4\n5\n",
+            b"1\n2\n3\n4\nfive\n",
+        );
+
+        assert!(merge.is_clean());
+        assert_eq!(
+            b"one\n2\n3\n4\nfive\n".to_vec(),
+            merge.to_bytes("ours", "theirs")
+        );
+    }
+
+    #[test]
+    fn merge_identical_changes() {
+        let merge = Diff3::merge(b"1\n2\n3\n", b"1\ntwo\n3\n", b"1\ntwo\n3\n");
+
+        assert!(merge.is_clean());
+        assert_eq!(b"1\ntwo\n3\n".to_vec(), merge.to_bytes("ours", "theirs"));
+    }
+
+    #[test]
+    fn mark_conflicting_changes() {
+        let merge = Diff3::merge(b"1\n2\n3\n", b"1\nleft\n3\n", b"1\nright\n3\n");
+
+        assert!(!merge.is_clean());
+        assert_eq!(
+            b"1\n<<<<<<< ours\nleft\n=======\nright\n>>>>>>> theirs\n3\n".to_vec(),
+            merge.to_bytes("ours", "theirs")
+        );
+    }
+
+    #[test]
+    fn mark_conflicting_additions_at_the_end() {
+        let merge = Diff3::merge(b"1\n", b"1\nleft\n", b"1\nright\n");
+
+        assert_eq!(
+            b"1\n<<<<<<< ours\nleft\n=======\nright\n>>>>>>> theirs\n".to_vec(),
+            merge.to_bytes("ours", "theirs")
+        );
+    }
+
+    #[test]
+    fn end_conflicting_lines_without_a_newline() {
+        let merge = Diff3::merge(b"1\n", b"1\nleft", b"1\nright");
+
+        assert_eq!(
+            b"1\n<<<<<<< ours\nleft\n=======\nright\n>>>>>>> theirs\n".to_vec(),
+            merge.to_bytes("ours", "theirs")
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use crate::errors::Error;
+use crate::merge::bases::Bases;
+use crate::repository::Repository;
+use crate::revision::Revision;
+
+/// The two commits being merged, along with the names they were given
+/// on the command line and their best common ancestors
+pub struct Inputs {
+    pub left_name: String,
+    pub right_name: String,
+    pub left_oid: String,
+    pub right_oid: String,
+    pub base_oids: Vec<String>,
+}
+
+impl Inputs {
+    pub fn new(repo: &mut Repository, left_name: &str, right_name: &str) -> Result<Inputs, Error> {
+        let left_oid = Revision::new(repo, left_name).resolve()?;
+        let right_oid = Revision::new(repo, right_name).resolve()?;
+        let base_oids = Bases::new(&mut repo.database, &left_oid, &right_oid).find()?;
+
+        Ok(Inputs {
+            left_name: left_name.to_string(),
+            right_name: right_name.to_string(),
+            left_oid,
+            right_oid,
+            base_oids,
+        })
+    }
+
+    pub fn is_already_merged(&self) -> bool {
+        self.base_oids == [self.right_oid.clone()]
+    }
+
+    pub fn is_fast_forward(&self) -> bool {
+        self.base_oids == [self.left_oid.clone()]
+    }
+}
@@ -0,0 +1,319 @@
+use crate::database::blob::Blob;
+use crate::database::object::Storable;
+use crate::database::tree::{TreeEntry, SYMLINK_MODE};
+use crate::database::Entry;
+use crate::diff::binary::is_binary;
+use crate::errors::Error;
+use crate::merge::diff3::Diff3;
+use crate::merge::inputs::Inputs;
+use crate::repository::Repository;
+use crate::workspace::Workspace;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+type TreeChanges = HashMap<PathBuf, (Option<TreeEntry>, Option<TreeEntry>)>;
+
+/// Three-way merge of the trees of two commits against their common
+/// ancestor. Paths changed on one side only are taken as-is, and
+/// paths changed on both sides are merged line by line. Anything that
+/// can't be merged is recorded in the index as a conflict.
+pub struct Resolve<'a> {
+    repo: &'a mut Repository,
+    inputs: &'a Inputs,
+    left_diff: TreeChanges,
+    clean_diff: TreeChanges,
+    conflicts: BTreeMap<PathBuf, [Option<TreeEntry>; 3]>,
+    untracked: BTreeMap<String, TreeEntry>,
+    pub messages: Vec<String>,
+}
+
+impl<'a> Resolve<'a> {
+    pub fn new(repo: &'a mut Repository, inputs: &'a Inputs) -> Resolve<'a> {
+        Resolve {
+            repo,
+            inputs,
+            left_diff: HashMap::new(),
+            clean_diff: HashMap::new(),
+            conflicts: BTreeMap::new(),
+            untracked: BTreeMap::new(),
+            messages: vec![],
+        }
+    }
+
+    pub fn execute(&mut self) -> Result<(), Error> {
+        self.prepare_tree_diffs()?;
+
+        let clean_diff = self.clean_diff.clone();
+        self.repo.migration(clean_diff).apply_changes()?;
+
+        self.add_conflicts_to_index();
+        self.write_untracked_files()
+    }
+
+    fn prepare_tree_diffs(&mut self) -> Result<(), Error> {
+        let base_oid = self.inputs.base_oids.first().map(|oid| oid.as_str());
+        self.left_diff = self
+            .repo
+            .database
+            .tree_diff(base_oid, Some(&self.inputs.left_oid))?;
+        let right_diff = self
+            .repo
+            .database
+            .tree_diff(base_oid, Some(&self.inputs.right_oid))?;
+
+        let left_diff = self.left_diff.clone();
+        let (left_name, right_name) = (&self.inputs.left_name, &self.inputs.right_name);
+
+        for path in Self::sorted_paths(&right_diff) {
+            let (old_item, new_item) = right_diff[&path].clone();
+            if new_item.is_some() {
+                self.file_dir_conflict(&path, &left_diff, left_name);
+            }
+            self.same_path_conflict(&path, old_item, new_item)?;
+        }
+
+        for path in Self::sorted_paths(&left_diff) {
+            if left_diff[&path].1.is_some() {
+                self.file_dir_conflict(&path, &right_diff, right_name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sorted_paths(diff: &TreeChanges) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = diff.keys().cloned().collect();
+        paths.sort();
+        paths
+    }
+
+    fn same_path_conflict(
+        &mut self,
+        path: &Path,
+        base: Option<TreeEntry>,
+        right: Option<TreeEntry>,
+    ) -> Result<(), Error> {
+        if self.conflicts.contains_key(path) {
+            return Ok(());
+        }
+
+        let left = match self.left_diff.get(path) {
+            Some((_, left)) => left.clone(),
+            None => {
+                self.clean_diff.insert(path.to_path_buf(), (base, right));
+                return Ok(());
+            }
+        };
+
+        if left == right {
+            return Ok(());
+        }
+
+        if left.is_some() && right.is_some() {
+            self.log(format!("Auto-merging {}", path.display()));
+        }
+
+        let oid = |item: &Option<TreeEntry>| item.as_ref().map(|e| e.get_oid());
+        let mode = |item: &Option<TreeEntry>| item.as_ref().map(|e| e.mode());
+
+        // Neither symlinks nor gitlinks can be merged line by line
+        let unmergeable = [&base, &left, &right].iter().any(|item| {
+            item.as_ref()
+                .is_some_and(|e| e.is_gitlink() || e.mode() == SYMLINK_MODE)
+        });
+        let (oid_ok, merged_oid) = if unmergeable {
+            Self::merge3(oid(&base), oid(&left), oid(&right)).unwrap_or((false, oid(&left)))
+        } else {
+            self.merge_blobs(oid(&base), oid(&left), oid(&right))?
+        };
+        let (mode_ok, merged_mode) =
+            Self::merge3(mode(&base), mode(&left), mode(&right)).unwrap_or((false, mode(&left)));
+
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let merged = TreeEntry::Entry(Entry::new(
+            name,
+            &merged_oid.expect("merged blob has no oid"),
+            merged_mode.expect("merged blob has no mode"),
+        ));
+        self.clean_diff
+            .insert(path.to_path_buf(), (left.clone(), Some(merged)));
+
+        if oid_ok && mode_ok {
+            return Ok(());
+        }
+
+        self.conflicts
+            .insert(path.to_path_buf(), [base, left, right]);
+        self.log_conflict(path, None);
+
+        Ok(())
+    }
+
+    fn merge_blobs(
+        &mut self,
+        base_oid: Option<String>,
+        left_oid: Option<String>,
+        right_oid: Option<String>,
+    ) -> Result<(bool, Option<String>), Error> {
+        if let Some(result) = Self::merge3(base_oid.clone(), left_oid.clone(), right_oid.clone()) {
+            return Ok(result);
+        }
+
+        let base = self.blob_data(base_oid)?;
+        let left = self.blob_data(left_oid.clone())?;
+        let right = self.blob_data(right_oid)?;
+
+        // Binary files can't be merged by lines, so ours is kept
+        if [&base, &left, &right].iter().any(|data| is_binary(data)) {
+            return Ok((false, left_oid));
+        }
+
+        let merge = Diff3::merge(&base, &left, &right);
+        let data = merge.to_bytes(&self.inputs.left_name, &self.inputs.right_name);
+
+        let blob = Blob::new(&data);
+        self.repo.database.store(&blob).map_err(|e| {
+            Error::io(
+                "write object",
+                self.repo.database.object_path(&blob.get_oid()),
+                e,
+            )
+        })?;
+
+        Ok((merge.is_clean(), Some(blob.get_oid())))
+    }
+
+    fn blob_data(&mut self, oid: Option<String>) -> Result<Vec<u8>, Error> {
+        match oid {
+            Some(oid) => Workspace::blob_data(&mut self.repo.database, &oid),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Decide a value trivially if at most one side changed it
+    fn merge3<T: PartialEq>(
+        base: Option<T>,
+        left: Option<T>,
+        right: Option<T>,
+    ) -> Option<(bool, Option<T>)> {
+        if left.is_none() {
+            return Some((false, right));
+        }
+        if right.is_none() {
+            return Some((false, left));
+        }
+
+        if left == base || left == right {
+            Some((true, right))
+        } else if right == base {
+            Some((true, left))
+        } else {
+            None
+        }
+    }
+
+    /// Check whether any parent directory of `path` was replaced by a
+    /// file in `diff`. The file is set aside as `<dir>~<name>`.
+    fn file_dir_conflict(&mut self, path: &Path, diff: &TreeChanges, name: &str) {
+        let mut parents: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|p| p.parent().is_some())
+            .collect();
+        parents.reverse();
+
+        for parent in parents {
+            let (old_item, new_item) = match diff.get(parent) {
+                Some((old_item, Some(new_item))) => (old_item.clone(), new_item.clone()),
+                _ => continue,
+            };
+
+            let items = if name == self.inputs.left_name {
+                [old_item, Some(new_item.clone()), None]
+            } else {
+                [old_item, None, Some(new_item.clone())]
+            };
+            self.conflicts.insert(parent.to_path_buf(), items);
+            self.clean_diff.remove(parent);
+
+            let rename = format!("{}~{}", parent.display(), name);
+            self.untracked.insert(rename.clone(), new_item);
+
+            if !diff.contains_key(path) {
+                self.log(format!("Adding {}", path.display()));
+            }
+            self.log_conflict(parent, Some(&rename));
+        }
+    }
+
+    fn add_conflicts_to_index(&mut self) {
+        for (path, items) in &self.conflicts {
+            self.repo
+                .index
+                .add_conflict_set(path.to_str().unwrap(), items.clone());
+        }
+    }
+
+    fn write_untracked_files(&mut self) -> Result<(), Error> {
+        for (path, item) in &self.untracked {
+            let data = Workspace::blob_data(&mut self.repo.database, &item.get_oid())?;
+            self.repo
+                .workspace
+                .write_file(path, &data)
+                .map_err(|e| Error::io("write", self.repo.workspace.abs_path(path), e))?;
+        }
+
+        Ok(())
+    }
+
+    fn log(&mut self, message: String) {
+        self.messages.push(message);
+    }
+
+    fn log_conflict(&mut self, path: &Path, rename: Option<&str>) {
+        let [base, left, right] = self.conflicts[path].clone();
+        let path = path.display();
+
+        let message = if left.is_some() && right.is_some() {
+            let conflict_type = if base.is_some() { "content" } else { "add/add" };
+            format!("CONFLICT ({}): Merge conflict in {}", conflict_type, path)
+        } else if base.is_some() {
+            let (deleted, modified) = self.log_branch_names(left.is_some());
+            let rename = rename.map(|r| format!(" at {}", r)).unwrap_or_default();
+            format!(
+                "CONFLICT (modify/delete): {} deleted in {} and modified in {}. Version {} of {} left in tree{}.",
+                path, deleted, modified, modified, path, rename
+            )
+        } else {
+            let conflict_type = if left.is_some() {
+                "file/directory"
+            } else {
+                "directory/file"
+            };
+            let (branch, _) = self.log_branch_names(left.is_some());
+            format!(
+                "CONFLICT ({}): There is a directory with name {} in {}. Adding {} as {}",
+                conflict_type,
+                path,
+                branch,
+                path,
+                rename.unwrap_or_default()
+            )
+        };
+
+        self.log(message);
+    }
+
+    /// The side that deleted a path, followed by the side that kept it
+    fn log_branch_names(&self, left_has_path: bool) -> (String, String) {
+        let (a, b) = (
+            self.inputs.left_name.clone(),
+            self.inputs.right_name.clone(),
+        );
+        if left_has_path {
+            (b, a)
+        } else {
+            (a, b)
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use crate::pack::entry::{Delta, Entry};
+use crate::pack::xdelta::XDelta;
+use std::collections::VecDeque;
+
+const WINDOW_SIZE: usize = 8;
+const MAX_DEPTH: usize = 50;
+
+// Objects outside this range are never deltified
+const MIN_SIZE: usize = 50;
+const MAX_SIZE: usize = 0x2000_0000;
+
+/// Picks delta bases for pack entries by comparing each object with
+/// the few similar objects that precede it in sorted order
+pub struct Compressor;
+
+impl Compressor {
+    pub fn compress(entries: &mut [Entry]) {
+        let mut order: Vec<usize> = (0..entries.len())
+            .filter(|&i| (MIN_SIZE..=MAX_SIZE).contains(&entries[i].size()))
+            .collect();
+        order.sort_by(|&a, &b| entries[a].sort_key().cmp(&entries[b].sort_key()));
+
+        let mut deltas = vec![];
+        let mut depths = vec![0; entries.len()];
+        let mut window: VecDeque<(usize, XDelta)> = VecDeque::with_capacity(WINDOW_SIZE + 1);
+
+        for i in order {
+            let target = &entries[i];
+            let mut best: Option<Delta> = None;
+
+            for (base, index) in window.iter().rev() {
+                let source = &entries[*base];
+                if source.type_code() != target.type_code() || depths[*base] >= MAX_DEPTH {
+                    continue;
+                }
+
+                let max_size = match best {
+                    Some(ref delta) => delta.data.len() - 1,
+                    None => (target.size() / 2).saturating_sub(20),
+                };
+
+                if let Some(data) = index.compress(&target.raw.data, max_size) {
+                    best = Some(Delta { base: *base, data });
+                }
+            }
+
+            if let Some(delta) = best {
+                depths[i] = depths[delta.base] + 1;
+                deltas.push((i, delta));
+            }
+
+            window.push_back((i, XDelta::create_index(&target.raw.data)));
+            if window.len() > WINDOW_SIZE {
+                window.pop_front();
+            }
+        }
+
+        for (i, delta) in deltas {
+            entries[i].delta = Some(delta);
+        }
+    }
+}
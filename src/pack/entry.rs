@@ -0,0 +1,54 @@
+use crate::database::Raw;
+use crate::pack;
+
+/// An object that is about to be written to a pack
+pub struct Entry {
+    pub oid: String,
+    pub raw: Raw,
+    // The path the object was reached by, used to group similar
+    // objects together when looking for delta bases
+    pub path: String,
+    pub delta: Option<Delta>,
+}
+
+/// A delta against another entry, identified by its position in the
+/// list of entries being packed
+pub struct Delta {
+    pub base: usize,
+    pub data: Vec<u8>,
+}
+
+impl Entry {
+    pub fn new(oid: &str, raw: Raw, path: &str) -> Entry {
+        Entry {
+            oid: oid.to_string(),
+            raw,
+            path: path.to_string(),
+            delta: None,
+        }
+    }
+
+    pub fn type_code(&self) -> u8 {
+        pack::type_code(&self.raw.obj_type).expect("unknown object type")
+    }
+
+    pub fn size(&self) -> usize {
+        self.raw.data.len()
+    }
+
+    fn basename(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or("")
+    }
+
+    /// Objects of the same type and name sort next to each other,
+    /// largest first, so that smaller versions are stored as deltas
+    /// against bigger ones
+    pub fn sort_key(&self) -> (u8, &str, &str, std::cmp::Reverse<usize>) {
+        (
+            self.type_code(),
+            self.basename(),
+            &self.path,
+            std::cmp::Reverse(self.size()),
+        )
+    }
+}
@@ -0,0 +1,80 @@
+use crate::pack::numbers::VarIntLE;
+use std::io::{self, ErrorKind};
+
+/// Rebuilds an object from its delta base and a delta produced by
+/// `git pack-objects`
+pub struct Expander;
+
+impl Expander {
+    pub fn expand(source: &[u8], delta: &[u8]) -> io::Result<Vec<u8>> {
+        let mut input = delta;
+
+        let (_, source_size) = VarIntLE::read(&mut input, 7)?;
+        let (_, target_size) = VarIntLE::read(&mut input, 7)?;
+
+        if source_size != source.len() as u64 {
+            return Err(invalid_delta("source size mismatch"));
+        }
+
+        let mut target = Vec::with_capacity(target_size as usize);
+
+        while let Some((&byte, rest)) = input.split_first() {
+            input = rest;
+
+            if byte >= 0x80 {
+                let offset = Self::read_packed_int(&mut input, byte, 0, 4)?;
+                let mut size = Self::read_packed_int(&mut input, byte, 4, 3)?;
+                if size == 0 {
+                    size = 0x10000;
+                }
+
+                let (start, end) = (offset as usize, (offset + size) as usize);
+                if end > source.len() {
+                    return Err(invalid_delta("copy out of range of source"));
+                }
+                target.extend_from_slice(&source[start..end]);
+            } else if byte > 0 {
+                let size = byte as usize;
+                if size > input.len() {
+                    return Err(invalid_delta("insert out of range of delta"));
+                }
+                target.extend_from_slice(&input[..size]);
+                input = &input[size..];
+            } else {
+                return Err(invalid_delta("unexpected delta opcode 0"));
+            }
+        }
+
+        if target.len() as u64 != target_size {
+            return Err(invalid_delta("target size mismatch"));
+        }
+
+        Ok(target)
+    }
+
+    /// Copy instructions only store the non-zero bytes of their offset
+    /// and size; bits `from..from + count` of the opcode say which
+    /// bytes are present
+    fn read_packed_int(input: &mut &[u8], header: u8, from: u32, count: u32) -> io::Result<u64> {
+        let mut value = 0;
+
+        for i in 0..count {
+            if header & (1 << (from + i)) != 0 {
+                let (&byte, rest) = input
+                    .split_first()
+                    .ok_or_else(|| invalid_delta("truncated copy instruction"))?;
+                *input = rest;
+                value |= u64::from(byte) << (8 * i);
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+fn invalid_delta(message: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid delta: {}", message),
+    )
+}
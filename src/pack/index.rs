@@ -0,0 +1,130 @@
+use crate::pack::{IDX_MAX_OFFSET, IDX_SIGNATURE, IDX_VERSION};
+use crate::util::*;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+const FANOUT_SIZE: usize = 256;
+const HEADER_SIZE: usize = 8;
+const OID_SIZE: usize = 20;
+
+/// In-memory copy of a version 2 `.idx` file, mapping object IDs to
+/// their offsets in the matching `.pack`
+pub struct Index {
+    fanout: Vec<u32>,
+    oids: Vec<String>,
+    offsets: Vec<u64>,
+}
+
+impl Index {
+    pub fn load(path: &Path) -> io::Result<Index> {
+        let data = fs::read(path)?;
+        Self::parse(&data)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(data: &[u8]) -> io::Result<Index> {
+        let min_size = HEADER_SIZE + FANOUT_SIZE * 4 + 2 * OID_SIZE;
+        if data.len() < min_size {
+            return Err(invalid_index("file too small"));
+        }
+
+        if read_u32(data, 0) != IDX_SIGNATURE || read_u32(data, 4) != IDX_VERSION {
+            return Err(invalid_index("unsupported index version"));
+        }
+
+        let fanout: Vec<u32> = (0..FANOUT_SIZE)
+            .map(|i| read_u32(data, HEADER_SIZE + i * 4))
+            .collect();
+        let count = fanout[FANOUT_SIZE - 1] as usize;
+
+        let oids_start = HEADER_SIZE + FANOUT_SIZE * 4;
+        let crcs_start = oids_start + count * OID_SIZE;
+        let offsets_start = crcs_start + count * 4;
+        let large_start = offsets_start + count * 4;
+
+        if data.len() < large_start + 2 * OID_SIZE {
+            return Err(invalid_index("file truncated"));
+        }
+
+        let oids = (0..count)
+            .map(|i| encode_hex(&data[oids_start + i * OID_SIZE..oids_start + (i + 1) * OID_SIZE]))
+            .collect();
+
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = u64::from(read_u32(data, offsets_start + i * 4));
+
+            if offset < IDX_MAX_OFFSET {
+                offsets.push(offset);
+            } else {
+                let pos = large_start + (offset - IDX_MAX_OFFSET) as usize * 8;
+                if data.len() < pos + 8 + 2 * OID_SIZE {
+                    return Err(invalid_index("large offset out of range"));
+                }
+                offsets.push(u64::from_be_bytes(data[pos..pos + 8].try_into().unwrap()));
+            }
+        }
+
+        Ok(Index {
+            fanout,
+            oids,
+            offsets,
+        })
+    }
+
+    pub fn oids(&self) -> &[String] {
+        &self.oids
+    }
+
+    pub fn oid_offset(&self, oid: &str) -> Option<u64> {
+        let (low, high) = self.fanout_range(oid)?;
+
+        self.oids[low..high]
+            .binary_search_by(|o| o.as_str().cmp(oid))
+            .ok()
+            .map(|pos| self.offsets[low + pos])
+    }
+
+    pub fn prefix_match(&self, name: &str) -> Vec<String> {
+        let (low, high) = match self.fanout_range(name) {
+            Some(range) => range,
+            None => return vec![],
+        };
+
+        let start = low + self.oids[low..high].partition_point(|o| o.as_str() < name);
+
+        self.oids[start..high]
+            .iter()
+            .take_while(|o| o.starts_with(name))
+            .cloned()
+            .collect()
+    }
+
+    /// The fanout table gives the range of positions where objects
+    /// whose first byte matches `oid`'s first byte are stored
+    fn fanout_range(&self, oid: &str) -> Option<(usize, usize)> {
+        let first = u8::from_str_radix(oid.get(0..2)?, 16).ok()? as usize;
+
+        let low = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1] as usize
+        };
+        let high = self.fanout[first] as usize;
+
+        Some((low, high))
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn invalid_index(message: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid pack index: {}", message),
+    )
+}
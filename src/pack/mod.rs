@@ -0,0 +1,61 @@
+pub mod compressor;
+pub mod entry;
+pub mod expander;
+pub mod index;
+pub mod numbers;
+pub mod reader;
+pub mod writer;
+pub mod xdelta;
+
+pub const SIGNATURE: &[u8; 4] = b"PACK";
+pub const VERSION: u32 = 2;
+pub const HEADER_SIZE: usize = 12;
+
+pub const IDX_SIGNATURE: u32 = 0xff74_4f63;
+pub const IDX_VERSION: u32 = 2;
+pub const IDX_MAX_OFFSET: u64 = 0x8000_0000;
+
+pub const COMMIT: u8 = 1;
+pub const TREE: u8 = 2;
+pub const BLOB: u8 = 3;
+pub const TAG: u8 = 4;
+pub const OFS_DELTA: u8 = 6;
+pub const REF_DELTA: u8 = 7;
+
+pub fn type_name(code: u8) -> Option<&'static str> {
+    match code {
+        COMMIT => Some("commit"),
+        TREE => Some("tree"),
+        BLOB => Some("blob"),
+        TAG => Some("tag"),
+        _ => None,
+    }
+}
+
+pub fn type_code(name: &str) -> Option<u8> {
+    match name {
+        "commit" => Some(COMMIT),
+        "tree" => Some(TREE),
+        "blob" => Some(BLOB),
+        "tag" => Some(TAG),
+        _ => None,
+    }
+}
+
+/// A single entry in a packfile, as stored on disk. Deltified
+/// records must be expanded against their base before use.
+#[derive(Debug)]
+pub enum Record {
+    Object {
+        obj_type: &'static str,
+        data: Vec<u8>,
+    },
+    OfsDelta {
+        base_ofs: u64,
+        delta: Vec<u8>,
+    },
+    RefDelta {
+        base_oid: String,
+        delta: Vec<u8>,
+    },
+}
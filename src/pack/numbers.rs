@@ -0,0 +1,105 @@
+use std::io::{self, Read};
+
+/// Little-endian base-128 integers, as used in object record headers
+/// and delta size prefixes. The first byte may reserve its high bits
+/// for other data, in which case `shift` is the number of value bits
+/// it carries.
+pub struct VarIntLE;
+
+impl VarIntLE {
+    /// Returns the first byte read(so callers can extract any other
+    /// fields packed into it) along with the decoded value
+    pub fn read<R: Read>(input: &mut R, shift: u32) -> io::Result<(u8, u64)> {
+        let first = read_byte(input)?;
+        let mut value = u64::from(first) & ((1 << shift) - 1);
+
+        let mut byte = first;
+        let mut shift = shift;
+
+        while byte >= 0x80 {
+            byte = read_byte(input)?;
+            value |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+        }
+
+        Ok((first, value))
+    }
+
+    pub fn write(value: u64, shift: u32) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut value = value;
+        let mut mask = (1 << shift) - 1;
+        let mut shift = shift;
+
+        while value > mask {
+            bytes.push(0x80 | (value & mask) as u8);
+            value >>= shift;
+            mask = 0x7f;
+            shift = 7;
+        }
+        bytes.push(value as u8);
+
+        bytes
+    }
+}
+
+/// Big-endian base-128 integers with the "add one" continuation rule,
+/// used for the base offsets of `OFS_DELTA` records
+pub struct VarIntBE;
+
+impl VarIntBE {
+    pub fn read<R: Read>(input: &mut R) -> io::Result<u64> {
+        let mut byte = read_byte(input)?;
+        let mut value = u64::from(byte & 0x7f);
+
+        while byte >= 0x80 {
+            byte = read_byte(input)?;
+            value = ((value + 1) << 7) | u64::from(byte & 0x7f);
+        }
+
+        Ok(value)
+    }
+
+    pub fn write(value: u64) -> Vec<u8> {
+        let mut value = value;
+        let mut bytes = vec![(value & 0x7f) as u8];
+        value >>= 7;
+
+        while value != 0 {
+            value -= 1;
+            bytes.push(0x80 | (value & 0x7f) as u8);
+            value >>= 7;
+        }
+        bytes.reverse();
+
+        bytes
+    }
+}
+
+fn read_byte<R: Read>(input: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_little_endian_integers() {
+        for &value in &[0, 15, 16, 127, 128, 300, 0xffff_ffff, u64::MAX >> 8] {
+            let bytes = VarIntLE::write(value, 4);
+            let (_, decoded) = VarIntLE::read(&mut &bytes[..], 4).unwrap();
+            assert_eq!(value, decoded);
+        }
+    }
+
+    #[test]
+    fn round_trip_big_endian_integers() {
+        for &value in &[0, 127, 128, 16511, 16512, 0xffff_ffff] {
+            let bytes = VarIntBE::write(value);
+            assert_eq!(value, VarIntBE::read(&mut &bytes[..]).unwrap());
+        }
+    }
+}
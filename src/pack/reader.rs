@@ -0,0 +1,83 @@
+use crate::pack::numbers::{VarIntBE, VarIntLE};
+use crate::pack::{self, Record, HEADER_SIZE, OFS_DELTA, REF_DELTA, SIGNATURE, VERSION};
+use crate::util::*;
+use flate2::bufread::ZlibDecoder;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Reads individual records out of a `.pack` file, given their
+/// offsets(which come from the pack's `.idx`)
+pub struct Reader {
+    file: File,
+}
+
+impl Reader {
+    pub fn open(path: &Path) -> io::Result<Reader> {
+        let mut file = File::open(path)?;
+
+        let mut header = [0; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+
+        let version = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        if &header[0..4] != SIGNATURE || version != VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: unsupported packfile", path.display()),
+            ));
+        }
+
+        Ok(Reader { file })
+    }
+
+    pub fn read_record(&self, offset: u64) -> io::Result<Record> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut input = BufReader::new(file);
+
+        let (byte, size) = VarIntLE::read(&mut input, 4)?;
+        let type_code = (byte >> 4) & 0x7;
+
+        match type_code {
+            OFS_DELTA => {
+                let base_ofs = VarIntBE::read(&mut input)?;
+                let delta = Self::read_zlib_stream(&mut input, size)?;
+                Ok(Record::OfsDelta { base_ofs, delta })
+            }
+            REF_DELTA => {
+                let mut base_oid = [0; 20];
+                input.read_exact(&mut base_oid)?;
+                let delta = Self::read_zlib_stream(&mut input, size)?;
+                Ok(Record::RefDelta {
+                    base_oid: encode_hex(&base_oid),
+                    delta,
+                })
+            }
+            _ => {
+                let obj_type = pack::type_name(type_code).ok_or_else(|| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("unknown object type {} at offset {}", type_code, offset),
+                    )
+                })?;
+                let data = Self::read_zlib_stream(&mut input, size)?;
+                Ok(Record::Object { obj_type, data })
+            }
+        }
+    }
+
+    fn read_zlib_stream(input: &mut BufReader<&File>, size: u64) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(size as usize);
+        ZlibDecoder::new(input).read_to_end(&mut data)?;
+
+        if data.len() as u64 != size {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "packed object has wrong size",
+            ));
+        }
+
+        Ok(data)
+    }
+}
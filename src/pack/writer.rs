@@ -0,0 +1,180 @@
+use crate::pack::entry::Entry;
+use crate::pack::numbers::{VarIntBE, VarIntLE};
+use crate::pack::{IDX_MAX_OFFSET, IDX_SIGNATURE, IDX_VERSION, OFS_DELTA, SIGNATURE, VERSION};
+use crate::util::*;
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes a list of entries out as a `.pack` file plus the version 2
+/// `.idx` that goes with it
+pub struct Writer<'a> {
+    entries: &'a [Entry],
+    offsets: Vec<Option<u64>>,
+    crcs: Vec<u32>,
+    output: BufWriter<File>,
+    digest: Sha1,
+    offset: u64,
+}
+
+impl<'a> Writer<'a> {
+    /// Returns the name the pack was saved under, which is derived
+    /// from its checksum
+    pub fn write(pack_dir: &Path, entries: &[Entry]) -> io::Result<String> {
+        fs::create_dir_all(pack_dir)?;
+        let temp_path = pack_dir.join(format!("tmp_pack_{}", generate_temp_name()));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+
+        let mut writer = Writer {
+            entries,
+            offsets: vec![None; entries.len()],
+            crcs: vec![0; entries.len()],
+            output: BufWriter::new(file),
+            digest: Sha1::new(),
+            offset: 0,
+        };
+
+        let result = writer.write_pack().and_then(|checksum| {
+            let name = format!("pack-{}", encode_hex(&checksum));
+            fs::rename(&temp_path, pack_dir.join(format!("{}.pack", name)))?;
+            writer.write_index(pack_dir, &name, &checksum)?;
+            Ok(name)
+        });
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    fn write_pack(&mut self) -> io::Result<[u8; 20]> {
+        let mut header = SIGNATURE.to_vec();
+        header.extend_from_slice(&VERSION.to_be_bytes());
+        header.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        self.write_bytes(&header)?;
+
+        for i in 0..self.entries.len() {
+            self.write_entry(i)?;
+        }
+
+        let mut checksum = [0; 20];
+        self.digest.result(&mut checksum);
+        self.output.write_all(&checksum)?;
+        self.output.flush()?;
+
+        Ok(checksum)
+    }
+
+    /// Deltas refer back to their base by offset, so the base has to
+    /// be written first
+    fn write_entry(&mut self, i: usize) -> io::Result<()> {
+        if self.offsets[i].is_some() {
+            return Ok(());
+        }
+
+        let entries = self.entries;
+        let entry = &entries[i];
+        if let Some(ref delta) = entry.delta {
+            self.write_entry(delta.base)?;
+        }
+
+        let offset = self.offset;
+        let (mut record, data) = match entry.delta {
+            Some(ref delta) => {
+                let base_offset = self.offsets[delta.base].unwrap();
+                let mut record = Self::record_header(OFS_DELTA, delta.data.len());
+                record.extend(VarIntBE::write(offset - base_offset));
+                (record, &delta.data)
+            }
+            None => (
+                Self::record_header(entry.type_code(), entry.size()),
+                &entry.raw.data,
+            ),
+        };
+
+        let mut encoder = ZlibEncoder::new(record, Compression::default());
+        encoder.write_all(data)?;
+        record = encoder.finish()?;
+
+        let mut crc = Crc::new();
+        crc.update(&record);
+        self.crcs[i] = crc.sum();
+        self.offsets[i] = Some(offset);
+
+        self.write_bytes(&record)
+    }
+
+    fn record_header(type_code: u8, size: usize) -> Vec<u8> {
+        let mut header = VarIntLE::write(size as u64, 4);
+        header[0] |= type_code << 4;
+        header
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+        self.output.write_all(data)?;
+        self.digest.input(data);
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn write_index(&self, pack_dir: &Path, name: &str, pack_checksum: &[u8]) -> io::Result<()> {
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by(|&a, &b| self.entries[a].oid.cmp(&self.entries[b].oid));
+
+        let oids: Vec<Vec<u8>> = order
+            .iter()
+            .map(|&i| decode_hex(&self.entries[i].oid).expect("invalid oid"))
+            .collect();
+
+        let mut data = vec![];
+        data.extend_from_slice(&IDX_SIGNATURE.to_be_bytes());
+        data.extend_from_slice(&IDX_VERSION.to_be_bytes());
+
+        let mut count = 0;
+        for first in 0..=255 {
+            while count < oids.len() && oids[count][0] == first {
+                count += 1;
+            }
+            data.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+
+        for oid in &oids {
+            data.extend_from_slice(oid);
+        }
+        for &i in &order {
+            data.extend_from_slice(&self.crcs[i].to_be_bytes());
+        }
+
+        let mut large_offsets = vec![];
+        for &i in &order {
+            let offset = self.offsets[i].unwrap();
+
+            if offset < IDX_MAX_OFFSET {
+                data.extend_from_slice(&(offset as u32).to_be_bytes());
+            } else {
+                let position = IDX_MAX_OFFSET as u32 | (large_offsets.len() / 8) as u32;
+                data.extend_from_slice(&position.to_be_bytes());
+                large_offsets.extend_from_slice(&offset.to_be_bytes());
+            }
+        }
+        data.extend(large_offsets);
+        data.extend_from_slice(pack_checksum);
+
+        let mut digest = Sha1::new();
+        digest.input(&data);
+        let mut checksum = [0; 20];
+        digest.result(&mut checksum);
+        data.extend_from_slice(&checksum);
+
+        let temp_path = pack_dir.join(format!("tmp_idx_{}", generate_temp_name()));
+        fs::write(&temp_path, &data)?;
+        fs::rename(temp_path, pack_dir.join(format!("{}.idx", name)))
+    }
+}
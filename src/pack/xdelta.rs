@@ -0,0 +1,182 @@
+use crate::pack::numbers::VarIntLE;
+use std::collections::HashMap;
+
+const BLOCK_SIZE: usize = 16;
+// Caps how many positions we remember for a block, so that highly
+// repetitive sources don't make matching quadratic
+const MAX_CHAIN: usize = 64;
+
+const MAX_COPY_SIZE: usize = 0xff_ffff;
+const MAX_INSERT_SIZE: usize = 0x7f;
+
+/// Builds deltas against a fixed source buffer, by indexing it in
+/// fixed-size blocks and then looking up each position of the target
+/// in that index
+pub struct XDelta<'a> {
+    source: &'a [u8],
+    index: HashMap<&'a [u8], Vec<usize>>,
+}
+
+impl<'a> XDelta<'a> {
+    pub fn create_index(source: &'a [u8]) -> XDelta<'a> {
+        let mut index: HashMap<&[u8], Vec<usize>> = HashMap::new();
+
+        for pos in (0..source.len() / BLOCK_SIZE).map(|i| i * BLOCK_SIZE) {
+            let offsets = index.entry(&source[pos..pos + BLOCK_SIZE]).or_default();
+            if offsets.len() < MAX_CHAIN {
+                offsets.push(pos);
+            }
+        }
+
+        XDelta { source, index }
+    }
+
+    /// Returns a delta that rebuilds `target` from the source, in the
+    /// format understood by `Expander`, or `None` if it would be
+    /// larger than `max_size`
+    pub fn compress(&self, target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+        let mut delta = VarIntLE::write(self.source.len() as u64, 7);
+        delta.extend(VarIntLE::write(target.len() as u64, 7));
+
+        let mut insert = vec![];
+        let mut offset = 0;
+
+        while offset < target.len() {
+            if delta.len() + insert.len() > max_size {
+                return None;
+            }
+
+            let (mut start, size) = self.longest_match(target, offset);
+            if size == 0 {
+                insert.push(target[offset]);
+                offset += 1;
+                continue;
+            }
+            offset += size;
+
+            // Extend the match backwards over any literal bytes that
+            // also precede it in the source
+            let mut copy_size = size;
+            while start > 0 && insert.last() == Some(&self.source[start - 1]) {
+                insert.pop();
+                start -= 1;
+                copy_size += 1;
+            }
+
+            Self::write_insert(&mut delta, &mut insert);
+            Self::write_copy(&mut delta, start, copy_size);
+        }
+        Self::write_insert(&mut delta, &mut insert);
+
+        if delta.len() > max_size {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+
+    fn longest_match(&self, target: &[u8], offset: usize) -> (usize, usize) {
+        if offset + BLOCK_SIZE > target.len() {
+            return (0, 0);
+        }
+
+        let candidates = match self.index.get(&target[offset..offset + BLOCK_SIZE]) {
+            Some(candidates) => candidates,
+            None => return (0, 0),
+        };
+
+        let mut best = (0, 0);
+        for &pos in candidates {
+            let size = self.source[pos..]
+                .iter()
+                .zip(&target[offset..])
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            if size > best.1 {
+                best = (pos, size);
+            }
+        }
+
+        best
+    }
+
+    fn write_insert(delta: &mut Vec<u8>, insert: &mut Vec<u8>) {
+        for chunk in insert.chunks(MAX_INSERT_SIZE) {
+            delta.push(chunk.len() as u8);
+            delta.extend_from_slice(chunk);
+        }
+        insert.clear();
+    }
+
+    fn write_copy(delta: &mut Vec<u8>, offset: usize, size: usize) {
+        let mut offset = offset;
+        let mut size = size;
+
+        while size > 0 {
+            let chunk = size.min(MAX_COPY_SIZE);
+            let mut header = 0x80;
+            let mut bytes = vec![];
+
+            for i in 0..4 {
+                let byte = (offset >> (8 * i)) & 0xff;
+                if byte != 0 {
+                    header |= 1 << i;
+                    bytes.push(byte as u8);
+                }
+            }
+            for i in 0..3 {
+                let byte = (chunk >> (8 * i)) & 0xff;
+                if byte != 0 {
+                    header |= 1 << (4 + i);
+                    bytes.push(byte as u8);
+                }
+            }
+
+            delta.push(header);
+            delta.extend(bytes);
+
+            offset += chunk;
+            size -= chunk;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::expander::Expander;
+
+    fn round_trip(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let delta = XDelta::create_index(source)
+            .compress(target, usize::MAX)
+            .unwrap();
+        assert_eq!(target, &Expander::expand(source, &delta).unwrap()[..]);
+        delta
+    }
+
+    #[test]
+    fn compress_similar_buffers() {
+        let source: Vec<u8> = (0..2000).map(|i| (i * 7 % 251) as u8).collect();
+        let mut target = b"prefix".to_vec();
+        target.extend_from_slice(&source[..900]);
+        target.extend_from_slice(b"something in the middle");
+        target.extend_from_slice(&source[1000..]);
+
+        let delta = round_trip(&source, &target);
+        assert!(delta.len() < 100);
+    }
+
+    #[test]
+    fn compress_unrelated_buffers() {
+        round_trip(b"the quick brown fox jumps over", &[0xab; 300]);
+        round_trip(b"", b"hello");
+        round_trip(b"hello", b"");
+    }
+
+    #[test]
+    fn give_up_when_delta_is_too_large() {
+        let index = XDelta::create_index(b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+        assert!(index.compress(&[0xff; 1000], 100).is_none());
+    }
+}
@@ -1,5 +1,9 @@
 use std::collections::HashMap;
-use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+use crate::commands::Terminal;
 
 const PAGER_CMD: &str = "less";
 
@@ -12,93 +16,111 @@ lazy_static! {
     };
 }
 
-mod utils {
-    use std::ffi::{CString, OsString};
-    use std::os::unix::ffi::OsStringExt;
-    use std::ptr;
-
-    use errno;
-    use libc;
+/// A command's standard output, which goes through a pager program
+/// once `start` is called if it is headed for a terminal, and straight
+/// to the wrapped writer otherwise
+pub struct Pager<O: Write> {
+    output: O,
+    terminal: Terminal,
+    process: Option<Child>,
+    // Collects what the pager writes when it cannot have the terminal
+    // to itself, for copying to `output` once it exits
+    paged: Option<JoinHandle<io::Result<Vec<u8>>>>,
+}
 
-    fn split_string(s: &OsString) -> Vec<OsString> {
-        match s.clone().into_string() {
-            Ok(cmd) => cmd.split_whitespace().map(OsString::from).collect(),
-            Err(cmd) => vec![cmd],
+impl<O: Write> Pager<O> {
+    pub fn new(output: O, terminal: Terminal) -> Pager<O> {
+        Pager {
+            output,
+            terminal,
+            process: None,
+            paged: None,
         }
     }
 
-    pub fn pipe() -> (i32, i32) {
-        let mut fds = [0; 2];
-        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
-        (fds[0], fds[1])
-    }
+    /// Send what is written from now on through GIT_PAGER, PAGER or
+    /// `less`, whichever is set first. An empty pager or `cat` turns
+    /// paging off.
+    pub fn start(&mut self, env: &HashMap<String, String>) {
+        if self.terminal == Terminal::None || self.process.is_some() {
+            return;
+        }
 
-    pub fn close(fd: i32) {
-        assert_eq!(unsafe { libc::close(fd) }, 0);
-    }
+        let cmd = env
+            .get("GIT_PAGER")
+            .or_else(|| env.get("PAGER"))
+            .map(String::as_str)
+            .unwrap_or(PAGER_CMD);
+        let mut args = cmd.split_whitespace();
+        let program = match args.next() {
+            Some("cat") | None => return,
+            Some(program) => program,
+        };
 
-    pub fn dup2(fd1: i32, fd2: i32) {
-        assert!(unsafe { libc::dup2(fd1, fd2) } > -1);
-    }
+        let stdout = match self.terminal {
+            Terminal::Process => Stdio::inherit(),
+            _ => Stdio::piped(),
+        };
 
-    fn osstring2cstring(s: OsString) -> CString {
-        unsafe { CString::from_vec_unchecked(s.into_vec()) }
+        // Output goes to the terminal directly if the pager cannot run
+        let _ = self.output.flush();
+        self.process = Command::new(program)
+            .args(args)
+            .envs(PAGER_ENV.iter())
+            .stdin(Stdio::piped())
+            .stdout(stdout)
+            .spawn()
+            .ok();
+
+        // Read the pager's output as it comes, so that it never blocks
+        // on a full pipe
+        let stdout = self.process.as_mut().and_then(|child| child.stdout.take());
+        self.paged = stdout.map(|mut stdout| {
+            thread::spawn(move || {
+                let mut paged = vec![];
+                stdout.read_to_end(&mut paged)?;
+                Ok(paged)
+            })
+        });
     }
+}
 
-    pub fn execvp(cmd: &OsString) {
-        let cstrings = split_string(cmd)
-            .into_iter()
-            .map(osstring2cstring)
-            .collect::<Vec<_>>();
-        let mut args = cstrings.iter().map(|c| c.as_ptr()).collect::<Vec<_>>();
-        args.push(ptr::null());
-        errno::set_errno(errno::Errno(0));
-        unsafe { libc::execvp(args[0], args.as_ptr()) };
+impl<O: Write> Write for Pager<O> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.process.as_mut().and_then(|child| child.stdin.as_mut()) {
+            // Once the user quits the pager, the rest of the output is
+            // dropped rather than reported as an error
+            Some(stdin) => match stdin.write(buf) {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(buf.len()),
+                result => result,
+            },
+            None => self.output.write(buf),
+        }
     }
 
-    // Helper wrappers around libc::* API
-    pub fn fork() -> libc::pid_t {
-        unsafe { libc::fork() }
+    fn flush(&mut self) -> io::Result<()> {
+        match self.process.as_mut().and_then(|child| child.stdin.as_mut()) {
+            Some(stdin) => match stdin.flush() {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                result => result,
+            },
+            None => self.output.flush(),
+        }
     }
 }
 
-pub struct Pager;
-
-impl Pager {
-    pub fn setup_pager() {
-        let (git_pager, pager) = (std::env::var("GIT_PAGER"), std::env::var("PAGER"));
-
-        let cmd = match (git_pager, pager) {
-            (Ok(git_pager), _) => git_pager,
-            (_, Ok(pager)) => pager,
-            _ => PAGER_CMD.to_string(),
-        };
-
-        let pager_cmd = OsString::from(cmd);
-
-        for (k, v) in PAGER_ENV.iter() {
-            std::env::set_var(k, v);
+impl<O: Write> Drop for Pager<O> {
+    /// Wait for the user to finish reading, once the pager has seen
+    /// the end of the output, and pass on whatever it wrote if it did
+    /// not have the terminal to itself
+    fn drop(&mut self) {
+        if let Some(mut child) = self.process.take() {
+            drop(child.stdin.take());
+            let _ = child.wait();
         }
-
-        let (pager_stdin, main_stdout) = utils::pipe();
-        let pager_pid = utils::fork();
-        match pager_pid {
-            -1 => {
-                // Fork failed
-                utils::close(pager_stdin);
-                utils::close(main_stdout);
-            }
-            0 => {
-                // Child
-                utils::dup2(main_stdout, libc::STDOUT_FILENO);
-                utils::close(pager_stdin);
-            }
-            _ => {
-                // Parent-- executes pager
-                utils::dup2(pager_stdin, libc::STDIN_FILENO);
-                utils::close(main_stdout);
-                utils::execvp(&pager_cmd);
-            }
+        if let Some(Ok(Ok(paged))) = self.paged.take().map(JoinHandle::join) {
+            let _ = self.output.write_all(&paged);
+            let _ = self.output.flush();
         }
     }
 }
@@ -0,0 +1,140 @@
+use crate::lockfile::Lockfile;
+use regex::Regex;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const ZERO_OID: &str = "0000000000000000000000000000000000000000";
+
+lazy_static! {
+    static ref ENTRY: Regex =
+        Regex::new(r"^([0-9a-f]{40}) ([0-9a-f]{40}) (.*> )(\d+) ([+-]\d{4})(?:\t(.*))?$").unwrap();
+}
+
+/// One line of a reflog, recording a single change to a ref
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub old_oid: String,
+    pub new_oid: String,
+    // "Name <email>"
+    pub identity: String,
+    pub time: i64,
+    pub timezone: String,
+    pub message: String,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<Entry> {
+        let caps = ENTRY.captures(line)?;
+
+        Some(Entry {
+            old_oid: caps[1].to_string(),
+            new_oid: caps[2].to_string(),
+            identity: caps[3].trim_end().to_string(),
+            time: caps[4].parse().ok()?,
+            timezone: caps[5].to_string(),
+            message: caps.get(6).map_or("", |m| m.as_str()).to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} {} {} {} {}\t{}",
+            self.old_oid, self.new_oid, self.identity, self.time, self.timezone, self.message
+        )
+    }
+}
+
+/// The history of a single ref, kept under `.git/logs` with the oldest
+/// entry first
+pub struct Reflog {
+    path: PathBuf,
+}
+
+impl Reflog {
+    pub fn new(path: &Path) -> Reflog {
+        Reflog {
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Lines that cannot be parsed are skipped, as git does
+    pub fn read(&self) -> Vec<Entry> {
+        fs::read(&self.path)
+            .map(|contents| {
+                String::from_utf8_lossy(&contents)
+                    .lines()
+                    .filter_map(Entry::parse)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn append(&self, entry: &Entry) -> Result<(), io::Error> {
+        fs::create_dir_all(self.path.parent().expect("reflog has no parent dir"))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        file.write_all(entry.to_string().as_bytes())
+    }
+
+    /// Replace the whole log, for when entries are dropped from it
+    pub fn write(&self, entries: &[Entry]) -> Result<(), io::Error> {
+        let mut lock = Lockfile::new(&self.path);
+        lock.hold_for_update()?;
+        for entry in entries {
+            lock.write(&entry.to_string())?;
+        }
+
+        lock.commit()
+    }
+
+    pub fn delete(&self) -> Result<(), io::Error> {
+        if self.exists() {
+            fs::remove_file(&self.path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format_entries() {
+        let line = format!(
+            "{} {} A. U. Thor <author@example.com> 1573325342 +0545\tcommit (initial): first",
+            ZERO_OID, "4c5b58f323d7b459664b5d3fb9587048bb0296de"
+        );
+        let entry = Entry::parse(&line).unwrap();
+
+        assert_eq!(ZERO_OID, entry.old_oid);
+        assert_eq!("A. U. Thor <author@example.com>", entry.identity);
+        assert_eq!(1573325342, entry.time);
+        assert_eq!("+0545", entry.timezone);
+        assert_eq!("commit (initial): first", entry.message);
+        assert_eq!(format!("{}\n", line), entry.to_string());
+    }
+
+    #[test]
+    fn skip_malformed_lines() {
+        assert!(Entry::parse("not a reflog line").is_none());
+        assert!(Entry::parse(&format!("{} {} <> 12 +0000", ZERO_OID, ZERO_OID)).is_some());
+    }
+}
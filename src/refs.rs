@@ -1,15 +1,18 @@
+use crate::errors::Error;
 use crate::lockfile::Lockfile;
+use crate::reflog::{self, Reflog};
 use crate::util;
+use chrono::Local;
 use regex::{Regex, RegexSet};
-use std::fs::{self, DirEntry, File};
+use std::cmp::{Ord, Ordering};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
-use std::cmp::{Ord, Ordering};
-use std::collections::HashMap;
 
 lazy_static! {
     static ref INVALID_FILENAME: RegexSet = {
-        RegexSet::new(&[
+        RegexSet::new([
             r"^\.",
             r"/\.",
             r"\.\.",
@@ -21,9 +24,22 @@ lazy_static! {
         .unwrap()
     };
     static ref SYMREF: Regex = Regex::new(r"^ref: (.+)$").unwrap();
+    static ref CHECKOUT_MESSAGE: Regex = Regex::new(r"^checkout: moving from (.+) to ").unwrap();
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd)]
+const PACKED_REFS_HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+// Where a short ref name is looked for, in order
+const REF_PREFIXES: [&str; 4] = ["", "refs/", "refs/tags/", "refs/heads/"];
+
+/// A line of `.git/packed-refs`, with the object an annotated tag
+/// points at when it was recorded on a `^` line after it
+#[derive(Debug, Clone)]
+struct PackedRef {
+    oid: String,
+    peeled: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum Ref {
     Ref { oid: String },
     SymRef { path: String },
@@ -37,6 +53,13 @@ impl Ref {
         }
     }
 
+    pub fn is_tag(&self) -> bool {
+        match self {
+            Ref::Ref { .. } => false,
+            Ref::SymRef { path } => path.starts_with("refs/tags/"),
+        }
+    }
+
     pub fn path(&self) -> &str {
         match self {
             Ref::Ref { .. } => unimplemented!(),
@@ -45,28 +68,50 @@ impl Ref {
     }
 }
 
+impl PartialOrd for Ref {
+    fn partial_cmp(&self, other: &Ref) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Ord for Ref {
     fn cmp(&self, other: &Ref) -> Ordering {
         match (self, other) {
-            (Ref::Ref { .. }, Ref::SymRef { ..} ) => Ordering::Less,
-            (Ref::SymRef { .. }, Ref::Ref { ..} ) => Ordering::Greater,
-            (Ref::SymRef { path: a }, Ref::SymRef { path: b } ) => a.cmp(b),
-            (Ref::Ref { oid: a }, Ref::Ref { oid: b } ) => a.cmp(b),
+            (Ref::Ref { .. }, Ref::SymRef { .. }) => Ordering::Less,
+            (Ref::SymRef { .. }, Ref::Ref { .. }) => Ordering::Greater,
+            (Ref::SymRef { path: a }, Ref::SymRef { path: b }) => a.cmp(b),
+            (Ref::Ref { oid: a }, Ref::Ref { oid: b }) => a.cmp(b),
         }
     }
 }
 
 pub struct Refs {
     pathname: PathBuf,
+    // "Name <email>" recorded in reflog entries, or why there is
+    // none to record
+    identity: Result<String, String>,
 }
 
 impl Refs {
     pub fn new(pathname: &Path) -> Refs {
         Refs {
             pathname: pathname.to_path_buf(),
+            identity: Err("fatal: unable to auto-detect email address\n".to_string()),
         }
     }
 
+    /// Set who ref updates are logged as. Updates that would be logged
+    /// fail while the identity is an error, before anything changes.
+    pub fn set_identity(&mut self, identity: Result<(String, String), Error>) {
+        self.identity = identity
+            .map(|(name, email)| format!("{} <{}>", name, email))
+            .map_err(|e| e.to_string());
+    }
+
+    fn identity(&self) -> Result<String, Error> {
+        self.identity.clone().map_err(Error::fatal)
+    }
+
     fn head_path(&self) -> PathBuf {
         (*self.pathname).join("HEAD")
     }
@@ -79,46 +124,182 @@ impl Refs {
         (*self.pathname).join("refs/heads")
     }
 
+    fn tags_path(&self) -> PathBuf {
+        (*self.pathname).join("refs/tags")
+    }
+
     pub fn update_ref_file(&self, path: &Path, oid: &str) -> Result<(), std::io::Error> {
         let mut lock = Lockfile::new(path);
         lock.hold_for_update()?;
-        Self::write_lockfile(lock, &oid)
+        Self::write_lockfile(lock, oid)
     }
 
-    pub fn update_head(&self, oid: &str) -> Result<(), std::io::Error> {
+    /// Move HEAD, or the branch it points at, to `oid`, logging the
+    /// change for both
+    pub fn update_head(&self, oid: &str, message: &str) -> Result<(), Error> {
+        self.identity()?;
+        let old_oid = self.read_head();
         self.update_symref(&self.head_path(), oid)
+            .map_err(|e| Error::io("update", self.head_path(), e))?;
+
+        self.log_update("HEAD", old_oid.as_deref(), oid, message)?;
+        let current = self.current_ref("HEAD");
+        if !current.is_head() {
+            self.log_update(current.path(), old_oid.as_deref(), oid, message)?;
+        }
+
+        Ok(())
     }
 
-    pub fn set_head(&self, revision: &str, oid: &str) -> Result<(), std::io::Error> {
+    pub fn set_head(&self, revision: &str, oid: &str, message: &str) -> Result<(), Error> {
+        self.identity()?;
         let path = self.heads_path().join(revision);
+        let old_oid = self.read_head();
 
-        if path.exists() {
+        let head = if self.branch_exists(revision) {
             let relative = util::relative_path_from(Path::new(&path), &self.pathname);
-            self.update_ref_file(&self.head_path(), &format!("ref: {}", relative))
+            format!("ref: {}", relative)
         } else {
-            self.update_ref_file(&self.head_path(), oid)
+            oid.to_string()
+        };
+        self.update_ref_file(&self.head_path(), &head)
+            .map_err(|e| Error::io("update", self.head_path(), e))?;
+
+        self.log_update("HEAD", old_oid.as_deref(), oid, message)
+    }
+
+    /// The log of the ref at `name`, relative to the .git directory
+    pub fn reflog(&self, name: &str) -> Reflog {
+        Reflog::new(&self.pathname.join("logs").join(name))
+    }
+
+    /// Every ref that has a log, HEAD first
+    pub fn list_reflogs(&self) -> Vec<String> {
+        let logs_path = self.pathname.join("logs");
+        let mut names = vec![];
+        let mut dirs = vec![logs_path.join("refs")];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).into_iter().flatten() {
+                let path = entry.expect("failed to read logs dir").path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    names.push(util::relative_path_from(&path, &logs_path));
+                }
+            }
         }
+        names.sort();
+
+        if self.reflog("HEAD").exists() {
+            names.insert(0, "HEAD".to_string());
+        }
+        names
+    }
+
+    fn log_update(
+        &self,
+        name: &str,
+        old_oid: Option<&str>,
+        new_oid: &str,
+        message: &str,
+    ) -> Result<(), Error> {
+        let now = Local::now();
+        let entry = reflog::Entry {
+            old_oid: old_oid.unwrap_or(reflog::ZERO_OID).to_string(),
+            new_oid: new_oid.to_string(),
+            identity: self.identity()?,
+            time: now.timestamp(),
+            timezone: now.format("%z").to_string(),
+            message: message.lines().next().unwrap_or("").to_string(),
+        };
+
+        self.reflog(name)
+            .append(&entry)
+            .map_err(|e| Error::io("append to the log of", name, e))
+    }
+
+    /// The branch that was checked out `n` switches ago, going by the
+    /// checkout entries in HEAD's log
+    pub fn previous_branch(&self, n: usize) -> Option<String> {
+        self.reflog("HEAD")
+            .read()
+            .iter()
+            .rev()
+            .filter_map(|entry| CHECKOUT_MESSAGE.captures(&entry.message))
+            .nth(n.checked_sub(1)?)
+            .map(|caps| caps[1].to_string())
     }
 
     pub fn read_head(&self) -> Option<String> {
         self.read_symref(&self.head_path())
     }
 
-    fn path_for_name(&self, name: &str) -> Option<PathBuf> {
-        let prefixes = [self.pathname.clone(), self.refs_path(), self.heads_path()];
-        for prefix in &prefixes {
-            if prefix.join(name).exists() {
-                return Some(prefix.join(name));
+    fn packed_refs_path(&self) -> PathBuf {
+        self.pathname.join("packed-refs")
+    }
+
+    /// Refs stored in `.git/packed-refs`, by full name
+    fn read_packed_refs(&self) -> BTreeMap<String, PackedRef> {
+        let contents = fs::read_to_string(self.packed_refs_path()).unwrap_or_default();
+        let mut refs: BTreeMap<String, PackedRef> = BTreeMap::new();
+        let mut last_name = None;
+
+        for line in contents.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some(peeled) = line.strip_prefix('^') {
+                if let Some(r#ref) = last_name.as_ref().and_then(|name| refs.get_mut(name)) {
+                    r#ref.peeled = Some(peeled.to_string());
+                }
+                continue;
+            }
+            if let Some((oid, name)) = line.split_once(' ') {
+                let r#ref = PackedRef {
+                    oid: oid.to_string(),
+                    peeled: None,
+                };
+                refs.insert(name.to_string(), r#ref);
+                last_name = Some(name.to_string());
             }
         }
-        None
+
+        refs
+    }
+
+    /// The full name of a ref, such as `refs/heads/master` for
+    /// `master`, whether it is loose or packed
+    pub fn full_name(&self, name: &str) -> Option<String> {
+        let packed = self.read_packed_refs();
+
+        REF_PREFIXES
+            .iter()
+            .map(|prefix| format!("{}{}", prefix, name))
+            .find(|candidate| {
+                self.pathname.join(candidate).is_file() || packed.contains_key(candidate)
+            })
+    }
+
+    fn branch_exists(&self, branch_name: &str) -> bool {
+        self.heads_path().join(branch_name).is_file()
+            || self
+                .read_packed_refs()
+                .contains_key(&format!("refs/heads/{}", branch_name))
     }
 
     pub fn read_ref(&self, name: &str) -> Option<String> {
-        if let Some(path) = self.path_for_name(name) {
+        self.full_name(name)
+            .and_then(|full_name| self.read_full_ref(&full_name))
+    }
+
+    /// Read a ref by its path relative to .git, preferring a loose
+    /// file over an entry in packed-refs
+    fn read_full_ref(&self, name: &str) -> Option<String> {
+        let path = self.pathname.join(name);
+        if path.is_file() {
             self.read_symref(&path)
         } else {
-            None
+            self.read_packed_refs().remove(name).map(|r#ref| r#ref.oid)
         }
     }
 
@@ -126,7 +307,7 @@ impl Refs {
     pub fn read_oid(&self, r#ref: &Ref) -> Option<String> {
         match r#ref {
             Ref::Ref { oid } => Some(oid.to_string()),
-            Ref::SymRef { path } => self.read_ref(&path),
+            Ref::SymRef { path } => self.read_ref(path),
         }
     }
 
@@ -136,7 +317,7 @@ impl Refs {
             let mut contents = String::new();
             file.read_to_string(&mut contents).unwrap();
 
-            if let Some(caps) = SYMREF.captures(&contents.trim()) {
+            if let Some(caps) = SYMREF.captures(contents.trim()) {
                 Some(Ref::SymRef {
                     path: caps[1].to_string(),
                 })
@@ -154,7 +335,7 @@ impl Refs {
         let r#ref = Self::read_oid_or_symref(path);
 
         match r#ref {
-            Some(Ref::SymRef { path }) => self.read_symref(&self.pathname.join(&path)),
+            Some(Ref::SymRef { path }) => self.read_full_ref(&path),
             Some(Ref::Ref { oid }) => Some(oid),
             None => None,
         }
@@ -166,13 +347,13 @@ impl Refs {
 
         let r#ref = Self::read_oid_or_symref(path);
         match r#ref {
-            None | Some(Ref::Ref { .. }) => Self::write_lockfile(lock, &oid),
+            None | Some(Ref::Ref { .. }) => Self::write_lockfile(lock, oid),
             Some(Ref::SymRef { path }) => self.update_symref(&self.pathname.join(path), oid),
         }
     }
 
     fn write_lockfile(mut lock: Lockfile, oid: &str) -> Result<(), io::Error> {
-        lock.write(&oid)?;
+        lock.write(oid)?;
         lock.write("\n")?;
         lock.commit()
     }
@@ -188,58 +369,137 @@ impl Refs {
         }
     }
 
-    pub fn create_branch(&self, branch_name: &str, start_oid: &str) -> Result<(), String> {
+    /// Whether `name` may be used as the name of a branch or tag
+    pub fn is_valid_name(name: &str) -> bool {
+        !INVALID_FILENAME.is_match(name)
+    }
+
+    pub fn create_branch(
+        &self,
+        branch_name: &str,
+        start_oid: &str,
+        message: &str,
+    ) -> Result<(), Error> {
         let path = self.heads_path().join(branch_name);
 
         if INVALID_FILENAME.matches(branch_name).into_iter().count() > 0 {
-            return Err(format!("{} is not a valid branch name.\n", branch_name));
+            return Err(Error::failure(format!(
+                "{} is not a valid branch name.\n",
+                branch_name
+            )));
+        }
+
+        if self.branch_exists(branch_name) {
+            return Err(Error::failure(format!(
+                "A branch named {} already exists.\n",
+                branch_name
+            )));
         }
+        self.identity()?;
+
+        self.write_new_ref(&path, start_oid)?;
+        let name = util::relative_path_from(&path, &self.pathname);
+        self.log_update(&name, None, start_oid, message)
+    }
+
+    /// Tags are not logged, as in git
+    pub fn create_tag(&self, tag_name: &str, oid: &str) -> Result<(), Error> {
+        let path = self.tags_path().join(tag_name);
 
-        if path.as_path().exists() {
-            return Err(format!("A branch named {} already exists.\n", branch_name));
+        if INVALID_FILENAME.matches(tag_name).into_iter().count() > 0 {
+            return Err(Error::fatal(format!(
+                "fatal: '{}' is not a valid tag name.\n",
+                tag_name
+            )));
         }
 
-        File::create(&path).expect("failed to create refs file for branch");
-        self.update_ref_file(&path, start_oid)
-            .map_err(|e| e.to_string())
+        if self.full_name(&format!("refs/tags/{}", tag_name)).is_some() {
+            return Err(Error::fatal(format!(
+                "fatal: tag '{}' already exists\n",
+                tag_name
+            )));
+        }
+
+        self.write_new_ref(&path, oid)
+    }
+
+    /// Write a ref that does not exist yet, creating the directories
+    /// it goes in
+    fn write_new_ref(&self, path: &Path, oid: &str) -> Result<(), Error> {
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir).map_err(|e| Error::io("create directory", dir, e))?;
+        self.update_ref_file(path, oid)
+            .map_err(|e| Error::io("create", path, e))
     }
 
     pub fn list_branches(&self) -> Vec<Ref> {
-        self.list_refs(&self.heads_path())
+        self.list_refs("refs/heads/")
     }
 
-    fn name_to_symref(&self, name: DirEntry) -> Vec<Ref> {
-        let path = name.path();
-        if path.is_dir() {
-            self.list_refs(&path)
-        } else {
-            let path = util::relative_path_from(&path, &self.pathname);
-            vec![Ref::SymRef { path }]
-        }
+    pub fn list_tags(&self) -> Vec<Ref> {
+        self.list_refs("refs/tags/")
     }
 
-    fn list_refs(&self, dirname: &Path) -> Vec<Ref> {
-        fs::read_dir(self.pathname.join(dirname))
-            .expect("failed to read dir")
-            .flat_map(|name| self.name_to_symref(name.unwrap()))
-            .collect()
+    /// Loose and packed refs whose names start with `prefix`, in name
+    /// order
+    fn list_refs(&self, prefix: &str) -> Vec<Ref> {
+        let mut names: BTreeSet<String> = self
+            .read_packed_refs()
+            .into_keys()
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.extend(self.list_loose_refs(&self.pathname.join(prefix)));
+
+        names.into_iter().map(|path| Ref::SymRef { path }).collect()
+    }
+
+    fn list_loose_refs(&self, dirname: &Path) -> Vec<String> {
+        let mut names = vec![];
+        for entry in fs::read_dir(dirname).into_iter().flatten() {
+            let path = entry.expect("failed to read dir").path();
+            if path.is_dir() {
+                names.extend(self.list_loose_refs(&path));
+            } else if path.extension().is_none_or(|ext| ext != "lock") {
+                names.push(util::relative_path_from(&path, &self.pathname));
+            }
+        }
+
+        names
     }
 
-    fn list_all_refs(&self) -> Vec<Ref> {
-        let mut all_refs = vec![Ref::SymRef { path: "HEAD".to_string() }];
-        let mut refs = self.list_refs(&self.refs_path());
+    pub fn list_all_refs(&self) -> Vec<Ref> {
+        let mut all_refs = vec![Ref::SymRef {
+            path: "HEAD".to_string(),
+        }];
+        let mut refs = self.list_refs("refs/");
 
         all_refs.append(&mut refs);
         all_refs
     }
 
-    pub fn reverse_refs(&self) -> HashMap<String, Vec<Ref>> {
-        let mut table : HashMap<String, Vec<Ref>> = HashMap::new();
+    /// Refs by the object they point at. Annotated tags are listed
+    /// under the object they tag, using the peeled value from
+    /// packed-refs where there is one and `peel` otherwise.
+    pub fn reverse_refs(&self, peel: &dyn Fn(&str) -> Option<String>) -> HashMap<String, Vec<Ref>> {
+        let mut table: HashMap<String, Vec<Ref>> = HashMap::new();
 
         let all_refs = self.list_all_refs();
+        let packed = self.read_packed_refs();
 
         for r#ref in all_refs {
-            let oid = self.read_oid(&r#ref).unwrap(); // TODO: handle error
+            // Such as HEAD on a branch yet to be born
+            let mut oid = match self.read_oid(&r#ref) {
+                Some(oid) => oid,
+                None => continue,
+            };
+            if r#ref.is_tag() {
+                let path = r#ref.path();
+                let peeled = match packed.get(path) {
+                    Some(packed) if !self.pathname.join(path).is_file() => packed.peeled.clone(),
+                    _ => peel(&oid),
+                };
+                oid = peeled.unwrap_or(oid);
+            }
             let oid_refs = table.get_mut(&oid);
 
             if let Some(oid_refs) = oid_refs {
@@ -247,7 +507,6 @@ impl Refs {
             } else {
                 table.insert(oid, vec![r#ref]);
             }
-            
         }
 
         table
@@ -259,7 +518,7 @@ impl Refs {
             Ref::SymRef { path } => {
                 let path = self.pathname.join(path);
 
-                let dirs = [self.heads_path(), self.pathname.clone()];
+                let dirs = [self.heads_path(), self.tags_path(), self.pathname.clone()];
                 let prefix = dirs.iter().find(|dir| {
                     path.parent()
                         .expect("failed to get parent")
@@ -273,19 +532,152 @@ impl Refs {
         }
     }
 
-    pub fn delete_branch(&self, branch_name: &str) -> Result<String, String> {
-        let path = self.heads_path().join(branch_name);
+    pub fn delete_branch(&self, branch_name: &str) -> Result<String, Error> {
+        let name = format!("refs/heads/{}", branch_name);
+        let oid = self
+            .delete_ref(&name)?
+            .ok_or_else(|| Error::failure(format!("branch {} not found", branch_name)))?;
+        self.reflog(&name)
+            .delete()
+            .map_err(|e| Error::io("delete the log of", &name, e))?;
+
+        Ok(oid)
+    }
+
+    pub fn delete_tag(&self, tag_name: &str) -> Result<String, Error> {
+        self.delete_ref(&format!("refs/tags/{}", tag_name))?
+            .ok_or_else(|| Error::failure(format!("error: tag '{}' not found.\n", tag_name)))
+    }
+
+    /// Remove a ref, loose and packed, returning the value it had or
+    /// None if it did not exist
+    fn delete_ref(&self, name: &str) -> Result<Option<String>, Error> {
+        let path = self.pathname.join(name);
 
         let mut lockfile = Lockfile::new(&path);
-        lockfile.hold_for_update().map_err(|e| e.to_string())?;
+        lockfile
+            .hold_for_update()
+            .map_err(|e| Error::io("lock", &path, e))?;
+
+        let oid = self.read_full_ref(name);
+        if oid.is_some() {
+            if path.is_file() {
+                fs::remove_file(&path).map_err(|e| Error::io("remove", &path, e))?;
+            }
+            self.delete_packed_ref(name)
+                .map_err(|e| Error::io("update", self.packed_refs_path(), e))?;
+        }
+        // To remove the .lock file
+        lockfile
+            .rollback()
+            .map_err(|e| Error::io("unlock", &path, e))?;
+
+        Ok(oid)
+    }
 
-        if let Some(oid) = self.read_symref(&path) {
-            fs::remove_file(path).map_err(|e| e.to_string())?;
-            // To remove the .lock file
-            lockfile.rollback().map_err(|e| e.to_string())?;
-            Ok(oid)
+    /// Drop `name` and its peeled line from packed-refs, leaving the
+    /// rest of the file as it was
+    fn delete_packed_ref(&self, name: &str) -> Result<(), io::Error> {
+        let path = self.packed_refs_path();
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let mut lock = Lockfile::new(&path);
+        lock.hold_for_update()?;
+
+        let contents = fs::read_to_string(&path)?;
+        let mut kept = String::new();
+        let mut removing = false;
+        let mut removed = false;
+        for line in contents.lines() {
+            if line.starts_with('^') && removing {
+                continue;
+            }
+            removing = !line.starts_with('#') && line.split_once(' ').map(|(_, n)| n) == Some(name);
+            if removing {
+                removed = true;
+                continue;
+            }
+            kept.push_str(line);
+            kept.push('\n');
+        }
+
+        if removed {
+            lock.write(&kept)?;
+            lock.commit()
         } else {
-            return Err(format!("branch {} not found", branch_name));
+            lock.rollback()
+        }
+    }
+
+    /// Move loose refs into packed-refs: tags, and every other ref as
+    /// well with `all`. `peel` gives the object an annotated tag
+    /// points at. With `prune`, the loose files are then removed.
+    pub fn pack_refs(
+        &self,
+        all: bool,
+        prune: bool,
+        peel: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<(), Error> {
+        let path = self.packed_refs_path();
+        let mut lock = Lockfile::new(&path);
+        lock.hold_for_update()
+            .map_err(|e| Error::io("lock", &path, e))?;
+
+        let mut packed = self.read_packed_refs();
+        let mut loose = vec![];
+        for name in self.list_loose_refs(&self.refs_path()) {
+            if !all && !name.starts_with("refs/tags/") {
+                continue;
+            }
+            // Symbolic refs always stay loose
+            if let Some(Ref::Ref { oid }) = Self::read_oid_or_symref(&self.pathname.join(&name)) {
+                packed.insert(name.clone(), PackedRef { oid, peeled: None });
+                loose.push(name);
+            }
         }
+
+        let mut contents = PACKED_REFS_HEADER.to_string();
+        for (name, r#ref) in packed.iter_mut() {
+            r#ref.peeled = peel(&r#ref.oid);
+            contents.push_str(&format!("{} {}\n", r#ref.oid, name));
+            if let Some(peeled) = &r#ref.peeled {
+                contents.push_str(&format!("^{}\n", peeled));
+            }
+        }
+        lock.write(&contents)
+            .and_then(|()| lock.commit())
+            .map_err(|e| Error::io("write", &path, e))?;
+
+        if prune {
+            for name in loose {
+                self.prune_loose_ref(&name)
+                    .map_err(|e| Error::io("remove", self.pathname.join(&name), e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove a loose ref that has been packed, along with any
+    /// directories that it leaves empty below refs/heads or refs/tags
+    fn prune_loose_ref(&self, name: &str) -> Result<(), io::Error> {
+        let path = self.pathname.join(name);
+        let mut lock = Lockfile::new(&path);
+        lock.hold_for_update()?;
+        fs::remove_file(&path)?;
+        lock.rollback()?;
+
+        let stop = self.refs_path();
+        let mut dir = path.parent();
+        while let Some(parent) = dir {
+            if parent.parent() == Some(stop.as_path()) || fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+
+        Ok(())
     }
 }
@@ -1,4 +1,5 @@
 use crate::database::tree::TreeEntry;
+use crate::errors::Error;
 use crate::index::Entry;
 use crate::repository::{ChangeType, Repository};
 use std::collections::{BTreeSet, HashMap, HashSet};
@@ -95,10 +96,10 @@ impl<'a> Migration<'a> {
             conflicts,
         }
     }
-    pub fn apply_changes(&mut self) -> Result<(), String> {
+    pub fn apply_changes(&mut self) -> Result<(), Error> {
         match self.plan_changes() {
             Ok(_) => (),
-            Err(errors) => return Err(errors.join("\n")),
+            Err(errors) => return Err(Error::failure(errors.join("\n"))),
         }
         self.update_workspace()?;
         self.update_index();
@@ -130,36 +131,47 @@ impl<'a> Migration<'a> {
         let path_str = path.to_str().unwrap();
         let entry = self.repo.index.entry_for_path(path_str).cloned();
         if self.index_differs_from_trees(entry.as_ref(), old_item.as_ref(), new_item.as_ref()) {
-            self.insert_conflict(&ConflictType::StaleFile, &path);
+            self.insert_conflict(&ConflictType::StaleFile, path);
             return;
         }
 
         let stat = self.repo.workspace.stat_file(path_str).ok();
         let error_type = self.get_error_type(&stat, &entry.as_ref(), new_item);
 
-        if stat.is_none() {
-            let parent = self.untracked_parent(path);
-            if parent.is_some() {
-                let parent = parent.unwrap();
-                let conflict_path = if entry.is_some() { path } else { &parent };
-                self.insert_conflict(&error_type, conflict_path);
+        match stat {
+            None => {
+                if let Some(parent) = self.untracked_parent(path) {
+                    let conflict_path = if entry.is_some() { path } else { &parent };
+                    self.insert_conflict(&error_type, conflict_path);
+                }
             }
-        } else if Self::stat_is_file(&stat) {
-            let changed = self
-                .repo
-                .compare_index_to_workspace(entry.as_ref(), stat.as_ref());
-            if changed != ChangeType::NoChange {
-                self.insert_conflict(&error_type, path);
+            // A checked out submodule is left to its own repository
+            Some(ref stat) if stat.is_dir() && entry.as_ref().is_some_and(Entry::is_gitlink) => (),
+            Some(ref stat) if !stat.is_dir() => {
+                // Ignored files are expendable, so they can be
+                // overwritten or removed without complaint
+                if entry.is_none() && self.repo.ignore.is_ignored(path_str, false) {
+                    return;
+                }
+
+                let changed = self
+                    .repo
+                    .compare_index_to_workspace(entry.as_ref(), Some(stat));
+                if changed != ChangeType::NoChange {
+                    self.insert_conflict(&error_type, path);
+                }
             }
-        } else if Self::stat_is_dir(&stat) {
-            let trackable = self
-                .repo
-                .is_trackable_path(path_str, &stat.unwrap())
-                .ok()
-                .unwrap_or(false);
-            if trackable {
-                self.insert_conflict(&error_type, path);
+            Some(ref stat) if stat.is_dir() => {
+                let trackable = self
+                    .repo
+                    .is_trackable_path(path_str, stat)
+                    .ok()
+                    .unwrap_or(false);
+                if trackable {
+                    self.insert_conflict(&error_type, path);
+                }
             }
+            Some(_) => (),
         }
     }
 
@@ -167,7 +179,7 @@ impl<'a> Migration<'a> {
         let dirname = path.parent().expect("failed to get dirname");
         for parent in dirname.ancestors() {
             let parent_path_str = parent.to_str().unwrap();
-            if parent_path_str == "" {
+            if parent_path_str.is_empty() {
                 continue;
             }
 
@@ -195,13 +207,6 @@ impl<'a> Migration<'a> {
         }
     }
 
-    fn stat_is_file(stat: &Option<fs::Metadata>) -> bool {
-        match stat {
-            None => false,
-            Some(stat) => stat.is_file(),
-        }
-    }
-
     fn get_error_type(
         &self,
         stat: &Option<fs::Metadata>,
@@ -210,7 +215,7 @@ impl<'a> Migration<'a> {
     ) -> ConflictType {
         if entry.is_some() {
             ConflictType::StaleFile
-        } else if Self::stat_is_dir(&stat) {
+        } else if Self::stat_is_dir(stat) {
             ConflictType::StaleDirectory
         } else if item.is_some() {
             ConflictType::UntrackedOverwritten
@@ -235,7 +240,7 @@ impl<'a> Migration<'a> {
                 continue;
             }
 
-            let (header, footer) = MESSAGES.get(&conflict_type).unwrap();
+            let (header, footer) = MESSAGES.get(conflict_type).unwrap();
             let mut error = vec![header.to_string()];
 
             for p in paths {
@@ -284,7 +289,7 @@ impl<'a> Migration<'a> {
         }
     }
 
-    fn update_workspace(&mut self) -> Result<(), String> {
+    fn update_workspace(&mut self) -> Result<(), Error> {
         self.repo.workspace.apply_migration(
             &mut self.repo.database,
             &self.changes,
@@ -303,7 +308,12 @@ impl<'a> Migration<'a> {
         for action in &[Action::Create, Action::Update] {
             for (path, entry) in self.changes.get(action).unwrap() {
                 let path = path.to_str().expect("failed to convert path to str");
-                let entry_oid = entry.clone().unwrap().get_oid();
+                let entry = entry.as_ref().unwrap();
+                if entry.is_gitlink() {
+                    self.repo.index.add_tree_entry(path, entry);
+                    continue;
+                }
+                let entry_oid = entry.get_oid();
                 let stat = self
                     .repo
                     .workspace
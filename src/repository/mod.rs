@@ -1,19 +1,25 @@
+use crate::config::stack::Stack;
 use crate::database::blob::Blob;
-use crate::database::commit::Commit;
-use crate::database::object::Object;
+use crate::database::object::Storable;
 use crate::database::tree::TreeEntry;
 use crate::database::Database;
-use crate::database::ParsedObject;
+use crate::errors::Error;
+use crate::ignore::Ignore;
 use crate::index;
 use crate::index::Index;
 use crate::refs::Refs;
 use crate::workspace::Workspace;
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 
 pub mod migration;
 use migration::Migration;
+pub mod pending_commit;
+use pending_commit::PendingCommit;
+pub mod status;
+pub use status::Status;
 
 #[derive(Clone, Copy, Hash, Eq, PartialEq, Debug)]
 pub enum ChangeType {
@@ -24,151 +30,273 @@ pub enum ChangeType {
     NoChange,
 }
 
-#[derive(Clone, Copy, Hash, Eq, PartialEq)]
-enum ChangeKind {
-    Workspace,
-    Index,
-}
-
 pub struct Repository {
     pub database: Database,
     pub index: Index,
     pub refs: Refs,
     pub workspace: Workspace,
+    pub ignore: Ignore,
+    pub config: Stack,
 
-    // status fields
+    pub git_path: PathBuf,
     pub root_path: PathBuf,
-    pub stats: HashMap<String, fs::Metadata>,
-    pub untracked: BTreeSet<String>,
-    pub changed: BTreeSet<String>,
-    pub workspace_changes: BTreeMap<String, ChangeType>,
-    pub index_changes: BTreeMap<String, ChangeType>,
-    pub head_tree: HashMap<String, TreeEntry>,
+    pub bare: bool,
 }
 
-impl Repository {
-    pub fn new(root_path: &Path) -> Repository {
-        let git_path = root_path.join(".git");
-        let db_path = git_path.join("objects");
+// Where a repository keeps its files, relative to its work tree
+const GIT_DIR: &str = ".git";
+const GITDIR_PREFIX: &str = "gitdir: ";
 
-        Repository {
-            database: Database::new(&db_path),
-            index: Index::new(&git_path.join("index")),
-            refs: Refs::new(&git_path),
-            workspace: Workspace::new(git_path.parent().unwrap()),
+/// What to tell a user who has not said who they are
+fn identity_unknown(role: &str) -> String {
+    format!(
+        "{} identity unknown
 
-            root_path: root_path.to_path_buf(),
-            stats: HashMap::new(),
-            untracked: BTreeSet::new(),
-            changed: BTreeSet::new(),
-            workspace_changes: BTreeMap::new(),
-            index_changes: BTreeMap::new(),
-            head_tree: HashMap::new(),
-        }
-    }
+*** Please tell me who you are.
 
-    pub fn initialize_status(&mut self) -> Result<(), String> {
-        self.scan_workspace(&self.root_path.clone()).unwrap();
-        self.load_head_tree();
-        self.check_index_entries().map_err(|e| e.to_string())?;
-        self.collect_deleted_head_files();
+Run
 
-        Ok(())
-    }
+  rug config --global user.email \"you@example.com\"
+  rug config --global user.name \"Your Name\"
+
+to set your account's default identity.
+Omit --global to set the identity only in this repository.
+
+",
+        role
+    )
+}
 
-    fn collect_deleted_head_files(&mut self) {
-        let paths: Vec<String> = {
-            self.head_tree
-                .iter()
-                .map(|(path, _)| path.clone())
-                .collect()
+impl Repository {
+    /// Find the repository `dir` is in, as git does: `GIT_DIR` if
+    /// set, else the nearest `.git` directory or `gitdir:` file in
+    /// `dir` or one of its parents, or the nearest of them that is
+    /// itself a bare repository. `GIT_WORK_TREE` overrides the work
+    /// tree, which otherwise is the directory holding `.git`, or the
+    /// current directory when `GIT_DIR` is set. Bare repositories have
+    /// no work tree.
+    pub fn locate(
+        dir: &Path,
+        env: &HashMap<String, String>,
+    ) -> Result<(PathBuf, Option<PathBuf>), Error> {
+        let not_found = || {
+            Error::fatal(format!(
+                "fatal: not a git repository (or any of the parent directories): {}
+",
+                GIT_DIR
+            ))
         };
-        for path in paths {
-            if !self.index.is_tracked_file(&path) {
-                self.record_change(&path, ChangeKind::Index, ChangeType::Deleted);
+        let var = |name: &str| env.get(name).filter(|value| !value.is_empty());
+
+        let (git_path, root_path) = match var("GIT_DIR") {
+            Some(git_dir) => {
+                let git_path = Self::read_gitdir(&dir.join(git_dir)).ok_or_else(|| {
+                    Error::fatal(format!("fatal: not a git repository: '{}'\n", git_dir))
+                })?;
+                (git_path, Some(dir.to_path_buf()))
             }
+            None => dir
+                .ancestors()
+                .find_map(Self::find_git_dir)
+                .ok_or_else(not_found)?,
+        };
+
+        let work_tree = var("GIT_WORK_TREE").map(|work_tree| dir.join(work_tree));
+        Self::resolve_paths(git_path, root_path, work_tree, env)
+    }
+
+    /// Open the repository `dir` is in, found as by `locate`
+    pub fn discover(dir: &Path, env: &HashMap<String, String>) -> Result<Repository, Error> {
+        let (git_path, root_path) = Self::locate(dir, env)?;
+        Self::open_paths(&git_path, root_path.as_deref(), env)
+    }
+
+    /// Open the repository at `path`, which is either a work tree or a
+    /// bare repository. Unlike `discover`, this neither searches
+    /// parent directories nor honours `GIT_DIR` and `GIT_WORK_TREE`;
+    /// the process's environment is only used to find config files.
+    pub fn open(path: &Path) -> Result<Repository, Error> {
+        let env: HashMap<String, String> = env::vars().collect();
+        let (git_path, root_path) = Self::find_git_dir(path).ok_or_else(|| {
+            Error::fatal(format!(
+                "fatal: not a git repository: '{}'\n",
+                path.display()
+            ))
+        })?;
+        let (git_path, root_path) = Self::resolve_paths(git_path, root_path, None, &env)?;
+        Self::open_paths(&git_path, root_path.as_deref(), &env)
+    }
+
+    fn open_paths(
+        git_path: &Path,
+        root_path: Option<&Path>,
+        env: &HashMap<String, String>,
+    ) -> Result<Repository, Error> {
+        match root_path {
+            Some(root_path) => Self::at(git_path, root_path, env),
+            None => Self::open_bare(git_path, env),
         }
     }
 
-    fn load_head_tree(&mut self) {
-        let head_oid = self.refs.read_head();
-        if let Some(head_oid) = head_oid {
-            let commit: Commit = {
-                if let ParsedObject::Commit(commit) = self.database.load(&head_oid) {
-                    commit.clone()
-                } else {
-                    panic!("HEAD points to a non-commit");
-                }
-            };
-            self.read_tree(&commit.tree_oid, Path::new(""));
+    /// The repository whose files are in `dir`, or in its `.git`
+    fn find_git_dir(dir: &Path) -> Option<(PathBuf, Option<PathBuf>)> {
+        if let Some(git_path) = Self::read_gitdir(&dir.join(GIT_DIR)) {
+            Some((git_path, Some(dir.to_path_buf())))
+        } else if Self::is_git_dir(dir) {
+            Some((dir.to_path_buf(), None))
+        } else {
+            None
         }
     }
 
-    fn read_tree(&mut self, tree_oid: &str, prefix: &Path) {
-        let entries = {
-            if let ParsedObject::Tree(tree) = self.database.load(tree_oid) {
-                tree.entries.clone()
-            } else {
-                BTreeMap::new()
-            }
+    /// The canonical git directory and work tree of a repository. A
+    /// bare one has no work tree unless `work_tree` gives one.
+    fn resolve_paths(
+        git_path: PathBuf,
+        root_path: Option<PathBuf>,
+        work_tree: Option<PathBuf>,
+        env: &HashMap<String, String>,
+    ) -> Result<(PathBuf, Option<PathBuf>), Error> {
+        let bare = Stack::load(&git_path, env)?.get_bool("core.bare") == Some(true);
+        let root_path = match work_tree {
+            Some(work_tree) => Some(work_tree),
+            None if bare => None,
+            None => root_path,
         };
 
-        for (name, entry) in entries {
-            let path = prefix.join(name);
+        let canonical = |path: PathBuf| path.canonicalize().map_err(|e| Error::io("open", path, e));
+        Ok((canonical(git_path)?, root_path.map(canonical).transpose()?))
+    }
 
-            if entry.is_tree() {
-                self.read_tree(&entry.get_oid(), &path);
-            } else {
-                self.head_tree
-                    .insert(path.to_str().unwrap().to_string(), entry);
-            }
-        }
+    /// Whether `path` holds a repository's files directly, as a bare
+    /// repository does
+    fn is_git_dir(path: &Path) -> bool {
+        path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
     }
 
-    fn scan_workspace(&mut self, prefix: &Path) -> Result<(), std::io::Error> {
-        for (mut path, stat) in self.workspace.list_dir(prefix)? {
-            if self.index.is_tracked(&path) {
-                if self.workspace.is_dir(&path) {
-                    self.scan_workspace(&self.workspace.abs_path(&path))?;
-                } else {
-                    // path is file
-                    self.stats.insert(path.to_string(), stat);
-                }
-            } else if self.is_trackable_path(&path, &stat)? {
-                if self.workspace.is_dir(&path) {
-                    path.push('/');
-                }
-                self.untracked.insert(path);
-            }
+    /// The git directory at `path`, following a `gitdir:` file to the
+    /// directory it names. Directories without a HEAD are not
+    /// repositories.
+    fn read_gitdir(path: &Path) -> Option<PathBuf> {
+        let git_path = if path.is_file() {
+            let contents = fs::read_to_string(path).ok()?;
+            let target = contents.trim_end().strip_prefix(GITDIR_PREFIX)?;
+            path.parent()?.join(target)
+        } else {
+            path.to_path_buf()
+        };
+
+        if git_path.join("HEAD").is_file() {
+            Some(git_path)
+        } else {
+            None
         }
+    }
 
-        Ok(())
+    /// Open the bare repository whose files are in `git_path`. It has
+    /// no work tree, so only commands that read the object database
+    /// and refs can use it.
+    pub fn open_bare(git_path: &Path, env: &HashMap<String, String>) -> Result<Repository, Error> {
+        let mut repo = Self::at(git_path, git_path, env)?;
+        repo.bare = true;
+        Ok(repo)
     }
 
-    fn check_index_entries(&mut self) -> Result<(), std::io::Error> {
-        let entries: Vec<index::Entry> = self
-            .index
-            .entries
-            .iter()
-            .map(|(_, entry)| entry.clone())
-            .collect();
-        for mut entry in entries {
-            self.check_index_against_workspace(&mut entry);
-            self.check_index_against_head_tree(&mut entry);
+    /// Fail unless the repository has a work tree
+    pub fn require_work_tree(&self) -> Result<(), Error> {
+        if self.bare {
+            Err(Error::fatal(
+                "fatal: this operation must be run in a work tree\n",
+            ))
+        } else {
+            Ok(())
         }
+    }
+
+    /// Open the repository whose files are in `git_path`, with
+    /// `root_path` as its work tree. Config files are found through
+    /// the variables in `env`.
+    pub fn at(
+        git_path: &Path,
+        root_path: &Path,
+        env: &HashMap<String, String>,
+    ) -> Result<Repository, Error> {
+        let git_path = git_path.to_path_buf();
+        let db_path = git_path.join("objects");
+        let config = Stack::load(&git_path, env)?;
+        let excludes_file = config.get("core.excludesFile");
+        let mut refs = Refs::new(&git_path);
+        refs.set_identity(Self::committer_identity(&config, env));
 
-        Ok(())
+        Ok(Repository {
+            database: Database::new(&db_path)?,
+            index: Index::new(&git_path.join("index")),
+            refs,
+            workspace: Workspace::new(root_path),
+            ignore: Ignore::new(root_path, &git_path, excludes_file, env),
+            config,
+            git_path: git_path.clone(),
+            root_path: root_path.to_path_buf(),
+            bare: false,
+        })
     }
 
-    fn record_change(&mut self, path: &str, change_kind: ChangeKind, change_type: ChangeType) {
-        self.changed.insert(path.to_string());
+    /// Where `dir` is within the work tree: empty at the top of it,
+    /// or when outside it
+    pub fn prefix(&self, dir: &Path) -> PathBuf {
+        dir.strip_prefix(&self.root_path)
+            .map(|prefix| prefix.to_path_buf())
+            .unwrap_or_default()
+    }
 
-        let changes_map = match change_kind {
-            ChangeKind::Index => &mut self.index_changes,
-            ChangeKind::Workspace => &mut self.workspace_changes,
-        };
+    /// Who to record as committer in commits, tags and reflogs
+    pub fn committer_identity(
+        config: &Stack,
+        env: &HashMap<String, String>,
+    ) -> Result<(String, String), Error> {
+        Self::identity(config, env, "Committer", "GIT_COMMITTER")
+    }
 
-        changes_map.insert(path.to_string(), change_type);
+    /// Who to record as author in commits
+    pub fn author_identity(
+        config: &Stack,
+        env: &HashMap<String, String>,
+    ) -> Result<(String, String), Error> {
+        Self::identity(config, env, "Author", "GIT_AUTHOR")
+    }
+
+    /// A name and email from `<prefix>_NAME` and `<prefix>_EMAIL`, or
+    /// else user.name and user.email, with EMAIL as a last resort for
+    /// the email. Neither is ever made up.
+    fn identity(
+        config: &Stack,
+        env: &HashMap<String, String>,
+        role: &str,
+        prefix: &str,
+    ) -> Result<(String, String), Error> {
+        let lookup = |var: &str, key: &str| {
+            env.get(&format!("{}_{}", prefix, var))
+                .cloned()
+                .filter(|value| !value.is_empty())
+                .or_else(|| config.get(key))
+                .filter(|value| !value.is_empty())
+        };
+        let email = lookup("EMAIL", "user.email")
+            .or_else(|| env.get("EMAIL").cloned())
+            .filter(|value| !value.is_empty());
+
+        match (lookup("NAME", "user.name"), email) {
+            (Some(name), Some(email)) => Ok((name, email)),
+            (None, email) => Err(Error::fatal(format!(
+                "{}fatal: empty ident name (for <{}>) not allowed\n",
+                identity_unknown(role),
+                email.unwrap_or_default()
+            ))),
+            (_, None) => Err(Error::fatal(format!(
+                "{}fatal: unable to auto-detect email address\n",
+                identity_unknown(role)
+            ))),
+        }
     }
 
     fn compare_index_to_workspace(
@@ -179,6 +307,10 @@ impl Repository {
         if entry.is_none() {
             return ChangeType::Untracked;
         }
+        // A missing submodule is one that was never checked out
+        if entry.is_some_and(index::Entry::is_gitlink) {
+            return ChangeType::NoChange;
+        }
         if stat.is_none() {
             return ChangeType::Deleted;
         }
@@ -188,11 +320,11 @@ impl Repository {
         let entry = entry.unwrap();
         let stat = stat.unwrap();
 
-        if !entry.stat_match(&stat) {
+        if !entry.stat_match(stat) {
             return ChangeType::Modified;
         }
 
-        if entry.times_match(&stat) {
+        if entry.times_match(stat) {
             return ChangeType::NoChange;
         }
 
@@ -200,7 +332,7 @@ impl Repository {
             .workspace
             .read_file(&entry.path)
             .expect("failed to read file");
-        let blob = Blob::new(data.as_bytes());
+        let blob = Blob::new(&data);
         let oid = blob.get_oid();
 
         if entry.oid != oid {
@@ -235,35 +367,20 @@ impl Repository {
         ChangeType::NoChange
     }
 
-    /// Adds modified entries to self.changed
-    fn check_index_against_workspace(&mut self, mut entry: &mut index::Entry) {
-        let stat = self.stats.get(&entry.path);
-        let status = self.compare_index_to_workspace(Some(entry), stat);
-        if status == ChangeType::NoChange {
-            let stat = stat.expect("empty stat");
-            self.index.update_entry_stat(&mut entry, &stat);
-        } else {
-            self.record_change(&entry.path, ChangeKind::Workspace, status);
-        }
-    }
-
-    fn check_index_against_head_tree(&mut self, entry: &mut index::Entry) {
-        let item = self.head_tree.get(&entry.path);
-        let status = self.compare_tree_to_index(item, Some(entry));
-        if status != ChangeType::NoChange {
-            self.record_change(&entry.path, ChangeKind::Index, status);
+    /// Check if path is trackable but not currently tracked. Ignored
+    /// paths are not trackable.
+    fn is_trackable_path(&self, path: &str, stat: &fs::Metadata) -> Result<bool, std::io::Error> {
+        if self.ignore.is_ignored(path, stat.is_dir()) {
+            return Ok(false);
         }
-    }
 
-    /// Check if path is trackable but not currently tracked
-    fn is_trackable_path(&self, path: &str, stat: &fs::Metadata) -> Result<bool, std::io::Error> {
-        if stat.is_file() {
+        if !stat.is_dir() {
             return Ok(!self.index.is_tracked_file(path));
         }
 
         let items = self.workspace.list_dir(&self.workspace.abs_path(path))?;
-        let (files, dirs): (Vec<(&String, &fs::Metadata)>, Vec<(&String, &fs::Metadata)>) =
-            items.iter().partition(|(_path, stat)| stat.is_file());
+        let (files, dirs): (Vec<_>, Vec<_>) =
+            items.iter().partition(|(_path, stat)| !stat.is_dir());
 
         for (file_path, file_stat) in files.iter() {
             if self.is_trackable_path(file_path, file_stat)? {
@@ -280,10 +397,51 @@ impl Repository {
         Ok(false)
     }
 
+    /// How the work tree and index differ from HEAD. The index must
+    /// have been loaded first, with `index.load` or, to write back the
+    /// refreshed stat information, `index.load_for_update`.
+    pub fn status(&mut self) -> Result<Status, Error> {
+        Status::new(self)
+    }
+
+    pub fn pending_commit(&self) -> PendingCommit {
+        PendingCommit::new(&self.git_path)
+    }
+
     pub fn migration(
         &mut self,
         tree_diff: HashMap<PathBuf, (Option<TreeEntry>, Option<TreeEntry>)>,
-    ) -> Migration {
+    ) -> Migration<'_> {
         Migration::new(self, tree_diff)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::repository::Repository;
+
+    #[test]
+    fn open_only_the_repository_at_a_path() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.mkdir("dir").unwrap();
+        cmd_helper.git_cmd(&["init", "-q", "--bare", "bare.git"]);
+        let root_path = cmd_helper.repo().root_path;
+
+        let repo = Repository::open(&root_path).unwrap();
+        assert_eq!(root_path.join(".git"), repo.git_path);
+        assert!(!repo.bare);
+
+        let repo = Repository::open(&root_path.join("bare.git")).unwrap();
+        assert!(repo.bare);
+
+        let error = Repository::open(&root_path.join("dir")).err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("fatal: not a git repository: "));
+        let env = Default::default();
+        let repo = Repository::discover(&root_path.join("dir"), &env).unwrap();
+        assert_eq!(root_path, repo.root_path);
+    }
+}
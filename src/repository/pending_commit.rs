@@ -0,0 +1,54 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::errors::Error;
+
+/// Tracks a merge that has been started but not yet committed, via
+/// the `MERGE_HEAD` and `MERGE_MSG` files
+pub struct PendingCommit {
+    head_path: PathBuf,
+    message_path: PathBuf,
+}
+
+impl PendingCommit {
+    pub fn new(git_path: &Path) -> PendingCommit {
+        PendingCommit {
+            head_path: git_path.join("MERGE_HEAD"),
+            message_path: git_path.join("MERGE_MSG"),
+        }
+    }
+
+    pub fn start(&self, oid: &str, message: &str) -> Result<(), Error> {
+        fs::write(&self.head_path, format!("{}\n", oid))
+            .map_err(|e| Error::io("write", &self.head_path, e))?;
+        fs::write(&self.message_path, message)
+            .map_err(|e| Error::io("write", &self.message_path, e))
+    }
+
+    pub fn in_progress(&self) -> bool {
+        self.head_path.exists()
+    }
+
+    pub fn merge_oid(&self) -> Result<String, Error> {
+        match fs::read_to_string(&self.head_path) {
+            Ok(oid) => Ok(oid.trim().to_string()),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Err(Error::fatal(
+                "fatal: There is no merge in progress (MERGE_HEAD missing).\n",
+            )),
+            Err(e) => Err(Error::io("read", &self.head_path, e)),
+        }
+    }
+
+    pub fn merge_message(&self) -> Result<String, Error> {
+        fs::read_to_string(&self.message_path).map_err(|e| Error::io("read", &self.message_path, e))
+    }
+
+    pub fn clear(&self) -> Result<(), Error> {
+        fs::remove_file(&self.head_path).map_err(|e| Error::io("remove", &self.head_path, e))?;
+        match fs::remove_file(&self.message_path) {
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            result => result.map_err(|e| Error::io("remove", &self.message_path, e)),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::database::commit::Commit;
+use crate::database::tree::TreeEntry;
+use crate::database::ParsedObject;
+use crate::errors::Error;
+use crate::index;
+use crate::repository::{ChangeType, Repository};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, Hash, Eq, PartialEq)]
+enum ChangeKind {
+    Workspace,
+    Index,
+}
+
+/// How the work tree and index differ from HEAD, as `rug status`
+/// reports it
+#[derive(Default)]
+pub struct Status {
+    // Stat information for the tracked files found in the work tree
+    stats: HashMap<String, fs::Metadata>,
+    pub untracked: BTreeSet<String>,
+    pub changed: BTreeSet<String>,
+    pub workspace_changes: BTreeMap<String, ChangeType>,
+    pub index_changes: BTreeMap<String, ChangeType>,
+    // Unmerged paths, with the index stages present for each
+    pub conflicts: BTreeMap<String, Vec<u8>>,
+    pub head_tree: HashMap<String, TreeEntry>,
+}
+
+impl Status {
+    /// Compare `repo`'s work tree, index and HEAD. Index entries whose
+    /// files are unchanged get their stat information refreshed, so
+    /// the caller should write the index back if it holds the lock.
+    pub fn new(repo: &mut Repository) -> Result<Status, Error> {
+        let mut status = Status::default();
+
+        let root_path = repo.root_path.clone();
+        status
+            .scan_workspace(repo, &root_path)
+            .map_err(|e| Error::io("scan", &root_path, e))?;
+        status.load_head_tree(repo)?;
+        status.check_index_entries(repo);
+        status.collect_deleted_head_files(repo);
+
+        Ok(status)
+    }
+
+    fn collect_deleted_head_files(&mut self, repo: &Repository) {
+        let paths: Vec<String> = { self.head_tree.keys().cloned().collect() };
+        for path in paths {
+            if !repo.index.is_tracked_file(&path) {
+                self.record_change(&path, ChangeKind::Index, ChangeType::Deleted);
+            }
+        }
+    }
+
+    fn load_head_tree(&mut self, repo: &mut Repository) -> Result<(), Error> {
+        let head_oid = repo.refs.read_head();
+        if let Some(head_oid) = head_oid {
+            let commit: Commit = {
+                if let ParsedObject::Commit(commit) = repo.database.load(&head_oid)? {
+                    commit.clone()
+                } else {
+                    return Err(Error::fatal(format!(
+                        "fatal: HEAD {} is not a commit\n",
+                        head_oid
+                    )));
+                }
+            };
+            self.read_tree(repo, &commit.tree_oid, Path::new(""))?;
+        }
+
+        Ok(())
+    }
+
+    fn read_tree(
+        &mut self,
+        repo: &mut Repository,
+        tree_oid: &str,
+        prefix: &Path,
+    ) -> Result<(), Error> {
+        let entries = {
+            if let ParsedObject::Tree(tree) = repo.database.load(tree_oid)? {
+                tree.entries.clone()
+            } else {
+                BTreeMap::new()
+            }
+        };
+
+        for (name, entry) in entries {
+            let path = prefix.join(name);
+
+            if entry.is_tree() {
+                self.read_tree(repo, &entry.get_oid(), &path)?;
+            } else {
+                self.head_tree
+                    .insert(path.to_str().unwrap().to_string(), entry);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scan_workspace(&mut self, repo: &Repository, prefix: &Path) -> Result<(), std::io::Error> {
+        for (mut path, stat) in repo.workspace.list_dir(prefix)? {
+            if repo.index.is_tracked(&path) {
+                let gitlink = repo
+                    .index
+                    .entry_for_path(&path)
+                    .is_some_and(index::Entry::is_gitlink);
+                if repo.workspace.is_dir(&path) && !gitlink {
+                    self.scan_workspace(repo, &repo.workspace.abs_path(&path))?;
+                } else {
+                    // path is file
+                    self.stats.insert(path.to_string(), stat);
+                }
+            } else if repo.is_trackable_path(&path, &stat)? {
+                if repo.workspace.is_dir(&path) {
+                    path.push('/');
+                }
+                self.untracked.insert(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_index_entries(&mut self, repo: &mut Repository) {
+        let entries: Vec<index::Entry> = repo.index.entries.values().cloned().collect();
+        for mut entry in entries {
+            if entry.stage() == 0 {
+                self.check_index_against_workspace(repo, &mut entry);
+                self.check_index_against_head_tree(repo, &entry);
+            }
+        }
+
+        self.conflicts = repo.index.conflicts();
+        self.changed.extend(self.conflicts.keys().cloned());
+    }
+
+    fn record_change(&mut self, path: &str, change_kind: ChangeKind, change_type: ChangeType) {
+        self.changed.insert(path.to_string());
+
+        let changes_map = match change_kind {
+            ChangeKind::Index => &mut self.index_changes,
+            ChangeKind::Workspace => &mut self.workspace_changes,
+        };
+
+        changes_map.insert(path.to_string(), change_type);
+    }
+
+    /// Adds modified entries to self.changed
+    fn check_index_against_workspace(&mut self, repo: &mut Repository, entry: &mut index::Entry) {
+        let stat = self.stats.get(&entry.path);
+        let status = repo.compare_index_to_workspace(Some(entry), stat);
+        if status == ChangeType::NoChange {
+            if let Some(stat) = stat {
+                repo.index.update_entry_stat(entry, stat);
+            }
+        } else {
+            self.record_change(&entry.path, ChangeKind::Workspace, status);
+        }
+    }
+
+    fn check_index_against_head_tree(&mut self, repo: &Repository, entry: &index::Entry) {
+        let item = self.head_tree.get(&entry.path);
+        let status = repo.compare_tree_to_index(item, Some(entry));
+        if status != ChangeType::NoChange {
+            self.record_change(&entry.path, ChangeKind::Index, status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::repository::{ChangeType, Repository};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn compare_the_workspace_and_index_with_head() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.run_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("a.txt", b"one").unwrap();
+        cmd_helper.write_file("b.txt", b"two").unwrap();
+        cmd_helper.run_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        cmd_helper.write_file("a.txt", b"changed").unwrap();
+        cmd_helper.delete("b.txt").unwrap();
+        cmd_helper.write_file("c.txt", b"three").unwrap();
+        cmd_helper.run_cmd(&["add", "c.txt"]).unwrap();
+        cmd_helper.write_file("d.txt", b"four").unwrap();
+
+        let mut repo = Repository::open(&cmd_helper.repo().root_path).unwrap();
+        repo.index.load().unwrap();
+        let status = repo.status().unwrap();
+
+        let changes = |changes: &[(&str, ChangeType)]| {
+            changes
+                .iter()
+                .map(|(path, change)| (path.to_string(), *change))
+                .collect::<BTreeMap<_, _>>()
+        };
+        assert_eq!(
+            changes(&[
+                ("a.txt", ChangeType::Modified),
+                ("b.txt", ChangeType::Deleted)
+            ]),
+            status.workspace_changes
+        );
+        assert_eq!(
+            changes(&[("c.txt", ChangeType::Added)]),
+            status.index_changes
+        );
+        assert_eq!(vec!["d.txt"], status.untracked.iter().collect::<Vec<_>>());
+    }
+}
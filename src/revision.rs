@@ -1,4 +1,5 @@
 use crate::database::{commit, Database, ParsedObject};
+use crate::errors::Error;
 use crate::repository::Repository;
 use regex::{Regex, RegexSet};
 use std::collections::HashMap;
@@ -6,7 +7,7 @@ use std::fmt;
 
 lazy_static! {
     static ref INVALID_NAME: RegexSet = {
-        RegexSet::new(&[
+        RegexSet::new([
             r"^\.",
             r"/\.",
             r"\.\.",
@@ -17,8 +18,10 @@ lazy_static! {
         ])
         .unwrap()
     };
-    static ref PARENT: Regex = Regex::new(r"^(.+)\^$").unwrap();
+    static ref PARENT: Regex = Regex::new(r"^(.+)\^(\d*)$").unwrap();
     static ref ANCESTOR: Regex = Regex::new(r"^(.+)~(\d+)$").unwrap();
+    static ref REFLOG: Regex = Regex::new(r"^(.*)@\{(\d+)\}$").unwrap();
+    static ref PREVIOUS_BRANCH: Regex = Regex::new(r"^@\{-(\d+)\}$").unwrap();
     static ref REF_ALIASES: HashMap<&'static str, &'static str> = {
         let mut m = HashMap::new();
         m.insert("@", "HEAD");
@@ -46,13 +49,17 @@ impl fmt::Display for HintedError {
 #[derive(Debug, Clone)]
 pub enum Rev {
     Ref { name: String },
-    Parent { rev: Box<Rev> },
+    Parent { rev: Box<Rev>, n: usize },
     Ancestor { rev: Box<Rev>, n: i32 },
+    // `name@{n}`, where an empty name means the current branch
+    Reflog { name: String, n: usize },
+    PreviousBranch { n: usize },
 }
 
 pub struct Revision<'a> {
     repo: &'a mut Repository,
-    query: Rev,
+    // None if the expression could not be parsed
+    query: Option<Rev>,
     expr: String,
     errors: Vec<HintedError>,
 }
@@ -62,21 +69,43 @@ impl<'a> Revision<'a> {
         Revision {
             repo,
             expr: expr.to_string(),
-            query: Self::parse(expr).expect("Revision parse failed"),
+            query: Self::parse(expr),
             errors: vec![],
         }
     }
 
     pub fn parse(revision: &str) -> Option<Rev> {
         if let Some(caps) = PARENT.captures(revision) {
-            let rev = Revision::parse(&caps[1]).expect("parsing parent rev failed");
-            return Some(Rev::Parent { rev: Box::new(rev) });
+            let rev = Revision::parse(&caps[1])?;
+            // `rev^` is shorthand for `rev^1`
+            let n = if caps[2].is_empty() {
+                1
+            } else {
+                caps[2].parse().ok()?
+            };
+            Some(Rev::Parent {
+                rev: Box::new(rev),
+                n,
+            })
         } else if let Some(caps) = ANCESTOR.captures(revision) {
-            let rev = Revision::parse(&caps[1]).expect("parsing ancestor rev failed");
-            return Some(Rev::Ancestor {
+            let rev = Revision::parse(&caps[1])?;
+            Some(Rev::Ancestor {
                 rev: Box::new(rev),
-                n: i32::from_str_radix(&caps[2], 10).expect("could not parse ancestor number"),
-            });
+                n: caps[2].parse().ok()?,
+            })
+        } else if let Some(caps) = PREVIOUS_BRANCH.captures(revision) {
+            Some(Rev::PreviousBranch {
+                n: caps[1].parse().ok()?,
+            })
+        } else if let Some(caps) = REFLOG.captures(revision) {
+            if !caps[1].is_empty() && !Revision::is_valid_ref(&caps[1]) {
+                return None;
+            }
+            let name = REF_ALIASES.get(&caps[1]).copied().unwrap_or(&caps[1]);
+            Some(Rev::Reflog {
+                name: name.to_string(),
+                n: caps[2].parse().ok()?,
+            })
         } else if Revision::is_valid_ref(revision) {
             let rev = REF_ALIASES.get(revision).unwrap_or(&revision);
             Some(Rev::Ref {
@@ -91,31 +120,74 @@ impl<'a> Revision<'a> {
         INVALID_NAME.matches(revision).into_iter().count() == 0
     }
 
-    pub fn resolve(&mut self) -> Result<String, Vec<HintedError>> {
-        match self.resolve_query(self.query.clone()) {
-            Some(revision) => {
+    pub fn resolve(&mut self) -> Result<String, Error> {
+        if let Some(query) = self.query.clone() {
+            if let Some(revision) = self.resolve_query(query) {
                 if self.load_commit(&revision).is_some() {
-                    Ok(revision)
-                } else {
-                    Err(self.errors.clone())
+                    return Ok(revision);
                 }
             }
-            None => Err(self.errors.clone()),
         }
+
+        self.errors.push(HintedError {
+            message: format!("Not a valid object name: '{}'.", self.expr),
+            hint: vec![],
+        });
+        Err(Error::InvalidRevision {
+            errors: self.errors.clone(),
+        })
+    }
+
+    /// Resolve `expr` to an object of any type, leaving tags unpeeled:
+    /// a ref, an object ID, a revision expression, or `<rev>:<path>`
+    /// for an entry in a commit's tree
+    pub fn resolve_object(repo: &mut Repository, expr: &str) -> Option<String> {
+        if let Some((rev, path)) = expr.split_once(':') {
+            let oid = Self::resolve_object(repo, rev)?;
+            return Self::tree_entry(repo, &oid, path);
+        }
+
+        let query = Self::parse(expr)?;
+        let mut revision = Revision {
+            repo,
+            query: Some(query.clone()),
+            expr: expr.to_string(),
+            errors: vec![],
+        };
+        match query {
+            Rev::Ref { name } => revision.read_ref(&name),
+            query => revision.resolve_query(query),
+        }
+    }
+
+    /// The object at `path` in the tree `oid` stands for
+    fn tree_entry(repo: &mut Repository, oid: &str, path: &str) -> Option<String> {
+        let mut oid = repo.database.peel_tree(oid)?;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            oid = match repo.database.load(&oid).ok()? {
+                ParsedObject::Tree(tree) => tree.entries.get(name)?.get_oid(),
+                _ => return None,
+            };
+        }
+
+        Some(oid)
     }
 
     /// Resolve Revision to commit object ID.
     pub fn resolve_query(&mut self, query: Rev) -> Option<String> {
         match query {
-            Rev::Ref { name } => self.read_ref(&name),
-            Rev::Parent { rev } => {
-                let oid = self.resolve_query(*rev).expect("Invalid parent rev");
-                self.commit_parent(&oid)
+            // Tags stand for the commit they point at
+            Rev::Ref { name } => self
+                .read_ref(&name)
+                .map(|oid| self.repo.database.peel_tag(&oid).unwrap_or(oid)),
+            Rev::Parent { rev, n } => {
+                let oid = self.resolve_query(*rev)?;
+                self.commit_parent(&oid, n)
             }
             Rev::Ancestor { rev, n } => {
-                let mut oid = self.resolve_query(*rev).expect("Invalid ancestor rev");
+                let mut oid = self.resolve_query(*rev)?;
                 for _ in 0..n {
-                    if let Some(parent_oid) = self.commit_parent(&oid) {
+                    if let Some(parent_oid) = self.commit_parent(&oid, 1) {
                         oid = parent_oid
                     } else {
                         break;
@@ -123,6 +195,43 @@ impl<'a> Revision<'a> {
                 }
                 Some(oid)
             }
+            Rev::Reflog { name, n } => self.read_reflog(&name, n),
+            Rev::PreviousBranch { n } => {
+                let branch = self.repo.refs.previous_branch(n)?;
+                self.read_ref(&branch)
+            }
+        }
+    }
+
+    /// The branch name that `@{-n}` stands for, so that checking it
+    /// out switches to the branch rather than detaching HEAD
+    pub fn previous_branch(repo: &Repository, expr: &str) -> Option<String> {
+        let n = PREVIOUS_BRANCH.captures(expr)?[1].parse().ok()?;
+        repo.refs.previous_branch(n)
+    }
+
+    /// The value `name` had `n` updates ago
+    fn read_reflog(&mut self, name: &str, n: usize) -> Option<String> {
+        let (name, full_name) = if name.is_empty() {
+            let current = self.repo.refs.current_ref("HEAD");
+            (
+                self.repo.refs.ref_short_name(&current),
+                current.path().to_string(),
+            )
+        } else {
+            (name.to_string(), self.repo.refs.full_name(name)?)
+        };
+
+        let entries = self.repo.refs.reflog(&full_name).read();
+        match entries.iter().rev().nth(n) {
+            Some(entry) => Some(entry.new_oid.clone()),
+            None => {
+                self.errors.push(HintedError {
+                    message: format!("log for '{}' only has {} entries", name, entries.len()),
+                    hint: vec![],
+                });
+                None
+            }
         }
     }
 
@@ -149,7 +258,10 @@ impl<'a> Revision<'a> {
         let mut hint = vec!["The candidates are:".to_string()];
 
         for oid in candidates {
-            let object = self.repo.database.load(&oid);
+            let object = match self.repo.database.load(&oid) {
+                Ok(object) => object,
+                Err(_) => continue,
+            };
             let long_oid = object.get_oid();
             let short = Database::short_oid(&long_oid);
             let info = format!(" {} {}", short, object.obj_type());
@@ -169,17 +281,27 @@ impl<'a> Revision<'a> {
         self.errors.push(HintedError { message, hint });
     }
 
-    fn commit_parent(&mut self, oid: &str) -> Option<String> {
+    /// Returns the `n`th parent of a commit, counting from 1. The
+    /// 0th parent is the commit itself.
+    fn commit_parent(&mut self, oid: &str, n: usize) -> Option<String> {
         match self.load_commit(oid) {
-            Some(commit) => commit.parent.clone(),
+            Some(_) if n == 0 => Some(oid.to_string()),
+            Some(commit) => commit.parents.get(n - 1).cloned(),
             None => None,
         }
     }
 
     fn load_commit(&mut self, oid: &str) -> Option<&commit::Commit> {
         match self.repo.database.load(oid) {
-            ParsedObject::Commit(commit) => Some(commit),
-            object => {
+            Ok(ParsedObject::Commit(commit)) => Some(commit),
+            Err(_) => {
+                self.errors.push(HintedError {
+                    message: format!("bad object {}", oid),
+                    hint: vec![],
+                });
+                None
+            }
+            Ok(object) => {
                 let message = format!("object {} is a {}, not a commit", oid, object.obj_type());
                 self.errors.push(HintedError {
                     message,
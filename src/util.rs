@@ -2,7 +2,7 @@ use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::fmt::Write;
 use std::num::ParseIntError;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 pub fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
     (0..s.len())
@@ -30,3 +30,78 @@ pub fn relative_path_from(path: &Path, from: &Path) -> String {
         .unwrap()
         .to_string()
 }
+
+/// Resolve `path`, given relative to `prefix`, to a path from the top
+/// of the work tree, dealing with `.` and `..` without touching the
+/// filesystem. `None` if it leads outside the work tree.
+pub fn normalize_path(prefix: &Path, path: &str) -> Option<String> {
+    let mut normal = PathBuf::new();
+    for component in prefix.join(path).components() {
+        match component {
+            Component::Normal(name) => normal.push(name),
+            Component::ParentDir => {
+                if !normal.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir => (),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    normal.to_str().map(|path| path.to_string())
+}
+
+/// Show `path`, which is relative to the top of the work tree, as a
+/// path from `prefix`, the current directory's place in the work tree
+pub fn path_from_prefix(path: &str, prefix: &Path) -> String {
+    let from: Vec<Component> = prefix.components().collect();
+    let to: Vec<Component> = Path::new(path).components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    relative.extend(&to[common..]);
+
+    let mut relative = relative.to_str().expect("invalid path").to_string();
+    if relative.is_empty() {
+        relative.push('.');
+    }
+    if path.ends_with('/') {
+        relative.push('/');
+    }
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_paths_from_a_prefix() {
+        let prefix = Path::new("src/commands");
+        assert_eq!(
+            Some("src/commands/add.rs".to_string()),
+            normalize_path(prefix, "add.rs")
+        );
+        assert_eq!(
+            Some("src/main.rs".to_string()),
+            normalize_path(prefix, "../main.rs")
+        );
+        assert_eq!(Some("".to_string()), normalize_path(prefix, "../.."));
+        assert_eq!(None, normalize_path(prefix, "../../.."));
+    }
+
+    #[test]
+    fn show_paths_from_a_prefix() {
+        let prefix = Path::new("src/commands");
+        assert_eq!("add.rs", path_from_prefix("src/commands/add.rs", prefix));
+        assert_eq!("../main.rs", path_from_prefix("src/main.rs", prefix));
+        assert_eq!("../../README", path_from_prefix("README", prefix));
+        assert_eq!("../../docs/", path_from_prefix("docs/", prefix));
+        assert_eq!("README", path_from_prefix("README", Path::new("")));
+        assert_eq!("./", path_from_prefix("src/commands/", prefix));
+    }
+}
@@ -1,19 +1,18 @@
-use crate::database::tree::{TreeEntry, TREE_MODE};
+use crate::database::tree::{TreeEntry, SYMLINK_MODE, TREE_MODE};
 use crate::database::{Database, ParsedObject};
+use crate::errors::Error;
 use crate::repository::migration::Action;
 use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsStr;
 use std::fs::{self, File, OpenOptions};
 use std::io::prelude::*;
 use std::io::BufReader;
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{symlink, PermissionsExt};
 use std::path::{Path, PathBuf};
 
-lazy_static! {
-    static ref IGNORE_PATHS: Vec<&'static str> = {
-        let v = vec![".git", "target"];
-        v
-    };
-}
+// Never part of the workspace, regardless of any ignore rules
+const GIT_DIR: &str = ".git";
 
 pub struct Workspace {
     path: PathBuf,
@@ -30,8 +29,12 @@ impl Workspace {
         self.path.join(rel_path)
     }
 
+    /// Whether `rel_path` is a directory. Symlinks to directories are
+    /// not, since they are tracked as links.
     pub fn is_dir(&self, rel_path: &str) -> bool {
-        self.abs_path(rel_path).is_dir()
+        self.stat_file(rel_path)
+            .map(|stat| stat.is_dir())
+            .unwrap_or(false)
     }
 
     /// List contents of directory. Does NOT list contents of
@@ -39,22 +42,21 @@ impl Workspace {
     pub fn list_dir(&self, dir: &Path) -> Result<HashMap<String, fs::Metadata>, std::io::Error> {
         let path = self.path.join(dir);
 
-        let entries = fs::read_dir(&path)?
-            .map(|f| f.unwrap().path())
-            .filter(|f| !IGNORE_PATHS.contains(&f.file_name().unwrap().to_str().unwrap()));
         let mut stats = HashMap::new();
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            if entry.file_name() == GIT_DIR {
+                continue;
+            }
 
-        for name in entries {
-            let relative = self
-                .path
-                .join(&name)
+            let name = self.path.join(entry.path());
+            let relative = name
                 .strip_prefix(&self.path)
-                .unwrap()
-                .to_str()
-                .unwrap()
+                .unwrap_or(&name)
+                .to_string_lossy()
                 .to_string();
 
-            let stat = self.stat_file(&relative).expect("stat file failed");
+            let stat = self.stat_file(&relative)?;
             stats.insert(relative, stat);
         }
 
@@ -62,9 +64,10 @@ impl Workspace {
     }
 
     /// Return list of files in dir. Nested files are flattened
-    /// strings eg. `a/b/c/inner.txt`
+    /// strings eg. `a/b/c/inner.txt`. Symlinks are listed as files,
+    /// and other repositories nested inside this one are skipped.
     pub fn list_files(&self, dir: &Path) -> Result<Vec<String>, std::io::Error> {
-        if dir.is_file() {
+        if !fs::symlink_metadata(dir)?.is_dir() {
             return Ok(vec![dir
                 .strip_prefix(&self.path)
                 .unwrap()
@@ -73,7 +76,10 @@ impl Workspace {
                 .to_string()]);
         }
 
-        if IGNORE_PATHS.contains(&dir.file_name().unwrap().to_str().unwrap()) {
+        let nested = dir
+            .strip_prefix(&self.path)
+            .is_ok_and(|rel| rel != Path::new(""));
+        if dir.file_name().unwrap() == GIT_DIR || (nested && dir.join(GIT_DIR).exists()) {
             return Ok(vec![]);
         }
 
@@ -85,18 +91,32 @@ impl Workspace {
         Ok(files)
     }
 
-    // TODO: Should return bytes instead?
-    pub fn read_file(&self, file_name: &str) -> Result<String, std::io::Error> {
-        let file = File::open(self.path.as_path().join(file_name))?;
+    /// Read a file's contents, or the target of a symlink
+    pub fn read_file(&self, file_name: &str) -> Result<Vec<u8>, std::io::Error> {
+        let path = self.path.join(file_name);
+        if fs::symlink_metadata(&path)?.file_type().is_symlink() {
+            let target = fs::read_link(&path)?;
+            return Ok(target.into_os_string().into_vec());
+        }
+
+        let file = File::open(path)?;
         let mut buf_reader = BufReader::new(file);
-        let mut contents = String::new();
+        let mut contents = vec![];
 
-        buf_reader.read_to_string(&mut contents)?;
+        buf_reader.read_to_end(&mut contents)?;
         Ok(contents)
     }
 
+    pub fn write_file(&self, file_name: &str, data: &[u8]) -> Result<(), std::io::Error> {
+        let path = self.path.join(file_name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)
+    }
+
     pub fn stat_file(&self, file_name: &str) -> Result<fs::Metadata, std::io::Error> {
-        fs::metadata(self.path.join(file_name))
+        fs::symlink_metadata(self.path.join(file_name))
     }
 
     pub fn apply_migration(
@@ -105,22 +125,20 @@ impl Workspace {
         changes: &HashMap<Action, Vec<(PathBuf, Option<TreeEntry>)>>,
         rmdirs: &BTreeSet<PathBuf>,
         mkdirs: &BTreeSet<PathBuf>,
-    ) -> Result<(), String> {
-        self.apply_change_list(database, changes, Action::Delete)
-            .map_err(|e| e.to_string())?;
+    ) -> Result<(), Error> {
+        self.apply_change_list(database, changes, Action::Delete)?;
         for dir in rmdirs.iter().rev() {
             let dir_path = self.path.join(dir);
             self.remove_directory(&dir_path).unwrap_or(());
         }
 
         for dir in mkdirs.iter() {
-            self.make_directory(dir).map_err(|e| e.to_string())?;
+            self.make_directory(dir)
+                .map_err(|e| Error::io("create directory", self.path.join(dir), e))?;
         }
 
-        self.apply_change_list(database, changes, Action::Update)
-            .map_err(|e| e.to_string())?;
+        self.apply_change_list(database, changes, Action::Update)?;
         self.apply_change_list(database, changes, Action::Create)
-            .map_err(|e| e.to_string())
     }
 
     fn apply_change_list(
@@ -128,53 +146,89 @@ impl Workspace {
         database: &mut Database,
         changes: &HashMap<Action, Vec<(PathBuf, Option<TreeEntry>)>>,
         action: Action,
-    ) -> std::io::Result<()> {
+    ) -> Result<(), Error> {
         let changes = changes.get(&action).unwrap().clone();
         for (filename, entry) in changes {
             let path = self.path.join(filename);
-            Self::remove_file_or_dir(&path)?;
+            // Symlinks and files need their blob, which is loaded before
+            // anything on disk is touched
+            let data = match &entry {
+                Some(entry) if !entry.is_gitlink() && entry.mode() != TREE_MODE => {
+                    Some(Self::blob_data(database, &entry.get_oid())?)
+                }
+                _ => None,
+            };
+
+            Self::write_entry(&path, entry.as_ref(), data.as_deref())
+                .map_err(|e| Error::io("update", path, e))?;
+        }
 
-            if action == Action::Delete {
-                continue;
+        Ok(())
+    }
+
+    /// Make `path` hold `entry`, whose blob is `data`, or remove it if
+    /// there is no entry
+    fn write_entry(
+        path: &Path,
+        entry: Option<&TreeEntry>,
+        data: Option<&[u8]>,
+    ) -> std::io::Result<()> {
+        let is_dir = fs::symlink_metadata(path)
+            .map(|stat| stat.is_dir())
+            .unwrap_or(false);
+
+        // A checked out submodule belongs to its own repository, so it
+        // is left in place
+        let entry = match entry {
+            None if is_dir && path.join(GIT_DIR).exists() => return Ok(()),
+            None => return Self::remove_file_or_dir(path),
+            Some(entry) => entry,
+        };
+
+        if entry.is_gitlink() {
+            if !is_dir {
+                Self::remove_file_or_dir(path)?;
+                fs::create_dir(path)?;
             }
+            return Ok(());
+        }
 
-            let mut file = OpenOptions::new()
-                .write(true)
-                .create_new(true)
-                .open(&path)?;
+        Self::remove_file_or_dir(path)?;
 
-            let entry = entry
-                .expect("entry missing for non-delete");
+        if entry.mode() == SYMLINK_MODE {
+            return symlink(OsStr::from_bytes(data.unwrap_or_default()), path);
+        }
 
-            if entry.mode() != TREE_MODE {
-                let data = Self::blob_data(database, &entry.get_oid());
-                file.write_all(&data)?;
+        let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
 
-                // Set mode
-                let metadata = file.metadata()?;
-                let mut permissions = metadata.permissions();
-                permissions.set_mode(entry.mode());
-                fs::set_permissions(path, permissions)?;
-            }
+        if let Some(data) = data {
+            file.write_all(data)?;
+
+            // Set mode
+            let metadata = file.metadata()?;
+            let mut permissions = metadata.permissions();
+            permissions.set_mode(entry.mode());
+            fs::set_permissions(path, permissions)?;
         }
 
         Ok(())
     }
 
-    pub fn blob_data(database: &mut Database, oid: &str) -> Vec<u8> {
-        match database.load(oid) {
-            ParsedObject::Blob(blob) => blob.data.clone(),
-            _ => panic!("not a blob oid"),
+    pub fn blob_data(database: &mut Database, oid: &str) -> Result<Vec<u8>, Error> {
+        match database.load(oid)? {
+            ParsedObject::Blob(blob) => Ok(blob.data.clone()),
+            object => Err(Error::CorruptObject {
+                oid: oid.to_string(),
+                reason: format!("object {} is a {}, not a blob", oid, object.obj_type()),
+            }),
         }
     }
 
     fn remove_file_or_dir(path: &Path) -> std::io::Result<()> {
-        if path.is_dir() {
-            std::fs::remove_dir_all(path)
-        } else if path.is_file() {
-            std::fs::remove_file(path)
-        } else {
-            Ok(())
+        match fs::symlink_metadata(path) {
+            Ok(stat) if stat.is_dir() => std::fs::remove_dir_all(path),
+            Ok(_) => std::fs::remove_file(path),
+            Err(_) => Ok(()),
         }
     }
 
@@ -187,10 +241,8 @@ impl Workspace {
         let path = self.path.join(dirname);
 
         if let Ok(stat) = self.stat_file(dirname.to_str().expect("conversion to str failed")) {
-            if stat.is_file() {
-                std::fs::remove_file(&path)?;
-            }
             if !stat.is_dir() {
+                std::fs::remove_file(&path)?;
                 std::fs::create_dir(&path)?;
             }
         } else {